@@ -0,0 +1,99 @@
+//! `agent:error` — agent-level failures outside any pipeline stage.
+//!
+//! Stage failures reach king through `pipeline:stage_result`; this event
+//! covers the rest (gateway unreachable at startup, skills that failed to
+//! load, a rejected registration) so king can surface them instead of
+//! leaving them in the log file.
+
+use serde::Serialize;
+use serde_json::json;
+use tracing::warn;
+
+use crate::emit::Emit;
+use crate::skill_engine::SkillLoad;
+
+/// Event reporting an agent-level failure (`{ agent_id, category, message }`).
+pub const AGENT_ERROR: &str = "agent:error";
+
+/// What kind of failure an [`AgentError`] reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    GatewayUnreachable,
+    SkillLoad,
+    RegistrationRejected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentError {
+    pub category: ErrorCategory,
+    pub message: String,
+}
+
+impl AgentError {
+    pub fn new(category: ErrorCategory, message: impl Into<String>) -> Self {
+        Self {
+            category,
+            message: message.into(),
+        }
+    }
+
+    /// One error per skill directory that failed to load.
+    pub fn from_skill_load(load: &SkillLoad) -> Vec<Self> {
+        load.failed
+            .iter()
+            .map(|f| {
+                Self::new(
+                    ErrorCategory::SkillLoad,
+                    format!("{}: {}", f.path.display(), f.error),
+                )
+            })
+            .collect()
+    }
+
+    /// Send as `agent:error`; a failed emit is only logged.
+    pub async fn emit(&self, socket: &dyn Emit, agent_id: &str) {
+        let payload = json!({
+            "agent_id": agent_id,
+            "category": self.category,
+            "message": self.message,
+        });
+        if let Err(e) = socket.emit(AGENT_ERROR, payload).await {
+            warn!(err = %e, category = ?self.category, "failed to emit agent:error");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skill_engine::SkillLoadFailure;
+    use crate::test_support::RecordingEmitter;
+
+    #[tokio::test]
+    async fn skill_load_failure_emits_agent_error() {
+        let load = SkillLoad {
+            skills: vec![],
+            failed: vec![SkillLoadFailure {
+                path: "skills/broken".into(),
+                error: "missing field `version`".to_string(),
+            }],
+        };
+        let emitter = RecordingEmitter::default();
+        for error in AgentError::from_skill_load(&load) {
+            error.emit(&emitter, "agent-1").await;
+        }
+
+        let events = emitter.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, AGENT_ERROR);
+        assert_eq!(events[0].1["agent_id"], "agent-1");
+        assert_eq!(events[0].1["category"], "skill_load");
+        assert!(
+            events[0].1["message"]
+                .as_str()
+                .unwrap()
+                .contains("skills/broken: missing field")
+        );
+    }
+}
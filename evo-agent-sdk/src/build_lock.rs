@@ -0,0 +1,235 @@
+//! File locks serializing self-upgrade builds on one host.
+//!
+//! A build holds `<EVO_HOME>/data/build-locks/<component>.lock`, so two
+//! builds of the same repo never share its staging directory, plus one of
+//! `MAX_CONCURRENT_BUILDS` global slots (`slot-<n>.lock`), so builds of
+//! different repos don't all compete for the CPU at once. With
+//! `BUILD_LOCK_MODE=queue` (the default) a build waits for its locks; with
+//! `reject` it fails with [`BuildBusy`] instead.
+//!
+//! Locks are OS advisory locks (`flock` on Unix) held on the open lock
+//! file, released when the [`BuildLockGuard`] drops, which also happens
+//! when a build errors out or panics. The OS drops them when the holding
+//! process exits, so a crashed build never leaves a lock behind.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::prompt_dump::sanitize;
+use crate::self_upgrade::Cancelled;
+
+/// Default cap on builds running at once across all repos.
+pub const DEFAULT_MAX_CONCURRENT_BUILDS: u32 = 2;
+
+/// How often a queued build re-checks its locks.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What a build does when its locks are taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildLockMode {
+    /// Wait until the locks are free (or the build is cancelled).
+    Queue,
+    /// Fail right away with [`BuildBusy`].
+    Reject,
+}
+
+/// A build refused because another one holds its locks.
+#[derive(Debug, thiserror::Error)]
+#[error("build of {component} refused: {reason}")]
+pub struct BuildBusy {
+    pub component: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildLocks {
+    dir: PathBuf,
+    mode: BuildLockMode,
+    max_concurrent: u32,
+}
+
+impl BuildLocks {
+    /// Locks under `dir`, queueing, with [`DEFAULT_MAX_CONCURRENT_BUILDS`].
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            mode: BuildLockMode::Queue,
+            max_concurrent: DEFAULT_MAX_CONCURRENT_BUILDS,
+        }
+    }
+
+    /// `<EVO_HOME>/data/build-locks`, with `BUILD_LOCK_MODE` and
+    /// `MAX_CONCURRENT_BUILDS`.
+    pub fn from_env() -> Self {
+        let mode = match std::env::var("BUILD_LOCK_MODE").as_deref() {
+            Ok("reject") => BuildLockMode::Reject,
+            Ok("queue") | Err(_) => BuildLockMode::Queue,
+            Ok(other) => {
+                warn!(mode = %other, "unknown BUILD_LOCK_MODE, queueing");
+                BuildLockMode::Queue
+            }
+        };
+        Self::new(
+            crate::self_upgrade::evo_home()
+                .join("data")
+                .join("build-locks"),
+        )
+        .with_mode(mode)
+        .with_max_concurrent(
+            crate::config::env_parse("MAX_CONCURRENT_BUILDS")
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_BUILDS),
+        )
+    }
+
+    pub fn with_mode(mut self, mode: BuildLockMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// At least 1.
+    pub fn with_max_concurrent(mut self, max: u32) -> Self {
+        self.max_concurrent = max.max(1);
+        self
+    }
+
+    /// Take the lock for `component` and a global slot, waiting or failing
+    /// per the mode. Both are held until the guard drops.
+    pub async fn acquire(
+        &self,
+        component: &str,
+        cancel: &CancellationToken,
+    ) -> Result<BuildLockGuard> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let repo_path = self.dir.join(format!("{}.lock", sanitize(component)));
+        let mut queued = false;
+        loop {
+            let reason = match LockFile::try_take(&repo_path)? {
+                None => "another build of this repo is running".to_string(),
+                Some(repo) => match self.take_slot()? {
+                    Some(slot) => {
+                        info!(component, "build locks acquired");
+                        return Ok(BuildLockGuard {
+                            _repo: repo,
+                            _slot: slot,
+                        });
+                    }
+                    // Free the repo lock while waiting, so it isn't held idle
+                    None => format!("{} builds already running", self.max_concurrent),
+                },
+            };
+            if self.mode == BuildLockMode::Reject {
+                warn!(component, reason = %reason, "rejecting concurrent build");
+                return Err(BuildBusy {
+                    component: component.to_string(),
+                    reason,
+                }
+                .into());
+            }
+            if !queued {
+                info!(component, reason = %reason, "build queued");
+                queued = true;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(QUEUE_POLL_INTERVAL) => {}
+                _ = cancel.cancelled() => return Err(Cancelled.into()),
+            }
+        }
+    }
+
+    fn take_slot(&self) -> Result<Option<LockFile>> {
+        for n in 0..self.max_concurrent {
+            if let Some(slot) = LockFile::try_take(&self.dir.join(format!("slot-{n}.lock")))? {
+                return Ok(Some(slot));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Held build locks; dropping it releases them.
+#[derive(Debug)]
+pub struct BuildLockGuard {
+    _repo: LockFile,
+    _slot: LockFile,
+}
+
+/// An open lock file with an exclusive advisory lock on it; closing it on
+/// drop releases the lock. The file itself stays, holding the last
+/// owner's pid for operators.
+#[derive(Debug)]
+struct LockFile {
+    _file: std::fs::File,
+}
+
+impl LockFile {
+    /// Lock the file at `path`, creating it if needed; `None` when another
+    /// holder has it locked.
+    fn try_take(path: &Path) -> Result<Option<Self>> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => return Ok(None),
+            Err(std::fs::TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+        }
+        use std::io::Write;
+        file.set_len(0)
+            .and_then(|()| write!(file, "{}", std::process::id()))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Some(Self { _file: file }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn concurrent_build_of_same_repo_is_rejected_or_queued() {
+        let dir = temp_dir("build-locks");
+        let cancel = CancellationToken::new();
+        let reject = BuildLocks::new(&dir).with_mode(BuildLockMode::Reject);
+
+        let first = reject.acquire("evo-king", &cancel).await.unwrap();
+        let err = reject.acquire("evo-king", &cancel).await.unwrap_err();
+        assert!(err.downcast_ref::<BuildBusy>().is_some(), "{err}");
+
+        // The global limit applies across repos
+        let single = BuildLocks::new(&dir)
+            .with_mode(BuildLockMode::Reject)
+            .with_max_concurrent(1);
+        let err = single.acquire("evo-gateway", &cancel).await.unwrap_err();
+        assert!(
+            err.to_string().contains("1 builds already running"),
+            "{err}"
+        );
+
+        // Queued, the second build starts once the first releases its locks
+        let queue = BuildLocks::new(&dir);
+        let started = Instant::now();
+        let release = async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            drop(first);
+        };
+        let (second, ()) = tokio::join!(queue.acquire("evo-king", &cancel), release);
+        assert!(second.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        drop(second);
+
+        // A lock file nobody holds a lock on (its owner exited) is free
+        std::fs::write(dir.join("evo-king.lock"), "999999999").unwrap();
+        assert!(reject.acquire("evo-king", &cancel).await.is_ok());
+    }
+}
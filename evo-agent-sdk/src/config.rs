@@ -0,0 +1,321 @@
+//! Runtime options for [`AgentRunner`](crate::AgentRunner), read from env.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::error::ErrorKind;
+use crate::redact::Redactor;
+use crate::registration::Requirements;
+use crate::run_cache::RunCache;
+
+/// Runner-level settings that are not part of the agent's soul.
+#[derive(Debug, Clone, Default)]
+pub struct RunnerConfig {
+    pub pipeline_retry: RetryPolicy,
+    /// Limit on non-critical outbound emits; `None` = unlimited.
+    pub emit_limit: Option<EmitRateLimit>,
+    /// King features checked against the registration ack.
+    pub requires: Requirements,
+    /// Cache of completed stage outputs for [`PipelineContext::previous_stage`](crate::PipelineContext::previous_stage).
+    pub run_cache: Option<RunCache>,
+    /// Extra headers sent on the Socket.IO handshake (e.g. king auth).
+    pub handshake_headers: HandshakeHeaders,
+    /// Attach raw LLM completions to stage results as `_raw_llm`.
+    pub include_raw_llm: bool,
+    /// Emit `pipeline:stage_metrics` after each stage result.
+    pub stage_metrics: bool,
+    /// Deadline for one `on_pipeline` attempt; `None` = unlimited.
+    pub stage_timeout: Option<Duration>,
+    /// Most LLM calls one stage dispatch may start; `None` = unlimited.
+    pub max_llm_calls: Option<u32>,
+    /// Attach a [`Diagnostics`](crate::health_check::Diagnostics) object to `agent:health`.
+    pub health_diagnostics: bool,
+    /// Time between `agent:status` heartbeats.
+    pub heartbeat_interval: Duration,
+    /// Re-send `agent:register` on the first heartbeat, as a safety net for
+    /// a registration lost during reconnect.
+    pub reregister_on_heartbeat: bool,
+    /// Cap on the backoff between attempts to reconnect to king.
+    pub reconnect_max_backoff: Duration,
+    /// How long a dropped connection waits for in-flight stages to finish
+    /// before reconnecting; their results are re-sent once reconnected.
+    pub reconnect_drain: Duration,
+    /// Send full stage outputs inline instead of the compact form.
+    pub verbose_results: bool,
+    /// Deployment environment added as `env` to every outbound payload.
+    pub env: Arc<str>,
+    /// Masks secrets in stage results before emit; `None` = off.
+    pub redactor: Option<Redactor>,
+}
+
+/// Default ceiling on LLM calls per stage dispatch; a runaway guard, not a
+/// cost control.
+pub const DEFAULT_MAX_LLM_CALLS: u32 = 100;
+
+/// Deployment environment when `EVO_ENV` is unset.
+pub const DEFAULT_ENV: &str = "prod";
+
+/// Default time between heartbeats.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Shortest heartbeat interval accepted; lower values are raised to it.
+pub const MIN_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Heartbeat interval for `secs` (default when unset), clamped to
+/// [`MIN_HEARTBEAT_INTERVAL`] with a warning.
+pub fn heartbeat_interval(secs: Option<u64>) -> Duration {
+    let Some(secs) = secs else {
+        return DEFAULT_HEARTBEAT_INTERVAL;
+    };
+    let interval = Duration::from_secs(secs);
+    if interval < MIN_HEARTBEAT_INTERVAL {
+        warn!(
+            requested_secs = secs,
+            floor_secs = MIN_HEARTBEAT_INTERVAL.as_secs(),
+            "HEARTBEAT_INTERVAL_SECS below the floor, clamping"
+        );
+        return MIN_HEARTBEAT_INTERVAL;
+    }
+    interval
+}
+
+/// Delay before the first reconnect attempt after losing king.
+pub const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Default cap on the reconnect backoff.
+pub const DEFAULT_RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Default bound on waiting for in-flight stages before reconnecting.
+pub const DEFAULT_RECONNECT_DRAIN: Duration = Duration::from_secs(30);
+
+/// Sleep for `timeout`, or never return when it's `None`; the deadline arm
+/// of a `select!` with an optional limit.
+pub(crate) async fn sleep_or_pending(timeout: Option<Duration>) {
+    match timeout {
+        Some(t) => tokio::time::sleep(t).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Backoff before reconnect attempt `attempt` (1-based): doubled from
+/// [`RECONNECT_INITIAL_BACKOFF`] on each attempt, capped at `max`.
+pub fn reconnect_delay(attempt: u32, max: Duration) -> Duration {
+    RECONNECT_INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(max)
+}
+
+impl RunnerConfig {
+    pub fn from_env() -> Self {
+        Self {
+            pipeline_retry: RetryPolicy::from_env(),
+            emit_limit: EmitRateLimit::from_env(),
+            requires: Requirements::from_env(),
+            run_cache: RunCache::from_env(),
+            handshake_headers: HandshakeHeaders::from_env(),
+            include_raw_llm: env_flag("INCLUDE_RAW_LLM"),
+            stage_metrics: env_flag("STAGE_METRICS"),
+            health_diagnostics: env_flag("HEALTH_DIAGNOSTICS"),
+            max_llm_calls: match env_parse::<u32>("STAGE_MAX_LLM_CALLS") {
+                Some(0) => None,
+                Some(n) => Some(n),
+                None => Some(DEFAULT_MAX_LLM_CALLS),
+            },
+            stage_timeout: env_parse::<u64>("STAGE_TIMEOUT_SECS")
+                .filter(|s| *s > 0)
+                .map(Duration::from_secs),
+            heartbeat_interval: heartbeat_interval(env_parse("HEARTBEAT_INTERVAL_SECS")),
+            reregister_on_heartbeat: env_flag_or("HEARTBEAT_REREGISTER", true),
+            reconnect_max_backoff: env_parse::<u64>("RECONNECT_MAX_BACKOFF_SECS")
+                .filter(|s| *s > 0)
+                .map_or(DEFAULT_RECONNECT_MAX_BACKOFF, Duration::from_secs),
+            reconnect_drain: env_parse::<u64>("RECONNECT_DRAIN_SECS")
+                .map_or(DEFAULT_RECONNECT_DRAIN, Duration::from_secs),
+            verbose_results: env_flag("STAGE_RESULT_VERBOSE"),
+            env: std::env::var("EVO_ENV")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| DEFAULT_ENV.to_string())
+                .into(),
+            redactor: Redactor::from_env(),
+        }
+    }
+}
+
+/// Handler-level retry of `on_pipeline` for transient failures.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts including the first (1 = no retry).
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled on each subsequent one.
+    pub backoff: Duration,
+    /// Error kinds that trigger a retry.
+    pub retryable: HashSet<ErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::from_millis(500),
+            retryable: ErrorKind::ALL
+                .into_iter()
+                .filter(|k| k.is_transient())
+                .collect(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Read `PIPELINE_RETRY_ATTEMPTS`, `PIPELINE_RETRY_BACKOFF_MS` and
+    /// `PIPELINE_RETRY_KINDS` (comma-separated [`ErrorKind`] names).
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+        if let Some(n) = env_parse::<u32>("PIPELINE_RETRY_ATTEMPTS") {
+            policy.max_attempts = n.max(1);
+        }
+        if let Some(ms) = env_parse::<u64>("PIPELINE_RETRY_BACKOFF_MS") {
+            policy.backoff = Duration::from_millis(ms);
+        }
+        if let Ok(kinds) = std::env::var("PIPELINE_RETRY_KINDS") {
+            policy.retryable = kinds.split(',').filter_map(ErrorKind::parse).collect();
+        }
+        policy
+    }
+
+    /// Whether a failure of `kind` on attempt `attempt` (1-based) should be retried.
+    pub fn should_retry(&self, kind: ErrorKind, attempt: u32) -> bool {
+        attempt < self.max_attempts && self.retryable.contains(&kind)
+    }
+
+    /// Backoff before retrying after attempt `attempt` (1-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+}
+
+/// What to do with an emit once the bucket is empty.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThrottlePolicy {
+    #[default]
+    Drop,
+    Delay,
+}
+
+/// Token-bucket settings for outbound king emits.
+#[derive(Debug, Clone)]
+pub struct EmitRateLimit {
+    /// Sustained events per second.
+    pub per_sec: f64,
+    /// Bucket capacity (events that may be sent back-to-back).
+    pub burst: u32,
+    pub policy: ThrottlePolicy,
+}
+
+impl EmitRateLimit {
+    /// Read `EMIT_RATE_LIMIT` (events/sec), `EMIT_BURST` and `EMIT_THROTTLE`
+    /// (`drop` or `delay`). Returns `None` when no rate is set.
+    pub fn from_env() -> Option<Self> {
+        let per_sec = env_parse::<f64>("EMIT_RATE_LIMIT").filter(|r| *r > 0.0)?;
+        let burst = env_parse::<u32>("EMIT_BURST").unwrap_or(per_sec.ceil() as u32);
+        let policy = match std::env::var("EMIT_THROTTLE").as_deref() {
+            Ok("delay") => ThrottlePolicy::Delay,
+            _ => ThrottlePolicy::Drop,
+        };
+        Some(Self {
+            per_sec,
+            burst: burst.max(1),
+            policy,
+        })
+    }
+}
+
+/// Headers attached to the Socket.IO handshake with king.
+///
+/// Values are never printed: `Debug` lists header names only.
+#[derive(Clone, Default)]
+pub struct HandshakeHeaders(Vec<(String, String)>);
+
+impl HandshakeHeaders {
+    /// Read `KING_AUTH_TOKEN`, sent as `Authorization: Bearer <token>`, or
+    /// verbatim under `KING_AUTH_HEADER` when that names a custom header.
+    pub fn from_env() -> Self {
+        let mut headers = Self::default();
+        if let Ok(token) = std::env::var("KING_AUTH_TOKEN")
+            && !token.is_empty()
+        {
+            headers = match std::env::var("KING_AUTH_HEADER") {
+                Ok(name) if !name.is_empty() => headers.with(name, token),
+                _ => headers.with("Authorization", format!("Bearer {token}")),
+            };
+        }
+        headers
+    }
+
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.0.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Debug for HandshakeHeaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(k, _)| format!("{k}: <redacted>")))
+            .finish()
+    }
+}
+
+/// `1` or `true` turns a flag on.
+pub(crate) fn env_flag(name: &str) -> bool {
+    env_flag_or(name, false)
+}
+
+/// `1`/`true` is on and `0`/`false` is off; unset or anything else keeps
+/// `default`.
+pub(crate) fn env_flag_or(name: &str, default: bool) -> bool {
+    match std::env::var(name).as_deref() {
+        Ok("1" | "true") => true,
+        Ok("0" | "false") => false,
+        _ => default,
+    }
+}
+
+pub(crate) fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_interval_defaults_and_clamps() {
+        assert_eq!(heartbeat_interval(None), DEFAULT_HEARTBEAT_INTERVAL);
+        assert_eq!(heartbeat_interval(Some(10)), Duration::from_secs(10));
+        assert_eq!(heartbeat_interval(Some(1)), MIN_HEARTBEAT_INTERVAL);
+        assert_eq!(heartbeat_interval(Some(0)), MIN_HEARTBEAT_INTERVAL);
+    }
+
+    #[test]
+    fn reconnect_backoff_doubles_up_to_the_cap() {
+        let max = Duration::from_secs(10);
+        let delays: Vec<u64> = (1..=6)
+            .map(|attempt| reconnect_delay(attempt, max).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+        assert_eq!(reconnect_delay(u32::MAX, max), max);
+    }
+}
@@ -0,0 +1,347 @@
+//! Outbound event path to king, with an optional token-bucket limiter.
+//!
+//! Critical events (stage results, pipeline requests and artifacts,
+//! heartbeats, registration, debug replies) always bypass the limiter; everything else is subject to
+//! [`EmitRateLimit`](crate::config::EmitRateLimit) when one is configured.
+//! [`Tagged`] stamps every payload with the deployment environment.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use evo_common::messages::events;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::config::{EmitRateLimit, ThrottlePolicy};
+
+/// Events that are never throttled.
+pub const CRITICAL_EVENTS: &[&str] = &[
+    events::PIPELINE_STAGE_RESULT,
+    events::AGENT_STATUS,
+    events::AGENT_HEALTH,
+    events::AGENT_REGISTER,
+    events::DEBUG_RESPONSE,
+    events::TASK_JOIN,
+    crate::agent_error::AGENT_ERROR,
+    crate::self_upgrade::SELF_UPGRADE_VERIFIED,
+    crate::self_upgrade::SELF_UPGRADE_FAILED,
+    crate::handler::PIPELINE_REQUEST,
+    crate::handler::PIPELINE_ARTIFACT,
+];
+
+/// Outbound event sink; the Socket.IO client in production, a recorder in tests.
+#[async_trait]
+pub trait Emit: Send + Sync {
+    async fn emit(&self, event: &str, payload: Value) -> Result<()>;
+
+    /// Emit and wait up to `timeout` for the receiver's ack payload.
+    ///
+    /// Returns `Ok(None)` when no ack arrives (or the sink can't carry acks).
+    async fn emit_with_ack(
+        &self,
+        event: &str,
+        payload: Value,
+        timeout: Duration,
+    ) -> Result<Option<Value>> {
+        let _ = timeout;
+        self.emit(event, payload).await?;
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl Emit for rust_socketio::asynchronous::Client {
+    async fn emit(&self, event: &str, payload: Value) -> Result<()> {
+        rust_socketio::asynchronous::Client::emit(self, event.to_string(), payload).await?;
+        Ok(())
+    }
+
+    async fn emit_with_ack(
+        &self,
+        event: &str,
+        payload: Value,
+        timeout: Duration,
+    ) -> Result<Option<Value>> {
+        let (tx, rx) = tokio::sync::oneshot::channel::<Value>();
+        let tx = Arc::new(Mutex::new(Some(tx)));
+        rust_socketio::asynchronous::Client::emit_with_ack(
+            self,
+            event.to_string(),
+            payload,
+            timeout,
+            move |ack: rust_socketio::Payload, _socket| {
+                let tx = Arc::clone(&tx);
+                Box::pin(async move {
+                    let value = match ack {
+                        rust_socketio::Payload::Text(mut values) if !values.is_empty() => {
+                            values.swap_remove(0)
+                        }
+                        _ => Value::Null,
+                    };
+                    if let Some(tx) = tx.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                        let _ = tx.send(value);
+                    }
+                })
+            },
+        )
+        .await?;
+
+        Ok(tokio::time::timeout(timeout, rx)
+            .await
+            .ok()
+            .and_then(Result::ok))
+    }
+}
+
+#[async_trait]
+impl<T: Emit + ?Sized> Emit for &T {
+    async fn emit(&self, event: &str, payload: Value) -> Result<()> {
+        (**self).emit(event, payload).await
+    }
+
+    async fn emit_with_ack(
+        &self,
+        event: &str,
+        payload: Value,
+        timeout: Duration,
+    ) -> Result<Option<Value>> {
+        (**self).emit_with_ack(event, payload, timeout).await
+    }
+}
+
+// ─── Token bucket ────────────────────────────────────────────────────────────
+
+/// Shared token bucket; one per runner, cloned into every socket callback.
+#[derive(Debug)]
+pub struct EmitLimiter {
+    limit: Option<EmitRateLimit>,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl EmitLimiter {
+    /// `None` means unlimited.
+    pub fn new(limit: Option<EmitRateLimit>) -> Self {
+        let tokens = limit.as_ref().map(|l| l.burst as f64).unwrap_or(0.0);
+        Self {
+            limit,
+            state: Mutex::new(Bucket {
+                tokens,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a token; on failure returns how long until the next one is available.
+    fn try_acquire(&self, limit: &EmitRateLimit) -> Result<(), Duration> {
+        let mut bucket = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_sec).min(limit.burst as f64);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(Duration::from_secs_f64(missing / limit.per_sec))
+        }
+    }
+
+    /// Whether `event` may be sent now, waiting first under [`ThrottlePolicy::Delay`].
+    async fn admit(&self, event: &str) -> bool {
+        let Some(limit) = &self.limit else {
+            return true;
+        };
+        if CRITICAL_EVENTS.contains(&event) {
+            return true;
+        }
+
+        loop {
+            match self.try_acquire(limit) {
+                Ok(()) => return true,
+                Err(wait) => match limit.policy {
+                    ThrottlePolicy::Drop => {
+                        warn!(event = %event, "emit rate limit exceeded, dropping event");
+                        return false;
+                    }
+                    ThrottlePolicy::Delay => {
+                        warn!(
+                            event = %event,
+                            wait_ms = wait.as_millis() as u64,
+                            "emit rate limit exceeded, delaying event"
+                        );
+                        tokio::time::sleep(wait).await;
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// An [`Emit`] wrapper that applies an [`EmitLimiter`] before forwarding.
+#[derive(Clone)]
+pub struct RateLimited<E> {
+    inner: E,
+    limiter: Arc<EmitLimiter>,
+}
+
+impl<E> RateLimited<E> {
+    pub fn new(inner: E, limiter: Arc<EmitLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait]
+impl<E: Emit> Emit for RateLimited<E> {
+    async fn emit(&self, event: &str, payload: Value) -> Result<()> {
+        if self.limiter.admit(event).await {
+            self.inner.emit(event, payload).await
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// An [`Emit`] wrapper adding `env` (the deployment environment,
+/// `EVO_ENV`) to every object payload that doesn't already carry one.
+#[derive(Clone)]
+pub struct Tagged<E> {
+    inner: E,
+    env: Arc<str>,
+}
+
+impl<E> Tagged<E> {
+    pub fn new(inner: E, env: Arc<str>) -> Self {
+        Self { inner, env }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    fn tag(&self, mut payload: Value) -> Value {
+        if let Value::Object(map) = &mut payload {
+            map.entry("env")
+                .or_insert_with(|| Value::String(self.env.to_string()));
+        }
+        payload
+    }
+}
+
+#[async_trait]
+impl<E: Emit> Emit for Tagged<E> {
+    async fn emit(&self, event: &str, payload: Value) -> Result<()> {
+        self.inner.emit(event, self.tag(payload)).await
+    }
+
+    async fn emit_with_ack(
+        &self,
+        event: &str,
+        payload: Value,
+        timeout: Duration,
+    ) -> Result<Option<Value>> {
+        self.inner
+            .emit_with_ack(event, self.tag(payload), timeout)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::RecordingEmitter;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn throttles_non_critical_but_not_stage_results() {
+        let limit = EmitRateLimit {
+            per_sec: 0.001,
+            burst: 2,
+            policy: ThrottlePolicy::Drop,
+        };
+        let recorder = RecordingEmitter::default();
+        let emitter = RateLimited::new(&recorder, Arc::new(EmitLimiter::new(Some(limit))));
+
+        for i in 0..5 {
+            emitter
+                .emit(events::TASK_LOG, json!({ "i": i }))
+                .await
+                .unwrap();
+            emitter
+                .emit(events::PIPELINE_STAGE_RESULT, json!({ "i": i }))
+                .await
+                .unwrap();
+            emitter
+                .emit(crate::handler::PIPELINE_REQUEST, json!({ "i": i }))
+                .await
+                .unwrap();
+            emitter
+                .emit(crate::handler::PIPELINE_ARTIFACT, json!({ "i": i }))
+                .await
+                .unwrap();
+        }
+
+        let sent = recorder.events();
+        let count = |name: &str| sent.iter().filter(|(e, _)| e == name).count();
+        assert_eq!(count(events::TASK_LOG), 2);
+        assert_eq!(count(events::PIPELINE_STAGE_RESULT), 5);
+        assert_eq!(count(crate::handler::PIPELINE_REQUEST), 5);
+        assert_eq!(count(crate::handler::PIPELINE_ARTIFACT), 5);
+    }
+
+    #[tokio::test]
+    async fn outbound_payloads_carry_the_deployment_env() {
+        let recorder = RecordingEmitter::with_ack(json!({ "accepted": true }));
+        let emitter = Tagged::new(&recorder, Arc::from("staging"));
+
+        crate::registration::register(
+            &emitter,
+            json!({ "agent_id": "a", "role": "learning" }),
+            &crate::registration::Requirements::default(),
+        )
+        .await
+        .unwrap();
+        emitter
+            .emit(
+                events::AGENT_STATUS,
+                json!({ "agent_id": "a", "status": "alive" }),
+            )
+            .await
+            .unwrap();
+        emitter
+            .emit(
+                events::PIPELINE_STAGE_RESULT,
+                json!({ "run_id": "r", "env": "custom" }),
+            )
+            .await
+            .unwrap();
+        emitter
+            .emit(events::TASK_SUMMARY, json!({ "task_id": "t" }))
+            .await
+            .unwrap();
+
+        let envs: Vec<_> = recorder
+            .events()
+            .into_iter()
+            .map(|(event, payload)| (event, payload["env"].clone()))
+            .collect();
+        assert_eq!(
+            envs,
+            vec![
+                (events::AGENT_REGISTER.to_string(), json!("staging")),
+                (events::AGENT_STATUS.to_string(), json!("staging")),
+                // A payload's own `env` is left alone
+                (events::PIPELINE_STAGE_RESULT.to_string(), json!("custom")),
+                (events::TASK_SUMMARY.to_string(), json!("staging")),
+            ]
+        );
+    }
+}
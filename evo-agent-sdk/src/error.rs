@@ -0,0 +1,168 @@
+//! Error classification used for retry decisions.
+//!
+//! Handlers return `anyhow::Error`; [`classify`] inspects the chain for known
+//! error types (gateway status, HTTP transport, I/O) and maps them to an
+//! [`ErrorKind`]. Handlers can flag anything else as retryable by wrapping it
+//! in [`TransientError`].
+
+use std::fmt;
+
+use crate::gateway_client::{BudgetExceeded, CallLimitExceeded, GatewayStatusError, StreamError};
+
+/// Coarse category of a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A request or operation timed out.
+    Timeout,
+    /// Connection refused/reset or similar transport failure.
+    Connection,
+    /// Upstream rate limiting (HTTP 429).
+    RateLimited,
+    /// Upstream server error (HTTP 5xx) or a stream aborted mid-way.
+    Upstream,
+    /// Transient local I/O condition (file lock, interrupted call).
+    Io,
+    /// Explicitly marked transient by the handler.
+    Transient,
+    /// Budget or quota exhausted — retrying cannot help.
+    Budget,
+    /// Anything else.
+    Other,
+}
+
+impl ErrorKind {
+    /// All kinds, in declaration order.
+    pub const ALL: [ErrorKind; 8] = [
+        Self::Timeout,
+        Self::Connection,
+        Self::RateLimited,
+        Self::Upstream,
+        Self::Io,
+        Self::Transient,
+        Self::Budget,
+        Self::Other,
+    ];
+
+    /// Whether this kind is worth retrying by default.
+    pub fn is_transient(self) -> bool {
+        !matches!(self, Self::Budget | Self::Other)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::Connection => "connection",
+            Self::RateLimited => "rate_limited",
+            Self::Upstream => "upstream",
+            Self::Io => "io",
+            Self::Transient => "transient",
+            Self::Budget => "budget",
+            Self::Other => "other",
+        }
+    }
+
+    /// Parse a kind from its [`as_str`](Self::as_str) name.
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.as_str() == name.trim())
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Wrap an error to mark it as transient (retryable).
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub struct TransientError(#[from] pub anyhow::Error);
+
+/// Mark `err` as transient so pipeline retries apply to it.
+pub fn transient(err: impl Into<anyhow::Error>) -> anyhow::Error {
+    TransientError(err.into()).into()
+}
+
+/// Classify an error by walking its source chain.
+pub fn classify(err: &anyhow::Error) -> ErrorKind {
+    if err.downcast_ref::<TransientError>().is_some() {
+        return ErrorKind::Transient;
+    }
+    if err.downcast_ref::<BudgetExceeded>().is_some()
+        || err.downcast_ref::<CallLimitExceeded>().is_some()
+    {
+        return ErrorKind::Budget;
+    }
+
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<GatewayStatusError>() {
+            return match e.status {
+                429 => ErrorKind::RateLimited,
+                408 | 504 => ErrorKind::Timeout,
+                s if s >= 500 => ErrorKind::Upstream,
+                _ => ErrorKind::Other,
+            };
+        }
+        if cause.downcast_ref::<StreamError>().is_some() {
+            return ErrorKind::Upstream;
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() {
+                return ErrorKind::Timeout;
+            }
+            if e.is_connect() || e.is_request() || e.is_body() {
+                return ErrorKind::Connection;
+            }
+            if let Some(status) = e.status() {
+                return match status.as_u16() {
+                    429 => ErrorKind::RateLimited,
+                    s if s >= 500 => ErrorKind::Upstream,
+                    _ => ErrorKind::Other,
+                };
+            }
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            use std::io::ErrorKind as Io;
+            return match e.kind() {
+                Io::TimedOut => ErrorKind::Timeout,
+                Io::ConnectionRefused | Io::ConnectionReset | Io::ConnectionAborted => {
+                    ErrorKind::Connection
+                }
+                Io::WouldBlock | Io::Interrupted | Io::ResourceBusy => ErrorKind::Io,
+                _ => ErrorKind::Other,
+            };
+        }
+    }
+
+    ErrorKind::Other
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_known_errors() {
+        let rate_limited: anyhow::Error = GatewayStatusError {
+            status: 429,
+            message: "slow down".into(),
+        }
+        .into();
+        assert_eq!(classify(&rate_limited), ErrorKind::RateLimited);
+
+        let io = anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::ResourceBusy))
+            .context("build lock held");
+        assert_eq!(classify(&io), ErrorKind::Io);
+
+        assert_eq!(
+            classify(&transient(anyhow::anyhow!("flaky"))),
+            ErrorKind::Transient
+        );
+        assert_eq!(classify(&anyhow::anyhow!("bad input")), ErrorKind::Other);
+        assert!(!ErrorKind::Other.is_transient());
+        assert_eq!(
+            ErrorKind::parse("rate_limited"),
+            Some(ErrorKind::RateLimited)
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Opt-in allow-list for inbound king events.
+//!
+//! With `INBOUND_EVENTS_STRICT=1` the runner acts only on events named in
+//! the soul's `## Events` section (plus `INBOUND_EVENTS_ALLOW`); anything
+//! else — built-in events the agent doesn't subscribe to, or unknown event
+//! types — is logged, counted and dropped. Permissive by default.
+
+use std::collections::BTreeSet;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{info, warn};
+
+use crate::config::env_flag;
+use crate::soul::{self, Soul};
+
+pub struct EventGate {
+    /// `None` = permissive. Replaced when the soul is reloaded.
+    allowed: RwLock<Option<BTreeSet<String>>>,
+    dropped: AtomicU64,
+}
+
+impl EventGate {
+    /// Read `INBOUND_EVENTS_STRICT` and `INBOUND_EVENTS_ALLOW` (comma-separated
+    /// extra event names) and combine them with the soul's `## Events`.
+    pub fn from_env(soul: &Soul) -> Self {
+        if !env_flag("INBOUND_EVENTS_STRICT") {
+            return Self::permissive();
+        }
+        let gate = Self::strict(Vec::<String>::new());
+        gate.reload(soul);
+        gate
+    }
+
+    /// Rebuild a strict allow-list from a reloaded soul's `## Events` (plus
+    /// `INBOUND_EVENTS_ALLOW`). A permissive gate stays permissive.
+    pub fn reload(&self, soul: &Soul) {
+        if !self.is_strict() {
+            return;
+        }
+        let extra = std::env::var("INBOUND_EVENTS_ALLOW").unwrap_or_default();
+        let allowed: BTreeSet<String> = subscribed_events(soul)
+            .into_iter()
+            .chain(
+                extra
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|e| !e.is_empty())
+                    .map(String::from),
+            )
+            .collect();
+        if allowed.is_empty() {
+            warn!(
+                "INBOUND_EVENTS_STRICT is on but no events are allowed — every king event will be dropped"
+            );
+        } else {
+            info!(allowed = ?allowed, "strict inbound event allow-list active");
+        }
+        *self.allowed.write().unwrap_or_else(|e| e.into_inner()) = Some(allowed);
+    }
+
+    pub fn permissive() -> Self {
+        Self {
+            allowed: RwLock::new(None),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn strict<S: Into<String>>(allowed: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            allowed: RwLock::new(Some(allowed.into_iter().map(Into::into).collect())),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.allowed
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+
+    /// Whether to act on `event`; a refused event is logged and counted.
+    pub fn admit(&self, event: &str) -> bool {
+        if let Some(allowed) = &*self.allowed.read().unwrap_or_else(|e| e.into_inner())
+            && !allowed.contains(event)
+        {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(
+                event,
+                dropped, "dropping inbound event not in the allow-list"
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Events dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Event names listed in the soul's `## Events` section: the first word of
+/// each bullet, when it looks like `namespace:event`.
+pub fn subscribed_events(soul: &Soul) -> Vec<String> {
+    let Some(section) = soul::extract_full_section(&soul.body, "Events") else {
+        return Vec::new();
+    };
+    section
+        .lines()
+        .filter_map(|line| {
+            line.trim()
+                .trim_start_matches(['-', '*'])
+                .split_whitespace()
+                .next()
+        })
+        .map(|word| word.trim_matches('`'))
+        .filter(|word| word.contains(':'))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn strict_mode_drops_and_counts_unlisted_events() {
+        let mut soul = test_support::soul("learning");
+        soul.body = "## Role\nlearning\n\n## Events\n\
+                     - pipeline:next (stage=learning) → discover skills\n\
+                     - `pipeline:cancel` → stop\n"
+            .to_string();
+        assert_eq!(
+            subscribed_events(&soul),
+            vec!["pipeline:next", "pipeline:cancel"]
+        );
+
+        let gate = EventGate::strict(subscribed_events(&soul));
+        assert!(gate.admit("pipeline:next"));
+        assert!(!gate.admit("debug:prompt"));
+        assert!(!gate.admit("forged:event"));
+        assert_eq!(gate.dropped(), 2);
+
+        let open = EventGate::permissive();
+        assert!(open.admit("forged:event"));
+        assert_eq!(open.dropped(), 0);
+    }
+}
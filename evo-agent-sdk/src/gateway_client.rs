@@ -1,35 +1,1118 @@
 use anyhow::{Context, Result};
 use futures_util::StreamExt;
 use serde_json::json;
-use tracing::{info, warn};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::config::env_parse;
+use crate::json_schema::OutputSchema;
+use crate::model::ModelRef;
+
+/// Maximum number of runs whose token usage is remembered at once.
+const MAX_TRACKED_RUNS: usize = 256;
+
+/// Consecutive failures after which a gateway endpoint is temporarily skipped.
+const ENDPOINT_FAILURE_THRESHOLD: u32 = 3;
+
+/// How long a failing gateway endpoint is skipped before being re-probed.
+const ENDPOINT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Approximate characters per token used for prompt-size estimates.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Marker appended to a user prompt that was cut to fit the prompt budget.
+const TRUNCATION_MARKER: &str = "\n…[truncated to fit context window]";
+
+tokio::task_local! {
+    static CURRENT_RUN: String;
+    static RAW_CAPTURE: RawCapture;
+    static USAGE_CAPTURE: UsageCapture;
+}
+
+/// Run `fut` with every gateway call inside it attributed to `run_id`.
+///
+/// The runner wraps each pipeline dispatch in this scope so per-run token
+/// budgets apply without handlers having to pass the run ID around. The run
+/// ID is also sent as the [`REQUEST_ID_HEADER`] of each call.
+pub async fn scope_run<F: Future>(run_id: impl Into<String>, fut: F) -> F::Output {
+    CURRENT_RUN.scope(run_id.into(), fut).await
+}
+
+fn current_run() -> Option<String> {
+    CURRENT_RUN.try_with(|r| r.clone()).ok()
+}
+
+/// Raw completion texts collected inside [`capture_raw`].
+#[derive(Debug, Clone, Default)]
+pub struct RawCapture(Arc<Mutex<Vec<String>>>);
+
+impl RawCapture {
+    /// Completions recorded so far, in call order.
+    pub fn texts(&self) -> Vec<String> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Run `fut`, recording the raw text of every successful completion into
+/// `capture` (used for `INCLUDE_RAW_LLM` auditing).
+pub async fn capture_raw<F: Future>(capture: RawCapture, fut: F) -> F::Output {
+    RAW_CAPTURE.scope(capture, fut).await
+}
+
+fn record_raw(text: &str) {
+    let _ = RAW_CAPTURE.try_with(|c| {
+        c.0.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(text.to_string())
+    });
+}
+
+/// LLM call totals for one dispatch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallUsage {
+    pub llm_calls: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Summed wall time of the gateway calls.
+    pub gateway_latency_ms: u64,
+}
+
+/// Accumulates [`CallUsage`] for calls made inside [`capture_usage`], and
+/// optionally caps how many calls may start there.
+#[derive(Debug, Clone, Default)]
+pub struct UsageCapture {
+    usage: Arc<Mutex<CallUsage>>,
+    started: Arc<AtomicU32>,
+    max_calls: Option<u32>,
+}
+
+impl UsageCapture {
+    /// Refuse calls beyond the first `max_calls` with [`CallLimitExceeded`].
+    pub fn with_call_limit(mut self, max_calls: Option<u32>) -> Self {
+        self.max_calls = max_calls;
+        self
+    }
+
+    pub fn totals(&self) -> CallUsage {
+        *self.usage.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Calls started so far, including failed and refused ones.
+    pub fn calls_started(&self) -> u32 {
+        self.started.load(Ordering::Relaxed)
+    }
+}
+
+/// Error returned once a stage has made its maximum number of LLM calls.
+#[derive(Debug, thiserror::Error)]
+#[error("LLM call limit exceeded: this stage may make at most {limit} calls")]
+pub struct CallLimitExceeded {
+    pub limit: u32,
+}
+
+/// Count a call against the current capture's limit.
+fn check_call_limit() -> Result<()> {
+    let refused = USAGE_CAPTURE
+        .try_with(|c| {
+            let started = c.started.fetch_add(1, Ordering::Relaxed) + 1;
+            c.max_calls.filter(|limit| started > *limit)
+        })
+        .ok()
+        .flatten();
+    match refused {
+        Some(limit) => {
+            warn!(limit, "LLM call limit reached, refusing call");
+            Err(CallLimitExceeded { limit }.into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Run `fut`, adding every successful completion's token counts and
+/// latency to `capture`.
+pub async fn capture_usage<F: Future>(capture: UsageCapture, fut: F) -> F::Output {
+    USAGE_CAPTURE.scope(capture, fut).await
+}
+
+/// `fut` with the raw and usage captures active here, for work moved to a
+/// spawned task (task-locals don't cross `tokio::spawn`). Outside any
+/// capture, fresh ones are used and simply dropped.
+pub(crate) fn inherit_captures<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    let raw = RAW_CAPTURE.try_with(Clone::clone).unwrap_or_default();
+    let usage = USAGE_CAPTURE.try_with(Clone::clone).unwrap_or_default();
+    capture_usage(usage, capture_raw(raw, fut))
+}
+
+fn record_usage(prompt_tokens: u64, completion_tokens: u64, latency: Duration) {
+    let _ = USAGE_CAPTURE.try_with(|c| {
+        let mut usage = c.usage.lock().unwrap_or_else(|e| e.into_inner());
+        usage.llm_calls += 1;
+        usage.prompt_tokens += prompt_tokens;
+        usage.completion_tokens += completion_tokens;
+        usage.gateway_latency_ms += latency.as_millis() as u64;
+    });
+}
+
+/// Structured output a completion is asked for, sent as `response_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// `{ "type": "json_object" }`: the reply is a single JSON object.
+    JsonObject,
+    /// `{ "type": "json_schema", ... }`: the reply follows the schema. Sent
+    /// as [`JsonObject`](Self::JsonObject) unless the client was built
+    /// [`with_json_schema`](GatewayClient::with_json_schema).
+    JsonSchema(&'static OutputSchema),
+}
+
+/// Per-call options for a completion.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompletionOptions {
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+    /// Ask the gateway for strictly formatted output (e.g. JSON mode).
+    pub response_format: Option<ResponseFormat>,
+    /// Also forward each delta to king as a progress event (only used by
+    /// [`PipelineContext::stream_completion`](crate::PipelineContext::stream_completion)).
+    pub forward_progress: bool,
+}
+
+impl CompletionOptions {
+    /// Options with just `temperature` and `max_tokens` set.
+    pub fn new(temperature: Option<f64>, max_tokens: Option<u32>) -> Self {
+        Self {
+            temperature,
+            max_tokens,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_response_format(mut self, format: ResponseFormat) -> Self {
+        self.response_format = Some(format);
+        self
+    }
+}
+
+/// A completion's text with the token counts the gateway reported for it;
+/// counts are zero when the response carries no `usage`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completion {
+    pub content: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl Completion {
+    fn new(content: String, usage: Option<ReportedUsage>) -> Self {
+        let usage = usage.unwrap_or_default();
+        let clamp = |n: u64| u32::try_from(n).unwrap_or(u32::MAX);
+        Self {
+            content,
+            prompt_tokens: clamp(usage.prompt),
+            completion_tokens: clamp(usage.completion),
+            total_tokens: clamp(usage.total),
+        }
+    }
+}
+
+/// Token counts from a response's `usage` object.
+#[derive(Debug, Clone, Copy, Default)]
+struct ReportedUsage {
+    prompt: u64,
+    completion: u64,
+    total: u64,
+}
+
+impl std::ops::AddAssign for ReportedUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt += other.prompt;
+        self.completion += other.completion;
+        self.total += other.total;
+    }
+}
+
+/// Correlation header sent on every gateway request.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// The current run ID inside [`scope_run`], otherwise a fresh UUID.
+fn request_id() -> String {
+    current_run().unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Error returned when the gateway reports a failure after a stream has
+/// already started (a `data: {"error": {...}}` chunk on a 200 response).
+///
+/// Carries the text accumulated before the error so callers can surface it.
+#[derive(Debug, thiserror::Error)]
+#[error("Gateway stream error: {message}")]
+pub struct StreamError {
+    pub message: String,
+    pub partial: String,
+}
+
+/// Error returned when an SSE stream runs past the line-size limit without
+/// a newline, so a broken gateway can't grow the buffer without bound.
+#[derive(Debug, thiserror::Error)]
+#[error("malformed SSE: line too long (no newline within {limit} bytes)")]
+pub struct SseLineTooLong {
+    pub limit: usize,
+}
+
+/// Default for [`GatewayClient::with_max_sse_line`].
+pub const DEFAULT_MAX_SSE_LINE: usize = 8 * 1024 * 1024;
+
+/// Non-success HTTP status returned by the gateway.
+#[derive(Debug, thiserror::Error)]
+#[error("Gateway returned {status}: {message}")]
+pub struct GatewayStatusError {
+    pub status: u16,
+    pub message: String,
+}
+
+/// Error returned when a run has used up its token budget.
+#[derive(Debug, thiserror::Error)]
+#[error("Token budget exceeded for run {run_id}: used {used} of {budget}")]
+pub struct BudgetExceeded {
+    pub run_id: String,
+    pub used: u64,
+    pub budget: u64,
+}
+
+/// What to do when a prompt exceeds the configured prompt-token limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PromptOverflow {
+    /// Cut the user prompt to fit (the system prompt is always kept intact).
+    #[default]
+    Trim,
+    /// Log a warning and send the prompt unchanged.
+    Warn,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct RunUsage {
+    tokens: u64,
+    budget: Option<u64>,
+}
+
+/// Token usage per run, bounded to the most recent [`MAX_TRACKED_RUNS`].
+#[derive(Debug, Default)]
+struct RunLedger {
+    runs: HashMap<String, RunUsage>,
+    order: VecDeque<String>,
+}
+
+impl RunLedger {
+    fn entry(&mut self, run_id: &str) -> &mut RunUsage {
+        if !self.runs.contains_key(run_id) {
+            if self.order.len() >= MAX_TRACKED_RUNS
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.runs.remove(&oldest);
+            }
+            self.order.push_back(run_id.to_string());
+        }
+        self.runs.entry(run_id.to_string()).or_default()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct EndpointState {
+    consecutive_failures: u32,
+    down_until: Option<Instant>,
+}
+
+/// Round-robin selection over one or more gateway base URLs.
+///
+/// An endpoint failing [`ENDPOINT_FAILURE_THRESHOLD`] times in a row is
+/// skipped for [`ENDPOINT_COOLDOWN`], after which the next call re-probes it.
+#[derive(Debug)]
+struct EndpointPool {
+    urls: Vec<String>,
+    state: Mutex<(usize, Vec<EndpointState>)>,
+}
+
+impl EndpointPool {
+    fn new(urls: Vec<String>) -> Self {
+        let state = vec![EndpointState::default(); urls.len()];
+        Self {
+            urls,
+            state: Mutex::new((0, state)),
+        }
+    }
+
+    /// Pick the next available endpoint, returning its index and base URL.
+    fn pick(&self) -> (usize, &str) {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (next, states) = &mut *guard;
+        let now = Instant::now();
+        let n = self.urls.len();
+
+        let available = (0..n)
+            .map(|offset| (*next + offset) % n)
+            .find(|&i| states[i].down_until.is_none_or(|until| until <= now));
+
+        // Everything is cooling down: use whichever recovers first
+        let idx =
+            available.unwrap_or_else(|| (0..n).min_by_key(|&i| states[i].down_until).unwrap_or(0));
+
+        *next = (idx + 1) % n;
+        (idx, &self.urls[idx])
+    }
+
+    fn report(&self, idx: usize, ok: bool) {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut guard.1[idx];
+
+        if ok {
+            if state.down_until.is_some() {
+                info!(gateway = %self.urls[idx], "gateway endpoint recovered");
+            }
+            *state = EndpointState::default();
+            return;
+        }
+
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= ENDPOINT_FAILURE_THRESHOLD && self.urls.len() > 1 {
+            state.down_until = Some(Instant::now() + ENDPOINT_COOLDOWN);
+            warn!(
+                gateway = %self.urls[idx],
+                failures = state.consecutive_failures,
+                cooldown_secs = ENDPOINT_COOLDOWN.as_secs(),
+                "skipping failing gateway endpoint"
+            );
+        }
+    }
+}
+
+/// Reasoning blocks (e.g. `<think>...</think>`) stripped from completions
+/// before they are returned, so handlers can parse the answer directly.
+///
+/// The unstripped text still reaches [`RawCapture`], so `_raw_llm` shows
+/// what the model actually produced.
+#[derive(Debug, Clone)]
+pub struct ReasoningTags(Vec<String>);
+
+impl Default for ReasoningTags {
+    fn default() -> Self {
+        Self::new(["think", "thinking", "reasoning"])
+    }
+}
+
+impl ReasoningTags {
+    pub fn new<S: Into<String>>(tags: impl IntoIterator<Item = S>) -> Self {
+        Self(tags.into_iter().map(Into::into).collect())
+    }
+
+    /// Read `STRIP_REASONING_TAGS`: `1`/`true` for the default tags, or a
+    /// comma-separated list of tag names. Returns `None` when unset.
+    pub fn from_env() -> Option<Self> {
+        let spec = std::env::var("STRIP_REASONING_TAGS").ok()?;
+        match spec.trim() {
+            "" | "0" | "false" => None,
+            "1" | "true" => Some(Self::default()),
+            list => Some(Self::new(
+                list.split(',').map(str::trim).filter(|t| !t.is_empty()),
+            )),
+        }
+    }
+
+    /// Remove every `<tag>...</tag>` block, plus any leading text closed by
+    /// a `</tag>` whose opening tag was part of the prompt template.
+    pub fn strip(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for tag in &self.0 {
+            let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+            if let Some(end) = out.find(&close)
+                && !out[..end].contains(&open)
+            {
+                out.drain(..end + close.len());
+            }
+            while let Some(start) = out.find(&open) {
+                let Some(len) = out[start..].find(&close) else {
+                    break;
+                };
+                out.drain(start..start + len + close.len());
+            }
+        }
+        if out.len() == text.len() {
+            return out;
+        }
+        out.trim().to_string()
+    }
+}
+
+/// Which OpenAI-compatible API shape the gateway speaks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApiStyle {
+    /// `POST /v1/chat/completions` with `messages`; text in
+    /// `choices[0].message.content`.
+    #[default]
+    ChatCompletions,
+    /// `POST /v1/responses` with `instructions` + `input`; text in the
+    /// `output_text` parts of `output[].content`.
+    Responses,
+}
+
+impl ApiStyle {
+    /// Read `GATEWAY_API_STYLE` (`chat` or `responses`, default `chat`).
+    pub fn from_env() -> Self {
+        match std::env::var("GATEWAY_API_STYLE").as_deref() {
+            Ok("responses") => Self::Responses,
+            _ => Self::ChatCompletions,
+        }
+    }
+
+    fn path(self) -> &'static str {
+        match self {
+            Self::ChatCompletions => "/v1/chat/completions",
+            Self::Responses => "/v1/responses",
+        }
+    }
+
+    fn request_body(
+        self,
+        model: &str,
+        system_prompt: &str,
+        user_prompt: &str,
+        opts: &CompletionOptions,
+        stream: bool,
+    ) -> serde_json::Value {
+        let (mut body, max_key) = match self {
+            Self::ChatCompletions => (
+                json!({
+                    "model": model,
+                    "messages": [
+                        { "role": "system", "content": system_prompt },
+                        { "role": "user", "content": user_prompt }
+                    ]
+                }),
+                "max_tokens",
+            ),
+            Self::Responses => (
+                json!({
+                    "model": model,
+                    "instructions": system_prompt,
+                    "input": user_prompt,
+                }),
+                "max_output_tokens",
+            ),
+        };
+        if stream {
+            body["stream"] = json!(true);
+        }
+        if let Some(temp) = opts.temperature {
+            body["temperature"] = json!(temp);
+        }
+        if let Some(max) = opts.max_tokens {
+            body[max_key] = json!(max);
+        }
+        match (opts.response_format, self) {
+            (None, _) => {}
+            (Some(ResponseFormat::JsonObject), Self::ChatCompletions) => {
+                body["response_format"] = json!({ "type": "json_object" });
+            }
+            (Some(ResponseFormat::JsonObject), Self::Responses) => {
+                body["text"] = json!({ "format": { "type": "json_object" } });
+            }
+            (Some(ResponseFormat::JsonSchema(schema)), Self::ChatCompletions) => {
+                body["response_format"] = json!({
+                    "type": "json_schema",
+                    "json_schema": { "name": schema.name, "schema": schema.schema },
+                });
+            }
+            (Some(ResponseFormat::JsonSchema(schema)), Self::Responses) => {
+                body["text"] = json!({
+                    "format": {
+                        "type": "json_schema",
+                        "name": schema.name,
+                        "schema": schema.schema,
+                    }
+                });
+            }
+        }
+        body
+    }
+
+    /// Assistant text of a non-streaming response.
+    fn response_text(self, body: &serde_json::Value) -> String {
+        match self {
+            Self::ChatCompletions => message_text(&body["choices"][0]["message"]["content"]),
+            Self::Responses => match body["output"].as_array() {
+                Some(items) => items
+                    .iter()
+                    .filter(|item| item["type"] == "message")
+                    .filter_map(|item| item["content"].as_array())
+                    .flatten()
+                    .filter(|part| part["type"] == "output_text")
+                    .filter_map(|part| part["text"].as_str())
+                    .collect(),
+                None => body["output_text"].as_str().unwrap_or("").to_string(),
+            },
+        }
+    }
+
+    /// `(prompt, completion, total)` tokens reported in `usage`.
+    fn usage(self, body: &serde_json::Value) -> (Option<u64>, Option<u64>, Option<u64>) {
+        let usage = &body["usage"];
+        let (prompt, completion) = match self {
+            Self::ChatCompletions => ("prompt_tokens", "completion_tokens"),
+            Self::Responses => ("input_tokens", "output_tokens"),
+        };
+        (
+            usage[prompt].as_u64(),
+            usage[completion].as_u64(),
+            usage["total_tokens"].as_u64(),
+        )
+    }
+
+    /// Text delta carried by one streamed event.
+    fn stream_delta(self, event: &serde_json::Value) -> Option<&str> {
+        match self {
+            Self::ChatCompletions => event["choices"][0]["delta"]["content"].as_str(),
+            Self::Responses if event["type"] == "response.output_text.delta" => {
+                event["delta"].as_str()
+            }
+            Self::Responses => None,
+        }
+    }
+
+    /// Usage carried by one streamed event: the final chunk when
+    /// `stream_options.include_usage` is set, or `response.completed`.
+    fn stream_usage(self, event: &serde_json::Value) -> Option<ReportedUsage> {
+        match self {
+            Self::ChatCompletions if event["usage"].is_object() => self.reported_usage(event),
+            Self::Responses if event["type"] == "response.completed" => {
+                self.reported_usage(&event["response"])
+            }
+            _ => None,
+        }
+    }
+
+    /// [`usage`](Self::usage) as counts, or `None` when nothing was reported.
+    fn reported_usage(self, body: &serde_json::Value) -> Option<ReportedUsage> {
+        match self.usage(body) {
+            (None, None, None) => None,
+            (prompt, completion, total) => {
+                let (prompt, completion) = (prompt.unwrap_or(0), completion.unwrap_or(0));
+                Some(ReportedUsage {
+                    prompt,
+                    completion,
+                    total: total.unwrap_or(prompt + completion),
+                })
+            }
+        }
+    }
+
+    /// Error reported by one streamed event, if any.
+    fn stream_error(self, event: &serde_json::Value) -> Option<String> {
+        let error = match self {
+            Self::ChatCompletions => event.get("error")?,
+            Self::Responses => match event["type"].as_str() {
+                Some("error") => event,
+                Some("response.failed") => &event["response"]["error"],
+                _ => return None,
+            },
+        };
+        Some(
+            error["message"]
+                .as_str()
+                .map(|m| m.to_string())
+                .unwrap_or_else(|| error.to_string()),
+        )
+    }
+}
+
+/// Transparently re-request a streaming completion whose connection drops,
+/// re-prompting with the text received so far. Off by default: the
+/// continuation is a new completion, so the result may not match what one
+/// uninterrupted stream would have produced.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamResume {
+    /// Resumes allowed per call.
+    pub attempts: u32,
+    /// Delay before the first resume, doubled per attempt, with jitter.
+    pub backoff: Duration,
+}
+
+impl StreamResume {
+    /// Read `STREAM_RESUME_ATTEMPTS` (unset or `0` = off) and
+    /// `STREAM_RESUME_BACKOFF_MS` (default 500).
+    pub fn from_env() -> Option<Self> {
+        let attempts = env_parse::<u32>("STREAM_RESUME_ATTEMPTS").filter(|n| *n > 0)?;
+        let backoff_ms = env_parse("STREAM_RESUME_BACKOFF_MS").unwrap_or(500);
+        Some(Self {
+            attempts,
+            backoff: Duration::from_millis(backoff_ms),
+        })
+    }
+
+    /// Exponential backoff for resume `attempt` (1-based), jittered to
+    /// between half and all of the nominal delay.
+    fn delay(&self, attempt: u32) -> Duration {
+        let nominal = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let jitter = (uuid::Uuid::new_v4().as_u128() % 1000) as f64 / 1000.0;
+        nominal.mul_f64(0.5 + jitter / 2.0)
+    }
+}
+
+/// Downgrade a model to an alternate after repeated rate limiting.
+///
+/// When a model receives `threshold` 429s within `window`, calls for it are
+/// routed to its fallback for `cooldown`; the next call after that re-probes
+/// the primary, and a further 429 re-opens the breaker immediately.
+#[derive(Debug, Clone)]
+pub struct ModelFallback {
+    /// Primary model → alternate model.
+    pub fallbacks: HashMap<String, String>,
+    /// Alternate for models without an explicit entry.
+    pub default_fallback: Option<String>,
+    pub threshold: u32,
+    pub window: Duration,
+    pub cooldown: Duration,
+}
+
+impl Default for ModelFallback {
+    fn default() -> Self {
+        Self {
+            fallbacks: HashMap::new(),
+            default_fallback: None,
+            threshold: 3,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(120),
+        }
+    }
+}
+
+impl ModelFallback {
+    /// Read `MODEL_FALLBACK` — either a single model used for everything or
+    /// `primary=alternate` pairs separated by commas — plus
+    /// `RATE_LIMIT_THRESHOLD`, `RATE_LIMIT_WINDOW_SECS` and
+    /// `RATE_LIMIT_COOLDOWN_SECS`. Returns `None` when `MODEL_FALLBACK` is unset.
+    pub fn from_env() -> Option<Self> {
+        let spec = std::env::var("MODEL_FALLBACK").ok()?;
+        let mut fallback = Self::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some((primary, alternate)) => {
+                    fallback
+                        .fallbacks
+                        .insert(primary.trim().to_string(), alternate.trim().to_string());
+                }
+                None => fallback.default_fallback = Some(part.to_string()),
+            }
+        }
+        if let Some(n) = env_parse::<u32>("RATE_LIMIT_THRESHOLD") {
+            fallback.threshold = n.max(1);
+        }
+        if let Some(secs) = env_parse::<u64>("RATE_LIMIT_WINDOW_SECS") {
+            fallback.window = Duration::from_secs(secs);
+        }
+        if let Some(secs) = env_parse::<u64>("RATE_LIMIT_COOLDOWN_SECS") {
+            fallback.cooldown = Duration::from_secs(secs);
+        }
+        Some(fallback)
+    }
+
+    fn alternate_for(&self, model: &str) -> Option<&str> {
+        self.fallbacks
+            .get(model)
+            .or(self.default_fallback.as_ref())
+            .map(String::as_str)
+            .filter(|alt| *alt != model)
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    rate_limits: VecDeque<Instant>,
+    open_until: Option<Instant>,
+    /// Set once the cooldown elapsed and the primary is being re-probed.
+    probing: bool,
+}
+
+/// Per-model rate-limit circuit breakers driving [`ModelFallback`].
+#[derive(Debug)]
+struct ModelBreakers {
+    policy: ModelFallback,
+    models: Mutex<HashMap<String, BreakerState>>,
+}
+
+impl ModelBreakers {
+    /// The model to actually call for a request for `model`.
+    fn route<'a>(&'a self, model: &'a str) -> &'a str {
+        let Some(alternate) = self.policy.alternate_for(model) else {
+            return model;
+        };
+        let mut models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(state) = models.get_mut(model) else {
+            return model;
+        };
+        match state.open_until {
+            Some(until) if Instant::now() < until => alternate,
+            Some(_) => {
+                state.open_until = None;
+                state.probing = true;
+                info!(
+                    model,
+                    "rate-limit cooldown elapsed, re-probing primary model"
+                );
+                model
+            }
+            None => model,
+        }
+    }
+
+    /// Record the outcome of a call made for `model` that was sent to `routed`.
+    fn record(&self, model: &str, routed: &str, rate_limited: bool) {
+        if model != routed {
+            return;
+        }
+        let Some(alternate) = self.policy.alternate_for(model) else {
+            return;
+        };
+        let mut models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+        let state = models.entry(model.to_string()).or_default();
+        let now = Instant::now();
+
+        if !rate_limited {
+            if state.probing {
+                info!(model, "primary model recovered from rate limiting");
+            }
+            *state = BreakerState::default();
+            return;
+        }
+
+        state.rate_limits.push_back(now);
+        while state
+            .rate_limits
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > self.policy.window)
+        {
+            state.rate_limits.pop_front();
+        }
+
+        if state.probing || state.rate_limits.len() as u32 >= self.policy.threshold {
+            state.open_until = Some(now + self.policy.cooldown);
+            state.probing = false;
+            state.rate_limits.clear();
+            warn!(
+                model,
+                fallback = alternate,
+                cooldown_secs = self.policy.cooldown.as_secs(),
+                "model rate limited repeatedly, routing to fallback"
+            );
+        }
+    }
+}
 
 /// HTTP client for calling evo-gateway's OpenAI-compatible chat completion API.
 ///
 /// All agent LLM interactions go through evo-gateway rather than calling
 /// providers directly. The gateway handles provider routing, rate limiting,
 /// and key management.
+///
+/// Several gateways may be given as a comma-separated list; calls are
+/// spread across them round-robin, skipping endpoints that keep failing.
+///
+/// Calls made inside [`scope_run`] are counted against that run's token
+/// budget (see [`GatewayClient::with_run_budget`]).
+///
+/// Cloning is cheap — a few reference-count bumps, no new connection pool —
+/// and clones share endpoint health, run budgets and rate-limit state, so
+/// a clone can be moved into a spawned task freely.
+#[derive(Clone)]
 pub struct GatewayClient {
     http_client: reqwest::Client,
-    gateway_url: String,
+    endpoints: Arc<EndpointPool>,
+    default_run_budget: Option<u64>,
+    ledger: Arc<Mutex<RunLedger>>,
+    prompt_limit: Option<usize>,
+    prompt_overflow: PromptOverflow,
+    breakers: Option<Arc<ModelBreakers>>,
+    reasoning_tags: Option<ReasoningTags>,
+    max_sse_line: usize,
+    api_style: ApiStyle,
+    stream_resume: Option<StreamResume>,
+    stream_usage: bool,
+    json_schema: bool,
 }
 
 impl GatewayClient {
     /// Create a new gateway client.
     ///
     /// `gateway_url` should be the base URL of the evo-gateway instance
-    /// (e.g. `http://localhost:8080`).
+    /// (e.g. `http://localhost:8080`), or a comma-separated list of them.
     pub fn new(gateway_url: &str) -> Result<Self> {
         let http_client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(120))
             .build()
             .context("Failed to build HTTP client for gateway")?;
 
+        let urls: Vec<String> = gateway_url
+            .split(',')
+            .map(|u| u.trim().trim_end_matches('/').to_string())
+            .filter(|u| !u.is_empty())
+            .collect();
+        if urls.is_empty() {
+            anyhow::bail!("No gateway address configured");
+        }
+
         Ok(Self {
             http_client,
-            gateway_url: gateway_url.trim_end_matches('/').to_string(),
+            endpoints: Arc::new(EndpointPool::new(urls)),
+            default_run_budget: None,
+            ledger: Arc::new(Mutex::new(RunLedger::default())),
+            prompt_limit: None,
+            prompt_overflow: PromptOverflow::default(),
+            breakers: None,
+            reasoning_tags: None,
+            max_sse_line: DEFAULT_MAX_SSE_LINE,
+            api_style: ApiStyle::default(),
+            stream_resume: None,
+            stream_usage: false,
+            json_schema: false,
         })
     }
 
+    /// Route calls to an alternate model while the requested one is being
+    /// rate limited. `None` (the default) disables the fallback.
+    pub fn with_model_fallback(mut self, fallback: Option<ModelFallback>) -> Self {
+        self.breakers = fallback.map(|policy| {
+            Arc::new(ModelBreakers {
+                policy,
+                models: Mutex::new(HashMap::new()),
+            })
+        });
+        self
+    }
+
+    /// Speak the given API shape to the gateway. Default:
+    /// [`ApiStyle::ChatCompletions`].
+    pub fn with_api_style(mut self, style: ApiStyle) -> Self {
+        self.api_style = style;
+        self
+    }
+
+    /// Ask for `stream_options.include_usage` on streaming chat completions,
+    /// so the final chunk reports real token counts instead of estimates.
+    pub fn with_stream_usage(mut self, enabled: bool) -> Self {
+        self.stream_usage = enabled;
+        self
+    }
+
+    /// Send [`ResponseFormat::JsonSchema`] as `json_schema` rather than
+    /// downgrading it to JSON mode; only for gateways that accept it.
+    pub fn with_json_schema(mut self, enabled: bool) -> Self {
+        self.json_schema = enabled;
+        self
+    }
+
+    /// `opts` with a schema format downgraded to JSON mode when the gateway
+    /// doesn't take schemas.
+    fn supported_options(&self, mut opts: CompletionOptions) -> CompletionOptions {
+        if !self.json_schema && matches!(opts.response_format, Some(ResponseFormat::JsonSchema(_)))
+        {
+            opts.response_format = Some(ResponseFormat::JsonObject);
+        }
+        opts
+    }
+
+    /// Resume dropped streaming completions. `None` (the default) fails
+    /// the call on a dropped connection.
+    pub fn with_stream_resume(mut self, resume: Option<StreamResume>) -> Self {
+        self.stream_resume = resume;
+        self
+    }
+
+    /// Strip reasoning blocks from completions before returning them.
+    pub fn with_reasoning_strip(mut self, tags: Option<ReasoningTags>) -> Self {
+        self.reasoning_tags = tags;
+        self
+    }
+
+    fn strip_reasoning(&self, text: String) -> String {
+        match &self.reasoning_tags {
+            Some(tags) => tags.strip(&text),
+            None => text,
+        }
+    }
+
+    fn route_model<'a>(&'a self, model: &'a str) -> &'a str {
+        self.breakers.as_ref().map_or(model, |b| b.route(model))
+    }
+
+    fn record_model_outcome(&self, model: &str, routed: &str, status: reqwest::StatusCode) {
+        if let Some(breakers) = &self.breakers {
+            breakers.record(
+                model,
+                routed,
+                status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+            );
+        }
+    }
+
+    /// Guard outgoing prompts against overflowing the model's context window.
+    ///
+    /// `max_tokens` is the estimated budget for system + user prompt combined
+    /// (~4 chars per token). `None` (the default) disables the guard.
+    pub fn with_prompt_limit(
+        mut self,
+        max_tokens: Option<usize>,
+        overflow: PromptOverflow,
+    ) -> Self {
+        self.prompt_limit = max_tokens;
+        self.prompt_overflow = overflow;
+        self
+    }
+
+    /// Apply the prompt guard, returning the user prompt to send.
+    fn fit_user_prompt<'a>(&self, system_prompt: &str, user_prompt: &'a str) -> Cow<'a, str> {
+        let Some(limit) = self.prompt_limit else {
+            return Cow::Borrowed(user_prompt);
+        };
+
+        let estimated = (system_prompt.len() + user_prompt.len()).div_ceil(CHARS_PER_TOKEN);
+        if estimated <= limit {
+            return Cow::Borrowed(user_prompt);
+        }
+
+        if self.prompt_overflow == PromptOverflow::Warn {
+            warn!(
+                estimated_tokens = estimated,
+                limit, "prompt exceeds token limit"
+            );
+            return Cow::Borrowed(user_prompt);
+        }
+
+        let budget_chars = (limit * CHARS_PER_TOKEN)
+            .saturating_sub(system_prompt.len())
+            .saturating_sub(TRUNCATION_MARKER.len());
+        let mut cut = budget_chars.min(user_prompt.len());
+        while !user_prompt.is_char_boundary(cut) {
+            cut -= 1;
+        }
+
+        warn!(
+            estimated_tokens = estimated,
+            limit,
+            original_chars = user_prompt.len(),
+            kept_chars = cut,
+            "trimming user prompt to fit token limit"
+        );
+
+        Cow::Owned(format!("{}{TRUNCATION_MARKER}", &user_prompt[..cut]))
+    }
+
+    /// Base URLs of all configured gateway endpoints.
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints.urls
+    }
+
+    /// POST `body` to `path` on the next available gateway endpoint,
+    /// recording the outcome for endpoint health tracking.
+    async fn post(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+        request_id: &str,
+    ) -> Result<reqwest::Response> {
+        let (idx, base) = self.endpoints.pick();
+        let url = format!("{base}{path}");
+        let started = Instant::now();
+
+        let request = self
+            .http_client
+            .post(&url)
+            .header(REQUEST_ID_HEADER, request_id)
+            .json(body);
+        match request.send().await {
+            Ok(resp) => {
+                self.endpoints.report(idx, !resp.status().is_server_error());
+                let echoed = resp
+                    .headers()
+                    .get(REQUEST_ID_HEADER)
+                    .and_then(|v| v.to_str().ok());
+                if echoed == Some(request_id) {
+                    info!(
+                        request_id,
+                        status = resp.status().as_u16(),
+                        elapsed_ms = started.elapsed().as_millis() as u64,
+                        "gateway round-trip"
+                    );
+                }
+                Ok(resp)
+            }
+            Err(e) => {
+                self.endpoints.report(idx, false);
+                Err(anyhow::Error::new(e).context(format!("Gateway request to {url} failed")))
+            }
+        }
+    }
+
+    /// Cap the total tokens any single run may consume through this client.
+    ///
+    /// Once a run reaches its budget, further calls fail with [`BudgetExceeded`].
+    /// `None` (the default) leaves runs unbounded.
+    pub fn with_run_budget(mut self, tokens: Option<u64>) -> Self {
+        self.default_run_budget = tokens;
+        self
+    }
+
+    /// Largest SSE line (in bytes) buffered while waiting for a newline;
+    /// longer lines fail the stream with [`SseLineTooLong`].
+    /// Default: [`DEFAULT_MAX_SSE_LINE`].
+    pub fn with_max_sse_line(mut self, bytes: usize) -> Self {
+        self.max_sse_line = bytes;
+        self
+    }
+
+    /// Override the token budget for a specific run (e.g. from pipeline metadata).
+    pub fn set_run_budget(&self, run_id: &str, tokens: u64) {
+        let mut ledger = self.ledger.lock().unwrap_or_else(|e| e.into_inner());
+        ledger.entry(run_id).budget = Some(tokens);
+    }
+
+    /// Tokens consumed so far by `run_id`.
+    pub fn run_tokens_used(&self, run_id: &str) -> u64 {
+        let ledger = self.ledger.lock().unwrap_or_else(|e| e.into_inner());
+        ledger.runs.get(run_id).map(|u| u.tokens).unwrap_or(0)
+    }
+
+    /// Refuse the call if the current run has exhausted its budget.
+    fn check_budget(&self) -> Result<()> {
+        let Some(run_id) = current_run() else {
+            return Ok(());
+        };
+        let ledger = self.ledger.lock().unwrap_or_else(|e| e.into_inner());
+        let usage = ledger.runs.get(&run_id).copied().unwrap_or_default();
+        if let Some(budget) = usage.budget.or(self.default_run_budget)
+            && usage.tokens >= budget
+        {
+            warn!(run_id = %run_id, used = usage.tokens, budget, "run token budget exhausted");
+            return Err(BudgetExceeded {
+                run_id,
+                used: usage.tokens,
+                budget,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn record_tokens(&self, tokens: u64) {
+        if let Some(run_id) = current_run() {
+            let mut ledger = self.ledger.lock().unwrap_or_else(|e| e.into_inner());
+            ledger.entry(&run_id).tokens += tokens;
+        }
+    }
+
     /// Send a chat completion request through the gateway.
     ///
     /// Returns the assistant's reply text.
@@ -41,38 +1124,66 @@ impl GatewayClient {
         temperature: Option<f64>,
         max_tokens: Option<u32>,
     ) -> Result<String> {
-        let url = format!("{}/v1/chat/completions", self.gateway_url);
-
-        let mut body = json!({
-            "model": model,
-            "messages": [
-                { "role": "system", "content": system_prompt },
-                { "role": "user", "content": user_prompt }
-            ]
-        });
+        self.chat_completion_with_usage(model, system_prompt, user_prompt, temperature, max_tokens)
+            .await
+            .map(|c| c.content)
+    }
 
-        if let Some(temp) = temperature {
-            body["temperature"] = json!(temp);
-        }
-        if let Some(max) = max_tokens {
-            body["max_tokens"] = json!(max);
-        }
+    /// [`chat_completion`](Self::chat_completion), also returning the token
+    /// counts from the response's `usage`.
+    pub async fn chat_completion_with_usage(
+        &self,
+        model: &str,
+        system_prompt: &str,
+        user_prompt: &str,
+        temperature: Option<f64>,
+        max_tokens: Option<u32>,
+    ) -> Result<Completion> {
+        let opts = CompletionOptions::new(temperature, max_tokens);
+        self.chat_completion_with_options(model, system_prompt, user_prompt, opts)
+            .await
+    }
 
+    /// A chat completion with full [`CompletionOptions`], e.g. JSON mode via
+    /// `response_format`.
+    pub async fn chat_completion_with_options(
+        &self,
+        model: &str,
+        system_prompt: &str,
+        user_prompt: &str,
+        opts: CompletionOptions,
+    ) -> Result<Completion> {
+        self.check_budget()?;
+        check_call_limit()?;
+        let opts = self.supported_options(opts);
+        let user_prompt = self.fit_user_prompt(system_prompt, user_prompt);
+
+        let routed = self.route_model(model);
+        let model_ref = ModelRef::parse(routed);
+        let body = self.api_style.request_body(
+            &model_ref.to_string(),
+            system_prompt,
+            &user_prompt,
+            &opts,
+            false,
+        );
+
+        let request_id = request_id();
         info!(
-            model = %model,
-            url = %url,
+            model = %model_ref.model,
+            provider = ?model_ref.provider,
+            request_id = %request_id,
             "sending chat completion request to gateway"
         );
 
+        let started = Instant::now();
         let resp = self
-            .http_client
-            .post(&url)
-            .json(&body)
-            .send()
+            .post(self.api_style.path(), &body, &request_id)
             .await
             .context("Gateway chat completion request failed")?;
 
         let status = resp.status();
+        self.record_model_outcome(model, routed, status);
         let resp_body: serde_json::Value = resp
             .json()
             .await
@@ -82,20 +1193,33 @@ impl GatewayClient {
             let error = resp_body["error"]["message"]
                 .as_str()
                 .unwrap_or("unknown error");
-            anyhow::bail!("Gateway returned {status}: {error}");
+            return Err(GatewayStatusError {
+                status: status.as_u16(),
+                message: error.to_string(),
+            }
+            .into());
         }
 
         // Extract the assistant message content from OpenAI-compatible response
-        let content = resp_body["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
+        let content = self.api_style.response_text(&resp_body);
 
         if content.is_empty() {
             warn!("gateway returned empty response content");
         }
 
-        Ok(content)
+        let (prompt_tokens, completion_tokens, total_tokens) = self.api_style.usage(&resp_body);
+        let tokens =
+            total_tokens.unwrap_or_else(|| estimate_tokens(system_prompt, &user_prompt, &content));
+        self.record_tokens(tokens);
+        record_usage(
+            prompt_tokens.unwrap_or_else(|| estimate_tokens(system_prompt, &user_prompt, "")),
+            completion_tokens.unwrap_or_else(|| estimate_tokens("", "", &content)),
+            started.elapsed(),
+        );
+        record_raw(&content);
+
+        let reported = self.api_style.reported_usage(&resp_body);
+        Ok(Completion::new(self.strip_reasoning(content), reported))
     }
 
     /// Send a streaming chat completion request through the gateway.
@@ -104,7 +1228,10 @@ impl GatewayClient {
     /// Returns the full accumulated response text when the stream completes.
     ///
     /// The gateway returns SSE format: `data: {"choices":[{"delta":{"content":"..."}}]}\n\n`
-    /// terminated by `data: [DONE]\n\n`.
+    /// terminated by `data: [DONE]\n\n`. A chunk carrying an `error` field aborts
+    /// the stream with a [`StreamError`] holding the partial text. With
+    /// [`ApiStyle::Responses`] the deltas come from `response.output_text.delta`
+    /// events and the stream ends at `response.completed`.
     pub async fn chat_completion_streaming<F>(
         &self,
         model: &str,
@@ -112,56 +1239,206 @@ impl GatewayClient {
         user_prompt: &str,
         temperature: Option<f64>,
         max_tokens: Option<u32>,
-        mut on_chunk: F,
+        on_chunk: F,
     ) -> Result<String>
     where
         F: FnMut(&str, u32) + Send,
     {
-        let url = format!("{}/v1/chat/completions", self.gateway_url);
-
-        let mut body = json!({
-            "model": model,
-            "messages": [
-                { "role": "system", "content": system_prompt },
-                { "role": "user", "content": user_prompt }
-            ],
-            "stream": true
-        });
+        self.chat_completion_streaming_with_usage(
+            model,
+            system_prompt,
+            user_prompt,
+            temperature,
+            max_tokens,
+            on_chunk,
+        )
+        .await
+        .map(|c| c.content)
+    }
 
-        if let Some(temp) = temperature {
-            body["temperature"] = json!(temp);
-        }
-        if let Some(max) = max_tokens {
-            body["max_tokens"] = json!(max);
-        }
+    /// [`chat_completion_streaming`](Self::chat_completion_streaming), also
+    /// returning token counts. These come from the stream's usage event (see
+    /// [`with_stream_usage`](Self::with_stream_usage)), summed across
+    /// resumes, and are zero when the gateway sent none.
+    pub async fn chat_completion_streaming_with_usage<F>(
+        &self,
+        model: &str,
+        system_prompt: &str,
+        user_prompt: &str,
+        temperature: Option<f64>,
+        max_tokens: Option<u32>,
+        on_chunk: F,
+    ) -> Result<Completion>
+    where
+        F: FnMut(&str, u32) + Send,
+    {
+        let opts = CompletionOptions::new(temperature, max_tokens);
+        self.chat_completion_streaming_with_options(
+            model,
+            system_prompt,
+            user_prompt,
+            opts,
+            on_chunk,
+        )
+        .await
+    }
 
-        info!(
-            model = %model,
-            url = %url,
-            "sending streaming chat completion request to gateway"
-        );
+    /// A streaming chat completion with full [`CompletionOptions`].
+    pub async fn chat_completion_streaming_with_options<F>(
+        &self,
+        model: &str,
+        system_prompt: &str,
+        user_prompt: &str,
+        opts: CompletionOptions,
+        mut on_chunk: F,
+    ) -> Result<Completion>
+    where
+        F: FnMut(&str, u32) + Send,
+    {
+        self.check_budget()?;
+        check_call_limit()?;
+        let opts = self.supported_options(opts);
+        let user_prompt = self.fit_user_prompt(system_prompt, user_prompt);
 
-        let resp = self
-            .http_client
-            .post(&url)
-            .json(&body)
-            .send()
-            .await
-            .context("Gateway streaming request failed")?;
+        let routed = self.route_model(model);
+        let model_ref = ModelRef::parse(routed);
+        let started = Instant::now();
+        let mut accumulated = String::new();
+        let mut chunk_index: u32 = 0;
+        let mut resumes: u32 = 0;
+        let mut reported: Option<ReportedUsage> = None;
 
-        let status = resp.status();
-        if !status.is_success() {
-            let text = resp.text().await.unwrap_or_default();
-            anyhow::bail!("Gateway returned {status}: {text}");
+        loop {
+            // After a drop, re-prompt with the partial answer as context
+            let prompt = if resumes == 0 {
+                Cow::Borrowed(user_prompt.as_ref())
+            } else {
+                Cow::Owned(continuation_prompt(&user_prompt, &accumulated))
+            };
+            let mut body = self.api_style.request_body(
+                &model_ref.to_string(),
+                system_prompt,
+                &prompt,
+                &opts,
+                true,
+            );
+            if self.stream_usage && self.api_style == ApiStyle::ChatCompletions {
+                body["stream_options"] = json!({ "include_usage": true });
+            }
+
+            let request_id = request_id();
+            info!(
+                model = %model_ref.model,
+                provider = ?model_ref.provider,
+                request_id = %request_id,
+                resumes,
+                "sending streaming chat completion request to gateway"
+            );
+
+            let resp = self
+                .post(self.api_style.path(), &body, &request_id)
+                .await
+                .context("Gateway streaming request failed")?;
+
+            let status = resp.status();
+            self.record_model_outcome(model, routed, status);
+            if !status.is_success() {
+                let text = resp.text().await.unwrap_or_default();
+                return Err(GatewayStatusError {
+                    status: status.as_u16(),
+                    message: text,
+                }
+                .into());
+            }
+
+            let end = self
+                .read_sse(
+                    resp,
+                    &mut accumulated,
+                    &mut chunk_index,
+                    &mut reported,
+                    &mut on_chunk,
+                )
+                .await?;
+            let (dropped, clean_eof) = match end {
+                StreamEnd::Done => break,
+                // Without resume a stream that just stops counts as complete
+                StreamEnd::Eof if self.stream_resume.is_none() => break,
+                StreamEnd::Eof => (anyhow::anyhow!("stream ended before completion"), true),
+                StreamEnd::Dropped(e) => (e, false),
+            };
+            let Some(resume) = self.stream_resume.filter(|r| resumes < r.attempts) else {
+                if clean_eof {
+                    warn!(
+                        resumes,
+                        "stream still incomplete after resuming, returning partial text"
+                    );
+                    break;
+                }
+                return Err(dropped);
+            };
+            resumes += 1;
+            let delay = resume.delay(resumes);
+            warn!(
+                attempt = resumes,
+                max = resume.attempts,
+                partial_len = accumulated.len(),
+                delay_ms = delay.as_millis() as u64,
+                err = %dropped,
+                "gateway stream dropped, resuming from partial text"
+            );
+            tokio::time::sleep(delay).await;
+        }
+
+        if accumulated.is_empty() {
+            warn!("streaming gateway response produced no content");
+        }
+
+        match reported {
+            Some(usage) => {
+                self.record_tokens(usage.total);
+                record_usage(usage.prompt, usage.completion, started.elapsed());
+            }
+            None => {
+                self.record_tokens(estimate_tokens(system_prompt, &user_prompt, &accumulated));
+                record_usage(
+                    estimate_tokens(system_prompt, &user_prompt, ""),
+                    estimate_tokens("", "", &accumulated),
+                    started.elapsed(),
+                );
+            }
         }
+        record_raw(&accumulated);
 
+        Ok(Completion::new(self.strip_reasoning(accumulated), reported))
+    }
+
+    /// Read one SSE response into `accumulated`, forwarding each delta.
+    /// Gateway-reported errors and oversized lines fail outright; a broken
+    /// connection is returned as [`StreamEnd::Dropped`] so it can be resumed.
+    async fn read_sse<F>(
+        &self,
+        resp: reqwest::Response,
+        accumulated: &mut String,
+        chunk_index: &mut u32,
+        reported: &mut Option<ReportedUsage>,
+        on_chunk: &mut F,
+    ) -> Result<StreamEnd>
+    where
+        F: FnMut(&str, u32) + Send,
+    {
         let mut stream = resp.bytes_stream();
-        let mut accumulated = String::new();
-        let mut chunk_index: u32 = 0;
         let mut line_buffer = String::new();
 
         while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.context("Error reading SSE stream chunk")?;
+            let chunk = match chunk_result {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    return Ok(StreamEnd::Dropped(
+                        anyhow::Error::new(e).context("Error reading SSE stream chunk"),
+                    ));
+                }
+            };
             let text = String::from_utf8_lossy(&chunk);
             line_buffer.push_str(&text);
 
@@ -175,25 +1452,689 @@ impl GatewayClient {
                 }
 
                 if line == "data: [DONE]" {
-                    break;
+                    return Ok(StreamEnd::Done);
                 }
 
-                if let Some(json_str) = line.strip_prefix("data: ")
-                    && let Ok(parsed) = serde_json::from_str::<serde_json::Value>(json_str)
-                    && let Some(delta) = parsed["choices"][0]["delta"]["content"].as_str()
+                let Some(parsed) = line
+                    .strip_prefix("data: ")
+                    .and_then(|json_str| serde_json::from_str::<serde_json::Value>(json_str).ok())
+                else {
+                    continue;
+                };
+
+                // Some providers fail mid-generation by emitting an error chunk
+                if let Some(message) = self.api_style.stream_error(&parsed) {
+                    warn!(
+                        err = %message,
+                        partial_len = accumulated.len(),
+                        "gateway stream reported an error mid-stream"
+                    );
+                    return Err(StreamError {
+                        message,
+                        partial: std::mem::take(accumulated),
+                    }
+                    .into());
+                }
+
+                if let Some(delta) = self.api_style.stream_delta(&parsed)
                     && !delta.is_empty()
                 {
                     accumulated.push_str(delta);
-                    on_chunk(delta, chunk_index);
-                    chunk_index += 1;
+                    on_chunk(delta, *chunk_index);
+                    *chunk_index += 1;
+                }
+
+                if let Some(usage) = self.api_style.stream_usage(&parsed) {
+                    *reported.get_or_insert_default() += usage;
                 }
+
+                // The Responses API ends with an event instead of `[DONE]`
+                if parsed["type"] == "response.completed" {
+                    return Ok(StreamEnd::Done);
+                }
+            }
+
+            if line_buffer.len() > self.max_sse_line {
+                warn!(
+                    limit = self.max_sse_line,
+                    partial_len = accumulated.len(),
+                    "gateway stream line exceeded size limit"
+                );
+                return Err(SseLineTooLong {
+                    limit: self.max_sse_line,
+                }
+                .into());
             }
         }
+        Ok(StreamEnd::Eof)
+    }
+}
 
-        if accumulated.is_empty() {
-            warn!("streaming gateway response produced no content");
+/// How one streamed response ended.
+enum StreamEnd {
+    /// `[DONE]` or `response.completed`.
+    Done,
+    /// The body ended without a terminator.
+    Eof,
+    /// The connection failed mid-body.
+    Dropped(anyhow::Error),
+}
+
+/// Re-prompt asking the model to pick up after `partial`.
+fn continuation_prompt(user_prompt: &str, partial: &str) -> String {
+    format!(
+        "{user_prompt}\n\n\
+         Your previous response was cut off. It ended with:\n\
+         <partial>\n{partial}\n</partial>\n\n\
+         Continue exactly where it stopped. Do not repeat any of it."
+    )
+}
+
+/// Text of a message `content`, which is either a string or (on multimodal
+/// gateways) an array of parts; `text` parts are concatenated and other
+/// part types are skipped with a debug note.
+fn message_text(content: &serde_json::Value) -> String {
+    let Some(parts) = content.as_array() else {
+        return content.as_str().unwrap_or("").to_string();
+    };
+    let mut text = String::new();
+    for part in parts {
+        match part["text"].as_str() {
+            Some(t) if part["type"].as_str().is_none_or(|ty| ty == "text") => text.push_str(t),
+            _ => debug!(
+                part_type = part["type"].as_str().unwrap_or("unknown"),
+                "skipping non-text content part"
+            ),
         }
+    }
+    text
+}
+
+/// Rough token estimate (~4 characters per token) for responses without `usage`.
+fn estimate_tokens(system_prompt: &str, user_prompt: &str, completion: &str) -> u64 {
+    ((system_prompt.len() + user_prompt.len() + completion.len()) as u64)
+        .div_ceil(CHARS_PER_TOKEN as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn think_block_is_stripped_but_kept_in_raw_capture() {
+        let raw = "<think>\nThe user wants JSON. {not this}\n</think>\n{\"score\": 0.9}";
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({ "choices": [{ "message": { "content": raw } }] }),
+        )])
+        .await;
+        let gateway = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_reasoning_strip(Some(ReasoningTags::default()));
+
+        let capture = RawCapture::default();
+        let text = capture_raw(
+            capture.clone(),
+            gateway.chat_completion("m", "s", "u", None, None),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(text, r#"{"score": 0.9}"#);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&text).unwrap()["score"],
+            0.9
+        );
+        assert_eq!(capture.texts(), vec![raw.to_string()]);
+        assert_eq!(
+            ReasoningTags::new(["reasoning"]).strip("step 1</reasoning> answer"),
+            "answer"
+        );
+    }
+
+    #[tokio::test]
+    async fn array_content_parts_are_concatenated() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({
+                "choices": [{ "message": { "content": [
+                    { "type": "text", "text": "Hello, " },
+                    { "type": "image_url", "image_url": { "url": "https://x/y.png" } },
+                    { "type": "text", "text": "world" },
+                ] } }],
+            }),
+        )])
+        .await;
+        let gateway = GatewayClient::new(&server.url).unwrap();
+        let text = gateway
+            .chat_completion("gpt-4o", "sys", "hi", None, None)
+            .await
+            .unwrap();
+        assert_eq!(text, "Hello, world");
+    }
+
+    #[tokio::test]
+    async fn responses_api_shape_is_mapped_to_plain_text() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({
+                "output": [
+                    { "type": "reasoning", "summary": [] },
+                    { "type": "message", "role": "assistant", "content": [
+                        { "type": "output_text", "text": "Hello, " },
+                        { "type": "output_text", "text": "world" },
+                    ]},
+                ],
+                "usage": { "input_tokens": 7, "output_tokens": 3, "total_tokens": 10 },
+            }),
+        )])
+        .await;
+
+        let gateway = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_api_style(ApiStyle::Responses);
+        let text = scope_run(
+            "run-resp".to_string(),
+            gateway.chat_completion("gpt-4o", "sys", "hi", Some(0.2), Some(64)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(text, "Hello, world");
+        assert_eq!(gateway.run_tokens_used("run-resp"), 10);
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/v1/responses");
+        let body = request.json();
+        assert_eq!(body["instructions"], "sys");
+        assert_eq!(body["input"], "hi");
+        assert_eq!(body["max_output_tokens"], 64);
+        assert!(body.get("messages").is_none());
+    }
+
+    #[tokio::test]
+    async fn responses_api_stream_deltas_are_forwarded() {
+        let server = MockServer::start(vec![MockResponse::sse(&[
+            r#"{"type":"response.created","response":{}}"#,
+            r#"{"type":"response.output_text.delta","delta":"Hel"}"#,
+            r#"{"type":"response.output_text.delta","delta":"lo"}"#,
+            r#"{"type":"response.completed","response":{}}"#,
+        ])])
+        .await;
+
+        let gateway = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_api_style(ApiStyle::Responses);
+        let mut chunks = Vec::new();
+        let text = gateway
+            .chat_completion_streaming("gpt-4o", "sys", "hi", None, None, |d, _| {
+                chunks.push(d.to_string())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(text, "Hello");
+        assert_eq!(chunks, vec!["Hel", "lo"]);
+        assert_eq!(server.requests()[0].json()["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn dropped_stream_is_resumed_from_partial_text() {
+        let cut = || {
+            MockResponse::new(
+                200,
+                "text/event-stream",
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+            )
+        };
+        let server = MockServer::start(vec![
+            cut(),
+            MockResponse::sse(&[r#"{"choices":[{"delta":{"content":"lo"}}]}"#, "[DONE]"]),
+            cut(),
+        ])
+        .await;
+
+        let gateway = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_stream_resume(Some(StreamResume {
+                attempts: 2,
+                backoff: Duration::from_millis(1),
+            }));
+        let mut chunks = Vec::new();
+        let text = gateway
+            .chat_completion_streaming("gpt-4o", "sys", "say hello", None, None, |d, i| {
+                chunks.push((d.to_string(), i))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(text, "Hello");
+        assert_eq!(chunks, vec![("Hel".to_string(), 0), ("lo".to_string(), 1)]);
+        let resumed = server.requests()[1].json();
+        let prompt = resumed["messages"][1]["content"].as_str().unwrap();
+        assert!(prompt.starts_with("say hello"), "{prompt}");
+        assert!(prompt.contains("<partial>\nHel\n</partial>"), "{prompt}");
+
+        // Without the flag a truncated stream is returned as-is
+        let plain = GatewayClient::new(&server.url).unwrap();
+        let text = plain
+            .chat_completion_streaming("gpt-4o", "sys", "say hello", None, None, |_, _| {})
+            .await
+            .unwrap();
+        assert_eq!(text, "Hel");
+    }
+
+    #[tokio::test]
+    async fn broken_connection_is_resumed_or_fails() {
+        let first = "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n";
+        let broken = || {
+            MockResponse::new(200, "text/event-stream", format!("{first}data: [DONE]\n\n"))
+                .with_cut_after(first.len())
+        };
+        let server = MockServer::start(vec![
+            broken(),
+            MockResponse::sse(&[r#"{"choices":[{"delta":{"content":"lo"}}]}"#, "[DONE]"]),
+            broken(),
+        ])
+        .await;
+
+        let gateway = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_stream_resume(Some(StreamResume {
+                attempts: 1,
+                backoff: Duration::from_millis(1),
+            }));
+        let text = gateway
+            .chat_completion_streaming("gpt-4o", "sys", "say hello", None, None, |_, _| {})
+            .await
+            .unwrap();
+        assert_eq!(text, "Hello");
+
+        // Without resume a broken read is an error, not a short answer
+        let plain = GatewayClient::new(&server.url).unwrap();
+        let err = plain
+            .chat_completion_streaming("gpt-4o", "sys", "say hello", None, None, |_, _| {})
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("SSE stream chunk"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn unterminated_sse_line_fails_at_limit() {
+        let body = format!(
+            "data: {}\n\ndata: {}",
+            r#"{"choices":[{"delta":{"content":"Hi"}}]}"#,
+            "x".repeat(64 * 1024)
+        );
+        let server =
+            MockServer::start(vec![MockResponse::new(200, "text/event-stream", body)]).await;
+
+        let client = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_max_sse_line(4 * 1024);
+        let err = client
+            .chat_completion_streaming("gpt-4o-mini", "sys", "hi", None, None, |_, _| {})
+            .await
+            .unwrap_err();
+
+        let too_long = err
+            .downcast_ref::<SseLineTooLong>()
+            .expect("SseLineTooLong");
+        assert_eq!(too_long.limit, 4 * 1024);
+        assert!(err.to_string().contains("malformed SSE: line too long"));
+    }
+
+    #[tokio::test]
+    async fn streaming_error_chunk_returns_partial_text() {
+        let server = MockServer::start(vec![MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"content":"Hello"}}]}"#,
+            r#"{"choices":[{"delta":{"content":", wor"}}]}"#,
+            r#"{"error":{"message":"upstream provider overloaded"}}"#,
+            r#"{"choices":[{"delta":{"content":"ld"}}]}"#,
+            "[DONE]",
+        ])])
+        .await;
+
+        let client = GatewayClient::new(&server.url).unwrap();
+        let mut chunks = Vec::new();
+        let err = client
+            .chat_completion_streaming("gpt-4o-mini", "sys", "hi", None, None, |d, _| {
+                chunks.push(d.to_string())
+            })
+            .await
+            .unwrap_err();
+
+        let stream_err = err.downcast_ref::<StreamError>().expect("StreamError");
+        assert_eq!(stream_err.message, "upstream provider overloaded");
+        assert_eq!(stream_err.partial, "Hello, wor");
+        assert_eq!(chunks, vec!["Hello", ", wor"]);
+    }
+
+    #[tokio::test]
+    async fn json_mode_sets_response_format() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({ "choices": [{ "message": { "content": "{\"ok\":true}" } }] }),
+        )])
+        .await;
+        let client = GatewayClient::new(&server.url).unwrap();
+
+        let opts = CompletionOptions::new(Some(0.3), None)
+            .with_response_format(ResponseFormat::JsonObject);
+        let completion = client
+            .chat_completion_with_options("m", "s", "u", opts)
+            .await
+            .unwrap();
+        assert_eq!(completion.content, r#"{"ok":true}"#);
+
+        let request = server.requests()[0].json();
+        assert_eq!(request["response_format"], json!({ "type": "json_object" }));
+        assert_eq!(request["temperature"], 0.3);
+        let responses = ApiStyle::Responses.request_body("m", "s", "u", &opts, false);
+        assert_eq!(responses["text"]["format"]["type"], "json_object");
+    }
+
+    #[tokio::test]
+    async fn usage_is_returned_with_the_completion() {
+        let server = MockServer::start(vec![
+            MockResponse::json(
+                200,
+                &json!({
+                    "choices": [{ "message": { "content": "ok" } }],
+                    "usage": { "prompt_tokens": 12, "completion_tokens": 4, "total_tokens": 16 }
+                }),
+            ),
+            MockResponse::json(
+                200,
+                &json!({ "choices": [{ "message": { "content": "bare" } }] }),
+            ),
+        ])
+        .await;
+        let client = GatewayClient::new(&server.url).unwrap();
+
+        let completion = client
+            .chat_completion_with_usage("m", "s", "u", None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            completion,
+            Completion {
+                content: "ok".to_string(),
+                prompt_tokens: 12,
+                completion_tokens: 4,
+                total_tokens: 16,
+            }
+        );
+
+        let bare = client
+            .chat_completion_with_usage("m", "s", "u", None, None)
+            .await
+            .unwrap();
+        assert_eq!((bare.content.as_str(), bare.total_tokens), ("bare", 0));
+    }
+
+    #[tokio::test]
+    async fn streaming_usage_comes_from_the_final_chunk() {
+        let server = MockServer::start(vec![MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"content":"Hi"}}]}"#,
+            r#"{"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":1,"total_tokens":10}}"#,
+            "[DONE]",
+        ])])
+        .await;
+        let client = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_stream_usage(true);
+
+        let completion = client
+            .chat_completion_streaming_with_usage("m", "s", "u", None, None, |_, _| {})
+            .await
+            .unwrap();
+        assert_eq!(completion.content, "Hi");
+        assert_eq!(
+            (
+                completion.prompt_tokens,
+                completion.completion_tokens,
+                completion.total_tokens
+            ),
+            (9, 1, 10)
+        );
+        assert_eq!(
+            server.requests()[0].json()["stream_options"]["include_usage"],
+            true
+        );
+    }
+
+    #[tokio::test]
+    async fn streaming_accumulates_until_done() {
+        let server = MockServer::start(vec![MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"content":"Hi"}}]}"#,
+            r#"{"choices":[{"delta":{"content":" there"}}]}"#,
+            "[DONE]",
+        ])])
+        .await;
+
+        let client = GatewayClient::new(&server.url).unwrap();
+        let text = client
+            .chat_completion_streaming("gpt-4o-mini", "sys", "hi", None, None, |_, _| {})
+            .await
+            .unwrap();
+        assert_eq!(text, "Hi there");
+    }
+
+    #[tokio::test]
+    async fn run_budget_refuses_calls_once_exhausted() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({
+                "choices": [{ "message": { "content": "ok" } }],
+                "usage": { "total_tokens": 60 }
+            }),
+        )])
+        .await;
+
+        let client = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_run_budget(Some(100));
+
+        scope_run("run-1", async {
+            client
+                .chat_completion("m", "s", "u", None, None)
+                .await
+                .unwrap();
+            client
+                .chat_completion("m", "s", "u", None, None)
+                .await
+                .unwrap();
+            let err = client
+                .chat_completion("m", "s", "u", None, None)
+                .await
+                .unwrap_err();
+            let budget_err = err
+                .downcast_ref::<BudgetExceeded>()
+                .expect("BudgetExceeded");
+            assert_eq!(budget_err.used, 120);
+            assert_eq!(budget_err.budget, 100);
+        })
+        .await;
+
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(client.run_tokens_used("run-1"), 120);
+
+        // Other runs keep their own budget
+        scope_run("run-2", client.chat_completion("m", "s", "u", None, None))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn request_id_header_carries_run_id() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({ "choices": [{ "message": { "content": "ok" } }] }),
+        )])
+        .await;
+        let client = GatewayClient::new(&server.url).unwrap();
+
+        scope_run(
+            "run-trace",
+            client.chat_completion("m", "s", "u", None, None),
+        )
+        .await
+        .unwrap();
+        client
+            .chat_completion("m", "s", "u", None, None)
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].headers["x-request-id"], "run-trace");
+        let generated = &requests[1].headers["x-request-id"];
+        assert!(uuid::Uuid::parse_str(generated).is_ok(), "{generated}");
+    }
+
+    #[tokio::test]
+    async fn cloned_client_works_in_spawned_task_and_shares_budget() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({
+                "choices": [{ "message": { "content": "ok" } }],
+                "usage": { "total_tokens": 25 }
+            }),
+        )])
+        .await;
+        let client = GatewayClient::new(&server.url).unwrap();
+
+        let handle = client.clone();
+        let text = tokio::spawn(scope_run("run-bg", async move {
+            handle.chat_completion("m", "s", "u", None, None).await
+        }))
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(text, "ok");
+        assert_eq!(client.run_tokens_used("run-bg"), 25);
+    }
+
+    #[tokio::test]
+    async fn oversized_prompt_is_trimmed_below_limit() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({ "choices": [{ "message": { "content": "ok" } }] }),
+        )])
+        .await;
+
+        let client = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_prompt_limit(Some(100), PromptOverflow::Trim);
+
+        let system = "You are a careful assistant.";
+        let user = "é".repeat(2000);
+        client
+            .chat_completion("m", system, &user, None, None)
+            .await
+            .unwrap();
+
+        let body = server.requests()[0].json();
+        assert_eq!(body["messages"][0]["content"], system);
+        let sent = body["messages"][1]["content"].as_str().unwrap();
+        assert!(sent.ends_with(TRUNCATION_MARKER));
+        assert!((system.len() + sent.len()).div_ceil(CHARS_PER_TOKEN) <= 100);
+    }
+
+    #[test]
+    fn prompt_within_limit_is_untouched() {
+        let client = GatewayClient::new("http://localhost")
+            .unwrap()
+            .with_prompt_limit(Some(100), PromptOverflow::Trim);
+        assert!(matches!(
+            client.fit_user_prompt("sys", "short"),
+            Cow::Borrowed("short")
+        ));
+
+        let warn_only = GatewayClient::new("http://localhost")
+            .unwrap()
+            .with_prompt_limit(Some(1), PromptOverflow::Warn);
+        assert_eq!(
+            warn_only.fit_user_prompt("sys", "long prompt"),
+            "long prompt"
+        );
+    }
+
+    #[tokio::test]
+    async fn calls_round_robin_across_gateways() {
+        let reply = MockResponse::json(
+            200,
+            &json!({ "choices": [{ "message": { "content": "ok" } }] }),
+        );
+        let a = MockServer::start(vec![reply.clone()]).await;
+        let b = MockServer::start(vec![reply]).await;
+
+        let client = GatewayClient::new(&format!("{}, {}/", a.url, b.url)).unwrap();
+        assert_eq!(client.endpoints().len(), 2);
+
+        for _ in 0..4 {
+            client
+                .chat_completion("m", "s", "u", None, None)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(a.requests().len(), 2);
+        assert_eq!(b.requests().len(), 2);
+    }
+
+    #[test]
+    fn failing_endpoint_is_skipped_until_cooldown() {
+        let pool = EndpointPool::new(vec!["a".into(), "b".into()]);
+        for _ in 0..ENDPOINT_FAILURE_THRESHOLD {
+            pool.report(0, false);
+        }
+        assert_eq!(pool.pick().1, "b");
+        assert_eq!(pool.pick().1, "b");
+
+        pool.report(0, true);
+        let picks: Vec<&str> = (0..2).map(|_| pool.pick().1).collect();
+        assert!(picks.contains(&"a"));
+    }
+
+    #[tokio::test]
+    async fn repeated_rate_limits_downgrade_to_fallback_model() {
+        let limited = MockResponse::json(429, &json!({ "error": { "message": "slow down" } }));
+        let ok = MockResponse::json(
+            200,
+            &json!({ "choices": [{ "message": { "content": "ok" } }] }),
+        );
+        let server = MockServer::start(vec![limited.clone(), limited, ok]).await;
+
+        let client = GatewayClient::new(&server.url)
+            .unwrap()
+            .with_model_fallback(Some(ModelFallback {
+                fallbacks: HashMap::from([("gpt-4o".to_string(), "gpt-4o-mini".to_string())]),
+                threshold: 2,
+                ..ModelFallback::default()
+            }));
+
+        for _ in 0..2 {
+            let err = client
+                .chat_completion("gpt-4o", "s", "u", None, None)
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<GatewayStatusError>().unwrap().status,
+                429
+            );
+        }
+        client
+            .chat_completion("gpt-4o", "s", "u", None, None)
+            .await
+            .unwrap();
 
-        Ok(accumulated)
+        let models: Vec<String> = server
+            .requests()
+            .iter()
+            .map(|r| r.json()["model"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(models, vec!["gpt-4o", "gpt-4o", "gpt-4o-mini"]);
     }
 }
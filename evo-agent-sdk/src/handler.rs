@@ -1,22 +1,404 @@
 use async_trait::async_trait;
-use serde_json::Value;
-use std::sync::Arc;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 
-use crate::gateway_client::GatewayClient;
-use crate::skill_engine::LoadedSkill;
+use crate::emit::Emit;
+use crate::gateway_client::{self, CompletionOptions, GatewayClient};
+use crate::registration::RegistrationAck;
+use crate::run_cache::RunCache;
+use crate::skill_engine::{SkillUsageLog, StageSkills};
 use crate::soul::Soul;
 
 // ─── Context types ───────────────────────────────────────────────────────────
 
 /// Context provided to [`AgentHandler::on_pipeline`] for every pipeline event.
+#[derive(Clone)]
 pub struct PipelineContext<'a> {
     pub soul: &'a Soul,
     pub gateway: &'a Arc<GatewayClient>,
-    pub skills: &'a [LoadedSkill],
+    /// Calls through `skills.execute(..)` are recorded in `skills_used`.
+    pub skills: StageSkills<'a>,
     pub run_id: String,
     pub stage: String,
     pub artifact_id: String,
     pub metadata: Value,
+    /// Fired when king cancels this stage (`pipeline:cancel`, `cancel_build`)
+    /// or reassigns it; long handlers can `tokio::select!` on
+    /// `cancel.cancelled()` to stop early. A cancelled stage sends no
+    /// stage result.
+    pub cancel: CancellationToken,
+    /// Skills invoked through [`invoke_skill`](Self::invoke_skill) or
+    /// `skills.execute(..)` this dispatch.
+    pub skills_used: SkillUsageLog,
+    /// Outbound channel to king, for handler-originated events.
+    pub emitter: Arc<dyn Emit>,
+    /// Outputs of earlier stages in this run, when the run cache is enabled.
+    pub run_cache: Option<Arc<RunCache>>,
+    /// Non-fatal problems reported through [`warn`](Self::warn).
+    pub warnings: StageWarnings,
+    /// Follow-up runs asked for through [`request_pipeline`](Self::request_pipeline).
+    pub pipeline_requests: PipelineRequests,
+}
+
+/// Warnings collected during one pipeline dispatch, sent as the stage
+/// result's `warnings`.
+#[derive(Debug, Clone, Default)]
+pub struct StageWarnings(Arc<Mutex<Vec<String>>>);
+
+impl StageWarnings {
+    pub fn push(&self, message: String) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(message);
+    }
+
+    pub fn snapshot(&self) -> Vec<String> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Forget warnings from a failed attempt before retrying.
+    pub(crate) fn clear(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+/// Most follow-up runs one stage dispatch may request, so handlers that
+/// request each other can't loop without bound.
+pub const MAX_PIPELINE_REQUESTS: usize = 5;
+
+/// Follow-up pipeline runs requested during one dispatch, each sent as
+/// [`PIPELINE_REQUEST`] after a completed stage result.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineRequests(Arc<Mutex<Vec<(String, Value)>>>);
+
+impl PipelineRequests {
+    /// Queue a request; `false` once [`MAX_PIPELINE_REQUESTS`] are queued.
+    pub fn push(&self, stage: String, metadata: Value) -> bool {
+        let mut requests = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if requests.len() >= MAX_PIPELINE_REQUESTS {
+            return false;
+        }
+        requests.push((stage, metadata));
+        true
+    }
+
+    /// `(stage, metadata)` pairs in request order.
+    pub fn snapshot(&self) -> Vec<(String, Value)> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Forget requests from a failed attempt before retrying.
+    pub(crate) fn clear(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+impl PipelineContext<'_> {
+    /// Ask king to start a new pipeline run at `stage` with `metadata`
+    /// (e.g. learning found a skill worth building right away). Sent as
+    /// [`PIPELINE_REQUEST`] once this stage completes; king decides whether
+    /// to honor it. Returns `false`, with a stage warning, when this
+    /// dispatch already made [`MAX_PIPELINE_REQUESTS`] requests.
+    pub fn request_pipeline(&self, stage: impl Into<String>, metadata: Value) -> bool {
+        let stage = stage.into();
+        if self.pipeline_requests.push(stage.clone(), metadata) {
+            info!(run_id = %self.run_id, stage = %self.stage, requested_stage = %stage, "pipeline run requested");
+            true
+        } else {
+            self.warn(format!(
+                "pipeline request for '{stage}' dropped: limit of {MAX_PIPELINE_REQUESTS} per stage reached"
+            ));
+            false
+        }
+    }
+
+    /// Report a recoverable problem: logged, and listed in the stage
+    /// result's `warnings` beside a result that still succeeds.
+    pub fn warn(&self, message: impl Into<String>) {
+        let message = message.into();
+        warn!(run_id = %self.run_id, stage = %self.stage, warning = %message, "stage warning");
+        self.warnings.push(message);
+    }
+
+    /// Invoke a loaded skill by name, recording it in the stage result's
+    /// `skills_used`.
+    pub async fn invoke_skill(&self, name: &str, input: &Value) -> anyhow::Result<Value> {
+        self.skills.execute(name, input).await
+    }
+
+    /// Emit a custom event to king (subject to the runner's emit rate limit).
+    pub async fn emit(&self, event: &str, payload: Value) -> anyhow::Result<()> {
+        self.emitter.emit(event, payload).await
+    }
+
+    /// An owned [`GatewayClient`] for moving into spawned tasks. Cheap: the
+    /// clone shares connections and budget state with `ctx.gateway`. Wrap
+    /// the task in [`scope_run`](crate::gateway_client::scope_run) to keep
+    /// its calls counted against this run.
+    pub fn gateway_handle(&self) -> GatewayClient {
+        (**self.gateway).clone()
+    }
+
+    /// Output of an earlier `stage` in this run, if cached and not expired.
+    pub fn previous_stage(&self, stage: &str) -> Option<Value> {
+        self.run_cache.as_ref()?.load(&self.run_id, stage)
+    }
+
+    /// Start forwarding incremental output for this stage as
+    /// [`PIPELINE_PROGRESS`] events.
+    pub fn progress(&self) -> ProgressReporter {
+        ProgressReporter::new(
+            Arc::clone(&self.emitter),
+            PIPELINE_PROGRESS,
+            json!({
+                "run_id": self.run_id,
+                "stage": self.stage,
+                "artifact_id": self.artifact_id,
+            }),
+        )
+    }
+
+    /// Stream a completion for `prompt` (system prompt: the soul's behavior)
+    /// as a [`Stream`] of text deltas, ending with an `Err` item if the
+    /// gateway fails mid-stream. The call is attributed to this run; with
+    /// `opts.forward_progress` each delta is also sent as a
+    /// [`PIPELINE_PROGRESS`] event. Dropping the stream aborts the request.
+    pub fn stream_completion(
+        &self,
+        model: &str,
+        prompt: &str,
+        opts: CompletionOptions,
+    ) -> DeltaStream {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let gateway = self.gateway_handle();
+        let progress = opts.forward_progress.then(|| self.progress());
+        let (model, system, prompt) = (
+            model.to_string(),
+            self.soul.behavior.clone(),
+            prompt.to_string(),
+        );
+        // Spawned, so carry the stage's usage (metrics, call cap) and raw
+        // captures into the task
+        let request = gateway_client::scope_run(
+            self.run_id.clone(),
+            gateway_client::inherit_captures(async move {
+                let result = gateway
+                    .chat_completion_streaming_with_options(
+                        &model,
+                        &system,
+                        &prompt,
+                        opts,
+                        |delta, chunk_index| {
+                            if let Some(progress) = &progress {
+                                progress.report(delta, chunk_index);
+                            }
+                            let _ = tx.send(Ok(delta.to_string()));
+                        },
+                    )
+                    .await;
+                if let Some(progress) = progress {
+                    progress.finish().await;
+                }
+                if let Err(e) = result {
+                    let _ = tx.send(Err(e));
+                }
+            }),
+        );
+        DeltaStream {
+            rx,
+            task: tokio::spawn(request),
+        }
+    }
+}
+
+/// Text deltas of a streaming completion; see
+/// [`PipelineContext::stream_completion`].
+pub struct DeltaStream {
+    rx: mpsc::UnboundedReceiver<anyhow::Result<String>>,
+    task: JoinHandle<()>,
+}
+
+impl Stream for DeltaStream {
+    type Item = anyhow::Result<String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for DeltaStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Outcome of a pipeline stage, sent as `status` in `pipeline:stage_result`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageStatus {
+    Completed,
+    /// The handler returned an error.
+    Failed,
+    /// The event was refused before the handler ran: a required capability
+    /// is missing or [`AgentHandler::validate_pipeline`] failed.
+    Rejected,
+    /// The stage already completed for this run; its cached output is resent.
+    Skipped,
+    /// Cancelled by king or superseded by a newer assignment.
+    Cancelled,
+    /// The stage deadline or a command timeout elapsed.
+    TimedOut,
+}
+
+impl StageStatus {
+    /// The wire name, as serialized in `status`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Rejected => "rejected",
+            Self::Skipped => "skipped",
+            Self::Cancelled => "cancelled",
+            Self::TimedOut => "timed_out",
+        }
+    }
+
+    /// Whether the stage produced usable output.
+    pub fn is_success(self) -> bool {
+        matches!(self, Self::Completed | Self::Skipped)
+    }
+}
+
+/// Event from king cancelling an in-flight stage (`{ run_id, stage? }`),
+/// e.g. after reassigning it to another agent.
+pub const PIPELINE_CANCEL: &str = "pipeline:cancel";
+
+/// Opt-in per-stage cost and timing summary, sent after the stage result.
+pub const PIPELINE_STAGE_METRICS: &str = "pipeline:stage_metrics";
+
+/// Event asking king to start a follow-up pipeline run (`{ run_id, stage,
+/// agent_id, requested_stage, metadata }`), sent after a completed stage
+/// result for each [`PipelineContext::request_pipeline`] call.
+pub const PIPELINE_REQUEST: &str = "pipeline:request";
+
+/// Event announcing an artifact produced by a stage (`{ run_id, stage,
+/// agent_id, artifact }`), sent before the stage result.
+pub const PIPELINE_ARTIFACT: &str = "pipeline:artifact";
+
+/// Event carrying a chunk of a stage's in-progress output.
+pub const PIPELINE_PROGRESS: &str = "pipeline:progress";
+
+/// Event carrying a chunk of an in-progress `task:evaluate` answer.
+pub const TASK_PROGRESS: &str = "task:progress";
+
+/// Forwards progress deltas to king from synchronous callbacks such as the
+/// `on_chunk` of [`GatewayClient::chat_completion_streaming`].
+pub struct ProgressReporter {
+    tx: mpsc::UnboundedSender<(String, u32)>,
+    forward: JoinHandle<()>,
+}
+
+impl ProgressReporter {
+    /// Emit each delta as `event`: `base` plus `delta` and `chunk_index`.
+    fn new(emitter: Arc<dyn Emit>, event: &'static str, base: Value) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<(String, u32)>();
+        let forward = tokio::spawn(async move {
+            while let Some((delta, chunk_index)) = rx.recv().await {
+                let mut payload = base.clone();
+                payload["delta"] = json!(delta);
+                payload["chunk_index"] = json!(chunk_index);
+                if let Err(e) = emitter.emit(event, payload).await {
+                    warn!(err = %e, event, "failed to emit progress chunk");
+                }
+            }
+        });
+        Self { tx, forward }
+    }
+
+    /// Queue a delta for emission. Never blocks.
+    pub fn report(&self, delta: &str, chunk_index: u32) {
+        let _ = self.tx.send((delta.to_string(), chunk_index));
+    }
+
+    /// Wait until every queued delta has been emitted.
+    pub async fn finish(self) {
+        drop(self.tx);
+        let _ = self.forward.await;
+    }
+}
+
+/// A reference to something a stage produced outside its `output`, such
+/// as a release archive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactRef {
+    pub name: String,
+    /// Where king can fetch it: a path on this host or a URL.
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+}
+
+impl ArtifactRef {
+    pub fn new(name: impl Into<String>, uri: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            uri: uri.into(),
+            size: None,
+            content_type: None,
+        }
+    }
+
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+}
+
+/// What [`AgentHandler::on_pipeline_output`] returns: the stage `output`,
+/// plus artifacts (each announced as [`PIPELINE_ARTIFACT`] and listed in the
+/// stage result) and handler metrics (added to `pipeline:stage_metrics`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HandlerOutput {
+    pub output: Value,
+    pub artifacts: Vec<ArtifactRef>,
+    pub metrics: Option<Value>,
+}
+
+impl HandlerOutput {
+    pub fn with_artifact(mut self, artifact: ArtifactRef) -> Self {
+        self.artifacts.push(artifact);
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Value) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+impl From<Value> for HandlerOutput {
+    fn from(output: Value) -> Self {
+        Self {
+            output,
+            ..Self::default()
+        }
+    }
 }
 
 /// Context provided to [`AgentHandler::on_command`] for king commands.
@@ -36,6 +418,48 @@ pub struct TaskEvaluateContext<'a> {
     pub exit_code: Option<i32>,
     pub latency_ms: Option<u64>,
     pub metadata: Value,
+    /// Outbound channel to king, e.g. for [`progress`](Self::progress).
+    pub emitter: Arc<dyn Emit>,
+}
+
+impl TaskEvaluateContext<'_> {
+    /// Start forwarding incremental evaluation output as [`TASK_PROGRESS`]
+    /// events.
+    pub fn progress(&self) -> ProgressReporter {
+        ProgressReporter::new(
+            Arc::clone(&self.emitter),
+            TASK_PROGRESS,
+            json!({
+                "task_id": self.task_id,
+                "agent_id": self.soul.agent_id,
+            }),
+        )
+    }
+}
+
+/// Context provided to [`AgentHandler::on_heartbeat`] on every heartbeat tick.
+pub struct HeartbeatContext<'a> {
+    pub soul: &'a Soul,
+    pub gateway: &'a Arc<GatewayClient>,
+    /// Outbound channel to king, for handler-originated events.
+    pub emitter: Arc<dyn Emit>,
+    /// 1 for the first heartbeat, incremented on each tick.
+    pub tick: u64,
+}
+
+/// Why the connection to king ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// Graceful shutdown began (SIGTERM or Ctrl-C); the agent is exiting.
+    Shutdown,
+    /// The socket closed or heartbeats kept failing; the runner reconnects.
+    TransportError,
+}
+
+/// Context provided to [`AgentHandler::on_disconnect`].
+pub struct DisconnectContext<'a> {
+    pub soul: &'a Soul,
+    pub reason: DisconnectReason,
 }
 
 // ─── AgentHandler trait ──────────────────────────────────────────────────────
@@ -65,9 +489,40 @@ pub struct TaskEvaluateContext<'a> {
 /// ```
 #[async_trait]
 pub trait AgentHandler: Send + Sync + 'static {
+    /// Check a `pipeline:next` event before any work starts. An `Err` fails
+    /// the stage with `status: "rejected"` and `on_pipeline` is not called.
+    /// Default implementation accepts everything.
+    fn validate_pipeline(&self, _ctx: &PipelineContext<'_>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Version of the `output` shape this handler returns, sent to king as
+    /// `output_schema` in every `pipeline:stage_result`.
+    ///
+    /// Bump it whenever a field is removed, renamed or changes type; adding
+    /// an optional field does not need a bump. Default: `1`.
+    fn output_schema_version(&self) -> u32 {
+        1
+    }
+
+    /// Built-in abilities this handler offers beyond its installed skills
+    /// (e.g. `"summarization"`). Merged with skill capabilities when
+    /// registering with king. Default: none.
+    fn capabilities(&self) -> Vec<String> {
+        Vec::new()
+    }
+
     /// Handle a `pipeline:next` event. Return output JSON on success.
     async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> anyhow::Result<Value>;
 
+    /// What the runner actually calls for `pipeline:next`. Override it
+    /// instead of relying on [`on_pipeline`](Self::on_pipeline) to return
+    /// artifacts or metrics beside the output. Default: `on_pipeline`'s
+    /// value as the output.
+    async fn on_pipeline_output(&self, ctx: PipelineContext<'_>) -> anyhow::Result<HandlerOutput> {
+        self.on_pipeline(ctx).await.map(HandlerOutput::from)
+    }
+
     /// Handle a `king:command` event. Default implementation logs and ignores.
     fn on_command(&self, ctx: &CommandContext<'_>) {
         tracing::info!(
@@ -83,4 +538,107 @@ pub trait AgentHandler: Send + Sync + 'static {
     async fn on_task_evaluate(&self, _ctx: TaskEvaluateContext<'_>) -> anyhow::Result<Value> {
         Ok(Value::Null)
     }
+
+    /// Called after each `agent:register` (including re-registration after
+    /// a reconnect) with king's ack. Default implementation does nothing.
+    async fn on_registered(&self, _ack: &RegistrationAck) {}
+
+    /// Periodic work on the heartbeat cadence (flush metrics, refresh a
+    /// token). Runs beside the `agent:status` emit, never delaying it; a
+    /// tick is skipped while the previous call is still running.
+    /// Default implementation does nothing.
+    async fn on_heartbeat(&self, _ctx: &HeartbeatContext<'_>) {}
+
+    /// Called when the connection to king ends, after any running
+    /// [`on_heartbeat`](Self::on_heartbeat) finishes: on graceful shutdown
+    /// before the socket is closed, or after a transport drop before
+    /// reconnecting. Use it to flush metrics or persist state. The wait for
+    /// `on_heartbeat` plus this call are cut off after 10 seconds.
+    /// Default implementation does nothing.
+    async fn on_disconnect(&self, _ctx: &DisconnectContext<'_>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockResponse, MockServer, RecordingEmitter, pipeline_ctx, soul};
+    use futures_util::StreamExt;
+
+    #[test]
+    fn stage_status_names_match_the_wire() {
+        use StageStatus::*;
+        for status in [Completed, Failed, Rejected, Skipped, Cancelled, TimedOut] {
+            assert_eq!(serde_json::json!(status), status.as_str());
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_completion_yields_deltas_and_forwards_progress() {
+        let server = MockServer::start(vec![MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"content":"Hel"}}]}"#,
+            r#"{"choices":[{"delta":{"content":"lo"}}]}"#,
+            "[DONE]",
+        ])])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let soul = soul("custom");
+        let emitter = Arc::new(RecordingEmitter::default());
+        let mut ctx = pipeline_ctx(&soul, &gateway, Value::Null);
+        ctx.emitter = emitter.clone();
+
+        let opts = CompletionOptions {
+            forward_progress: true,
+            ..Default::default()
+        };
+        let mut stream = ctx.stream_completion("gpt-4o-mini", "hi", opts);
+        let mut deltas = Vec::new();
+        while let Some(delta) = stream.next().await {
+            deltas.push(delta.unwrap());
+        }
+
+        assert_eq!(deltas, vec!["Hel", "lo"]);
+        let progress = emitter.events();
+        assert_eq!(progress.len(), 2);
+        assert_eq!(progress[1].0, PIPELINE_PROGRESS);
+        assert_eq!(progress[1].1["delta"], "lo");
+        let request = server.requests()[0].json();
+        assert_eq!(request["messages"][0]["content"], soul.behavior);
+    }
+
+    #[tokio::test]
+    async fn streamed_completion_counts_in_stage_usage_and_call_cap() {
+        let server = MockServer::start(vec![MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"content":"Hi"}}]}"#,
+            "[DONE]",
+        ])])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let soul = soul("custom");
+        let ctx = pipeline_ctx(&soul, &gateway, Value::Null);
+        let collect = |usage: gateway_client::UsageCapture, raw: gateway_client::RawCapture| {
+            let ctx = ctx.clone();
+            gateway_client::capture_usage(
+                usage,
+                gateway_client::capture_raw(raw, async move {
+                    ctx.stream_completion("gpt-4o-mini", "hi", Default::default())
+                        .collect::<Vec<_>>()
+                        .await
+                }),
+            )
+        };
+
+        let usage = gateway_client::UsageCapture::default();
+        let raw = gateway_client::RawCapture::default();
+        let deltas = collect(usage.clone(), raw.clone()).await;
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(usage.totals().llm_calls, 1);
+        assert_eq!(raw.texts(), vec!["Hi"]);
+
+        // The stage's call cap applies to streamed calls too
+        let capped = gateway_client::UsageCapture::default().with_call_limit(Some(0));
+        let deltas = collect(capped, Default::default()).await;
+        let err = deltas[0].as_ref().unwrap_err();
+        assert!(err.is::<gateway_client::CallLimitExceeded>(), "{err}");
+        assert_eq!(server.requests().len(), 1);
+    }
 }
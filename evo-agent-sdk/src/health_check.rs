@@ -1,6 +1,11 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use std::time::Instant;
-use tracing::info;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 
 // ─── Health check ─────────────────────────────────────────────────────────────
 
@@ -10,13 +15,55 @@ pub struct EndpointHealth {
     pub reachable: bool,
     pub latency_ms: Option<u64>,
     pub status_code: Option<u16>,
+    /// Probes sent, including the one that succeeded.
+    pub attempts: u32,
+    /// Why the last failed probe failed (connection error or unexpected
+    /// status); `None` when the first probe succeeded.
+    pub last_error: Option<String>,
 }
 
-/// Probe a list of URLs and return health results.
-pub async fn check_endpoints(client: &reqwest::Client, urls: &[String]) -> Vec<EndpointHealth> {
-    let mut results = Vec::with_capacity(urls.len());
+/// How often a failing probe is retried before the endpoint counts as
+/// unreachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeRetry {
+    /// Total probes, at least 1.
+    pub attempts: u32,
+    /// Wait before the first retry; doubled for each one after it.
+    pub backoff: Duration,
+}
+
+impl Default for ProbeRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+impl ProbeRetry {
+    /// Read `HEALTH_PROBE_ATTEMPTS` and `HEALTH_PROBE_BACKOFF_MS`.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            attempts: crate::config::env_parse::<u32>("HEALTH_PROBE_ATTEMPTS")
+                .map_or(default.attempts, |n| n.max(1)),
+            backoff: crate::config::env_parse::<u64>("HEALTH_PROBE_BACKOFF_MS")
+                .map_or(default.backoff, Duration::from_millis),
+        }
+    }
+
+    /// Wait before retry `retry` (1-based).
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    }
+}
 
-    for url in urls {
+/// Probe a list of URLs concurrently and return health results, in the
+/// order of `urls`.
+pub async fn check_endpoints(client: &reqwest::Client, urls: &[String]) -> Vec<EndpointHealth> {
+    futures_util::future::join_all(urls.iter().map(|url| async move {
         let health = probe_url(client, url).await;
         info!(
             url = %url,
@@ -24,32 +71,130 @@ pub async fn check_endpoints(client: &reqwest::Client, urls: &[String]) -> Vec<E
             latency_ms = ?health.latency_ms,
             "endpoint health check"
         );
-        results.push(health);
+        health
+    }))
+    .await
+}
+
+/// Probe a single URL with a GET and record reachability and latency.
+pub async fn probe_url(client: &reqwest::Client, url: &str) -> EndpointHealth {
+    probe(client, &HealthProbe::get(url)).await
+}
+
+/// A dedicated health-check request for a skill endpoint, declared as
+/// `health = { method, path, body, expect_status }` beside its `url`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthProbe {
+    pub method: reqwest::Method,
+    pub url: String,
+    /// Sent as JSON when set.
+    pub body: Option<Value>,
+    /// Status the probe must return; any response counts when unset.
+    pub expect_status: Option<u16>,
+}
+
+impl HealthProbe {
+    /// A plain GET of `url`, the fallback for endpoints without `health`.
+    pub fn get(url: &str) -> Self {
+        Self {
+            method: reqwest::Method::GET,
+            url: url.to_string(),
+            body: None,
+            expect_status: None,
+        }
     }
 
-    results
+    /// Parse a `health` sub-config of the endpoint at `endpoint_url`.
+    /// `path` is resolved against that URL (an absolute URL replaces it).
+    pub fn from_config(endpoint_url: &str, health: &Value) -> Result<Self> {
+        let method = health["method"].as_str().unwrap_or("GET");
+        let method = reqwest::Method::from_bytes(method.to_uppercase().as_bytes())
+            .with_context(|| format!("invalid health method {method:?}"))?;
+        let url = match health["path"].as_str() {
+            Some(path) => reqwest::Url::parse(endpoint_url)
+                .and_then(|base| base.join(path))
+                .with_context(|| format!("invalid health path {path:?} for {endpoint_url}"))?
+                .to_string(),
+            None => endpoint_url.to_string(),
+        };
+        let expect_status = match &health["expect_status"] {
+            Value::Null => None,
+            v => Some(
+                v.as_u64()
+                    .and_then(|s| u16::try_from(s).ok())
+                    .with_context(|| format!("invalid health expect_status {v}"))?,
+            ),
+        };
+        Ok(Self {
+            method,
+            url,
+            body: health.get("body").filter(|b| !b.is_null()).cloned(),
+            expect_status,
+        })
+    }
+}
+
+/// [`probe`] with retries: the endpoint is unreachable only once every
+/// attempt has failed.
+pub async fn probe_with_retry(
+    client: &reqwest::Client,
+    probe: &HealthProbe,
+    retry: &ProbeRetry,
+) -> EndpointHealth {
+    let attempts = retry.attempts.max(1);
+    let mut last_error = None;
+    let mut attempt = 1;
+    loop {
+        let mut health = self::probe(client, probe).await;
+        health.attempts = attempt;
+        let Some(err) = health.last_error.take() else {
+            // Keep why the earlier attempts failed
+            health.last_error = last_error;
+            return health;
+        };
+        warn!(url = %probe.url, attempt, err = %err, "health probe failed");
+        health.last_error = Some(err.clone());
+        if attempt >= attempts {
+            return health;
+        }
+        last_error = Some(err);
+        tokio::time::sleep(retry.delay(attempt)).await;
+        attempt += 1;
+    }
 }
 
-async fn probe_url(client: &reqwest::Client, url: &str) -> EndpointHealth {
+/// Send `probe` once and record reachability and latency. With
+/// `expect_status` a response with any other status counts as unreachable;
+/// `last_error` is set when it failed.
+pub async fn probe(client: &reqwest::Client, probe: &HealthProbe) -> EndpointHealth {
     let start = Instant::now();
 
-    match client
-        .get(url)
-        .timeout(std::time::Duration::from_secs(5))
-        .send()
-        .await
-    {
-        Ok(resp) => EndpointHealth {
-            url: url.to_string(),
-            reachable: true,
-            latency_ms: Some(start.elapsed().as_millis() as u64),
-            status_code: Some(resp.status().as_u16()),
-        },
-        Err(_) => EndpointHealth {
-            url: url.to_string(),
+    let mut req = client
+        .request(probe.method.clone(), &probe.url)
+        .timeout(std::time::Duration::from_secs(5));
+    if let Some(body) = &probe.body {
+        req = req.json(body);
+    }
+    match req.send().await {
+        Ok(resp) => {
+            let status = resp.status().as_u16();
+            let reachable = probe.expect_status.is_none_or(|s| s == status);
+            EndpointHealth {
+                url: probe.url.clone(),
+                reachable,
+                latency_ms: Some(start.elapsed().as_millis() as u64),
+                status_code: Some(status),
+                attempts: 1,
+                last_error: (!reachable).then(|| format!("unexpected status {status}")),
+            }
+        }
+        Err(e) => EndpointHealth {
+            url: probe.url.clone(),
             reachable: false,
             latency_ms: None,
             status_code: None,
+            attempts: 1,
+            last_error: Some(e.to_string()),
         },
     }
 }
@@ -64,6 +209,8 @@ pub fn health_to_json(agent_id: &str, results: &[EndpointHealth]) -> Value {
                 "reachable":   h.reachable,
                 "latency_ms":  h.latency_ms,
                 "status_code": h.status_code,
+                "attempts":    h.attempts,
+                "last_error":  h.last_error,
             })
         })
         .collect();
@@ -73,3 +220,444 @@ pub fn health_to_json(agent_id: &str, results: &[EndpointHealth]) -> Value {
         "health_checks": checks,
     })
 }
+
+// ─── Diagnostics ──────────────────────────────────────────────────────────────
+
+/// Env vars the SDK reads; diagnostics report which are set, never their values.
+const KNOWN_ENV: &[&str] = &[
+    "KING_ADDRESS",
+    "GATEWAY_ADDRESS",
+    "AGENT_FOLDER",
+    "SOUL_REQUIRED_SECTIONS",
+    "SOUL_MIN_BEHAVIOR_CHARS",
+    "AGENT_REPLICA_ID",
+    "EVO_HOME",
+    "EVO_ENV",
+    "EVO_LOG_DIR",
+    "EVO_OTLP_ENDPOINT",
+    "RUN_TOKEN_BUDGET",
+    "PROMPT_TOKEN_LIMIT",
+    "PROMPT_OVERFLOW",
+    "MODEL_FALLBACK",
+    "RATE_LIMIT_THRESHOLD",
+    "RATE_LIMIT_WINDOW_SECS",
+    "RATE_LIMIT_COOLDOWN_SECS",
+    "GATEWAY_API_STYLE",
+    "STRIP_REASONING_TAGS",
+    "STREAM_RESUME_ATTEMPTS",
+    "STREAM_RESUME_BACKOFF_MS",
+    "GATEWAY_STREAM_USAGE",
+    "GATEWAY_JSON_SCHEMA",
+    "SSE_MAX_LINE_BYTES",
+    "PRELOAD_SLA_POLICY",
+    "PRELOAD_POLICY",
+    "HEALTH_PROBE_ATTEMPTS",
+    "HEALTH_PROBE_BACKOFF_MS",
+    "PIPELINE_RETRY_ATTEMPTS",
+    "PIPELINE_RETRY_BACKOFF_MS",
+    "PIPELINE_RETRY_KINDS",
+    "EMIT_RATE_LIMIT",
+    "EMIT_BURST",
+    "EMIT_THROTTLE",
+    "KING_AUTH_TOKEN",
+    "KING_AUTH_HEADER",
+    "KING_MIN_VERSION",
+    "KING_COMPAT_POLICY",
+    "REGISTER_ACK_TIMEOUT_SECS",
+    "REGISTER_REJECT_EXIT",
+    "INCLUDE_RAW_LLM",
+    "STAGE_RESULT_VERBOSE",
+    "STAGE_RESULTS_TTL_SECS",
+    "STAGE_RESULTS_MAX_RUNS",
+    "OUTPUT_REDACT",
+    "OUTPUT_REDACT_PREFIXES",
+    "OUTPUT_REDACT_FIELDS",
+    "STAGE_METRICS",
+    "STAGE_TIMEOUT_SECS",
+    "STAGE_MAX_LLM_CALLS",
+    "HEARTBEAT_INTERVAL_SECS",
+    "HEARTBEAT_REREGISTER",
+    "RECONNECT_MAX_BACKOFF_SECS",
+    "RECONNECT_DRAIN_SECS",
+    "HEALTH_DIAGNOSTICS",
+    "DUMP_PROMPTS_DIR",
+    "EVENT_STREAM_STDOUT",
+    "RECENT_EVENTS",
+    "RECENT_EVENTS_PATH",
+    "INBOUND_EVENTS_STRICT",
+    "INBOUND_EVENTS_ALLOW",
+    "ENDPOINT_QUARANTINE_AFTER",
+    "ENDPOINT_CANARY_SECS",
+    "SKILL_DUPLICATE_POLICY",
+    "SKILL_MISSING_DEPS",
+    "RUN_CACHE_TTL_SECS",
+    "RUN_CACHE_MAX_RUNS",
+    "SKILL_ENV_ALLOW",
+    "SKILL_CPU_SECS",
+    "SKILL_MEMORY_MB",
+    "SKILL_SANDBOX_WRAPPER",
+    "SKILL_TIMEOUT_SECS",
+    "EVALUATION_STREAM",
+    "BUILD_STREAM_MANIFEST",
+    "EVALUATION_SAMPLES",
+    "ACTIVATION_MIN_CONFIDENCE",
+    "SKILL_REGISTRY_URL",
+    "UPGRADE_VERIFY_TIMEOUT_SECS",
+    "UPGRADE_VERIFY_HEALTH_URL",
+    "BUILD_LOCK_MODE",
+    "MAX_CONCURRENT_BUILDS",
+    "EVO_SAFE_MODE",
+    "EVO_REQUIRE_SIGNATURE",
+    "EVO_RELEASE_PUBKEY",
+    "EVO_SIGNATURE_SCHEME",
+    "RUST_LOG",
+    "LOG_COMBINED",
+];
+
+/// Runtime environment attached to `agent:health` when `HEALTH_DIAGNOSTICS=1`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostics {
+    pub king_address: String,
+    pub gateway_addresses: Vec<String>,
+    pub model: String,
+    pub evo_home: String,
+    pub platform: String,
+    /// Names of known env vars that are set (values are never included).
+    pub env_set: Vec<String>,
+}
+
+impl Diagnostics {
+    /// `model` is the one the agent actually uses, normally
+    /// [`Soul::default_model`](crate::soul::Soul::default_model).
+    pub fn collect(king_address: &str, gateway_addresses: &[String], model: &str) -> Self {
+        Self {
+            king_address: king_address.to_string(),
+            gateway_addresses: gateway_addresses.to_vec(),
+            model: model.to_string(),
+            evo_home: crate::self_upgrade::evo_home().display().to_string(),
+            platform: crate::self_upgrade::detect_target().to_string(),
+            env_set: set_env_names(|name| std::env::var_os(name).is_some()),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+fn set_env_names(is_set: impl Fn(&str) -> bool) -> Vec<String> {
+    KNOWN_ENV
+        .iter()
+        .filter(|name| is_set(name))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+// ─── Endpoint quarantine ──────────────────────────────────────────────────────
+
+/// When to stop probing an endpoint that keeps failing pre-load.
+#[derive(Debug, Clone)]
+pub struct QuarantinePolicy {
+    /// Consecutive failed pre-loads before the endpoint is quarantined.
+    pub max_failures: u32,
+    /// How often a quarantined endpoint still gets a canary probe.
+    pub canary_interval: Duration,
+}
+
+impl QuarantinePolicy {
+    /// Read `ENDPOINT_QUARANTINE_AFTER` and `ENDPOINT_CANARY_SECS` (default
+    /// 3600). Returns `None` when quarantine is disabled (unset or `0`).
+    pub fn from_env() -> Option<Self> {
+        let max_failures =
+            crate::config::env_parse::<u32>("ENDPOINT_QUARANTINE_AFTER").filter(|n| *n > 0)?;
+        let canary_secs = crate::config::env_parse::<u64>("ENDPOINT_CANARY_SECS").unwrap_or(3600);
+        Some(Self {
+            max_failures,
+            canary_interval: Duration::from_secs(canary_secs),
+        })
+    }
+}
+
+/// Persistent failure record for one endpoint.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EndpointRecord {
+    pub consecutive_failures: u32,
+    pub quarantined_since: Option<DateTime<Utc>>,
+    pub last_probe: Option<DateTime<Utc>>,
+}
+
+/// What pre-load should do with an endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeDecision {
+    Probe,
+    /// Quarantined, but due for an occasional canary probe.
+    Canary,
+    /// Quarantined — fail fast without probing.
+    Skip,
+}
+
+/// Per-endpoint consecutive-failure counters, persisted as JSON.
+///
+/// Delete an entry (or the whole file) to manually release an endpoint
+/// from quarantine.
+#[derive(Debug, Default)]
+pub struct EndpointLedger {
+    path: PathBuf,
+    records: HashMap<String, EndpointRecord>,
+}
+
+impl EndpointLedger {
+    /// Default location: `<evo_home>/data/endpoint-failures.json`.
+    pub fn default_path() -> PathBuf {
+        crate::self_upgrade::evo_home()
+            .join("data")
+            .join("endpoint-failures.json")
+    }
+
+    /// Load the ledger at `path`; a missing or unreadable file starts empty.
+    pub fn load(path: &Path) -> Self {
+        let records = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self {
+            path: path.to_path_buf(),
+            records,
+        }
+    }
+
+    /// Load, apply `f` and save, holding an exclusive lock on a sidecar
+    /// `.lock` file throughout, so concurrent stages (in this process or
+    /// another) don't lose each other's updates. Blocks while waiting.
+    pub fn update<R>(path: &Path, f: impl FnOnce(&mut Self) -> R) -> Result<R> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let lock_path = path.with_extension("json.lock");
+        let lock = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+        lock.lock()
+            .with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+        let mut ledger = Self::load(path);
+        let out = f(&mut ledger);
+        ledger.save()?;
+        Ok(out)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.records)?)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    pub fn get(&self, url: &str) -> Option<&EndpointRecord> {
+        self.records.get(url)
+    }
+
+    pub fn decide(
+        &self,
+        url: &str,
+        policy: &QuarantinePolicy,
+        now: DateTime<Utc>,
+    ) -> ProbeDecision {
+        let Some(record) = self.records.get(url) else {
+            return ProbeDecision::Probe;
+        };
+        if record.quarantined_since.is_none() {
+            return ProbeDecision::Probe;
+        }
+        let due = record
+            .last_probe
+            .is_none_or(|last| (now - last).to_std().unwrap_or_default() >= policy.canary_interval);
+        if due {
+            ProbeDecision::Canary
+        } else {
+            ProbeDecision::Skip
+        }
+    }
+
+    /// Record a probe outcome. A success clears the counter (and any
+    /// quarantine); failures quarantine the endpoint once they reach
+    /// `policy.max_failures`.
+    pub fn record(
+        &mut self,
+        url: &str,
+        healthy: bool,
+        policy: &QuarantinePolicy,
+        now: DateTime<Utc>,
+    ) {
+        let record = self.records.entry(url.to_string()).or_default();
+        record.last_probe = Some(now);
+        if healthy {
+            if record.quarantined_since.is_some() {
+                info!(url, "endpoint recovered — released from quarantine");
+            }
+            record.consecutive_failures = 0;
+            record.quarantined_since = None;
+            return;
+        }
+        record.consecutive_failures += 1;
+        if record.quarantined_since.is_none() && record.consecutive_failures >= policy.max_failures
+        {
+            warn!(
+                url,
+                failures = record.consecutive_failures,
+                "endpoint quarantined after repeated pre-load failures"
+            );
+            record.quarantined_since = Some(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn diagnostics_report_addresses_platform_and_env_presence_only() {
+        let diag = Diagnostics::collect(
+            "http://king:3000",
+            &[
+                "http://gw-a:8080".to_string(),
+                "http://gw-b:8080".to_string(),
+            ],
+            "gpt-4o",
+        );
+        let json = diag.to_json();
+        assert_eq!(json["king_address"], "http://king:3000");
+        assert_eq!(json["model"], "gpt-4o");
+        assert_eq!(json["gateway_addresses"][1], "http://gw-b:8080");
+        assert_eq!(json["platform"], crate::self_upgrade::detect_target());
+        assert!(!json["evo_home"].as_str().unwrap().is_empty());
+
+        let set = set_env_names(|name| name == "KING_AUTH_TOKEN" || name == "NOT_OURS");
+        assert_eq!(set, vec!["KING_AUTH_TOKEN"]);
+    }
+
+    /// A feature registers its env var here in the same change that
+    /// documents it in CLAUDE.md's env table.
+    #[test]
+    fn every_documented_env_var_is_known() {
+        let documented: Vec<&str> = include_str!("../../CLAUDE.md")
+            .lines()
+            .filter_map(|line| line.strip_prefix("| `")?.split_once("` |"))
+            .map(|(name, _)| name)
+            .filter(|name| {
+                name.chars()
+                    .all(|c| c.is_ascii_uppercase() || c == '_' || c.is_ascii_digit())
+            })
+            .collect();
+        assert!(documented.len() > 50, "{documented:?}");
+        let missing: Vec<&&str> = documented
+            .iter()
+            .filter(|name| !KNOWN_ENV.contains(name))
+            .collect();
+        assert!(missing.is_empty(), "not in KNOWN_ENV: {missing:?}");
+    }
+
+    #[tokio::test]
+    async fn failed_probes_are_retried_before_giving_up() {
+        let server = test_support::MockServer::start(vec![
+            test_support::MockResponse::new(503, "text/plain", "warming up"),
+            test_support::MockResponse::new(200, "text/plain", "ok"),
+        ])
+        .await;
+        let client = reqwest::Client::new();
+        let retry = ProbeRetry {
+            attempts: 3,
+            backoff: Duration::ZERO,
+        };
+        let mut cold = HealthProbe::get(&server.url);
+        cold.expect_status = Some(200);
+
+        let health = probe_with_retry(&client, &cold, &retry).await;
+        assert!(health.reachable);
+        assert_eq!(health.attempts, 2);
+        assert_eq!(health.last_error.as_deref(), Some("unexpected status 503"));
+
+        let dead = probe_with_retry(&client, &HealthProbe::get("http://127.0.0.1:9"), &retry).await;
+        assert!(!dead.reachable);
+        assert_eq!(dead.attempts, 3);
+        assert!(dead.last_error.is_some());
+    }
+
+    #[test]
+    fn repeated_failures_quarantine_endpoint_until_canary_succeeds() {
+        let dir = test_support::temp_dir("quarantine");
+        let path = dir.join("endpoint-failures.json");
+        let policy = QuarantinePolicy {
+            max_failures: 3,
+            canary_interval: Duration::from_secs(600),
+        };
+        let url = "http://dead.example";
+        let start = Utc::now();
+
+        let mut ledger = EndpointLedger::load(&path);
+        for i in 0..3 {
+            assert_eq!(
+                ledger.decide(url, &policy, start),
+                ProbeDecision::Probe,
+                "run {i}"
+            );
+            ledger.record(url, false, &policy, start);
+        }
+        ledger.save().unwrap();
+
+        // Counter survives a restart
+        let mut ledger = EndpointLedger::load(&path);
+        assert_eq!(ledger.get(url).unwrap().consecutive_failures, 3);
+        assert_eq!(ledger.decide(url, &policy, start), ProbeDecision::Skip);
+
+        let later = start + chrono::Duration::seconds(601);
+        assert_eq!(ledger.decide(url, &policy, later), ProbeDecision::Canary);
+        ledger.record(url, true, &policy, later);
+        assert_eq!(ledger.decide(url, &policy, later), ProbeDecision::Probe);
+        assert_eq!(ledger.get(url).unwrap().consecutive_failures, 0);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn concurrent_updates_are_not_lost() {
+        let dir = test_support::temp_dir("ledger-lock");
+        let path = dir.join("endpoint-failures.json");
+        let policy = QuarantinePolicy {
+            max_failures: 100,
+            canary_interval: Duration::from_secs(600),
+        };
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                let policy = policy.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..5 {
+                        EndpointLedger::update(&path, |ledger| {
+                            ledger.record("http://dead.invalid", false, &policy, Utc::now())
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let ledger = EndpointLedger::load(&path);
+        assert_eq!(
+            ledger
+                .get("http://dead.invalid")
+                .unwrap()
+                .consecutive_failures,
+            40
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
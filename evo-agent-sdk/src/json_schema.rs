@@ -0,0 +1,124 @@
+//! JSON schemas for structured LLM output, checked in Rust.
+//!
+//! Handlers send an [`OutputSchema`] as `response_format: json_schema` when
+//! the gateway supports it (`GATEWAY_JSON_SCHEMA=1`), and plain JSON mode
+//! otherwise. Either way the reply is validated here, since neither mode
+//! guarantees the shape. Only the keywords the kernel schemas use are
+//! understood: `type`, `properties`, `required`, `additionalProperties:
+//! false`, `items`, `enum`, `minimum` and `maximum`.
+
+use serde_json::Value;
+
+/// A named schema a completion is asked to follow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputSchema {
+    /// Sent as the schema's `name`; `[a-zA-Z0-9_-]` only.
+    pub name: &'static str,
+    pub schema: Value,
+}
+
+impl OutputSchema {
+    pub fn new(name: &'static str, schema: Value) -> Self {
+        Self { name, schema }
+    }
+
+    /// Every way `value` breaks the schema; empty when it conforms.
+    pub fn validate(&self, value: &Value) -> Vec<String> {
+        let mut errors = Vec::new();
+        check(value, &self.schema, "$", &mut errors);
+        errors
+    }
+}
+
+fn check(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| has_type(value, t)) {
+            errors.push(format!(
+                "{path}: expected {}, got {}",
+                allowed.join(" or "),
+                type_name(value)
+            ));
+            // Nested keywords don't mean much against the wrong type
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(Value::as_array)
+        && !options.contains(value)
+    {
+        let options: Vec<String> = options.iter().map(Value::to_string).collect();
+        errors.push(format!("{path}: must be one of {}", options.join(", ")));
+    }
+
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64)
+            && n < min
+        {
+            errors.push(format!("{path}: {n} is below the minimum {min}"));
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64)
+            && n > max
+        {
+            errors.push(format!("{path}: {n} is above the maximum {max}"));
+        }
+    }
+
+    if let Value::Object(map) = value {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for key in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !map.contains_key(key) {
+                errors.push(format!("{path}: missing required field '{key}'"));
+            }
+        }
+        for (key, v) in map {
+            match properties.and_then(|p| p.get(key)) {
+                Some(sub) => check(v, sub, &format!("{path}.{key}"), errors),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    errors.push(format!("{path}: unexpected field '{key}'"));
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check(item, item_schema, &format!("{path}[{i}]"), errors);
+        }
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Object(_) => "object",
+        Value::Array(_) => "array",
+        Value::String(_) => "string",
+        Value::Number(_) => "number",
+        Value::Bool(_) => "boolean",
+        Value::Null => "null",
+    }
+}
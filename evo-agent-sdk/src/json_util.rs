@@ -0,0 +1,105 @@
+//! Lenient JSON extraction from LLM replies.
+//!
+//! Models often wrap the JSON they were asked for in ```` ```json ```` fences
+//! or a sentence of prose. [`parse_llm_json`] tolerates both, so handlers
+//! keep their structured output instead of falling back to `raw_response`.
+
+use serde_json::Value;
+
+/// Parse the JSON in an LLM reply: the whole text, else the contents of
+/// its first code fence, else the first balanced `{...}` or `[...]` that
+/// parses. `None` when there is no JSON to be found.
+pub fn parse_llm_json(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(value) = serde_json::from_str(text) {
+        return Some(value);
+    }
+    if let Some(fenced) = fenced_block(text)
+        && let Ok(value) = serde_json::from_str(fenced.trim())
+    {
+        return Some(value);
+    }
+
+    let mut rest = text;
+    while let Some(start) = rest.find(['{', '[']) {
+        let candidate = &rest[start..];
+        if let Some(end) = balanced_end(candidate)
+            && let Ok(value) = serde_json::from_str(&candidate[..end])
+        {
+            return Some(value);
+        }
+        rest = &candidate[1..];
+    }
+    None
+}
+
+/// Body of the first ```` ``` ```` fence, without its language tag. An
+/// unterminated fence runs to the end of the text.
+fn fenced_block(text: &str) -> Option<&str> {
+    let open = text.find("```")?;
+    let after = &text[open + 3..];
+    // Skip the info string (`json`, `JSON`, ...) on the opening line
+    let body = &after[after.find('\n').map_or(after.len(), |i| i + 1)..];
+    Some(body.find("```").map_or(body, |close| &body[..close]))
+}
+
+/// Byte length of the bracketed value `text` starts with, honoring strings
+/// and escapes; `None` when it never closes.
+fn balanced_end(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fenced_and_prose_wrapped_json_is_recovered() {
+        assert_eq!(parse_llm_json(r#"{"a": 1}"#), Some(json!({ "a": 1 })));
+        assert_eq!(
+            parse_llm_json("```json\n{\"score\": 0.8}\n```"),
+            Some(json!({ "score": 0.8 }))
+        );
+        assert_eq!(
+            parse_llm_json("Here are the candidates:\n```\n[1, 2]\n```\nLet me know!"),
+            Some(json!([1, 2]))
+        );
+        assert_eq!(
+            parse_llm_json(r#"Sure! {"note": "braces } in [strings]", "n": {"x": 2}} Done."#),
+            Some(json!({ "note": "braces } in [strings]", "n": { "x": 2 } }))
+        );
+        // A bracket in prose before the real object is skipped
+        assert_eq!(
+            parse_llm_json(r#"Result [see below]: {"ok": true}"#),
+            Some(json!({ "ok": true }))
+        );
+        assert_eq!(parse_llm_json("no json here"), None);
+        assert_eq!(parse_llm_json("{\"cut\": "), None);
+    }
+}
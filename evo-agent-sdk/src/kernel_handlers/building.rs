@@ -1,11 +1,33 @@
 use async_trait::async_trait;
+use evo_common::skill::SkillManifest;
 use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::sync::LazyLock;
 use tracing::{info, warn};
 
+use crate::gateway_client::{CompletionOptions, ResponseFormat};
 use crate::handler::{AgentHandler, PipelineContext};
+use crate::json_schema::OutputSchema;
+use crate::prompt_dump;
 use crate::self_upgrade;
 
-const DEFAULT_MODEL: &str = "gpt-4o-mini";
+/// Stream manifest generation as `pipeline:progress` events (`1` to enable).
+const STREAM_ENV: &str = "BUILD_STREAM_MANIFEST";
+
+/// The manifest/config wrapper a skill build replies with.
+static BUILD_SCHEMA: LazyLock<OutputSchema> = LazyLock::new(|| {
+    OutputSchema::new(
+        "skill_package",
+        json!({
+            "type": "object",
+            "properties": {
+                "manifest_toml": { "type": "string" },
+                "config_toml": { "type": "string" },
+            },
+            "required": ["manifest_toml"],
+        }),
+    )
+});
 
 /// Default handler for the **Building** kernel agent.
 ///
@@ -18,18 +40,27 @@ pub struct BuildingHandler;
 
 #[async_trait]
 impl AgentHandler for BuildingHandler {
+    fn validate_pipeline(&self, ctx: &PipelineContext<'_>) -> anyhow::Result<()> {
+        if self_upgrade::is_self_upgrade(&ctx.metadata) {
+            self_upgrade::validate_upgrade_metadata(&ctx.metadata, &ctx.artifact_id)?;
+        }
+        Ok(())
+    }
+
     async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> anyhow::Result<Value> {
         if self_upgrade::is_self_upgrade(&ctx.metadata) {
             return self.build_upgrade(&ctx).await;
         }
 
-        self.build_skill(&ctx).await
+        let stream = crate::config::env_flag(STREAM_ENV);
+        self.build_skill(&ctx, stream).await
     }
 }
 
 impl BuildingHandler {
-    /// Original skill packaging via LLM.
-    async fn build_skill(&self, ctx: &PipelineContext<'_>) -> anyhow::Result<Value> {
+    /// Original skill packaging via LLM. With `stream`, the response is
+    /// forwarded to king as it is generated.
+    async fn build_skill(&self, ctx: &PipelineContext<'_>, stream: bool) -> anyhow::Result<Value> {
         info!(artifact_id = %ctx.artifact_id, "building agent: packaging skill");
 
         let prompt = format!(
@@ -45,24 +76,67 @@ impl BuildingHandler {
             serde_json::to_string_pretty(&ctx.metadata).unwrap_or_default()
         );
 
-        let response = ctx
-            .gateway
-            .chat_completion(
-                DEFAULT_MODEL,
-                &ctx.soul.behavior,
-                &prompt,
-                Some(0.3),
-                Some(2048),
-            )
-            .await?;
+        prompt_dump::dump(
+            &ctx.run_id,
+            &ctx.stage,
+            ctx.soul.default_model(),
+            &ctx.soul.behavior,
+            &prompt,
+        );
+
+        // Schema (or JSON) mode, so the manifest and config come back parseable
+        let opts = CompletionOptions::new(Some(0.3), Some(2048))
+            .with_response_format(ResponseFormat::JsonSchema(&BUILD_SCHEMA));
+        let response = if stream {
+            let progress = ctx.progress();
+            let result = ctx
+                .gateway
+                .chat_completion_streaming_with_options(
+                    ctx.soul.default_model(),
+                    &ctx.soul.behavior,
+                    &prompt,
+                    opts,
+                    |delta, chunk_index| progress.report(delta, chunk_index),
+                )
+                .await;
+            progress.finish().await;
+            result?.content
+        } else {
+            ctx.gateway
+                .chat_completion_with_options(
+                    ctx.soul.default_model(),
+                    &ctx.soul.behavior,
+                    &prompt,
+                    opts,
+                )
+                .await?
+                .content
+        };
 
-        let build_output = serde_json::from_str::<Value>(&response)
-            .unwrap_or_else(|_| json!({ "raw_response": response }));
+        let mut build_output =
+            super::conform_to_schema(ctx, &prompt, response, &BUILD_SCHEMA, opts).await;
 
         // Validate manifest if present
-        if let Some(manifest_str) = build_output["manifest_toml"].as_str() {
-            match toml::from_str::<evo_common::skill::SkillManifest>(manifest_str) {
-                Ok(manifest) => {
+        if let Some(manifest_str) = build_output["manifest_toml"].as_str().map(String::from) {
+            match toml::from_str::<SkillManifest>(&manifest_str) {
+                Ok(mut manifest) => {
+                    let declared = manifest.capabilities.len();
+                    let warnings = check_capabilities(&mut manifest);
+                    if manifest.capabilities.len() < declared {
+                        // Ship the deduplicated list, not the colliding one
+                        match replace_capabilities(&manifest_str, &manifest.capabilities) {
+                            Ok(fixed) => build_output["manifest_toml"] = json!(fixed),
+                            Err(e) => {
+                                warn!(err = %e, "failed to rewrite deduplicated manifest")
+                            }
+                        }
+                    }
+                    for warning in &warnings {
+                        ctx.warn(format!("manifest {}: {warning}", manifest.name));
+                    }
+                    if !warnings.is_empty() {
+                        build_output["manifest_warnings"] = json!(warnings);
+                    }
                     info!(
                         skill = %manifest.name,
                         capabilities = ?manifest.capabilities,
@@ -95,7 +169,14 @@ impl BuildingHandler {
             "building agent: self-upgrade build"
         );
 
-        let result = self_upgrade::build_and_release(component, new_version).await?;
+        let force_rebuild = ctx.metadata["force_rebuild"].as_bool().unwrap_or(false);
+        let result = self_upgrade::build_and_release_with(
+            component,
+            new_version,
+            &ctx.cancel,
+            force_rebuild,
+        )
+        .await?;
 
         info!(
             component,
@@ -115,3 +196,157 @@ impl BuildingHandler {
         }))
     }
 }
+
+/// Semantic checks on a generated manifest's `capabilities`: duplicates are
+/// removed in place (first occurrence kept); an empty list and names that
+/// are not kebab-case are reported. Returns one message per problem.
+fn check_capabilities(manifest: &mut SkillManifest) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut seen = HashSet::new();
+    manifest.capabilities.retain(|capability| {
+        let first = seen.insert(capability.clone());
+        if !first {
+            warnings.push(format!("duplicate capability '{capability}' removed"));
+        }
+        first
+    });
+    if manifest.capabilities.is_empty() {
+        warnings.push("manifest declares no capabilities".to_string());
+    }
+    for capability in &manifest.capabilities {
+        if !is_kebab_case(capability) {
+            warnings.push(format!("capability '{capability}' is not kebab-case"));
+        }
+    }
+    warnings
+}
+
+/// Lowercase ASCII words of letters and digits joined by single hyphens.
+fn is_kebab_case(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('-').all(|word| {
+            !word.is_empty()
+                && word
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+}
+
+/// `manifest_toml` with only its `capabilities` array replaced, so keys
+/// [`SkillManifest`] doesn't model (e.g. `advertise`) survive.
+fn replace_capabilities(manifest_toml: &str, capabilities: &[String]) -> anyhow::Result<String> {
+    let mut table: toml::Table = toml::from_str(manifest_toml)?;
+    table.insert(
+        "capabilities".to_string(),
+        toml::Value::Array(
+            capabilities
+                .iter()
+                .map(|c| toml::Value::String(c.clone()))
+                .collect(),
+        ),
+    );
+    Ok(toml::to_string(&table)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway_client::GatewayClient;
+    use crate::handler::PIPELINE_PROGRESS;
+    use crate::safe_mode::{self, SafeModeRefused};
+    use crate::test_support::{
+        MockResponse, MockServer, RecordingEmitter, llm_reply, pipeline_ctx, soul,
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn streamed_build_emits_progress_and_parses_full_response() {
+        let server = MockServer::start(vec![MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"content":"{\"manifest_toml\": "}}]}"#,
+            r#"{"choices":[{"delta":{"content":"\"name = 'x'\"}"}}]}"#,
+            "[DONE]",
+        ])])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let soul = soul("building");
+        let emitter = Arc::new(RecordingEmitter::default());
+        let mut ctx = pipeline_ctx(&soul, &gateway, json!({ "name": "x" }));
+        ctx.emitter = emitter.clone();
+
+        let out = BuildingHandler.build_skill(&ctx, true).await.unwrap();
+
+        assert_eq!(out["build_output"]["manifest_toml"], "name = 'x'");
+        let progress: Vec<_> = emitter
+            .events()
+            .into_iter()
+            .filter(|(event, _)| event == PIPELINE_PROGRESS)
+            .map(|(_, payload)| payload)
+            .collect();
+        assert_eq!(progress.len(), 2);
+        assert_eq!(progress[0]["run_id"], "run-test");
+        assert_eq!(progress[0]["stage"], "building");
+        assert_eq!(progress[1]["chunk_index"], 1);
+        assert_eq!(server.requests()[0].json()["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn empty_or_duplicate_capabilities_are_flagged() {
+        let reply = |capabilities: &str| {
+            let manifest = format!(
+                "name = \"weather\"\nversion = \"0.1.0\"\ndescription = \"d\"\n\
+                 capabilities = {capabilities}\ninputs = []\noutputs = []\nadvertise = false\n"
+            );
+            llm_reply(json!({ "manifest_toml": manifest, "config_toml": "" }))
+        };
+        let server = MockServer::start(vec![
+            reply("[]"),
+            reply(r#"["get-forecast", "get-forecast", "Get_Alerts"]"#),
+        ])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let soul = soul("building");
+        let ctx = pipeline_ctx(&soul, &gateway, json!({ "name": "weather" }));
+
+        let empty = BuildingHandler.build_skill(&ctx, false).await.unwrap();
+        assert_eq!(
+            empty["build_output"]["manifest_warnings"],
+            json!(["manifest declares no capabilities"])
+        );
+
+        let dupes = BuildingHandler.build_skill(&ctx, false).await.unwrap();
+        assert_eq!(
+            dupes["build_output"]["manifest_warnings"],
+            json!([
+                "duplicate capability 'get-forecast' removed",
+                "capability 'Get_Alerts' is not kebab-case",
+            ])
+        );
+        let fixed: SkillManifest =
+            toml::from_str(dupes["build_output"]["manifest_toml"].as_str().unwrap()).unwrap();
+        assert_eq!(fixed.capabilities, vec!["get-forecast", "Get_Alerts"]);
+        // Keys outside the shared manifest schema are kept
+        let raw: toml::Table =
+            toml::from_str(dupes["build_output"]["manifest_toml"].as_str().unwrap()).unwrap();
+        assert_eq!(raw["advertise"], toml::Value::Boolean(false));
+    }
+
+    #[tokio::test]
+    async fn self_upgrade_build_is_refused_in_safe_mode() {
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let soul = soul("building");
+        let ctx = pipeline_ctx(
+            &soul,
+            &gateway,
+            json!({ "build_type": "self_upgrade", "component": "king", "new_version": "v9.9.9" }),
+        );
+
+        let err = safe_mode::scope(true, BuildingHandler.on_pipeline(ctx))
+            .await
+            .unwrap_err();
+
+        let refused = err
+            .downcast_ref::<SafeModeRefused>()
+            .expect("SafeModeRefused");
+        assert_eq!(refused.action, "self-upgrade build");
+    }
+}
@@ -1,17 +1,63 @@
 use async_trait::async_trait;
 use serde_json::{Value, json};
+use std::sync::LazyLock;
 use tracing::info;
 
+use crate::gateway_client::{CompletionOptions, ResponseFormat};
 use crate::handler::{AgentHandler, PipelineContext, TaskEvaluateContext};
+use crate::json_schema::OutputSchema;
+use crate::json_util;
+use crate::prompt_dump;
 use crate::self_upgrade;
 
-const DEFAULT_MODEL: &str = "gpt-4o-mini";
+/// Times to score a skill; the scores are averaged and their spread lowers
+/// the reported confidence. Default `1`.
+const SAMPLES_ENV: &str = "EVALUATION_SAMPLES";
+
+/// Stream `task:evaluate` scoring as `task:progress` events (`1` to enable).
+const STREAM_ENV: &str = "EVALUATION_STREAM";
+
+/// Shape of one skill score: the four dimensions, the weighted overall
+/// score and the recommendation with its follow-up subtasks.
+static EVALUATION_SCHEMA: LazyLock<OutputSchema> = LazyLock::new(|| {
+    let score = json!({ "type": "number", "minimum": 0.0, "maximum": 1.0 });
+    OutputSchema::new(
+        "skill_evaluation",
+        json!({
+            "type": "object",
+            "properties": {
+                "utility": score,
+                "reliability": score,
+                "novelty": score,
+                "integration": score,
+                "overall_score": score,
+                "confidence": score,
+                "recommendation": { "type": "string", "enum": ["activate", "hold", "discard"] },
+                "reasoning": { "type": "string" },
+                "subtasks": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "task_type": { "type": "string" },
+                            "summary": { "type": "string" },
+                            "payload": { "type": "object" },
+                        },
+                        "required": ["task_type", "summary"],
+                    },
+                },
+            },
+            "required": ["overall_score"],
+        }),
+    )
+});
 
 /// Default handler for the **Evaluation** kernel agent.
 ///
 /// Two modes:
 /// - **Skill evaluation** (default): Scores and benchmarks a skill across
-///   multiple dimensions using the LLM.
+///   multiple dimensions using the LLM. When metadata carries a
+///   `candidates` array, each candidate is scored and the results ranked.
 /// - **Self-upgrade evaluation** (`build_type: "self_upgrade"`): Compares
 ///   new version vs current, verifies all pre-load checks passed, and
 ///   produces a pass/fail verdict.
@@ -24,10 +70,30 @@ impl AgentHandler for EvaluationHandler {
             return self.evaluate_upgrade(&ctx).await;
         }
 
-        self.evaluate_skill(&ctx).await
+        if let Some(candidates) = ctx.metadata["candidates"].as_array()
+            && !candidates.is_empty()
+        {
+            return self.evaluate_candidates(&ctx, candidates).await;
+        }
+
+        let samples = crate::config::env_parse::<usize>(SAMPLES_ENV).unwrap_or(1);
+        self.evaluate_skill(&ctx, samples).await
     }
 
     async fn on_task_evaluate(&self, ctx: TaskEvaluateContext<'_>) -> anyhow::Result<Value> {
+        let stream = crate::config::env_flag(STREAM_ENV);
+        self.evaluate_task(&ctx, stream).await
+    }
+}
+
+impl EvaluationHandler {
+    /// Summarize and score a finished task's output. With `stream`, the
+    /// scoring completion is forwarded to king as it is generated.
+    async fn evaluate_task(
+        &self,
+        ctx: &TaskEvaluateContext<'_>,
+        stream: bool,
+    ) -> anyhow::Result<Value> {
         // Skip pipeline tasks — those are handled by on_pipeline
         if ctx.task_type == "pipeline" {
             return Ok(Value::Null);
@@ -58,19 +124,43 @@ impl AgentHandler for EvaluationHandler {
             output = &ctx.output_summary[..ctx.output_summary.len().min(4000)],
         );
 
-        let response = ctx
-            .gateway
-            .chat_completion(
-                DEFAULT_MODEL,
-                &ctx.soul.behavior,
-                &prompt,
-                Some(0.3),
-                Some(512),
-            )
-            .await?;
+        prompt_dump::dump(
+            &ctx.task_id,
+            "task-evaluate",
+            ctx.soul.default_model(),
+            &ctx.soul.behavior,
+            &prompt,
+        );
 
-        let evaluation = serde_json::from_str::<Value>(&response)
-            .unwrap_or_else(|_| json!({ "summary": response, "score": 0.5, "tags": [] }));
+        let response = if stream {
+            let progress = ctx.progress();
+            let result = ctx
+                .gateway
+                .chat_completion_streaming(
+                    ctx.soul.default_model(),
+                    &ctx.soul.behavior,
+                    &prompt,
+                    Some(0.3),
+                    Some(512),
+                    |delta, chunk_index| progress.report(delta, chunk_index),
+                )
+                .await;
+            progress.finish().await;
+            result?
+        } else {
+            ctx.gateway
+                .chat_completion(
+                    ctx.soul.default_model(),
+                    &ctx.soul.behavior,
+                    &prompt,
+                    Some(0.3),
+                    Some(512),
+                )
+                .await?
+        };
+
+        let evaluation = json_util::parse_llm_json(&response)
+            .unwrap_or_else(|| json!({ "summary": response, "score": 0.5, "tags": [] }));
 
         Ok(json!({
             "summary": evaluation["summary"].as_str().unwrap_or("Task completed"),
@@ -79,13 +169,140 @@ impl AgentHandler for EvaluationHandler {
             "evaluation": evaluation,
         }))
     }
-}
 
-impl EvaluationHandler {
-    /// Original LLM-based skill evaluation.
-    async fn evaluate_skill(&self, ctx: &PipelineContext<'_>) -> anyhow::Result<Value> {
-        info!(artifact_id = %ctx.artifact_id, "evaluation agent: scoring skill");
+    /// Original LLM-based skill evaluation, scored `samples` times.
+    ///
+    /// `overall_score` is the mean of the samples and `confidence` the mean
+    /// self-reported confidence (`1.0` when the LLM gives none) minus the
+    /// spread between the highest and lowest score. The sample closest to
+    /// the mean supplies the recommendation and subtasks.
+    async fn evaluate_skill(
+        &self,
+        ctx: &PipelineContext<'_>,
+        samples: usize,
+    ) -> anyhow::Result<Value> {
+        info!(artifact_id = %ctx.artifact_id, samples, "evaluation agent: scoring skill");
+
+        let mut evaluations = Vec::with_capacity(samples.max(1));
+        for _ in 0..samples.max(1) {
+            evaluations.push(self.score(ctx, &ctx.metadata, &ctx.stage).await?);
+        }
+        let scores: Vec<f64> = evaluations
+            .iter()
+            .map(|e| e["overall_score"].as_f64().unwrap_or(0.0))
+            .collect();
+        let overall_score = scores.iter().sum::<f64>() / scores.len() as f64;
+        let spread = scores.iter().cloned().fold(f64::MIN, f64::max)
+            - scores.iter().cloned().fold(f64::MAX, f64::min);
+        let reported: Vec<f64> = evaluations
+            .iter()
+            .filter_map(|e| e["confidence"].as_f64())
+            .collect();
+        let reported = if reported.is_empty() {
+            1.0
+        } else {
+            reported.iter().sum::<f64>() / reported.len() as f64
+        };
+        let confidence = (reported - spread).clamp(0.0, 1.0);
+
+        let closest = scores
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                (*a - overall_score)
+                    .abs()
+                    .total_cmp(&(*b - overall_score).abs())
+            })
+            .map_or(0, |(i, _)| i);
+        let evaluation = evaluations.swap_remove(closest);
+        let recommendation = evaluation["recommendation"]
+            .as_str()
+            .unwrap_or("hold")
+            .to_string();
+
+        info!(
+            artifact_id = %ctx.artifact_id,
+            overall_score = %overall_score,
+            confidence = %confidence,
+            recommendation = %recommendation,
+            "evaluation complete"
+        );
+
+        let subtasks = evaluation.get("subtasks").cloned().unwrap_or(json!([]));
+
+        Ok(json!({
+            "evaluation": evaluation,
+            "artifact_id": ctx.artifact_id,
+            "overall_score": overall_score,
+            "confidence": confidence,
+            "samples": scores,
+            "recommendation": recommendation,
+            "subtasks": subtasks,
+        }))
+    }
+
+    /// Batch evaluation: score every entry of `candidates` and rank them by
+    /// `overall_score`. Top-level score and recommendation are the best
+    /// candidate's, so downstream stages read the output like a single skill.
+    async fn evaluate_candidates(
+        &self,
+        ctx: &PipelineContext<'_>,
+        candidates: &[Value],
+    ) -> anyhow::Result<Value> {
+        info!(
+            artifact_id = %ctx.artifact_id,
+            candidates = candidates.len(),
+            "evaluation agent: scoring candidate batch"
+        );
+
+        let mut evaluations = Vec::with_capacity(candidates.len());
+        for (index, candidate) in candidates.iter().enumerate() {
+            let stage = format!("{}-{index}", ctx.stage);
+            evaluations.push(self.score(ctx, candidate, &stage).await?);
+        }
+
+        let mut ranking: Vec<Value> = evaluations
+            .iter()
+            .enumerate()
+            .map(|(index, evaluation)| {
+                json!({
+                    "index": index,
+                    "name": candidates[index]["name"],
+                    "overall_score": evaluation["overall_score"].as_f64().unwrap_or(0.0),
+                    "recommendation": evaluation["recommendation"].as_str().unwrap_or("hold"),
+                })
+            })
+            .collect();
+        // Stable sort keeps input order among equal scores
+        ranking.sort_by(|a, b| {
+            let (a, b) = (a["overall_score"].as_f64(), b["overall_score"].as_f64());
+            b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal)
+        });
 
+        let best = &ranking[0];
+        info!(
+            artifact_id = %ctx.artifact_id,
+            best = %best["index"],
+            overall_score = %best["overall_score"],
+            "batch evaluation complete"
+        );
+
+        Ok(json!({
+            "evaluations": evaluations,
+            "ranking": ranking,
+            "artifact_id": ctx.artifact_id,
+            "overall_score": best["overall_score"],
+            "recommendation": best["recommendation"],
+        }))
+    }
+
+    /// Ask the LLM to score one skill description.
+    async fn score(
+        &self,
+        ctx: &PipelineContext<'_>,
+        skill: &Value,
+        stage: &str,
+    ) -> anyhow::Result<Value> {
         let prompt = format!(
             "You are a skill evaluator for an AI self-evolution system.\n\
              Evaluate the following skill:\n\
@@ -98,51 +315,38 @@ impl EvaluationHandler {
              Also provide:\n\
              - overall_score: weighted average (utility=0.4, reliability=0.3, novelty=0.2, integration=0.1)\n\
              - recommendation: 'activate', 'hold', or 'discard'\n\
+             - confidence: 0.0 to 1.0, how sure you are of overall_score\n\
              - reasoning: brief explanation\n\
              - subtasks: an array of follow-up work items if recommendation is 'activate'.\n\
                Each subtask should have: task_type (string), summary (string), payload (object with relevant details).\n\
                Examples: integration testing, documentation, dependency check, configuration setup.\n\
                Return an empty array if no follow-up work is needed.\n\n\
              Respond with valid JSON.",
-            serde_json::to_string_pretty(&ctx.metadata).unwrap_or_default()
+            serde_json::to_string_pretty(skill).unwrap_or_default()
         );
 
+        prompt_dump::dump(
+            &ctx.run_id,
+            stage,
+            ctx.soul.default_model(),
+            &ctx.soul.behavior,
+            &prompt,
+        );
+
+        let opts = CompletionOptions::new(Some(0.3), Some(1024))
+            .with_response_format(ResponseFormat::JsonSchema(&EVALUATION_SCHEMA));
         let response = ctx
             .gateway
-            .chat_completion(
-                DEFAULT_MODEL,
+            .chat_completion_with_options(
+                ctx.soul.default_model(),
                 &ctx.soul.behavior,
                 &prompt,
-                Some(0.3),
-                Some(1024),
+                opts,
             )
-            .await?;
-
-        let evaluation = serde_json::from_str::<Value>(&response)
-            .unwrap_or_else(|_| json!({ "raw_response": response }));
-
-        let overall_score = evaluation["overall_score"].as_f64().unwrap_or(0.0);
-        let recommendation = evaluation["recommendation"]
-            .as_str()
-            .unwrap_or("hold")
-            .to_string();
-
-        info!(
-            artifact_id = %ctx.artifact_id,
-            overall_score = %overall_score,
-            recommendation = %recommendation,
-            "evaluation complete"
-        );
-
-        let subtasks = evaluation.get("subtasks").cloned().unwrap_or(json!([]));
+            .await?
+            .content;
 
-        Ok(json!({
-            "evaluation": evaluation,
-            "artifact_id": ctx.artifact_id,
-            "overall_score": overall_score,
-            "recommendation": recommendation,
-            "subtasks": subtasks,
-        }))
+        Ok(super::conform_to_schema(ctx, &prompt, response, &EVALUATION_SCHEMA, opts).await)
     }
 
     /// Self-upgrade: evaluate the new release against current version.
@@ -201,3 +405,180 @@ impl EvaluationHandler {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway_client::GatewayClient;
+    use crate::handler::TASK_PROGRESS;
+    use crate::test_support::{
+        MockResponse, MockServer, RecordingEmitter, llm_reply, pipeline_ctx, soul,
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn streamed_task_evaluation_forwards_progress() {
+        let server = MockServer::start(vec![MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"content":"{\"summary\": \"ran fine\", "}}]}"#,
+            r#"{"choices":[{"delta":{"content":"\"score\": 0.9, \"tags\": [\"ok\"]}"}}]}"#,
+            "[DONE]",
+        ])])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let soul = soul("evaluation");
+        let emitter = RecordingEmitter::default();
+        let ctx = TaskEvaluateContext {
+            soul: &soul,
+            gateway: &gateway,
+            task_id: "task-1".to_string(),
+            task_type: "shell".to_string(),
+            output_summary: "done".to_string(),
+            exit_code: Some(0),
+            latency_ms: None,
+            metadata: Value::Null,
+            emitter: Arc::new(emitter.clone()),
+        };
+
+        let output = EvaluationHandler.evaluate_task(&ctx, true).await.unwrap();
+        assert_eq!(output["summary"], "ran fine");
+        assert_eq!(output["score"], 0.9);
+
+        let progress = emitter.events();
+        assert_eq!(progress.len(), 2);
+        assert!(progress.iter().all(|(event, _)| event == TASK_PROGRESS));
+        assert_eq!(progress[0].1["task_id"], "task-1");
+        assert_eq!(progress[1].1["chunk_index"], 1);
+    }
+
+    #[tokio::test]
+    async fn evaluation_uses_the_soul_model() {
+        let server = MockServer::start(vec![
+            llm_reply(json!({ "overall_score": 0.5 })),
+            llm_reply(json!({ "overall_score": 0.5 })),
+        ])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let mut soul = soul("evaluation");
+        let candidate = json!({ "candidates": [{ "name": "weather" }] });
+
+        EvaluationHandler
+            .on_pipeline(pipeline_ctx(&soul, &gateway, candidate.clone()))
+            .await
+            .unwrap();
+        soul.model = Some("gpt-4o".to_string());
+        EvaluationHandler
+            .on_pipeline(pipeline_ctx(&soul, &gateway, candidate))
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].json()["model"], "gpt-4o-mini");
+        assert_eq!(requests[1].json()["model"], "gpt-4o");
+    }
+
+    #[tokio::test]
+    async fn schema_mismatch_is_repaired_and_conforming_reply_is_kept() {
+        let server = MockServer::start(vec![
+            llm_reply(json!({ "overall_score": 0.8, "recommendation": "activate" })),
+            llm_reply(json!({ "overall_score": "high", "recommendation": "ship it" })),
+            llm_reply(json!({ "overall_score": 0.6, "recommendation": "hold" })),
+            llm_reply(json!({ "overall_score": 0.7 })),
+        ])
+        .await;
+        let schema_gateway = Arc::new(
+            GatewayClient::new(&server.url)
+                .unwrap()
+                .with_json_schema(true),
+        );
+        let soul = soul("evaluation");
+        let ctx = pipeline_ctx(&soul, &schema_gateway, json!({ "name": "weather" }));
+
+        let conforming = EvaluationHandler
+            .score(&ctx, &json!({}), "evaluation")
+            .await;
+        assert_eq!(conforming.unwrap()["overall_score"], 0.8);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let format = &requests[0].json()["response_format"];
+        assert_eq!(format["type"], "json_schema");
+        assert_eq!(format["json_schema"]["name"], "skill_evaluation");
+
+        let repaired = EvaluationHandler
+            .score(&ctx, &json!({}), "evaluation")
+            .await;
+        let repaired = repaired.unwrap();
+        assert_eq!(repaired["overall_score"], 0.6);
+        assert!(repaired.get("schema_errors").is_none());
+        let warnings = ctx.warnings.snapshot();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("needed a repair"), "{warnings:?}");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        let repair_prompt = requests[2].json()["messages"][1]["content"].to_string();
+        assert!(repair_prompt.contains("$.overall_score: expected number, got string"));
+        assert!(repair_prompt.contains("$.recommendation: must be one of"));
+
+        // Without schema support the same request falls back to JSON mode
+        let plain_gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let ctx = pipeline_ctx(&soul, &plain_gateway, json!({ "name": "weather" }));
+        EvaluationHandler
+            .score(&ctx, &json!({}), "evaluation")
+            .await
+            .unwrap();
+        assert_eq!(
+            server.requests()[3].json()["response_format"],
+            json!({ "type": "json_object" })
+        );
+    }
+
+    #[tokio::test]
+    async fn candidates_are_scored_and_ranked() {
+        let server = MockServer::start(vec![
+            llm_reply(json!({ "overall_score": 0.4, "recommendation": "hold" })),
+            llm_reply(json!({ "overall_score": 0.9, "recommendation": "activate" })),
+        ])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let soul = soul("evaluation");
+        let ctx = pipeline_ctx(
+            &soul,
+            &gateway,
+            json!({ "candidates": [{ "name": "weather" }, { "name": "search" }] }),
+        );
+
+        let out = EvaluationHandler.on_pipeline(ctx).await.unwrap();
+
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(out["evaluations"].as_array().unwrap().len(), 2);
+        assert_eq!(out["ranking"][0]["name"], "search");
+        assert_eq!(out["ranking"][0]["index"], 1);
+        assert_eq!(out["ranking"][1]["name"], "weather");
+        assert_eq!(out["overall_score"], 0.9);
+        assert_eq!(out["recommendation"], "activate");
+    }
+
+    #[tokio::test]
+    async fn repeated_samples_are_averaged_and_spread_lowers_confidence() {
+        let server = MockServer::start(vec![
+            llm_reply(
+                json!({ "overall_score": 0.9, "confidence": 0.9, "recommendation": "activate" }),
+            ),
+            llm_reply(json!({ "overall_score": 0.5, "confidence": 0.9, "recommendation": "hold" })),
+            llm_reply(
+                json!({ "overall_score": 0.7, "confidence": 0.9, "recommendation": "activate" }),
+            ),
+        ])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let soul = soul("evaluation");
+        let ctx = pipeline_ctx(&soul, &gateway, json!({ "name": "weather" }));
+
+        let out = EvaluationHandler.evaluate_skill(&ctx, 3).await.unwrap();
+
+        assert_eq!(server.requests().len(), 3);
+        assert!((out["overall_score"].as_f64().unwrap() - 0.7).abs() < 1e-9);
+        assert!((out["confidence"].as_f64().unwrap() - 0.5).abs() < 1e-9);
+        assert_eq!(out["samples"], json!([0.9, 0.5, 0.7]));
+        assert_eq!(out["recommendation"], "activate");
+    }
+}
@@ -1,27 +1,70 @@
 use async_trait::async_trait;
 use serde_json::{Value, json};
-use tracing::info;
+use std::sync::OnceLock;
+use tracing::{info, warn};
 
 use crate::handler::{AgentHandler, PipelineContext};
-
-const DEFAULT_MODEL: &str = "gpt-4o-mini";
+use crate::json_util;
+use crate::prompt_dump;
+use crate::registry::{RegistryClient, SkillCandidate};
 
 /// Default handler for the **Learning** kernel agent.
 ///
-/// Discovers potential new skills by querying the LLM via the gateway.
+/// Discovers potential new skills by querying the LLM via the gateway. With
+/// `SKILL_REGISTRY_URL` set, the registry is searched first: its hits are
+/// offered to the LLM and merged into the candidates.
 pub struct LearningHandler;
 
+/// Built on first use and shared by every stage.
+static REGISTRY: OnceLock<Option<RegistryClient>> = OnceLock::new();
+
 #[async_trait]
 impl AgentHandler for LearningHandler {
     async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> anyhow::Result<Value> {
+        let registry = REGISTRY.get_or_init(RegistryClient::from_env);
+        self.discover(&ctx, registry.as_ref()).await
+    }
+}
+
+impl LearningHandler {
+    async fn discover(
+        &self,
+        ctx: &PipelineContext<'_>,
+        registry: Option<&RegistryClient>,
+    ) -> anyhow::Result<Value> {
         info!("learning agent: starting skill discovery");
 
         let existing_skills: Vec<&str> = ctx.skills.iter().map(|s| s.name.as_str()).collect();
 
+        // The registry is a hint, never a reason to fail discovery
+        let query = ctx.metadata["query"]
+            .as_str()
+            .or_else(|| ctx.metadata["topic"].as_str())
+            .unwrap_or_default()
+            .trim();
+        let registry_hits = match registry {
+            // Nothing to search for without a query or topic
+            Some(_) if query.is_empty() => Vec::new(),
+            Some(registry) => registry.search(query).await.unwrap_or_else(|e| {
+                warn!(err = format!("{e:#}"), "skill registry search failed");
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let registry_section = if registry_hits.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "Skills available in the skill registry (prefer these when they fit):\n{}\n\n",
+                serde_json::to_string_pretty(&registry_hits).unwrap_or_default()
+            )
+        };
+
         let prompt = format!(
             "You are a skill discovery agent for an AI self-evolution system.\n\
              Existing skills: {:?}\n\
              Trigger metadata: {}\n\n\
+             {registry_section}\
              Identify 1-3 potential new skills that would complement the existing set.\n\
              For each candidate, provide:\n\
              - name: a short kebab-case identifier\n\
@@ -33,10 +76,18 @@ impl AgentHandler for LearningHandler {
             serde_json::to_string_pretty(&ctx.metadata).unwrap_or_default()
         );
 
+        prompt_dump::dump(
+            &ctx.run_id,
+            &ctx.stage,
+            ctx.soul.default_model(),
+            &ctx.soul.behavior,
+            &prompt,
+        );
+
         let response = ctx
             .gateway
             .chat_completion(
-                DEFAULT_MODEL,
+                ctx.soul.default_model(),
                 &ctx.soul.behavior,
                 &prompt,
                 Some(0.7),
@@ -45,17 +96,114 @@ impl AgentHandler for LearningHandler {
             .await?;
 
         // Try to parse as JSON, fall back to wrapping in object
-        let candidates = serde_json::from_str::<Value>(&response)
-            .unwrap_or_else(|_| json!({ "raw_response": response }));
+        let mut candidates = json_util::parse_llm_json(&response)
+            .unwrap_or_else(|| json!({ "raw_response": response }));
+        if let Some(list) = candidates.as_array_mut() {
+            merge_registry_hits(list, &registry_hits, &existing_skills);
+        }
 
         info!(
             candidates = %candidates,
             "learning agent: discovery complete"
         );
 
-        Ok(json!({
+        let mut output = json!({
             "candidates": candidates,
             "existing_skills": existing_skills,
-        }))
+        });
+        if registry.is_some() {
+            output["registry_candidates"] = json!(registry_hits);
+        }
+        Ok(output)
+    }
+}
+
+/// Append registry hits the LLM didn't already name, marked
+/// `origin: "registry"`; existing skills are left out.
+fn merge_registry_hits(candidates: &mut Vec<Value>, hits: &[SkillCandidate], existing: &[&str]) {
+    for hit in hits {
+        if existing.contains(&hit.name.as_str())
+            || candidates.iter().any(|c| c["name"] == hit.name.as_str())
+        {
+            continue;
+        }
+        let mut candidate = json!(hit);
+        candidate["origin"] = json!("registry");
+        candidates.push(candidate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway_client::GatewayClient;
+    use crate::test_support::{MockResponse, MockServer, llm_reply, pipeline_ctx, soul};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn registry_hits_ground_and_join_the_candidates() {
+        let registry_server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({ "skills": [
+                { "name": "weather-api", "description": "Forecasts", "source": "https://reg/weather" },
+                { "name": "geo-lookup", "description": "Geocoding", "source": "https://reg/geo" },
+            ] }),
+        )])
+        .await;
+        let ideas = json!([
+            { "name": "weather-api", "description": "Forecasts", "priority": "high" },
+            { "name": "news-digest", "description": "Headlines", "priority": "low" },
+        ]);
+        let gateway_server = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({ "choices": [{ "message": { "content": ideas.to_string() } }] }),
+        )])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&gateway_server.url).unwrap());
+        let soul = soul("learning");
+        let ctx = pipeline_ctx(&soul, &gateway, json!({ "query": "weather" }));
+        let registry = RegistryClient::new(&registry_server.url).unwrap();
+
+        let out = LearningHandler
+            .discover(&ctx, Some(&registry))
+            .await
+            .unwrap();
+
+        let names: Vec<_> = out["candidates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["weather-api", "news-digest", "geo-lookup"]);
+        assert_eq!(out["candidates"][2]["origin"], "registry");
+        assert_eq!(out["candidates"][2]["source"], "https://reg/geo");
+        assert_eq!(out["registry_candidates"].as_array().unwrap().len(), 2);
+
+        assert!(registry_server.requests()[0].path.contains("q=weather"));
+        let prompt = gateway_server.requests()[0].json()["messages"][1]["content"].to_string();
+        assert!(prompt.contains("https://reg/geo"));
+    }
+
+    #[tokio::test]
+    async fn registry_is_not_searched_without_a_query() {
+        let registry_server =
+            MockServer::start(vec![MockResponse::json(200, &json!({ "skills": [] }))]).await;
+        let gateway_server = MockServer::start(vec![llm_reply(json!([
+            { "name": "news-digest", "description": "Headlines", "priority": "low" },
+        ]))])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&gateway_server.url).unwrap());
+        let soul = soul("learning");
+        let ctx = pipeline_ctx(&soul, &gateway, json!({ "trigger": "schedule" }));
+        let registry = RegistryClient::new(&registry_server.url).unwrap();
+
+        let out = LearningHandler
+            .discover(&ctx, Some(&registry))
+            .await
+            .unwrap();
+
+        assert_eq!(out["candidates"][0]["name"], "news-digest");
+        assert!(registry_server.requests().is_empty());
     }
 }
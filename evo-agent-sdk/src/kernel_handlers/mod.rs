@@ -2,6 +2,106 @@
 //!
 //! Each handler wraps the role-specific logic and implements [`AgentHandler`].
 
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::gateway_client::CompletionOptions;
+use crate::handler::PipelineContext;
+use crate::json_schema::OutputSchema;
+use crate::json_util;
+
+/// Model the kernel handlers request from the gateway.
+pub(crate) const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+/// Whether `role` is served by one of the kernel handlers.
+pub fn is_kernel_role(role: &str) -> bool {
+    matches!(
+        role,
+        "learning"
+            | "building"
+            | "pre-load"
+            | "pre_load"
+            | "evaluation"
+            | "skill-manage"
+            | "skill_manage"
+    )
+}
+
+/// Parse `response` as JSON following `schema`. A reply that isn't JSON or
+/// breaks the schema is sent back once, with the problems listed, for a
+/// non-streamed repair. A reply still wrong after that is returned as is
+/// (or as `raw_response`) with its `schema_errors` attached. Both cases are
+/// reported as stage warnings.
+pub(crate) async fn conform_to_schema(
+    ctx: &PipelineContext<'_>,
+    prompt: &str,
+    response: String,
+    schema: &OutputSchema,
+    opts: CompletionOptions,
+) -> Value {
+    let (parsed, errors) = check_reply(&response, schema);
+    if errors.is_empty() {
+        return parsed;
+    }
+    ctx.warn(format!(
+        "LLM reply did not match the {} schema and needed a repair: {}",
+        schema.name,
+        errors.join("; ")
+    ));
+
+    let repair_prompt = format!(
+        "{prompt}\n\n\
+         Your previous reply was:\n{response}\n\n\
+         It does not match the required JSON schema:\n- {}\n\n\
+         Reply again with only the corrected JSON.",
+        errors.join("\n- ")
+    );
+    let (value, errors) = match ctx
+        .gateway
+        .chat_completion_with_options(
+            ctx.soul.default_model(),
+            &ctx.soul.behavior,
+            &repair_prompt,
+            opts,
+        )
+        .await
+    {
+        Ok(repaired) => check_reply(&repaired.content, schema),
+        Err(e) => {
+            warn!(err = %e, schema = schema.name, "schema repair completion failed");
+            (parsed, errors)
+        }
+    };
+    if errors.is_empty() {
+        return value;
+    }
+    ctx.warn(format!(
+        "LLM reply still does not match the {} schema after a repair",
+        schema.name
+    ));
+    with_schema_errors(value, errors)
+}
+
+fn check_reply(response: &str, schema: &OutputSchema) -> (Value, Vec<String>) {
+    match json_util::parse_llm_json(response) {
+        Some(value) => {
+            let errors = schema.validate(&value);
+            (value, errors)
+        }
+        None => (
+            json!({ "raw_response": response }),
+            vec!["reply is not JSON".to_string()],
+        ),
+    }
+}
+
+fn with_schema_errors(mut value: Value, errors: Vec<String>) -> Value {
+    if let Value::Object(map) = &mut value {
+        map.insert("schema_errors".to_string(), json!(errors));
+    }
+    value
+}
+
 mod building;
 mod evaluation;
 mod learning;
@@ -12,4 +112,4 @@ pub use building::BuildingHandler;
 pub use evaluation::EvaluationHandler;
 pub use learning::LearningHandler;
 pub use pre_load::PreLoadHandler;
-pub use skill_manage::SkillManageHandler;
+pub use skill_manage::{Rollout, RolloutStrategy, SkillManageHandler};
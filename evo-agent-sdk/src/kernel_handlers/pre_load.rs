@@ -1,3 +1,4 @@
+use anyhow::Context;
 use async_trait::async_trait;
 use serde_json::{Value, json};
 use tracing::{info, warn};
@@ -15,6 +16,30 @@ use crate::self_upgrade;
 ///   the release archive, extracts, and validates structure + binary health.
 pub struct PreLoadHandler;
 
+/// An endpoint to probe, with its optional `max_latency_ms` SLA and the
+/// request that checks it (its `health` sub-config, or a GET of `url`).
+struct EndpointCheck {
+    url: String,
+    max_latency_ms: Option<u64>,
+    probe: health_check::HealthProbe,
+}
+
+impl EndpointCheck {
+    /// Read `max_latency_ms` and `health` from an endpoint's raw config.
+    fn new(url: &str, raw: &Value) -> anyhow::Result<Self> {
+        let probe = match raw.get("health").filter(|h| !h.is_null()) {
+            Some(health) => health_check::HealthProbe::from_config(url, health)
+                .with_context(|| format!("invalid health config for {url}"))?,
+            None => health_check::HealthProbe::get(url),
+        };
+        Ok(Self {
+            url: url.to_string(),
+            max_latency_ms: raw["max_latency_ms"].as_u64(),
+            probe,
+        })
+    }
+}
+
 #[async_trait]
 impl AgentHandler for PreLoadHandler {
     async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> anyhow::Result<Value> {
@@ -31,14 +56,25 @@ impl PreLoadHandler {
     async fn check_endpoints(&self, ctx: &PipelineContext<'_>) -> anyhow::Result<Value> {
         info!(artifact_id = %ctx.artifact_id, "pre-load agent: health-checking endpoints");
 
-        // Extract endpoint URLs from build output config
-        let mut urls_to_check = Vec::new();
+        // Extract endpoints (and their optional latency SLA) from build output config
+        let mut checks: Vec<EndpointCheck> = Vec::new();
 
         if let Some(config_str) = ctx.metadata["build_output"]["config_toml"].as_str()
             && let Ok(config) = toml::from_str::<evo_common::skill::SkillConfig>(config_str)
         {
-            for endpoint in &config.endpoints {
-                urls_to_check.push(endpoint.url.clone());
+            let raw: toml::Table = toml::from_str(config_str).unwrap_or_default();
+            let raw_endpoints = raw
+                .get("endpoints")
+                .and_then(|e| e.as_array())
+                .cloned()
+                .unwrap_or_default();
+
+            for (i, endpoint) in config.endpoints.iter().enumerate() {
+                let raw = raw_endpoints
+                    .get(i)
+                    .and_then(|e| serde_json::to_value(e).ok())
+                    .unwrap_or_default();
+                checks.push(EndpointCheck::new(&endpoint.url, &raw)?);
             }
         }
 
@@ -46,12 +82,12 @@ impl PreLoadHandler {
         if let Some(endpoints) = ctx.metadata["endpoints"].as_array() {
             for ep in endpoints {
                 if let Some(url) = ep["url"].as_str() {
-                    urls_to_check.push(url.to_string());
+                    checks.push(EndpointCheck::new(url, ep)?);
                 }
             }
         }
 
-        if urls_to_check.is_empty() {
+        if checks.is_empty() {
             info!("no endpoints to check — passing pre-load");
             return Ok(json!({
                 "health_results": [],
@@ -60,45 +96,184 @@ impl PreLoadHandler {
             }));
         }
 
+        // `strict` fails the stage on any unreachable endpoint; `lenient`
+        // passes it as `degraded`
+        let lenient = match ctx.metadata["preload_policy"]
+            .as_str()
+            .map(|p| p.to_string())
+            .or_else(|| std::env::var("PRELOAD_POLICY").ok())
+            .as_deref()
+        {
+            None | Some("strict") => false,
+            Some("lenient") => true,
+            Some(other) => {
+                warn!(policy = %other, "unknown pre-load policy, using strict");
+                false
+            }
+        };
+
+        // Chronically dead endpoints fail fast instead of being probed again
+        let mut quarantined: Vec<String> = Vec::new();
+        let quarantine = health_check::QuarantinePolicy::from_env();
+        let ledger_path = health_check::EndpointLedger::default_path();
+        let ledger = quarantine
+            .as_ref()
+            .map(|_| health_check::EndpointLedger::load(&ledger_path));
+        if let (Some(policy), Some(ledger)) = (&quarantine, &ledger) {
+            let now = chrono::Utc::now();
+            quarantined = checks
+                .iter()
+                .filter(|c| ledger.decide(&c.url, policy, now) == health_check::ProbeDecision::Skip)
+                .map(|c| c.url.clone())
+                .collect();
+            if !quarantined.is_empty() {
+                warn!(quarantined = ?quarantined, "skipping probe of quarantined endpoints");
+                if !lenient {
+                    return Err(anyhow::anyhow!(
+                        "quarantined endpoints (repeated pre-load failures): {:?}",
+                        quarantined
+                    ));
+                }
+                checks.retain(|c| !quarantined.contains(&c.url));
+            }
+        }
+
         let http_client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(10))
             .build()
             .unwrap_or_default();
 
-        let results = health_check::check_endpoints(&http_client, &urls_to_check).await;
+        let retry = health_check::ProbeRetry::from_env();
+        let mut results = Vec::with_capacity(checks.len());
+        for check in &checks {
+            let mut health =
+                health_check::probe_with_retry(&http_client, &check.probe, &retry).await;
+            info!(
+                url = %check.url,
+                probe_url = %health.url,
+                method = %check.probe.method,
+                reachable = health.reachable,
+                latency_ms = ?health.latency_ms,
+                "endpoint health check"
+            );
+            // Report (and quarantine) by the operational URL
+            health.url = check.url.clone();
+            results.push(health);
+        }
 
         let all_healthy = results.iter().all(|h| h.reachable);
+
+        if let Some(policy) = quarantine {
+            // Re-read under the ledger lock; another stage may have saved since
+            let outcomes: Vec<(String, bool)> = results
+                .iter()
+                .map(|h| (h.url.clone(), h.reachable))
+                .collect();
+            let saved = tokio::task::spawn_blocking(move || {
+                health_check::EndpointLedger::update(&ledger_path, |ledger| {
+                    let now = chrono::Utc::now();
+                    for (url, reachable) in &outcomes {
+                        ledger.record(url, *reachable, &policy, now);
+                    }
+                })
+            })
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|r| r);
+            if let Err(e) = saved {
+                warn!(err = %e, "failed to persist endpoint failure counters");
+            }
+        }
+
+        // Reachable endpoints slower than their declared SLA
+        let sla_violations: Vec<String> = results
+            .iter()
+            .zip(&checks)
+            .filter_map(|(h, c)| match (h.latency_ms, c.max_latency_ms) {
+                (Some(measured), Some(allowed)) if h.reachable && measured > allowed => {
+                    Some(format!("{} ({measured}ms > {allowed}ms allowed)", h.url))
+                }
+                _ => None,
+            })
+            .collect();
+
         let health_json: Vec<Value> = results
             .iter()
-            .map(|h| {
+            .zip(&checks)
+            .map(|(h, c)| {
                 json!({
                     "url": h.url,
+                    "probe_url": c.probe.url,
                     "reachable": h.reachable,
                     "latency_ms": h.latency_ms,
                     "status_code": h.status_code,
+                    "attempts": h.attempts,
+                    "last_error": h.last_error,
+                    "max_latency_ms": c.max_latency_ms,
+                    "sla_met": match (h.latency_ms, c.max_latency_ms) {
+                        (Some(measured), Some(allowed)) => measured <= allowed,
+                        _ => true,
+                    },
                 })
             })
             .collect();
 
-        if !all_healthy {
-            let failed: Vec<&str> = results
-                .iter()
-                .filter(|h| !h.reachable)
-                .map(|h| h.url.as_str())
-                .collect();
-            warn!(failed = ?failed, "some endpoints failed health check");
-            return Err(anyhow::anyhow!(
-                "health check failed for endpoints: {:?}",
-                failed
-            ));
+        let unreachable: Vec<&str> = quarantined
+            .iter()
+            .map(String::as_str)
+            .chain(
+                results
+                    .iter()
+                    .filter(|h| !h.reachable)
+                    .map(|h| h.url.as_str()),
+            )
+            .collect();
+        if !unreachable.is_empty() {
+            warn!(failed = ?unreachable, lenient, "some endpoints failed health check");
+            if !lenient {
+                return Err(anyhow::anyhow!(
+                    "health check failed for endpoints: {:?}",
+                    unreachable
+                ));
+            }
         }
 
-        info!(checked = results.len(), "all endpoints healthy");
+        if !sla_violations.is_empty() {
+            let policy = ctx.metadata["sla_policy"]
+                .as_str()
+                .map(|p| p.to_string())
+                .or_else(|| std::env::var("PRELOAD_SLA_POLICY").ok())
+                .unwrap_or_else(|| "fail".to_string());
 
-        Ok(json!({
+            warn!(violations = ?sla_violations, policy = %policy, "endpoints exceeded latency SLA");
+
+            if policy != "warn" {
+                return Err(anyhow::anyhow!(
+                    "latency SLA exceeded for endpoints: {:?}",
+                    sla_violations
+                ));
+            }
+        }
+
+        if unreachable.is_empty() {
+            info!(checked = results.len(), "all endpoints healthy");
+        }
+
+        let mut output = json!({
             "health_results": health_json,
-            "all_healthy": all_healthy,
-        }))
+            "all_healthy": all_healthy && unreachable.is_empty(),
+            "sla_violations": sla_violations,
+        });
+        if !unreachable.is_empty() {
+            ctx.warn(format!("unreachable endpoints: {}", unreachable.join(", ")));
+            output["degraded"] = json!(true);
+            output["unreachable_endpoints"] = json!(unreachable);
+        }
+        if !quarantined.is_empty() {
+            // Lets king deactivate the skill, not just skip the endpoint
+            output["quarantined_endpoints"] = json!(quarantined);
+        }
+        Ok(output)
     }
 
     /// Self-upgrade: validate the release archive.
@@ -137,6 +312,7 @@ impl PreLoadHandler {
             "component": component,
             "new_version": new_version,
             "validation": {
+                "signature_verified": result.signature_verified,
                 "binary_exists": result.binary_exists,
                 "binary_executable": result.binary_executable,
                 "soul_md_exists": result.soul_md_exists,
@@ -148,3 +324,118 @@ impl PreLoadHandler {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway_client::GatewayClient;
+    use crate::test_support::{self, MockResponse, MockServer};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn slow_endpoint_exceeding_sla_fails_stage() {
+        let server = MockServer::start(vec![
+            MockResponse::new(200, "text/plain", "ok").with_delay(Duration::from_millis(300)),
+        ])
+        .await;
+
+        let soul = test_support::soul("pre-load");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let ctx = test_support::pipeline_ctx(
+            &soul,
+            &gateway,
+            json!({ "endpoints": [{ "url": server.url, "max_latency_ms": 50 }] }),
+        );
+
+        let err = PreLoadHandler.check_endpoints(&ctx).await.unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("latency SLA exceeded"), "{msg}");
+        assert!(msg.contains("50ms allowed"), "{msg}");
+
+        // Warn policy passes but still reports the violation
+        let ctx = test_support::pipeline_ctx(
+            &soul,
+            &gateway,
+            json!({
+                "sla_policy": "warn",
+                "endpoints": [{ "url": server.url, "max_latency_ms": 50 }]
+            }),
+        );
+        let output = PreLoadHandler.check_endpoints(&ctx).await.unwrap();
+        assert_eq!(output["sla_violations"].as_array().unwrap().len(), 1);
+        assert_eq!(output["health_results"][0]["sla_met"], json!(false));
+    }
+
+    #[tokio::test]
+    async fn lenient_policy_passes_degraded_while_strict_fails() {
+        let server = MockServer::start(vec![
+            MockResponse::new(200, "text/plain", "ok"),
+            MockResponse::new(200, "text/plain", "ok"),
+        ])
+        .await;
+        let dead = "http://127.0.0.1:9/api";
+        let endpoints = json!([{ "url": server.url }, { "url": dead }]);
+        let soul = test_support::soul("pre-load");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+
+        let strict = test_support::pipeline_ctx(
+            &soul,
+            &gateway,
+            json!({ "preload_policy": "strict", "endpoints": endpoints }),
+        );
+        let err = PreLoadHandler.check_endpoints(&strict).await.unwrap_err();
+        assert!(err.to_string().contains("health check failed"), "{err}");
+
+        let lenient = test_support::pipeline_ctx(
+            &soul,
+            &gateway,
+            json!({ "preload_policy": "lenient", "endpoints": endpoints }),
+        );
+        let output = PreLoadHandler.check_endpoints(&lenient).await.unwrap();
+        assert_eq!(output["degraded"], true);
+        assert_eq!(output["all_healthy"], false);
+        assert_eq!(output["unreachable_endpoints"], json!([dead]));
+        assert_eq!(output["health_results"][0]["reachable"], true);
+    }
+
+    #[tokio::test]
+    async fn health_sub_config_probes_post_route_with_body() {
+        let server = MockServer::start(vec![MockResponse::json(200, &json!({ "ok": true }))]).await;
+        let config_toml = format!(
+            "[[endpoints]]\nname = \"search\"\nurl = \"{}/v1/search\"\nmethod = \"POST\"\n\
+             health = {{ method = \"POST\", path = \"/healthz\", body = {{ ping = true }}, expect_status = 200 }}\n",
+            server.url
+        );
+
+        let soul = test_support::soul("pre-load");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let ctx = test_support::pipeline_ctx(
+            &soul,
+            &gateway,
+            json!({ "build_output": { "config_toml": config_toml } }),
+        );
+
+        let output = PreLoadHandler.check_endpoints(&ctx).await.unwrap();
+        let result = &output["health_results"][0];
+        assert_eq!(result["url"], format!("{}/v1/search", server.url));
+        assert_eq!(result["probe_url"], format!("{}/healthz", server.url));
+
+        let request = &server.requests()[0];
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/healthz")
+        );
+        assert_eq!(request.json(), json!({ "ping": true }));
+
+        // An unexpected status fails the probe
+        let probe = health_check::HealthProbe::from_config(
+            &server.url,
+            &json!({ "path": "/healthz", "expect_status": 204 }),
+        )
+        .unwrap();
+        let health = health_check::probe(&reqwest::Client::new(), &probe).await;
+        assert!(!health.reachable);
+        assert_eq!(health.status_code, Some(200));
+    }
+}
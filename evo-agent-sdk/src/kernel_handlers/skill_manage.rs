@@ -1,15 +1,77 @@
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use tracing::info;
+use std::sync::Arc;
+use tracing::{info, warn};
 
 use crate::handler::{AgentHandler, PipelineContext};
+use crate::json_util;
+use crate::prompt_dump;
 use crate::self_upgrade;
 
-const DEFAULT_MODEL: &str = "gpt-4o-mini";
-
 /// Activation score threshold. Skills below this are discarded.
 const ACTIVATION_THRESHOLD: f64 = 0.6;
 
+/// Minimum evaluator `confidence` to activate; a passing score with lower
+/// confidence is held. Unset = no minimum.
+const MIN_CONFIDENCE_ENV: &str = "ACTIVATION_MIN_CONFIDENCE";
+
+/// How king should stage an activation across target agents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RolloutStrategy {
+    /// Start with `canary_agents` (or `percentage` of targets), widen later.
+    Canary,
+    /// Every target at once.
+    #[default]
+    All,
+}
+
+/// The `rollout` section of a deployment plan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rollout {
+    pub strategy: RolloutStrategy,
+    /// Share of target agents in the first wave, 1–100.
+    pub percentage: u8,
+    #[serde(default)]
+    pub canary_agents: Vec<String>,
+}
+
+impl Default for Rollout {
+    fn default() -> Self {
+        Self {
+            strategy: RolloutStrategy::All,
+            percentage: 100,
+            canary_agents: Vec::new(),
+        }
+    }
+}
+
+impl Rollout {
+    /// Read the LLM's `rollout` suggestion, falling back to [`Rollout::default`]
+    /// (everything at once) when it is missing or malformed.
+    pub fn from_plan(rollout: &Value) -> Self {
+        let strategy = match rollout["strategy"].as_str() {
+            Some("canary") => RolloutStrategy::Canary,
+            _ => return Self::default(),
+        };
+        let canary_agents: Vec<String> = rollout["canary_agents"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|a| a.as_str().map(str::to_string))
+            .collect();
+        let percentage = rollout["percentage"]
+            .as_f64()
+            .map_or(10, |p| p.round().clamp(1.0, 100.0) as u8);
+        Self {
+            strategy,
+            percentage,
+            canary_agents,
+        }
+    }
+}
+
 /// Default handler for the **Skill Manage** kernel agent.
 ///
 /// Two modes:
@@ -27,20 +89,29 @@ impl AgentHandler for SkillManageHandler {
             return self.manage_upgrade(&ctx).await;
         }
 
-        self.manage_skill(&ctx).await
+        let min_confidence = crate::config::env_parse::<f64>(MIN_CONFIDENCE_ENV).unwrap_or(0.0);
+        self.manage_skill(&ctx, min_confidence).await
     }
 }
 
 impl SkillManageHandler {
-    /// Original skill lifecycle management.
-    async fn manage_skill(&self, ctx: &PipelineContext<'_>) -> anyhow::Result<Value> {
+    /// Original skill lifecycle management. A skill that passes the score
+    /// threshold is still held when the evaluator's `confidence` (absent =
+    /// `1.0`) is below `min_confidence`.
+    async fn manage_skill(
+        &self,
+        ctx: &PipelineContext<'_>,
+        min_confidence: f64,
+    ) -> anyhow::Result<Value> {
         let recommendation = ctx.metadata["recommendation"].as_str().unwrap_or("hold");
         let overall_score = ctx.metadata["overall_score"].as_f64().unwrap_or(0.0);
+        let confidence = ctx.metadata["confidence"].as_f64().unwrap_or(1.0);
 
         info!(
             artifact_id = %ctx.artifact_id,
             recommendation = %recommendation,
             score = %overall_score,
+            confidence = %confidence,
             "skill-manage agent: processing lifecycle decision"
         );
 
@@ -58,6 +129,24 @@ impl SkillManageHandler {
             }));
         }
 
+        if confidence < min_confidence {
+            info!(
+                artifact_id = %ctx.artifact_id,
+                confidence = %confidence,
+                min_confidence = %min_confidence,
+                "skill held (evaluation confidence too low)"
+            );
+            return Ok(json!({
+                "action": "held",
+                "artifact_id": ctx.artifact_id,
+                "overall_score": overall_score,
+                "confidence": confidence,
+                "reason": format!(
+                    "confidence {confidence:.2} below minimum {min_confidence:.2}"
+                ),
+            }));
+        }
+
         // Use LLM to plan deployment
         let prompt = format!(
             "You are a skill deployment manager for an AI self-evolution system.\n\
@@ -66,15 +155,25 @@ impl SkillManageHandler {
              Determine:\n\
              1. target_agents: Which user agents should receive this skill? (array of role names)\n\
              2. deployment_notes: Any special configuration needed\n\
-             3. rollback_plan: How to revert if the skill causes issues\n\n\
+             3. rollback_plan: How to revert if the skill causes issues\n\
+             4. rollout: {{ strategy: 'canary' or 'all', percentage: 1-100 for the first wave, \
+             canary_agents: array of agent roles to try first }}. Use 'canary' for risky skills.\n\n\
              Respond with valid JSON.",
             serde_json::to_string_pretty(&ctx.metadata).unwrap_or_default()
         );
 
+        prompt_dump::dump(
+            &ctx.run_id,
+            &ctx.stage,
+            ctx.soul.default_model(),
+            &ctx.soul.behavior,
+            &prompt,
+        );
+
         let response = ctx
             .gateway
             .chat_completion(
-                DEFAULT_MODEL,
+                ctx.soul.default_model(),
                 &ctx.soul.behavior,
                 &prompt,
                 Some(0.3),
@@ -82,12 +181,19 @@ impl SkillManageHandler {
             )
             .await?;
 
-        let deployment = serde_json::from_str::<Value>(&response)
-            .unwrap_or_else(|_| json!({ "raw_response": response }));
+        let mut deployment = json_util::parse_llm_json(&response)
+            .unwrap_or_else(|| json!({ "raw_response": response }));
+        let rollout = Rollout::from_plan(&deployment["rollout"]);
+        if deployment.is_object() {
+            deployment["rollout"] = json!(rollout);
+        } else {
+            deployment = json!({ "raw_response": deployment, "rollout": rollout });
+        }
 
         info!(
             artifact_id = %ctx.artifact_id,
             action = "activated",
+            rollout = ?rollout.strategy,
             "skill lifecycle complete"
         );
 
@@ -145,6 +251,35 @@ impl SkillManageHandler {
             "self-upgrade approved — king will trigger update.sh"
         );
 
+        // Optionally watch for the swap to land and report back to king
+        let verification = match self_upgrade::VerifyPolicy::from_env() {
+            Some(policy) => {
+                let emitter = Arc::clone(&ctx.emitter);
+                let (component, new_version, run_id) = (
+                    component.to_string(),
+                    new_version.to_string(),
+                    ctx.run_id.clone(),
+                );
+                tokio::spawn(async move {
+                    let home = self_upgrade::evo_home();
+                    let mut outcome =
+                        self_upgrade::verify_activation(&home, &component, &new_version, &policy)
+                            .await;
+                    outcome["run_id"] = json!(run_id);
+                    let event = if outcome["verified"].as_bool() == Some(true) {
+                        self_upgrade::SELF_UPGRADE_VERIFIED
+                    } else {
+                        self_upgrade::SELF_UPGRADE_FAILED
+                    };
+                    if let Err(e) = emitter.emit(event, outcome).await {
+                        warn!(err = %e, event, "failed to emit self-upgrade verification");
+                    }
+                });
+                "pending"
+            }
+            None => "disabled",
+        };
+
         Ok(json!({
             "build_type": "self_upgrade",
             "action": "activated",
@@ -152,6 +287,64 @@ impl SkillManageHandler {
             "new_version": new_version,
             "artifact_id": ctx.artifact_id,
             "overall_score": overall_score,
+            "verification": verification,
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway_client::GatewayClient;
+    use crate::test_support::{MockServer, llm_reply, pipeline_ctx, soul};
+
+    #[tokio::test]
+    async fn activation_carries_a_well_formed_rollout() {
+        let server = MockServer::start(vec![
+            llm_reply(json!({
+                "target_agents": ["research", "support"],
+                "rollout": { "strategy": "canary", "percentage": 250, "canary_agents": ["research"] },
+            })),
+            llm_reply(json!({ "target_agents": ["research"] })),
+        ])
+        .await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let soul = soul("skill-manage");
+        let metadata = json!({ "overall_score": 0.9, "recommendation": "activate" });
+
+        let canary = SkillManageHandler
+            .manage_skill(&pipeline_ctx(&soul, &gateway, metadata.clone()), 0.0)
+            .await
+            .unwrap();
+        assert_eq!(canary["action"], "activated");
+        assert_eq!(
+            canary["deployment"]["rollout"],
+            json!({ "strategy": "canary", "percentage": 100, "canary_agents": ["research"] })
+        );
+
+        let all = SkillManageHandler
+            .manage_skill(&pipeline_ctx(&soul, &gateway, metadata), 0.0)
+            .await
+            .unwrap();
+        assert_eq!(
+            all["deployment"]["rollout"],
+            json!({ "strategy": "all", "percentage": 100, "canary_agents": [] })
+        );
+    }
+
+    #[tokio::test]
+    async fn high_score_with_low_confidence_is_held() {
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let soul = soul("skill-manage");
+        let ctx = pipeline_ctx(
+            &soul,
+            &gateway,
+            json!({ "overall_score": 0.95, "recommendation": "activate", "confidence": 0.3 }),
+        );
+
+        let out = SkillManageHandler.manage_skill(&ctx, 0.7).await.unwrap();
+
+        assert_eq!(out["action"], "held");
+        assert_eq!(out["confidence"], 0.3);
+    }
+}
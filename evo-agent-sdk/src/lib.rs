@@ -43,21 +43,50 @@
 //! }
 //! ```
 
+pub mod agent_error;
+pub mod build_lock;
+pub mod config;
+pub mod emit;
+pub mod error;
+pub mod event_gate;
 pub mod gateway_client;
 pub mod handler;
 pub mod health_check;
+pub mod json_schema;
+pub mod json_util;
 pub mod kernel_handlers;
+pub mod lifecycle;
+pub mod logging;
+pub mod model;
+pub mod prompt_dump;
+pub mod recent_events;
+pub mod redact;
+pub mod registration;
+pub mod registry;
+pub mod run_cache;
 pub mod runner;
+pub mod safe_mode;
+pub mod sandbox;
 pub mod self_upgrade;
 pub mod skill_engine;
 pub mod soul;
+pub mod stage_output;
+
+#[cfg(test)]
+mod test_support;
 
 // ─── Re-exports ──────────────────────────────────────────────────────────────
 
-pub use gateway_client::GatewayClient;
-pub use handler::{AgentHandler, CommandContext, PipelineContext, TaskEvaluateContext};
+pub use error::{ErrorKind, TransientError};
+pub use gateway_client::{Completion, CompletionOptions, GatewayClient, ResponseFormat};
+pub use handler::{
+    AgentHandler, ArtifactRef, CommandContext, DisconnectContext, DisconnectReason, HandlerOutput,
+    HeartbeatContext, PipelineContext, StageStatus, TaskEvaluateContext,
+};
+pub use model::ModelRef;
+pub use registration::RegistrationAck;
 pub use runner::AgentRunner;
-pub use skill_engine::LoadedSkill;
+pub use skill_engine::{LoadedSkill, SkillSet, StageSkills};
 pub use soul::Soul;
 
 /// Convenience re-export of `evo_common` for downstream crates.
@@ -71,10 +100,15 @@ pub use evo_common;
 /// use evo_agent_sdk::prelude::*;
 /// ```
 pub mod prelude {
-    pub use crate::gateway_client::GatewayClient;
-    pub use crate::handler::{AgentHandler, CommandContext, PipelineContext, TaskEvaluateContext};
+    pub use crate::gateway_client::{CompletionOptions, GatewayClient, ResponseFormat};
+    pub use crate::handler::{
+        AgentHandler, ArtifactRef, CommandContext, DisconnectContext, DisconnectReason,
+        HandlerOutput, HeartbeatContext, PipelineContext, TaskEvaluateContext,
+    };
+    pub use crate::model::ModelRef;
+    pub use crate::registration::RegistrationAck;
     pub use crate::runner::AgentRunner;
-    pub use crate::skill_engine::LoadedSkill;
+    pub use crate::skill_engine::{LoadedSkill, SkillSet, StageSkills};
     pub use crate::soul::Soul;
     pub use serde_json::{self, json};
 }
@@ -0,0 +1,122 @@
+//! Opt-in machine-readable lifecycle events on stdout.
+//!
+//! With `EVENT_STREAM_STDOUT=1` the runner writes one JSON object per line
+//! at each lifecycle transition, e.g.
+//!
+//! ```json
+//! {"event":"stage_completed","ts":"2026-01-01T00:00:00Z","agent_id":"learning-learning","run_id":"r1","stage":"learning"}
+//! ```
+//!
+//! Supervisors can follow this stream instead of parsing the log files.
+
+use serde_json::{Map, Value, json};
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Lifecycle transitions reported on the event stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    Connected,
+    Registered,
+    StageStarted,
+    StageCompleted,
+    StageFailed,
+    Disconnected,
+    ShuttingDown,
+}
+
+impl Lifecycle {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Connected => "connected",
+            Self::Registered => "registered",
+            Self::StageStarted => "stage_started",
+            Self::StageCompleted => "stage_completed",
+            Self::StageFailed => "stage_failed",
+            Self::Disconnected => "disconnected",
+            Self::ShuttingDown => "shutting_down",
+        }
+    }
+}
+
+/// JSON-lines writer for [`Lifecycle`] events; a no-op when disabled.
+pub struct EventStream {
+    agent_id: String,
+    sink: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl EventStream {
+    /// Write to stdout when `EVENT_STREAM_STDOUT=1`, otherwise disabled.
+    pub fn from_env(agent_id: &str) -> Self {
+        let enabled = matches!(
+            std::env::var("EVENT_STREAM_STDOUT").as_deref(),
+            Ok("1") | Ok("true")
+        );
+        if enabled {
+            Self::to_writer(agent_id, Box::new(std::io::stdout()))
+        } else {
+            Self::disabled()
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            agent_id: String::new(),
+            sink: None,
+        }
+    }
+
+    pub fn to_writer(agent_id: &str, writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            agent_id: agent_id.to_string(),
+            sink: Some(Mutex::new(writer)),
+        }
+    }
+
+    /// Report `event` with extra `fields` (a JSON object, or `Value::Null`).
+    ///
+    /// The whole line is written and flushed under one lock, so concurrent
+    /// callers never interleave partial lines.
+    pub fn emit(&self, event: Lifecycle, fields: Value) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+
+        let mut obj = Map::new();
+        obj.insert("event".into(), json!(event.as_str()));
+        obj.insert("ts".into(), json!(chrono::Utc::now().to_rfc3339()));
+        obj.insert("agent_id".into(), json!(self.agent_id));
+        if let Value::Object(extra) = fields {
+            obj.extend(extra);
+        }
+        let mut line = Value::Object(obj).to_string();
+        line.push('\n');
+
+        let mut writer = sink.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writer.write_all(line.as_bytes());
+        let _ = writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::SharedBuf;
+
+    #[test]
+    fn writes_one_json_object_per_line() {
+        let buf = SharedBuf::default();
+        let stream = EventStream::to_writer("agent-1", Box::new(buf.clone()));
+
+        stream.emit(Lifecycle::Connected, Value::Null);
+        stream.emit(Lifecycle::StageStarted, json!({ "run_id": "r1" }));
+        EventStream::disabled().emit(Lifecycle::ShuttingDown, Value::Null);
+
+        let lines = buf.json_lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "connected");
+        assert_eq!(lines[0]["agent_id"], "agent-1");
+        assert_eq!(lines[1]["event"], "stage_started");
+        assert_eq!(lines[1]["run_id"], "r1");
+    }
+}
@@ -0,0 +1,277 @@
+//! Logging setup for agents.
+//!
+//! By default this is `evo_common::logging::init_logging_with_otel`
+//! (per-role JSON file, stdout, OTLP traces). Two options need layers that
+//! function has no hook for, so they build the subscriber here instead,
+//! without the OTLP export: `LOG_COMBINED=1` adds a shared `evo-agents.log`
+//! that every co-located agent appends to with the role on each line, and
+//! `EVENT_STREAM_STDOUT=1` moves console logs to stderr.
+
+use evo_common::logging::OtelGuard;
+use serde_json::{Map, Value, json};
+use std::io::Write;
+use std::path::Path;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, Layer, fmt};
+
+use crate::config::env_flag;
+
+/// File stem of the shared log written when `LOG_COMBINED=1`.
+pub const COMBINED_LOG_STEM: &str = "evo-agents";
+
+/// Guards that flush logs and shut down tracing on drop.
+///
+/// Must be held for the lifetime of the process; hand them to [`finish`]
+/// on exit so the final entries are flushed.
+pub struct LogGuards {
+    _file: WorkerGuard,
+    _combined: Option<WorkerGuard>,
+    /// `None` when the OTLP export is off.
+    _otel: Option<OtelGuard>,
+}
+
+/// Initialise logging for an agent with the given role.
+///
+/// Writes `logs/<role>.log` + stdout and exports spans to `otlp_endpoint`.
+/// When `LOG_COMBINED=1` the events are also appended to
+/// `logs/evo-agents.log` with a `role` field; that and
+/// `EVENT_STREAM_STDOUT=1` turn the span export off.
+pub fn init_logging(role: &str, otlp_endpoint: &str) -> LogGuards {
+    let combined = env_flag("LOG_COMBINED");
+    // Keep stdout clean for the lifecycle event stream when it is enabled
+    let event_stream = env_flag("EVENT_STREAM_STDOUT");
+    if !combined && !event_stream {
+        let (file, otel) = evo_common::logging::init_logging_with_otel(role, otlp_endpoint);
+        install_panic_hook();
+        return LogGuards {
+            _file: file,
+            _combined: None,
+            _otel: Some(otel),
+        };
+    }
+
+    let dir = evo_common::logging::log_dir();
+    std::fs::create_dir_all(&dir).expect("Failed to create log directory");
+
+    let file_appender = tracing_appender::rolling::daily(&dir, format!("{role}.log"));
+    let (non_blocking, file_guard) = tracing_appender::non_blocking(file_appender);
+
+    let file_layer = fmt::layer()
+        .json()
+        .with_writer(non_blocking)
+        .with_target(true)
+        .with_thread_ids(true)
+        .with_file(true)
+        .with_line_number(true);
+
+    let (combined_layer, combined_guard) = if combined {
+        let (layer, guard) = combined_layer(&dir, role);
+        (Some(layer), Some(guard))
+    } else {
+        (None, None)
+    };
+
+    let console = if event_stream {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let stdout_layer = fmt::layer()
+        .with_target(true)
+        .with_thread_ids(false)
+        .with_writer(console);
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(file_layer)
+        .with(combined_layer)
+        .with(stdout_layer)
+        .init();
+    install_panic_hook();
+    tracing::warn!(
+        otlp_endpoint,
+        "OTLP trace export is off with LOG_COMBINED or EVENT_STREAM_STDOUT"
+    );
+
+    LogGuards {
+        _file: file_guard,
+        _combined: combined_guard,
+        _otel: None,
+    }
+}
+
+/// Log the runner's exit (including the error, if any) and flush `guards`.
+///
+/// Returns `result` unchanged so callers can end with `finish(guards, result)`.
+pub fn finish<T>(guards: LogGuards, result: anyhow::Result<T>) -> anyhow::Result<T> {
+    match &result {
+        Ok(_) => tracing::info!("runner exiting"),
+        Err(e) => tracing::error!(err = format!("{e:#}"), "runner exiting with error"),
+    }
+    drop(guards);
+    result
+}
+
+/// Log panics through tracing before the default hook runs; unwinding then
+/// drops [`LogGuards`] and flushes the entry to the log file.
+fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!(panic = %info, "runner panicked");
+        previous(info);
+    }));
+}
+
+/// Build the layer appending role-tagged JSON lines to `<dir>/evo-agents.log`.
+pub fn combined_layer(dir: &Path, role: &str) -> (CombinedLayer<NonBlocking>, WorkerGuard) {
+    let appender = tracing_appender::rolling::daily(dir, format!("{COMBINED_LOG_STEM}.log"));
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    (CombinedLayer::new(role, writer), guard)
+}
+
+// ─── Combined layer ──────────────────────────────────────────────────────────
+
+/// Writes each event as one JSON line carrying the agent's `role`.
+pub struct CombinedLayer<W> {
+    role: String,
+    writer: W,
+}
+
+impl<W> CombinedLayer<W> {
+    pub fn new(role: &str, writer: W) -> Self {
+        Self {
+            role: role.to_string(),
+            writer,
+        }
+    }
+}
+
+impl<S, W> Layer<S> for CombinedLayer<W>
+where
+    S: Subscriber,
+    W: for<'w> MakeWriter<'w> + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = Map::new();
+        event.record(&mut JsonVisitor(&mut fields));
+
+        let meta = event.metadata();
+        let mut line = json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "level": meta.level().as_str(),
+            "target": meta.target(),
+            "role": self.role,
+            "fields": fields,
+        })
+        .to_string();
+        line.push('\n');
+
+        // One write per line so concurrent agents never interleave partial lines
+        let _ = self.writer.make_writer().write_all(line.as_bytes());
+    }
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{value:?}")));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn exit_error_is_flushed_to_log_file() {
+        let dir = temp_dir("exit-log");
+        let appender = tracing_appender::rolling::never(&dir, "agent.log");
+        let (writer, file_guard) = tracing_appender::non_blocking(appender);
+        let subscriber =
+            tracing_subscriber::registry().with(fmt::layer().json().with_writer(writer));
+        let guards = LogGuards {
+            _file: file_guard,
+            _combined: None,
+            _otel: None,
+        };
+
+        let result: anyhow::Result<()> = tracing::subscriber::with_default(subscriber, || {
+            finish(guards, Err(anyhow::anyhow!("king refused registration")))
+        });
+
+        assert!(result.is_err());
+        let contents = std::fs::read_to_string(dir.join("agent.log")).unwrap();
+        let last: Value = serde_json::from_str(contents.lines().last().unwrap()).unwrap();
+        assert_eq!(last["fields"]["message"], "runner exiting with error");
+        assert_eq!(last["fields"]["err"], "king refused registration");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn combined_file_receives_entries_from_multiple_roles() {
+        let dir = temp_dir("combined-log");
+
+        for role in ["learning", "building"] {
+            let (layer, guard) = combined_layer(&dir, role);
+            let subscriber = tracing_subscriber::registry().with(layer);
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info!(stage = role, "hello from agent");
+            });
+            drop(guard); // flush
+        }
+
+        let contents: String = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with(COMBINED_LOG_STEM)
+            })
+            .map(|e| std::fs::read_to_string(e.path()).unwrap())
+            .collect();
+
+        let roles: Vec<String> = contents
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap())
+            .map(|v| {
+                assert_eq!(v["fields"]["message"], "hello from agent");
+                v["role"].as_str().unwrap().to_string()
+            })
+            .collect();
+
+        assert_eq!(roles, vec!["learning", "building"]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
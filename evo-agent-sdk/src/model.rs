@@ -0,0 +1,104 @@
+//! Model references in the gateway's `provider:model` convention.
+
+use std::fmt;
+
+/// A model name with an optional provider prefix, e.g. `openai:gpt-4o-mini`.
+///
+/// The provider is everything before the **first** colon, so model names
+/// that themselves contain colons keep them: `ollama:llama3:8b` is provider
+/// `ollama`, model `llama3:8b`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModelRef {
+    pub provider: Option<String>,
+    pub model: String,
+}
+
+impl ModelRef {
+    /// Parse a `provider:model` or bare `model` string.
+    pub fn parse(raw: &str) -> Self {
+        let raw = raw.trim();
+        match raw.split_once(':') {
+            Some((provider, model)) if !provider.is_empty() && !model.is_empty() => Self {
+                provider: Some(provider.to_string()),
+                model: model.to_string(),
+            },
+            _ => Self {
+                provider: None,
+                model: raw.to_string(),
+            },
+        }
+    }
+
+    /// Build a reference from a model and an explicitly supplied provider.
+    ///
+    /// An empty provider is ignored. A model already carrying the same
+    /// provider prefix is not prefixed twice; any other colons in `model`
+    /// are treated as part of the model name.
+    pub fn with_provider(model: &str, provider: Option<&str>) -> Self {
+        match provider.map(str::trim).filter(|p| !p.is_empty()) {
+            Some(p) => {
+                let model = model.trim();
+                let model = model
+                    .strip_prefix(p)
+                    .and_then(|rest| rest.strip_prefix(':'))
+                    .unwrap_or(model);
+                Self {
+                    provider: Some(p.to_string()),
+                    model: model.to_string(),
+                }
+            }
+            None => Self::parse(model),
+        }
+    }
+}
+
+impl fmt::Display for ModelRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.provider {
+            Some(p) => write!(f, "{p}:{}", self.model),
+            None => f.write_str(&self.model),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_model() {
+        let m = ModelRef::parse("gpt-4o-mini");
+        assert_eq!(m.provider, None);
+        assert_eq!(m.model, "gpt-4o-mini");
+        assert_eq!(m.to_string(), "gpt-4o-mini");
+    }
+
+    #[test]
+    fn provider_prefixed_model() {
+        let m = ModelRef::parse("openai:gpt-4o");
+        assert_eq!(m.provider.as_deref(), Some("openai"));
+        assert_eq!(m.model, "gpt-4o");
+        assert_eq!(m.to_string(), "openai:gpt-4o");
+    }
+
+    #[test]
+    fn model_name_containing_colons() {
+        let m = ModelRef::parse("ollama:llama3:8b");
+        assert_eq!(m.provider.as_deref(), Some("ollama"));
+        assert_eq!(m.model, "llama3:8b");
+        assert_eq!(m.to_string(), "ollama:llama3:8b");
+    }
+
+    #[test]
+    fn explicit_provider_is_not_doubled() {
+        let m = ModelRef::with_provider("openai:gpt-4o", Some("openai"));
+        assert_eq!(m.to_string(), "openai:gpt-4o");
+
+        let m = ModelRef::with_provider("llama3:8b", Some("ollama"));
+        assert_eq!(m.model, "llama3:8b");
+        assert_eq!(m.to_string(), "ollama:llama3:8b");
+
+        let m = ModelRef::with_provider("gpt-4o", Some(""));
+        assert_eq!(m.to_string(), "gpt-4o");
+    }
+}
@@ -0,0 +1,103 @@
+//! Opt-in dump of the fully resolved prompts each handler sends.
+//!
+//! With `DUMP_PROMPTS_DIR` set, every call to [`dump`] writes
+//! `<dir>/<run_id>/<stage>-<timestamp>.json` containing the model, system
+//! prompt and user prompt, so bad LLM output can be traced to its exact input.
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Dump a prompt for `run_id`/`stage` if `DUMP_PROMPTS_DIR` is set.
+///
+/// Write failures are logged and never fail the handler.
+pub fn dump(run_id: &str, stage: &str, model: &str, system_prompt: &str, user_prompt: &str) {
+    let Ok(dir) = std::env::var("DUMP_PROMPTS_DIR") else {
+        return;
+    };
+    match write_dump(
+        Path::new(&dir),
+        run_id,
+        stage,
+        model,
+        system_prompt,
+        user_prompt,
+    ) {
+        Ok(path) => debug!(path = %path.display(), "prompt dumped"),
+        Err(e) => warn!(err = %e, "failed to dump prompt"),
+    }
+}
+
+/// Write one prompt dump file and return its path.
+pub fn write_dump(
+    dir: &Path,
+    run_id: &str,
+    stage: &str,
+    model: &str,
+    system_prompt: &str,
+    user_prompt: &str,
+) -> Result<PathBuf> {
+    let now = chrono::Utc::now();
+    let run_dir = dir.join(sanitize(run_id));
+    std::fs::create_dir_all(&run_dir)
+        .with_context(|| format!("Failed to create {}", run_dir.display()))?;
+
+    let path = run_dir.join(format!(
+        "{}-{}.json",
+        sanitize(stage),
+        now.format("%Y%m%dT%H%M%S%.6fZ")
+    ));
+    let body = json!({
+        "run_id": run_id,
+        "stage": stage,
+        "model": model,
+        "timestamp": now.to_rfc3339(),
+        "system_prompt": system_prompt,
+        "user_prompt": user_prompt,
+    });
+    std::fs::write(&path, serde_json::to_string_pretty(&body)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Keep path components to a safe character set.
+pub(crate) fn sanitize(part: &str) -> String {
+    part.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use serde_json::Value;
+
+    #[test]
+    fn writes_dump_with_expected_fields() {
+        let dir = test_support::temp_dir("prompt-dump");
+        let path = write_dump(&dir, "run-1", "learning", "gpt-4o-mini", "sys", "user").unwrap();
+
+        assert!(path.starts_with(dir.join("run-1")));
+        assert!(
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("learning-")
+        );
+
+        let dumped: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(dumped["run_id"], "run-1");
+        assert_eq!(dumped["stage"], "learning");
+        assert_eq!(dumped["model"], "gpt-4o-mini");
+        assert_eq!(dumped["system_prompt"], "sys");
+        assert_eq!(dumped["user_prompt"], "user");
+    }
+}
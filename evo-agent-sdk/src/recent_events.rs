@@ -0,0 +1,237 @@
+//! Opt-in on-disk ring buffer of recent events, for crash post-mortems.
+//!
+//! With `RECENT_EVENTS=<n>` the runner keeps the last `n` inbound king
+//! events and stage outcomes in
+//! `<EVO_HOME>/data/<agent_id>/recent_events.jsonl` (or `RECENT_EVENTS_PATH`).
+//! The file is rewritten after every entry by a background writer thread,
+//! so it survives a crash without blocking the event loop, and is reloaded
+//! on start so the history spans restarts. Payloads pass through the output
+//! [`Redactor`] first.
+
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use tracing::warn;
+
+use crate::config::env_parse;
+use crate::redact::Redactor;
+
+/// Payloads larger than this are recorded by size only.
+const MAX_PAYLOAD_BYTES: usize = 8 * 1024;
+
+/// Ring buffer of the last `capacity` events; a no-op when disabled.
+pub struct RecentEvents {
+    path: Option<PathBuf>,
+    capacity: usize,
+    entries: Mutex<VecDeque<Value>>,
+    redactor: Option<Redactor>,
+    /// Snapshots for the writer thread; `None` once dropped.
+    writes: Option<mpsc::Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl RecentEvents {
+    /// Read `RECENT_EVENTS` (buffer size; unset or `0` = off) and
+    /// `RECENT_EVENTS_PATH`.
+    pub fn from_env(agent_id: &str) -> Self {
+        let Some(capacity) = env_parse::<usize>("RECENT_EVENTS").filter(|n| *n > 0) else {
+            return Self::disabled();
+        };
+        let path = std::env::var("RECENT_EVENTS_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| {
+                crate::self_upgrade::evo_home()
+                    .join("data")
+                    .join(agent_id)
+                    .join("recent_events.jsonl")
+            });
+        Self::new(path, capacity)
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            path: None,
+            capacity: 0,
+            entries: Mutex::new(VecDeque::new()),
+            redactor: None,
+            writes: None,
+            writer: None,
+        }
+    }
+
+    /// Persist to `path`, keeping entries already there from a previous run.
+    pub fn new(path: impl Into<PathBuf>, capacity: usize) -> Self {
+        let path = path.into();
+        let mut entries: VecDeque<Value> = std::fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect();
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+        let (writes, snapshots) = mpsc::channel::<String>();
+        let target = path.clone();
+        let writer = std::thread::spawn(move || {
+            while let Ok(mut body) = snapshots.recv() {
+                // Only the newest snapshot matters
+                while let Ok(newer) = snapshots.try_recv() {
+                    body = newer;
+                }
+                persist(&target, &body);
+            }
+        });
+        Self {
+            path: Some(path),
+            capacity,
+            entries: Mutex::new(entries),
+            redactor: None,
+            writes: Some(writes),
+            writer: Some(writer),
+        }
+    }
+
+    /// Mask secrets in recorded payloads and errors.
+    pub fn with_redactor(mut self, redactor: Option<Redactor>) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Record an event received from king.
+    pub fn inbound(&self, event: &str, payload: &Value) {
+        if self.path.is_none() {
+            return;
+        }
+        let size = payload.to_string().len();
+        let payload = if size > MAX_PAYLOAD_BYTES {
+            json!({ "truncated": true, "bytes": size })
+        } else {
+            let mut payload = payload.clone();
+            if let Some(redactor) = &self.redactor {
+                redactor.redact(&mut payload);
+            }
+            payload
+        };
+        self.push(json!({ "direction": "in", "event": event, "payload": payload }));
+    }
+
+    /// Record how a pipeline stage ended.
+    pub fn outcome(&self, run_id: &str, stage: &str, status: &str, error: Option<&str>) {
+        let error = error.map(|e| {
+            self.redactor
+                .as_ref()
+                .and_then(|r| r.redact_str(e))
+                .unwrap_or_else(|| e.to_string())
+        });
+        self.push(json!({
+            "direction": "out",
+            "run_id": run_id,
+            "stage": stage,
+            "status": status,
+            "error": error,
+        }));
+    }
+
+    /// Entries currently held, oldest first.
+    pub fn entries(&self) -> Vec<Value> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().cloned().collect()
+    }
+
+    fn push(&self, mut entry: Value) {
+        let Some(writes) = &self.writes else {
+            return;
+        };
+        entry["ts"] = json!(chrono::Utc::now().to_rfc3339());
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.push_back(entry);
+        while entries.len() > self.capacity {
+            entries.pop_front();
+        }
+
+        // Sent under the lock so snapshots reach the writer in order
+        let body: String = entries.iter().map(|e| format!("{e}\n")).collect();
+        let _ = writes.send(body);
+    }
+}
+
+impl Drop for RecentEvents {
+    /// Flush pending writes before going away.
+    fn drop(&mut self) {
+        self.writes.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Write a temp file and rename, so a crash mid-write keeps the old file.
+fn persist(path: &Path, body: &str) {
+    let tmp = path.with_extension("jsonl.tmp");
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(&tmp, body))
+        .and_then(|()| std::fs::rename(&tmp, path));
+    if let Err(e) = written {
+        warn!(path = %path.display(), err = %e, "failed to persist recent events");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn keeps_most_recent_events_across_restarts() {
+        let dir = temp_dir("recent-events");
+        let path = dir.join("agent").join("recent_events.jsonl");
+
+        let recent = RecentEvents::new(&path, 3);
+        for i in 0..4 {
+            recent.inbound("pipeline:next", &json!({ "run_id": format!("r{i}") }));
+        }
+        recent.outcome("r3", "learning", "completed", None);
+        drop(recent);
+
+        // A fresh buffer (as after a crash) sees what was flushed to disk
+        let reloaded = RecentEvents::new(&path, 3);
+        let entries = reloaded.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0]["payload"]["run_id"], "r2");
+        assert_eq!(entries[1]["payload"]["run_id"], "r3");
+        assert_eq!(entries[2]["status"], "completed");
+
+        RecentEvents::disabled().inbound("pipeline:next", &json!({}));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn secrets_are_masked_before_they_reach_disk() {
+        let dir = temp_dir("recent-events-redact");
+        let path = dir.join("recent_events.jsonl");
+
+        let recent = RecentEvents::new(&path, 5).with_redactor(Some(Redactor::new()));
+        recent.inbound(
+            "king:command",
+            &json!({ "prompt": "use sk-abcdef1234567890", "api_key": "hunter2hunter2" }),
+        );
+        recent.outcome(
+            "r1",
+            "building",
+            "failed",
+            Some("401 for sk-abcdef1234567890"),
+        );
+        drop(recent);
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(!written.contains("sk-abcdef1234567890"), "{written}");
+        assert!(!written.contains("hunter2hunter2"), "{written}");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,235 @@
+//! Secret masking for data sent to king.
+//!
+//! Stage outputs can carry secrets by accident: an endpoint URL with an
+//! `api_key=` query, a token a skill echoed back, the value of a secret env
+//! var. Before a `pipeline:stage_result` is emitted, [`Redactor`] replaces
+//! them with [`MASK`]:
+//!
+//! - tokens starting with a known secret prefix (`sk-`, `ghp_`, `AKIA`, ...)
+//! - values of secret-looking query parameters (`?api_key=...`) and object
+//!   fields (`"password": "..."`)
+//! - literal values of env vars whose names contain `KEY`, `TOKEN`, `SECRET`
+//!   or `PASSWORD`
+//!
+//! On by default; `OUTPUT_REDACT=0` turns it off, and
+//! `OUTPUT_REDACT_PREFIXES` / `OUTPUT_REDACT_FIELDS` add patterns.
+
+use serde_json::Value;
+
+/// What a secret is replaced with.
+pub const MASK: &str = "[REDACTED]";
+
+/// Token prefixes of well-known credential formats.
+const DEFAULT_PREFIXES: &[&str] = &[
+    "sk-",
+    "sk_live_",
+    "ghp_",
+    "gho_",
+    "github_pat_",
+    "glpat-",
+    "xoxb-",
+    "xoxp-",
+    "AKIA",
+    "AIza",
+];
+
+/// Query parameter and object field names whose values are secret.
+const DEFAULT_FIELDS: &[&str] = &[
+    "api_key",
+    "apikey",
+    "access_token",
+    "token",
+    "secret",
+    "client_secret",
+    "password",
+];
+
+/// Env var name fragments that mark its value as secret.
+const SECRET_ENV_MARKERS: &[&str] = &["KEY", "TOKEN", "SECRET", "PASSWORD"];
+
+/// Whether an env var's name marks its value as a secret.
+pub(crate) fn is_secret_env_name(name: &str) -> bool {
+    let name = name.to_uppercase();
+    SECRET_ENV_MARKERS.iter().any(|m| name.contains(m))
+}
+
+/// Shortest token tail after a prefix (and shortest env value) treated as
+/// a secret, so `sk-` in prose isn't masked.
+const MIN_SECRET_LEN: usize = 8;
+
+#[derive(Clone, Default)]
+pub struct Redactor {
+    prefixes: Vec<String>,
+    /// Lowercase.
+    fields: Vec<String>,
+    values: Vec<String>,
+}
+
+impl Redactor {
+    /// The default prefixes and fields, with no literal values.
+    pub fn new() -> Self {
+        Self {
+            prefixes: DEFAULT_PREFIXES.iter().map(|p| p.to_string()).collect(),
+            fields: DEFAULT_FIELDS.iter().map(|f| f.to_string()).collect(),
+            values: Vec::new(),
+        }
+    }
+
+    /// `None` with `OUTPUT_REDACT=0`; otherwise the defaults plus
+    /// `OUTPUT_REDACT_PREFIXES` and `OUTPUT_REDACT_FIELDS` (comma-separated)
+    /// and the values of secret-looking env vars.
+    pub fn from_env() -> Option<Self> {
+        if !crate::config::env_flag_or("OUTPUT_REDACT", true) {
+            return None;
+        }
+        let list = |name: &str| -> Vec<String> {
+            std::env::var(name)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let secret_values =
+            std::env::vars().filter_map(|(name, value)| is_secret_env_name(&name).then_some(value));
+        Some(
+            Self::new()
+                .with_prefixes(list("OUTPUT_REDACT_PREFIXES"))
+                .with_fields(list("OUTPUT_REDACT_FIELDS"))
+                .with_values(secret_values),
+        )
+    }
+
+    pub fn with_prefixes(mut self, prefixes: impl IntoIterator<Item = String>) -> Self {
+        self.prefixes.extend(prefixes);
+        self
+    }
+
+    pub fn with_fields(mut self, fields: impl IntoIterator<Item = String>) -> Self {
+        self.fields
+            .extend(fields.into_iter().map(|f| f.to_ascii_lowercase()));
+        self
+    }
+
+    /// Literal secrets to mask wherever they appear; short ones are ignored.
+    pub fn with_values(mut self, values: impl IntoIterator<Item = String>) -> Self {
+        self.values.extend(
+            values
+                .into_iter()
+                .map(|v| v.trim().to_string())
+                .filter(|v| v.len() >= MIN_SECRET_LEN),
+        );
+        self
+    }
+
+    /// Mask secrets in every string of `value`, in place. Returns how many
+    /// strings changed.
+    pub fn redact(&self, value: &mut Value) -> usize {
+        match value {
+            Value::String(s) => match self.redact_str(s) {
+                Some(masked) => {
+                    *s = masked;
+                    1
+                }
+                None => 0,
+            },
+            Value::Array(items) => items.iter_mut().map(|v| self.redact(v)).sum(),
+            Value::Object(map) => map
+                .iter_mut()
+                .map(|(key, v)| match v {
+                    Value::String(s)
+                        if !s.is_empty() && self.fields.contains(&key.to_ascii_lowercase()) =>
+                    {
+                        *s = MASK.to_string();
+                        1
+                    }
+                    _ => self.redact(v),
+                })
+                .sum(),
+            _ => 0,
+        }
+    }
+
+    /// `text` with its secrets masked; `None` when there are none.
+    pub fn redact_str(&self, text: &str) -> Option<String> {
+        let mut spans = Vec::new();
+        let bytes = text.as_bytes();
+
+        for value in &self.values {
+            spans.extend(
+                text.match_indices(value.as_str())
+                    .map(|(i, v)| (i, i + v.len())),
+            );
+        }
+
+        for prefix in &self.prefixes {
+            for (start, _) in text.match_indices(prefix.as_str()) {
+                if start > 0 && is_token_byte(bytes[start - 1]) {
+                    continue;
+                }
+                let tail_start = start + prefix.len();
+                let tail = bytes[tail_start..]
+                    .iter()
+                    .take_while(|b| is_token_byte(**b))
+                    .count();
+                if tail >= MIN_SECRET_LEN {
+                    spans.push((start, tail_start + tail));
+                }
+            }
+        }
+
+        // Same byte offsets as `text`: only ASCII is lowercased
+        let lower = text.to_ascii_lowercase();
+        for field in &self.fields {
+            let needle = format!("{field}=");
+            for (start, _) in lower.match_indices(&needle) {
+                if start > 0 && is_token_byte(bytes[start - 1]) {
+                    continue;
+                }
+                let value_start = start + needle.len();
+                let len = bytes[value_start..]
+                    .iter()
+                    .take_while(|b| !b"&#\"' \t\r\n,;".contains(b))
+                    .count();
+                if len > 0 {
+                    spans.push((value_start, value_start + len));
+                }
+            }
+        }
+
+        if spans.is_empty() {
+            return None;
+        }
+        spans.sort_unstable();
+        let mut masked = String::with_capacity(text.len());
+        let mut pos = 0;
+        for (start, end) in spans {
+            if end <= pos {
+                continue;
+            }
+            if start >= pos {
+                masked.push_str(&text[pos..start]);
+                masked.push_str(MASK);
+            }
+            pos = end;
+        }
+        masked.push_str(&text[pos..]);
+        Some(masked)
+    }
+}
+
+/// Never prints the secret values themselves.
+impl std::fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Redactor")
+            .field("prefixes", &self.prefixes)
+            .field("fields", &self.fields)
+            .field("values", &format!("<{} redacted>", self.values.len()))
+            .finish()
+    }
+}
+
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
@@ -0,0 +1,378 @@
+//! Registration compatibility negotiation with king.
+//!
+//! The agent attaches a `requires` object to `agent:register` and waits
+//! briefly for king's ack, which is expected to look like:
+//!
+//! ```json
+//! { "king_version": "0.4.2", "supported_events": ["pipeline:next", ...] }
+//! ```
+//!
+//! A king that explicitly rejects the registration (`"accepted": false` or
+//! `"status": "rejected"`, with an optional `reason`) aborts startup, unless
+//! `REGISTER_REJECT_EXIT=0` keeps the agent running. Fields king assigns on
+//! acceptance (such as a `session_id`) are returned as a
+//! [`RegistrationAck`], which the runner hands to
+//! [`AgentHandler::on_registered`](crate::AgentHandler::on_registered). Older
+//! kings that don't ack within `REGISTER_ACK_TIMEOUT_SECS` are tolerated:
+//! the agent logs a warning and proceeds, relying on heartbeat
+//! re-registration as the safety net.
+
+use anyhow::{Result, bail};
+use evo_common::messages::events;
+use serde_json::{Value, json};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::config::{env_flag_or, env_parse};
+use crate::emit::Emit;
+
+/// Default wait for king's registration ack.
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Events the runner depends on king understanding.
+pub const DEFAULT_REQUIRED_EVENTS: &[&str] = &[
+    events::AGENT_REGISTER,
+    events::AGENT_STATUS,
+    events::PIPELINE_NEXT,
+    events::PIPELINE_STAGE_RESULT,
+];
+
+/// What to do when king doesn't meet the agent's requirements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompatPolicy {
+    /// Log a prominent warning and keep running.
+    #[default]
+    Warn,
+    /// Abort startup.
+    Refuse,
+}
+
+/// King-side features this agent needs.
+#[derive(Debug, Clone)]
+pub struct Requirements {
+    pub min_king_version: Option<String>,
+    pub events: Vec<String>,
+    pub policy: CompatPolicy,
+    /// How long to wait for the registration ack before proceeding.
+    pub ack_timeout: Duration,
+    /// Abort startup when king rejects the registration.
+    pub exit_on_reject: bool,
+}
+
+impl Default for Requirements {
+    fn default() -> Self {
+        Self {
+            min_king_version: None,
+            events: DEFAULT_REQUIRED_EVENTS
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+            policy: CompatPolicy::Warn,
+            ack_timeout: ACK_TIMEOUT,
+            exit_on_reject: true,
+        }
+    }
+}
+
+impl Requirements {
+    /// Read `KING_MIN_VERSION`, `KING_COMPAT_POLICY` (`warn` or `refuse`),
+    /// `REGISTER_ACK_TIMEOUT_SECS` and `REGISTER_REJECT_EXIT`.
+    pub fn from_env() -> Self {
+        Self {
+            ack_timeout: env_parse::<f64>("REGISTER_ACK_TIMEOUT_SECS")
+                .filter(|s| s.is_finite() && *s >= 0.0)
+                .map(Duration::from_secs_f64)
+                .unwrap_or(ACK_TIMEOUT),
+            min_king_version: std::env::var("KING_MIN_VERSION").ok(),
+            policy: match std::env::var("KING_COMPAT_POLICY").as_deref() {
+                Ok("refuse") => CompatPolicy::Refuse,
+                _ => CompatPolicy::Warn,
+            },
+            exit_on_reject: env_flag_or("REGISTER_REJECT_EXIT", true),
+            ..Self::default()
+        }
+    }
+
+    /// The `requires` object sent in the registration payload.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "min_king_version": self.min_king_version,
+            "events": self.events,
+        })
+    }
+
+    /// List every requirement king's ack fails to meet.
+    pub fn check_ack(&self, ack: &Value) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Some(min) = &self.min_king_version {
+            match ack["king_version"].as_str() {
+                Some(actual) if version_lt(actual, min) => {
+                    problems.push(format!(
+                        "king version {actual} is older than required {min}"
+                    ));
+                }
+                Some(_) => {}
+                None => problems.push(format!("king did not report a version (need >= {min})")),
+            }
+        }
+
+        if let Some(supported) = ack["supported_events"].as_array() {
+            for event in &self.events {
+                if !supported.iter().any(|s| s.as_str() == Some(event)) {
+                    problems.push(format!("king does not support event '{event}'"));
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// King's answer to `agent:register`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistrationAck {
+    /// `false` only when king explicitly rejected the registration.
+    pub accepted: bool,
+    pub reason: Option<String>,
+    /// Server-side session id king assigned, if any.
+    pub session_id: Option<String>,
+    /// The ack as received; `None` when none arrived in time.
+    pub raw: Option<Value>,
+}
+
+impl RegistrationAck {
+    /// No ack arrived (older king, timeout, or failed emit).
+    pub fn unacknowledged() -> Self {
+        Self {
+            accepted: true,
+            reason: None,
+            session_id: None,
+            raw: None,
+        }
+    }
+
+    fn from_ack(ack: Value) -> Self {
+        let rejected =
+            ack["accepted"] == json!(false) || ack["status"].as_str() == Some("rejected");
+        Self {
+            accepted: !rejected,
+            reason: ack["reason"].as_str().map(String::from),
+            session_id: ack["session_id"].as_str().map(String::from),
+            raw: Some(ack),
+        }
+    }
+}
+
+/// Emit `agent:register` with `requires` attached and verify king's ack.
+///
+/// Returns an error when king rejects the registration (unless
+/// [`Requirements::exit_on_reject`] is off), or under
+/// [`CompatPolicy::Refuse`] with a confirmed incompatibility. Emit failures
+/// and acks that don't arrive within [`Requirements::ack_timeout`] are
+/// logged and startup proceeds.
+pub async fn register(
+    socket: &dyn Emit,
+    mut payload: Value,
+    requires: &Requirements,
+) -> Result<RegistrationAck> {
+    payload["requires"] = requires.to_json();
+
+    // Bounded here too, so a sink that ignores its timeout can't stall startup
+    let sent = tokio::time::timeout(
+        requires.ack_timeout,
+        socket.emit_with_ack(events::AGENT_REGISTER, payload, requires.ack_timeout),
+    )
+    .await;
+    let ack = match sent {
+        Err(_elapsed) => None,
+        Ok(Ok(ack)) => ack,
+        Ok(Err(e)) => {
+            warn!(err = %e, "initial registration emit failed — will retry on next heartbeat");
+            return Ok(RegistrationAck::unacknowledged());
+        }
+    };
+
+    let Some(ack) = ack else {
+        warn!(
+            timeout_secs = requires.ack_timeout.as_secs_f64(),
+            "no registration ack from king — proceeding to heartbeat, compatibility unknown"
+        );
+        return Ok(RegistrationAck::unacknowledged());
+    };
+    let ack = RegistrationAck::from_ack(ack);
+
+    if !ack.accepted {
+        let reason = ack.reason.as_deref().unwrap_or("no reason given");
+        if requires.exit_on_reject {
+            bail!("King rejected agent registration: {reason}");
+        }
+        error!(
+            reason,
+            "king rejected agent registration — continuing (REGISTER_REJECT_EXIT=0)"
+        );
+        return Ok(ack);
+    }
+    if let Some(session_id) = &ack.session_id {
+        info!(session_id = %session_id, "registration accepted by king");
+    }
+
+    let raw = ack.raw.as_ref().unwrap_or(&Value::Null);
+    let problems = requires.check_ack(raw);
+    if problems.is_empty() {
+        info!(king_version = ?raw["king_version"].as_str(), "king compatibility confirmed");
+        return Ok(ack);
+    }
+
+    for problem in &problems {
+        warn!(problem = %problem, "!!! KING INCOMPATIBLE !!!");
+    }
+    if requires.policy == CompatPolicy::Refuse {
+        bail!(
+            "King does not meet agent requirements: {}",
+            problems.join("; ")
+        );
+    }
+    Ok(ack)
+}
+
+/// Compare dotted numeric versions (`0.4.10` > `0.4.9`); missing segments
+/// count as `0` and non-numeric suffixes such as `-rc1` are ignored.
+fn version_lt(a: &str, b: &str) -> bool {
+    fn parts(v: &str) -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split('.')
+            .map(|p| {
+                p.chars()
+                    .take_while(|c| c.is_ascii_digit())
+                    .collect::<String>()
+                    .parse()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+    let (mut a, mut b) = (parts(a), parts(b));
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    a < b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::RecordingEmitter;
+
+    #[tokio::test]
+    async fn refuses_incompatible_king() {
+        let king = RecordingEmitter::with_ack(json!({
+            "king_version": "0.3.9",
+            "supported_events": ["agent:register", "agent:status", "pipeline:next"],
+        }));
+        let requires = Requirements {
+            min_king_version: Some("0.4.0".into()),
+            policy: CompatPolicy::Refuse,
+            ..Requirements::default()
+        };
+
+        let err = register(&king, json!({ "agent_id": "a" }), &requires)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("0.3.9 is older than required 0.4.0"), "{err}");
+        assert!(err.contains("pipeline:stage_result"), "{err}");
+
+        let sent = king.events();
+        assert_eq!(sent[0].1["requires"]["min_king_version"], "0.4.0");
+    }
+
+    #[tokio::test]
+    async fn accepted_ack_proceeds() {
+        let king = RecordingEmitter::with_ack(json!({ "accepted": true, "king_version": "0.5.0" }));
+        let requires = Requirements {
+            min_king_version: Some("0.4.0".into()),
+            policy: CompatPolicy::Refuse,
+            ..Requirements::default()
+        };
+        register(&king, json!({}), &requires).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejected_ack_bails_even_under_warn_policy() {
+        let king = RecordingEmitter::with_ack(json!({
+            "status": "rejected",
+            "reason": "duplicate agent_id",
+        }));
+        let err = register(&king, json!({}), &Requirements::default())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("duplicate agent_id"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn ack_fields_are_returned_and_rejection_can_be_survived() {
+        let king = RecordingEmitter::with_ack(json!({
+            "accepted": true,
+            "session_id": "sess-42",
+        }));
+        let ack = register(&king, json!({}), &Requirements::default())
+            .await
+            .unwrap();
+        assert!(ack.accepted);
+        assert_eq!(ack.session_id.as_deref(), Some("sess-42"));
+
+        let king = RecordingEmitter::with_ack(json!({
+            "accepted": false,
+            "reason": "unknown role",
+        }));
+        let requires = Requirements {
+            exit_on_reject: false,
+            ..Requirements::default()
+        };
+        let ack = register(&king, json!({}), &requires).await.unwrap();
+        assert!(!ack.accepted);
+        assert_eq!(ack.reason.as_deref(), Some("unknown role"));
+    }
+
+    #[tokio::test]
+    async fn late_ack_times_out_and_proceeds() {
+        // Even a rejection is ignored if it arrives after the grace period
+        let king =
+            RecordingEmitter::with_ack_after(json!({ "accepted": false }), Duration::from_secs(5));
+        let requires = Requirements {
+            ack_timeout: Duration::from_millis(20),
+            ..Requirements::default()
+        };
+        let started = std::time::Instant::now();
+        let ack = register(&king, json!({}), &requires).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(ack, RegistrationAck::unacknowledged());
+        assert_eq!(king.events()[0].0, events::AGENT_REGISTER);
+    }
+
+    #[tokio::test]
+    async fn missing_ack_is_tolerated() {
+        let king = RecordingEmitter::default();
+        let requires = Requirements {
+            policy: CompatPolicy::Refuse,
+            ..Requirements::default()
+        };
+        register(&king, json!({}), &requires).await.unwrap();
+    }
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert!(version_lt("0.4.9", "0.4.10"));
+        assert!(!version_lt("0.4.10", "0.4.9"));
+        assert!(version_lt("v0.3.9", "0.4.0"));
+        assert!(!version_lt("0.4.0", "0.4.0"));
+        // Missing segments count as zero
+        assert!(!version_lt("0.4", "0.4.0"));
+        assert!(!version_lt("0.4.0", "0.4"));
+        assert!(version_lt("0.4", "0.4.1"));
+        // Pre-release suffixes are ignored
+        assert!(!version_lt("0.4.0-rc1", "0.4.0"));
+        assert!(version_lt("0.4.0-rc1", "0.4.1"));
+    }
+}
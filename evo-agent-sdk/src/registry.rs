@@ -0,0 +1,83 @@
+//! Client for an external skill registry.
+//!
+//! With `SKILL_REGISTRY_URL` set, the learning handler searches the registry
+//! before asking the LLM for skill ideas, so candidates point at skills that
+//! can actually be obtained. `GET <url>/search?q=<query>` must answer with a
+//! JSON array of [`SkillCandidate`]s, or an object holding one under `skills`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+/// A skill the registry offers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkillCandidate {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Where the skill is fetched from (repository, package or API URL).
+    #[serde(default)]
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+pub struct RegistryClient {
+    http_client: reqwest::Client,
+    base_url: String,
+}
+
+impl RegistryClient {
+    pub fn new(base_url: &str) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(15))
+            .build()
+            .context("Failed to build HTTP client for skill registry")?;
+        Ok(Self {
+            http_client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// A client for `SKILL_REGISTRY_URL`; `None` when it is unset or empty.
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("SKILL_REGISTRY_URL").ok()?;
+        let url = url.trim();
+        if url.is_empty() {
+            return None;
+        }
+        match Self::new(url) {
+            Ok(client) => Some(client),
+            Err(e) => {
+                tracing::warn!(err = %e, "skill registry disabled");
+                None
+            }
+        }
+    }
+
+    /// Skills matching `query`, in the registry's order.
+    pub async fn search(&self, query: &str) -> Result<Vec<SkillCandidate>> {
+        let url = format!("{}/search", self.base_url);
+        let resp = self
+            .http_client
+            .get(&url)
+            .query(&[("q", query)])
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach skill registry at {url}"))?;
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("skill registry search failed ({status})");
+        }
+        let body: Value = resp
+            .json()
+            .await
+            .context("skill registry returned invalid JSON")?;
+        let skills = match body {
+            Value::Object(mut map) => map.remove("skills").unwrap_or(Value::Null),
+            other => other,
+        };
+        serde_json::from_value(skills).context("skill registry returned an unexpected shape")
+    }
+}
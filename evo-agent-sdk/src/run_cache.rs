@@ -0,0 +1,153 @@
+//! Per-run cache of completed stage outputs.
+//!
+//! With `RUN_CACHE_TTL_SECS` set, the runner stores each completed stage's
+//! output under `<EVO_HOME>/data/run-cache/<run_id>/<stage>.json`, so a later
+//! stage on the same host can read it through
+//! [`PipelineContext::previous_stage`](crate::PipelineContext::previous_stage)
+//! without king re-sending it, and a redispatch of the same stage can resend
+//! the stored result, artifacts and metrics included. Entries expire after the TTL and only the
+//! most recent `RUN_CACHE_MAX_RUNS` runs are kept.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+use crate::config::env_parse;
+use crate::handler::HandlerOutput;
+use crate::prompt_dump::sanitize;
+
+pub(crate) const DEFAULT_MAX_RUNS: usize = 50;
+
+#[derive(Debug, Clone)]
+pub struct RunCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_runs: usize,
+}
+
+impl RunCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration, max_runs: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+            max_runs: max_runs.max(1),
+        }
+    }
+
+    /// Read `RUN_CACHE_TTL_SECS` and `RUN_CACHE_MAX_RUNS`. Returns `None`
+    /// (cache disabled) when the TTL is unset or `0`.
+    pub fn from_env() -> Option<Self> {
+        let ttl = env_parse::<u64>("RUN_CACHE_TTL_SECS").filter(|s| *s > 0)?;
+        let max_runs = env_parse::<usize>("RUN_CACHE_MAX_RUNS").unwrap_or(DEFAULT_MAX_RUNS);
+        let dir = crate::self_upgrade::evo_home()
+            .join("data")
+            .join("run-cache");
+        Some(Self::new(dir, Duration::from_secs(ttl), max_runs))
+    }
+
+    /// Store `result` as the result of `stage` in `run_id`, then prune.
+    pub fn store(&self, run_id: &str, stage: &str, result: &HandlerOutput) -> Result<()> {
+        let run_dir = self.dir.join(sanitize(run_id));
+        std::fs::create_dir_all(&run_dir)
+            .with_context(|| format!("Failed to create {}", run_dir.display()))?;
+        let path = run_dir.join(format!("{}.json", sanitize(stage)));
+        let entry = json!({
+            "stored_at": Utc::now().to_rfc3339(),
+            "output": result.output,
+            "artifacts": result.artifacts,
+            "metrics": result.metrics,
+        });
+        std::fs::write(&path, serde_json::to_string(&entry)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        self.prune();
+        Ok(())
+    }
+
+    /// The cached output of `stage` in `run_id`, unless missing or expired.
+    pub fn load(&self, run_id: &str, stage: &str) -> Option<Value> {
+        Some(self.entry(run_id, stage)?["output"].take())
+    }
+
+    /// The whole cached result of `stage` in `run_id`, unless missing or
+    /// expired.
+    pub fn load_result(&self, run_id: &str, stage: &str) -> Option<HandlerOutput> {
+        let mut entry = self.entry(run_id, stage)?;
+        Some(HandlerOutput {
+            output: entry["output"].take(),
+            artifacts: serde_json::from_value(entry["artifacts"].take()).unwrap_or_default(),
+            metrics: Some(entry["metrics"].take()).filter(|m| !m.is_null()),
+        })
+    }
+
+    fn entry(&self, run_id: &str, stage: &str) -> Option<Value> {
+        let path = self
+            .dir
+            .join(sanitize(run_id))
+            .join(format!("{}.json", sanitize(stage)));
+        let entry: Value = serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()?;
+        let stored_at: DateTime<Utc> = entry["stored_at"].as_str()?.parse().ok()?;
+        if (Utc::now() - stored_at).to_std().unwrap_or_default() > self.ttl {
+            debug!(run_id, stage, "cached stage result expired");
+            return None;
+        }
+        Some(entry)
+    }
+
+    fn prune(&self) {
+        prune_runs(&self.dir, self.ttl, self.max_runs);
+    }
+}
+
+/// Drop the run directories under `dir` that are older than `ttl`, and all
+/// but the newest `max_runs`.
+pub(crate) fn prune_runs(dir: &Path, ttl: Duration, max_runs: usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut runs: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    runs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    let now = SystemTime::now();
+    for (i, (modified, path)) in runs.iter().enumerate() {
+        let expired = now.duration_since(*modified).unwrap_or_default() > ttl;
+        if (expired || i >= max_runs)
+            && let Err(e) = std::fs::remove_dir_all(path)
+        {
+            warn!(path = %path.display(), err = %e, "failed to prune run directory");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn only_newest_runs_are_kept() {
+        let dir = test_support::temp_dir("run-cache");
+        let cache = RunCache::new(&dir, Duration::from_secs(60), 2);
+
+        // Explicit mtimes, so coarse filesystem timestamps can't tie
+        for (run, age) in [("run-a", 30), ("run-b", 20), ("run-c", 10)] {
+            cache
+                .store(run, "learning", &json!({ "run": run }).into())
+                .unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(age);
+            std::fs::File::open(dir.join(run))
+                .and_then(|f| f.set_modified(modified))
+                .unwrap();
+        }
+
+        assert!(cache.load("run-a", "learning").is_none());
+        assert_eq!(cache.load("run-b", "learning").unwrap()["run"], "run-b");
+        assert_eq!(cache.load("run-c", "learning").unwrap()["run"], "run-c");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
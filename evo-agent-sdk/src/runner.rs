@@ -1,16 +1,50 @@
 use anyhow::{Context, Result, bail};
-use evo_common::{logging::init_logging_with_otel, messages::events};
+use evo_common::messages::events;
 use rust_socketio::{Payload, asynchronous::ClientBuilder};
 use serde_json::{Value, json};
-use std::{collections::HashSet, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::{Notify, oneshot};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
-use crate::gateway_client::GatewayClient;
-use crate::handler::{AgentHandler, CommandContext, PipelineContext, TaskEvaluateContext};
+use crate::agent_error::{AgentError, ErrorCategory};
+use crate::config::{
+    self, HandshakeHeaders, RetryPolicy, RunnerConfig, env_flag, env_parse, sleep_or_pending,
+};
+use crate::emit::{Emit, EmitLimiter, RateLimited, Tagged};
+use crate::error;
+use crate::event_gate::EventGate;
+use crate::gateway_client::{
+    self, ApiStyle, GatewayClient, ModelFallback, PromptOverflow, ReasoningTags, StreamError,
+    StreamResume,
+};
+use crate::handler::{
+    AgentHandler, CommandContext, DisconnectContext, DisconnectReason, HandlerOutput,
+    HeartbeatContext, PIPELINE_ARTIFACT, PIPELINE_CANCEL, PIPELINE_REQUEST, PIPELINE_STAGE_METRICS,
+    PipelineContext, StageStatus, TaskEvaluateContext,
+};
 use crate::health_check;
 use crate::kernel_handlers::*;
-use crate::skill_engine::{self, LoadedSkill};
-use crate::soul::{self, Soul};
+use crate::lifecycle::{EventStream, Lifecycle};
+use crate::logging;
+use crate::model::ModelRef;
+use crate::recent_events::RecentEvents;
+use crate::redact::Redactor;
+use crate::registration;
+use crate::run_cache::RunCache;
+use crate::safe_mode;
+use crate::self_upgrade::{self, Cancelled};
+use crate::skill_engine::{self, LoadedSkill, SkillSet, SkillUsageLog, StageSkills};
+use crate::soul::{self, SharedSoul, Soul};
+use crate::stage_output::{self, FullOutputStore};
 
 // ─── AgentRunner ─────────────────────────────────────────────────────────────
 
@@ -35,36 +69,44 @@ impl AgentRunner {
     /// Parses CLI args (or `AGENT_FOLDER` env) for the agent directory,
     /// loads `soul.md` and skills, connects to king, and enters the event loop.
     pub async fn run<H: AgentHandler>(handler: H) -> Result<()> {
-        let agent_folder = std::env::args()
-            .nth(1)
-            .unwrap_or_else(|| std::env::var("AGENT_FOLDER").unwrap_or_else(|_| ".".to_string()));
-
-        let agent_dir = PathBuf::from(&agent_folder);
-
-        if !agent_dir.exists() {
-            bail!("Agent folder does not exist: {}", agent_dir.display());
-        }
+        let agent_dir = agent_dir_from_args()?;
 
         // Load soul.md to determine this runner's identity
         let soul = soul::load_soul(&agent_dir)
             .with_context(|| format!("Failed to load soul from {}", agent_dir.display()))?;
 
-        // Init logging with OpenTelemetry (→ logs/<role>.log + OTLP export)
+        // Init logging with OpenTelemetry (→ logs/<role>.log + OTLP export,
+        // plus logs/evo-agents.log when LOG_COMBINED=1)
         let otlp_endpoint = std::env::var("EVO_OTLP_ENDPOINT")
             .unwrap_or_else(|_| "http://localhost:3300".to_string());
-        let (_log_guard, _otel_guard) = init_logging_with_otel(&soul.role, &otlp_endpoint);
+        let log_guards = logging::init_logging(&soul.role, &otlp_endpoint);
+
+        // Log the exit reason before the guards flush, so it reaches the file
+        let result = Self::serve(&agent_dir, &soul, handler).await;
+        logging::finish(log_guards, result)
+    }
 
+    /// Everything after logging is up: skills, gateway, king connection.
+    async fn serve<H: AgentHandler>(agent_dir: &Path, soul: &Soul, handler: H) -> Result<()> {
         info!(
             agent_id = %soul.agent_id,
             role     = %soul.role,
+            replica  = ?soul.replica_id,
             folder   = %agent_dir.display(),
             behavior_len = soul.behavior.len(),
             "runner starting"
         );
+        soul::warn_if_terse_behavior(soul, soul::min_behavior_chars());
+        if safe_mode::enabled() {
+            warn!("EVO_SAFE_MODE is on — builds, publishing and code skills are disabled");
+        }
 
         // Load available skills
-        let skills = skill_engine::load_skills(&agent_dir);
-        info!(skills = skills.len(), "skills loaded");
+        let load = skill_engine::load_skills_report(agent_dir);
+        skill_engine::log_skill_load(&soul.role, &load);
+        // Reported to king as agent:error once connected
+        let mut startup_errors = AgentError::from_skill_load(&load);
+        let skills = load.skills;
 
         // King address (Socket.IO server)
         let king_address =
@@ -76,14 +118,54 @@ impl AgentRunner {
 
         info!(king = %king_address, gateway = %gateway_address, "connecting to king");
 
+        // Optional per-run token ceiling across all gateway calls
+        let run_budget = std::env::var("RUN_TOKEN_BUDGET")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok());
+
+        // Optional prompt-size guard (estimated tokens, system + user prompt)
+        let prompt_limit = std::env::var("PROMPT_TOKEN_LIMIT")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok());
+        let prompt_overflow = match std::env::var("PROMPT_OVERFLOW").as_deref() {
+            Ok("warn") => PromptOverflow::Warn,
+            _ => PromptOverflow::Trim,
+        };
+
         // Create gateway client for LLM calls
         let gateway = Arc::new(
-            GatewayClient::new(&gateway_address).context("Failed to create gateway client")?,
+            GatewayClient::new(&gateway_address)
+                .context("Failed to create gateway client")?
+                .with_run_budget(run_budget)
+                .with_prompt_limit(prompt_limit, prompt_overflow)
+                .with_model_fallback(ModelFallback::from_env())
+                .with_reasoning_strip(ReasoningTags::from_env())
+                .with_api_style(ApiStyle::from_env())
+                .with_stream_resume(StreamResume::from_env())
+                .with_stream_usage(env_flag("GATEWAY_STREAM_USAGE"))
+                .with_json_schema(env_flag("GATEWAY_JSON_SCHEMA"))
+                .with_max_sse_line(
+                    env_parse("SSE_MAX_LINE_BYTES").unwrap_or(gateway_client::DEFAULT_MAX_SSE_LINE),
+                ),
         );
 
-        run_client(&soul, &king_address, &skills, &gateway, handler).await?;
+        startup_errors.extend(probe_gateway(&gateway).await);
 
-        Ok(())
+        let config = RunnerConfig::from_env();
+
+        // Reloadable on king:command reload_soul
+        let soul = Arc::new(SharedSoul::new(agent_dir, soul.clone()));
+
+        run_client(
+            soul,
+            &king_address,
+            &skills,
+            &gateway,
+            &config,
+            handler,
+            &startup_errors,
+        )
+        .await
     }
 
     /// Convenience: auto-dispatch to the correct kernel handler based on `soul.md` role.
@@ -92,14 +174,7 @@ impl AgentRunner {
     /// matching kernel handler. Returns an error for unknown roles.
     pub async fn run_kernel() -> Result<()> {
         // We need to peek at the soul to determine the role before dispatching
-        let agent_folder = std::env::args()
-            .nth(1)
-            .unwrap_or_else(|| std::env::var("AGENT_FOLDER").unwrap_or_else(|_| ".".to_string()));
-
-        let agent_dir = PathBuf::from(&agent_folder);
-        if !agent_dir.exists() {
-            bail!("Agent folder does not exist: {}", agent_dir.display());
-        }
+        let agent_dir = agent_dir_from_args()?;
 
         let soul = soul::load_soul(&agent_dir)
             .with_context(|| format!("Failed to load soul from {}", agent_dir.display()))?;
@@ -117,224 +192,1033 @@ impl AgentRunner {
     }
 }
 
+/// Probe each gateway endpoint's `/health`; unreachable ones are logged and
+/// returned for reporting once connected to king. Startup continues either
+/// way, since the gateway may come up later.
+async fn probe_gateway(gateway: &GatewayClient) -> Vec<AgentError> {
+    let http_client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap_or_default();
+    let urls: Vec<String> = gateway
+        .endpoints()
+        .iter()
+        .map(|e| format!("{e}/health"))
+        .collect();
+    health_check::check_endpoints(&http_client, &urls)
+        .await
+        .into_iter()
+        .filter(|h| !h.reachable)
+        .map(|h| {
+            warn!(url = %h.url, "gateway unreachable at startup");
+            AgentError::new(
+                ErrorCategory::GatewayUnreachable,
+                format!("gateway unreachable at startup: {}", h.url),
+            )
+        })
+        .collect()
+}
+
+/// Register with king, reporting a rejection as `agent:error`. The
+/// `startup_errors` follow the registration, since king may drop events
+/// from an agent it doesn't know yet.
+async fn register_with_king(
+    socket: &dyn Emit,
+    payload: Value,
+    requires: &registration::Requirements,
+    agent_id: &str,
+    startup_errors: &[AgentError],
+) -> Result<registration::RegistrationAck> {
+    let ack = match registration::register(socket, payload, requires).await {
+        Ok(ack) => ack,
+        Err(e) => {
+            AgentError::new(ErrorCategory::RegistrationRejected, format!("{e:#}"))
+                .emit(socket, agent_id)
+                .await;
+            return Err(e);
+        }
+    };
+    if !ack.accepted {
+        let reason = ack.reason.as_deref().unwrap_or("no reason given");
+        AgentError::new(
+            ErrorCategory::RegistrationRejected,
+            format!("King rejected agent registration: {reason}"),
+        )
+        .emit(socket, agent_id)
+        .await;
+    }
+    for error in startup_errors {
+        error.emit(socket, agent_id).await;
+    }
+    Ok(ack)
+}
+
+/// A Socket.IO client builder for `king_address` carrying the configured
+/// handshake headers.
+fn king_client_builder(king_address: &str, headers: &HandshakeHeaders) -> ClientBuilder {
+    if !headers.is_empty() {
+        let names: Vec<&str> = headers.iter().map(|(name, _)| name).collect();
+        info!(headers = ?names, "adding handshake headers for king");
+    }
+    headers
+        .iter()
+        .fold(
+            ClientBuilder::new(king_address),
+            |builder, (name, value)| builder.opening_header(name, value),
+        )
+        .namespace("/")
+        // run_client reconnects itself so it can re-register afterwards
+        .reconnect(false)
+}
+
+/// Resolve the agent directory from the CLI arg (or `AGENT_FOLDER` env),
+/// descending one level when the folder itself has no `soul.md`.
+fn agent_dir_from_args() -> Result<PathBuf> {
+    let agent_folder = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| std::env::var("AGENT_FOLDER").unwrap_or_else(|_| ".".to_string()));
+
+    let agent_dir = PathBuf::from(&agent_folder);
+    if !agent_dir.exists() {
+        bail!("Agent folder does not exist: {}", agent_dir.display());
+    }
+
+    soul::resolve_agent_dir(&agent_dir)
+}
+
 // ─── Socket.IO client loop ────────────────────────────────────────────────────
 
 async fn run_client<H: AgentHandler>(
-    soul: &Soul,
+    shared_soul: Arc<SharedSoul>,
     king_address: &str,
     skills: &[LoadedSkill],
     gateway: &Arc<GatewayClient>,
+    config: &RunnerConfig,
     handler: H,
+    startup_errors: &[AgentError],
 ) -> Result<()> {
+    // Closures read a fresh snapshot, so a reloaded soul applies from the
+    // next event on
+    let soul = shared_soul.load();
     let agent_id = soul.agent_id.clone();
-    let role = soul.role.clone();
 
-    // Build capabilities from skill manifests (deduplicated)
-    let capabilities: Vec<String> = skills
-        .iter()
-        .flat_map(|s| s.manifest.capabilities.clone())
-        .collect::<HashSet<_>>()
-        .into_iter()
-        .collect();
+    // Build capabilities from advertised skill manifests plus the handler's own
+    let capabilities = agent_capabilities(&handler, skills);
 
     let skill_names: Vec<String> = skills.iter().map(|s| s.name.clone()).collect();
 
     // Wrap handler in Arc for shared ownership across closures
     let handler = Arc::new(handler);
 
-    // Clone identifiers for each closure
-    let (id_cmd, role_cmd) = (agent_id.clone(), role.clone());
+    // One token bucket shared by every outbound emit path
+    let limiter = Arc::new(EmitLimiter::new(config.emit_limit.clone()));
 
-    // Clones for command handler
-    let handler_cmd = Arc::clone(&handler);
+    // Machine-readable lifecycle events on stdout (EVENT_STREAM_STDOUT=1)
+    let lifecycle = Arc::new(EventStream::from_env(&agent_id));
 
-    // Clones for pipeline handler
-    let soul_pipe = soul.clone();
-    let gateway_pipe = Arc::clone(gateway);
-    let handler_pipe = Arc::clone(&handler);
+    // Skill-scoped secrets never reach the process env the redactor reads
+    let redactor = config.redactor.clone().map(|redactor| {
+        redactor.with_values(
+            skills
+                .iter()
+                .flat_map(|s| s.env.iter())
+                .filter(|(name, _)| crate::redact::is_secret_env_name(name))
+                .map(|(_, value)| value.to_string()),
+        )
+    });
 
-    // Clones for debug prompt handler
-    let soul_debug = soul.clone();
-    let gateway_debug = Arc::clone(gateway);
-    let id_debug = agent_id.clone();
-    let role_debug = role.clone();
+    // Last N inbound events and outcomes on disk (RECENT_EVENTS=<n>)
+    let recent = Arc::new(RecentEvents::from_env(&agent_id).with_redactor(redactor.clone()));
+
+    // Inbound allow-list (INBOUND_EVENTS_STRICT=1), checked before any handling
+    let gate = Arc::new(EventGate::from_env(&soul));
+
+    // Retry policy and in-flight run tokens, shared by pipeline + command handlers
+    let pipeline = Arc::new(
+        PipelineControl::new(config.pipeline_retry.clone(), Arc::clone(&lifecycle))
+            .with_run_cache(config.run_cache.clone())
+            .with_raw_llm(config.include_raw_llm)
+            .with_redactor(redactor)
+            .with_stage_metrics(config.stage_metrics)
+            .with_stage_timeout(config.stage_timeout)
+            .with_max_llm_calls(config.max_llm_calls)
+            .with_recent_events(Arc::clone(&recent))
+            .with_verbose_results(config.verbose_results)
+            .with_full_outputs(Some(FullOutputStore::default_location())),
+    );
 
-    // Clones for task:invite handler
-    let id_invite = agent_id.clone();
+    let skills_shared = Arc::new(SkillSet::new(skills.to_vec()));
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
 
-    // Clones for task:evaluate handler
-    let soul_eval = soul.clone();
-    let gateway_eval = Arc::clone(gateway);
-    let handler_eval = Arc::clone(&handler);
-    let id_eval = agent_id.clone();
+    // Reconnect attempts since the connection dropped; 0 until the first drop
+    let mut reconnect_attempt = 0u32;
+    let mut connected_before = false;
 
-    let socket = ClientBuilder::new(king_address)
-        .namespace("/")
-        // Dispatch king:command via handler
-        .on(events::KING_COMMAND, move |payload, _socket| {
-            let id = id_cmd.clone();
-            let r = role_cmd.clone();
-            let h = Arc::clone(&handler_cmd);
-            Box::pin(async move {
-                if let Some(data) = payload_to_json(&payload) {
-                    let stub = Soul {
-                        agent_id: id,
-                        role: r,
-                        behavior: String::new(),
-                        body: String::new(),
-                    };
-                    let ctx = CommandContext {
-                        soul: &stub,
-                        event: events::KING_COMMAND.to_string(),
-                        data,
-                    };
-                    h.on_command(&ctx);
-                }
-            })
-        })
-        // Dispatch pipeline:next via handler
-        .on(events::PIPELINE_NEXT, move |payload, socket| {
-            let soul = soul_pipe.clone();
-            let gateway = Arc::clone(&gateway_pipe);
-            let h = Arc::clone(&handler_pipe);
-            Box::pin(async move {
-                if let Some(data) = payload_to_json(&payload) {
-                    dispatch_pipeline(&soul, &data, &socket, &gateway, &[], &*h).await;
+    // One pass per connection: (re)connect, register, health check, heartbeat
+    // until king goes away
+    loop {
+        if reconnect_attempt > 0 {
+            let delay = config::reconnect_delay(reconnect_attempt, config.reconnect_max_backoff);
+            info!(
+                attempt = reconnect_attempt,
+                delay_ms = delay.as_millis() as u64,
+                "reconnecting to king"
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = &mut shutdown => {
+                    info!("shutdown signal received while reconnecting");
+                    lifecycle.emit(Lifecycle::ShuttingDown, Value::Null);
+                    return Ok(());
                 }
+            }
+        }
+
+        // Clone identifiers for each closure
+        let soul_cmd = Arc::clone(&shared_soul);
+        let [
+            gate_cmd,
+            gate_pipe,
+            gate_cancel,
+            gate_debug,
+            gate_invite,
+            gate_eval,
+            gate_any,
+        ] = std::array::from_fn(|_| Arc::clone(&gate));
+
+        // Every outbound payload is tagged with the deployment env
+        let [env_pipe, env_debug, env_invite, env_eval] =
+            std::array::from_fn(|_| Arc::clone(&config.env));
+
+        // Clones for command handler
+        let handler_cmd = Arc::clone(&handler);
+        let pipeline_cmd = Arc::clone(&pipeline);
+
+        // Clones for pipeline handler
+        let soul_pipe = Arc::clone(&shared_soul);
+        let skills_pipe = Arc::clone(&skills_shared);
+        let gateway_pipe = Arc::clone(gateway);
+        let handler_pipe = Arc::clone(&handler);
+        let control_pipe = Arc::clone(&pipeline);
+        let limiter_pipe = Arc::clone(&limiter);
+        let control_cancel = Arc::clone(&pipeline);
+
+        // Clones for debug prompt handler
+        let soul_debug = Arc::clone(&shared_soul);
+        let gateway_debug = Arc::clone(gateway);
+        let id_debug = agent_id.clone();
+        let limiter_debug = Arc::clone(&limiter);
+        let recent_debug = Arc::clone(&recent);
+
+        // Clones for task:invite handler
+        let id_invite = agent_id.clone();
+        let recent_invite = Arc::clone(&recent);
+
+        // Clones for task:evaluate handler
+        let soul_eval = Arc::clone(&shared_soul);
+        let gateway_eval = Arc::clone(gateway);
+        let handler_eval = Arc::clone(&handler);
+        let id_eval = agent_id.clone();
+        let limiter_eval = Arc::clone(&limiter);
+        let recent_eval = Arc::clone(&recent);
+
+        // Fired by the "close" callback; a fresh one per connection
+        let disconnected = Arc::new(Notify::new());
+        let disconnected_close = Arc::clone(&disconnected);
+        let lifecycle_close = Arc::clone(&lifecycle);
+
+        let connecting = king_client_builder(king_address, &config.handshake_headers)
+            // Dispatch king:command via handler
+            .on(events::KING_COMMAND, move |payload, _socket| {
+                let shared = Arc::clone(&soul_cmd);
+                let h = Arc::clone(&handler_cmd);
+                let pipeline = Arc::clone(&pipeline_cmd);
+                let gate = Arc::clone(&gate_cmd);
+                Box::pin(async move {
+                    if !gate.admit(events::KING_COMMAND) {
+                        return;
+                    }
+                    if let Some(data) = payload_to_json(&payload) {
+                        pipeline.recent_events.inbound(events::KING_COMMAND, &data);
+                        if data["command"].as_str() == Some("cancel_build") {
+                            let run_id = data["run_id"].as_str().unwrap_or("");
+                            if pipeline.cancel(run_id, None) {
+                                warn!(run_id = %run_id, "cancelling run on king command");
+                            } else {
+                                warn!(run_id = %run_id, "cancel_build for unknown or finished run");
+                            }
+                        }
+                        if data["command"].as_str() == Some("reload_soul") {
+                            reload_soul(&shared, &gate);
+                        }
+                        let soul = shared.load();
+                        let ctx = CommandContext {
+                            soul: &soul,
+                            event: events::KING_COMMAND.to_string(),
+                            data,
+                        };
+                        h.on_command(&ctx);
+                    }
+                })
             })
-        })
-        // Dispatch debug:prompt — send prompt to gateway, return response
-        .on(events::DEBUG_PROMPT, move |payload, socket| {
-            let soul = soul_debug.clone();
-            let gateway = Arc::clone(&gateway_debug);
-            let id = id_debug.clone();
-            let r = role_debug.clone();
-            Box::pin(async move {
-                if let Some(data) = payload_to_json(&payload) {
-                    dispatch_debug_prompt(&soul, &data, &socket, &gateway, &id, &r).await;
-                }
+            // Dispatch pipeline:next via handler
+            .on(events::PIPELINE_NEXT, move |payload, socket| {
+                let soul = soul_pipe.load();
+                let gateway = Arc::clone(&gateway_pipe);
+                let h = Arc::clone(&handler_pipe);
+                let control = Arc::clone(&control_pipe);
+                let skills = Arc::clone(&skills_pipe);
+                let socket: Arc<dyn Emit> = Arc::new(RateLimited::new(
+                    Tagged::new(socket, Arc::clone(&env_pipe)),
+                    Arc::clone(&limiter_pipe),
+                ));
+                let gate = Arc::clone(&gate_pipe);
+                Box::pin(async move {
+                    if !gate.admit(events::PIPELINE_NEXT) {
+                        return;
+                    }
+                    if let Some(data) = payload_to_json(&payload) {
+                        control.recent_events.inbound(events::PIPELINE_NEXT, &data);
+                        // Off the event loop, so pipeline:cancel can arrive mid-stage,
+                        // but behind the run's earlier stages
+                        let mut turn = PipelineControl::queue(
+                            &control,
+                            data["run_id"].as_str().unwrap_or("unknown"),
+                            data["stage"].as_str().unwrap_or("unknown"),
+                        );
+                        tokio::spawn(async move {
+                            turn.wait().await;
+                            dispatch_pipeline(
+                                &soul, &data, socket, &gateway, &skills, &*h, &control,
+                            )
+                            .await;
+                        });
+                    }
+                })
             })
-        })
-        .on(events::TASK_INVITE, move |payload, socket| {
-            let id = id_invite.clone();
-            Box::pin(async move {
-                if let Some(data) = payload_to_json(&payload) {
-                    let task_id = data["task_id"].as_str().unwrap_or("");
-                    if !task_id.is_empty() {
-                        let join_payload = json!({ "task_id": task_id, "agent_id": id });
-                        if let Err(e) = socket.emit(events::TASK_JOIN, join_payload).await {
-                            warn!(err = %e, "failed to emit task:join");
+            // King reassigned or aborted an in-flight stage
+            .on(PIPELINE_CANCEL, move |payload, _socket| {
+                let control = Arc::clone(&control_cancel);
+                let gate = Arc::clone(&gate_cancel);
+                Box::pin(async move {
+                    if !gate.admit(PIPELINE_CANCEL) {
+                        return;
+                    }
+                    if let Some(data) = payload_to_json(&payload) {
+                        control.recent_events.inbound(PIPELINE_CANCEL, &data);
+                        let run_id = data["run_id"].as_str().unwrap_or("");
+                        let stage = data["stage"].as_str();
+                        if control.cancel(run_id, stage) {
+                            warn!(run_id = %run_id, stage = ?stage, "aborting in-flight stage");
                         } else {
-                            info!(task_id = %task_id, "joined task room");
+                            info!(run_id = %run_id, stage = ?stage, "nothing in flight to cancel");
                         }
                     }
-                }
+                })
             })
-        })
-        .on(events::TASK_EVALUATE, move |payload, socket| {
-            let soul = soul_eval.clone();
-            let gateway = Arc::clone(&gateway_eval);
-            let h = Arc::clone(&handler_eval);
-            let agent_id = id_eval.clone();
-            Box::pin(async move {
-                if let Some(data) = payload_to_json(&payload) {
-                    dispatch_task_evaluate(&soul, &data, &socket, &gateway, &agent_id, &*h).await;
-                }
+            // Dispatch debug:prompt — send prompt to gateway, return response
+            .on(events::DEBUG_PROMPT, move |payload, socket| {
+                let soul = soul_debug.load();
+                let gateway = Arc::clone(&gateway_debug);
+                let id = id_debug.clone();
+                let socket = RateLimited::new(
+                    Tagged::new(socket, Arc::clone(&env_debug)),
+                    Arc::clone(&limiter_debug),
+                );
+                let recent = Arc::clone(&recent_debug);
+                let gate = Arc::clone(&gate_debug);
+                Box::pin(async move {
+                    if !gate.admit(events::DEBUG_PROMPT) {
+                        return;
+                    }
+                    if let Some(data) = payload_to_json(&payload) {
+                        recent.inbound(events::DEBUG_PROMPT, &data);
+                        dispatch_debug_prompt(&soul, &data, &socket, &gateway, &id, &soul.role)
+                            .await;
+                    }
+                })
             })
-        })
-        .on("error", |err, _socket| {
-            Box::pin(async move {
-                error!(err = ?err, "socket error received");
+            .on(events::TASK_INVITE, move |payload, socket| {
+                let id = id_invite.clone();
+                let recent = Arc::clone(&recent_invite);
+                let gate = Arc::clone(&gate_invite);
+                let socket = Tagged::new(socket, Arc::clone(&env_invite));
+                Box::pin(async move {
+                    if !gate.admit(events::TASK_INVITE) {
+                        return;
+                    }
+                    if let Some(data) = payload_to_json(&payload) {
+                        recent.inbound(events::TASK_INVITE, &data);
+                        let task_id = data["task_id"].as_str().unwrap_or("");
+                        if !task_id.is_empty() {
+                            let join_payload = json!({ "task_id": task_id, "agent_id": id });
+                            if let Err(e) = socket.emit(events::TASK_JOIN, join_payload).await {
+                                warn!(err = %e, "failed to emit task:join");
+                            } else {
+                                info!(task_id = %task_id, "joined task room");
+                            }
+                        }
+                    }
+                })
             })
-        })
-        .connect()
-        .await
-        .context("Failed to connect to king Socket.IO server")?;
+            .on(events::TASK_EVALUATE, move |payload, socket| {
+                let soul = soul_eval.load();
+                let gateway = Arc::clone(&gateway_eval);
+                let h = Arc::clone(&handler_eval);
+                let agent_id = id_eval.clone();
+                let socket: Arc<dyn Emit> = Arc::new(RateLimited::new(
+                    Tagged::new(socket, Arc::clone(&env_eval)),
+                    Arc::clone(&limiter_eval),
+                ));
+                let recent = Arc::clone(&recent_eval);
+                let gate = Arc::clone(&gate_eval);
+                Box::pin(async move {
+                    if !gate.admit(events::TASK_EVALUATE) {
+                        return;
+                    }
+                    if let Some(data) = payload_to_json(&payload) {
+                        recent.inbound(events::TASK_EVALUATE, &data);
+                        dispatch_task_evaluate(&soul, &data, socket, &gateway, &agent_id, &*h)
+                            .await;
+                    }
+                })
+            })
+            // Event types with no handler at all: nothing to run, but strict mode
+            // still records them as dropped
+            .on_any(move |event, _payload, _socket| {
+                let gate = Arc::clone(&gate_any);
+                Box::pin(async move {
+                    let name = String::from(event);
+                    if gate.is_strict() && !HANDLED_EVENTS.contains(&name.as_str()) {
+                        gate.admit(&name);
+                    }
+                })
+            })
+            .on("error", |err, _socket| {
+                Box::pin(async move {
+                    error!(err = ?err, "socket error received");
+                })
+            })
+            .on("close", move |_payload, _socket| {
+                let lifecycle = Arc::clone(&lifecycle_close);
+                let disconnected = Arc::clone(&disconnected_close);
+                Box::pin(async move {
+                    warn!("disconnected from king");
+                    lifecycle.emit(Lifecycle::Disconnected, Value::Null);
+                    disconnected.notify_one();
+                })
+            })
+            .connect()
+            .await;
+        let socket = match connecting {
+            Ok(socket) => Tagged::new(socket, Arc::clone(&config.env)),
+            // King never reachable: fail startup rather than retry forever
+            Err(e) if !connected_before => {
+                return Err(e).context("Failed to connect to king Socket.IO server");
+            }
+            Err(e) => {
+                warn!(attempt = reconnect_attempt, err = %e, "reconnect to king failed");
+                reconnect_attempt += 1;
+                continue;
+            }
+        };
+        if connected_before {
+            info!(attempts = reconnect_attempt, "reconnected to king");
+        }
+        lifecycle.emit(Lifecycle::Connected, json!({ "king": king_address }));
+
+        // ── Registration ─────────────────────────────────────────────────────
+        let soul = shared_soul.load();
+        let role = soul.role.clone();
+        info!(agent_id = %agent_id, role = %role, "connected to king, sending registration");
+        let reg_payload = registration_payload(&soul, &capabilities, &skill_names);
+        let pending_errors = if connected_before {
+            &[][..]
+        } else {
+            startup_errors
+        };
+        let ack = register_with_king(
+            &socket,
+            reg_payload,
+            &config.requires,
+            &agent_id,
+            pending_errors,
+        )
+        .await?;
+        connected_before = true;
+        if ack.accepted {
+            lifecycle.emit(Lifecycle::Registered, json!({ "role": role }));
+        }
+        handler.on_registered(&ack).await;
+        pipeline.redeliver(&socket).await;
+
+        // ── Post-connect health check ────────────────────────────────────────
+        info!("running post-connect health check against king");
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+
+        let king_health_url = format!("{}/health", king_address);
+        let health_results = health_check::check_endpoints(&http_client, &[king_health_url]).await;
+        let mut health_payload = health_check::health_to_json(&agent_id, &health_results);
+        if config.health_diagnostics {
+            health_payload["diagnostics"] = health_check::Diagnostics::collect(
+                king_address,
+                gateway.endpoints(),
+                soul.default_model(),
+            )
+            .to_json();
+        }
 
-    // ── Registration ─────────────────────────────────────────────────────────
-    info!(agent_id = %agent_id, role = %role, "connected to king, sending registration");
+        let all_healthy = health_results.iter().all(|h| h.reachable);
+        if all_healthy {
+            info!("king health check passed");
+        } else {
+            warn!("king health check failed — king may not be fully reachable via HTTP");
+        }
+
+        if let Err(e) = socket.emit(events::AGENT_HEALTH, health_payload).await {
+            warn!(err = %e, "failed to emit health check results");
+        }
+
+        // ── Heartbeat loop ───────────────────────────────────────────────────
+        info!("entering heartbeat loop");
+
+        let mut hook = HeartbeatHook {
+            handler: Arc::clone(&handler),
+            soul: Arc::clone(&shared_soul),
+            gateway: Arc::clone(gateway),
+            emitter: Arc::new(RateLimited::new(socket.clone(), Arc::clone(&limiter))),
+            tick: 0,
+            running: None,
+            disconnect_timeout: DISCONNECT_HOOK_TIMEOUT,
+        };
+        let mut first = true;
+        let mut failed_heartbeats = 0u32;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(config.heartbeat_interval) => {}
+                _ = disconnected.notified() => break,
+                _ = &mut shutdown => {
+                    info!("shutdown signal received, disconnecting");
+                    lifecycle.emit(Lifecycle::ShuttingDown, Value::Null);
+                    hook.disconnect(DisconnectReason::Shutdown).await;
+                    if let Err(e) = socket.inner().disconnect().await {
+                        warn!(err = %e, "socket disconnect failed");
+                    }
+                    return Ok(());
+                }
+            }
+
+            // Re-register on first heartbeat as a safety net for reconnects
+            if first && config.reregister_on_heartbeat {
+                first = false;
+                let reg = json!({
+                    "agent_id":     agent_id.clone(),
+                    "role":         role.clone(),
+                    "capabilities": capabilities,
+                    "skills":       skill_names,
+                });
+                if let Err(e) = socket.emit(events::AGENT_REGISTER, reg).await {
+                    warn!(err = %e, "heartbeat re-registration failed");
+                }
+            }
+
+            hook.fire();
+            // Stages that finished while the socket was failing
+            pipeline.redeliver(&socket).await;
+
+            let mut payload = json!({
+                "agent_id": agent_id.clone(),
+                "status":   "alive",
+            });
+            if gate.is_strict() {
+                payload["dropped_events"] = json!(gate.dropped());
+            }
+            if let Some(session_id) = &ack.session_id {
+                payload["session_id"] = json!(session_id);
+            }
+
+            match socket.emit(events::AGENT_STATUS, payload).await {
+                Ok(()) => failed_heartbeats = 0,
+                Err(e) => {
+                    failed_heartbeats += 1;
+                    warn!(err = %e, failed = failed_heartbeats, "heartbeat emission failed");
+                    if failed_heartbeats >= MAX_FAILED_HEARTBEATS {
+                        warn!("king connection looks dead, dropping it");
+                        if let Err(e) = socket.inner().disconnect().await {
+                            warn!(err = %e, "socket disconnect failed");
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Let in-flight stages finish; results they can't deliver now are
+        // held and re-sent after reconnecting
+        if pipeline.is_busy() {
+            info!(
+                timeout_secs = config.reconnect_drain.as_secs(),
+                "waiting for in-flight stages before reconnecting"
+            );
+            if !pipeline.drain(config.reconnect_drain).await {
+                warn!("in-flight stages still running, reconnecting anyway");
+            }
+        }
+        hook.disconnect(DisconnectReason::TransportError).await;
+        reconnect_attempt = 1;
+    }
+}
+
+/// The full `agent:register` payload for `soul`.
+fn registration_payload(soul: &Soul, capabilities: &[String], skill_names: &[String]) -> Value {
     let binary_path = std::env::current_exe()
         .map(|p| p.display().to_string())
         .unwrap_or_default();
     let version = option_env!("CARGO_PKG_VERSION").unwrap_or("unknown");
-
-    let reg_payload = json!({
-        "agent_id":      agent_id.clone(),
-        "role":          role.clone(),
+    json!({
+        "agent_id":      soul.agent_id,
+        "role":          soul.role,
         "capabilities":  capabilities,
         "skills":        skill_names,
-        "soul_content":  soul.body.clone(),
+        "soul_content":  soul.body,
         "version":       version,
         "binary_path":   binary_path,
-    });
-    if let Err(e) = socket.emit(events::AGENT_REGISTER, reg_payload).await {
-        warn!(err = %e, "initial registration emit failed — will retry on next heartbeat");
+    })
+}
+
+/// Handle `king:command` `reload_soul`: swap in the re-read `soul.md` and
+/// rebuild the inbound allow-list from its `## Events`. A soul that fails to
+/// load, or changes the role, is logged and the current one kept.
+fn reload_soul(shared: &SharedSoul, gate: &EventGate) {
+    let soul = match shared.reload() {
+        Ok(soul) => soul,
+        Err(e) => {
+            error!(
+                err = format!("{e:#}"),
+                "soul reload failed, keeping the current soul"
+            );
+            return;
+        }
+    };
+    gate.reload(&soul);
+    info!(
+        role = %soul.role,
+        behavior_len = soul.behavior.len(),
+        "soul reloaded"
+    );
+}
+
+/// How often [`PipelineControl::drain`] checks for finished stages.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Longest [`HeartbeatHook::disconnect`] waits for the handler's hooks.
+const DISCONNECT_HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Consecutive failed heartbeat emits after which the connection is treated
+/// as dropped and re-established.
+const MAX_FAILED_HEARTBEATS: u32 = 3;
+
+/// Inbound events `run_client` registers a handler for.
+const HANDLED_EVENTS: &[&str] = &[
+    events::KING_COMMAND,
+    events::PIPELINE_NEXT,
+    PIPELINE_CANCEL,
+    events::DEBUG_PROMPT,
+    events::TASK_INVITE,
+    events::TASK_EVALUATE,
+];
+
+/// Runs [`AgentHandler::on_heartbeat`] on its own task so a slow hook never
+/// delays the heartbeat emit.
+struct HeartbeatHook<H> {
+    handler: Arc<H>,
+    soul: Arc<SharedSoul>,
+    gateway: Arc<GatewayClient>,
+    emitter: Arc<dyn Emit>,
+    tick: u64,
+    running: Option<tokio::task::JoinHandle<()>>,
+    /// Bound on [`disconnect`](Self::disconnect), so a hung hook can't
+    /// block shutdown.
+    disconnect_timeout: Duration,
+}
+
+impl<H: AgentHandler> HeartbeatHook<H> {
+    /// Start the hook for the next tick, unless the last one is still busy.
+    fn fire(&mut self) {
+        self.tick += 1;
+        if self.running.as_ref().is_some_and(|t| !t.is_finished()) {
+            warn!(
+                tick = self.tick,
+                "previous on_heartbeat still running, skipping tick"
+            );
+            return;
+        }
+        let (handler, soul, gateway, emitter, tick) = (
+            Arc::clone(&self.handler),
+            self.soul.load(),
+            Arc::clone(&self.gateway),
+            Arc::clone(&self.emitter),
+            self.tick,
+        );
+        self.running = Some(tokio::spawn(async move {
+            let ctx = HeartbeatContext {
+                soul: &soul,
+                gateway: &gateway,
+                emitter,
+                tick,
+            };
+            handler.on_heartbeat(&ctx).await;
+        }));
     }
 
-    // ── Post-connect health check ────────────────────────────────────────────
-    info!("running post-connect health check against king");
-    let http_client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(5))
-        .build()
-        .unwrap_or_default();
+    /// Let a running `on_heartbeat` finish, then call `on_disconnect`, for
+    /// at most `disconnect_timeout` in total; past it the heartbeat task is
+    /// aborted.
+    async fn disconnect(&mut self, reason: DisconnectReason) {
+        let mut running = self.running.take();
+        let soul = self.soul.load();
+        let finish = async {
+            if let Some(running) = running.as_mut()
+                && let Err(e) = running.await
+            {
+                warn!(err = %e, "on_heartbeat task failed");
+            }
+            let ctx = DisconnectContext {
+                soul: &soul,
+                reason,
+            };
+            self.handler.on_disconnect(&ctx).await;
+        };
+        if tokio::time::timeout(self.disconnect_timeout, finish)
+            .await
+            .is_err()
+        {
+            warn!(
+                timeout_ms = self.disconnect_timeout.as_millis() as u64,
+                "on_heartbeat/on_disconnect hooks timed out, abandoning them"
+            );
+            if let Some(running) = running {
+                running.abort();
+            }
+        }
+    }
+}
+
+/// Resolve on Ctrl-C, or SIGTERM on unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
 
-    let king_health_url = format!("{}/health", king_address);
-    let health_results = health_check::check_endpoints(&http_client, &[king_health_url]).await;
-    let health_payload = health_check::health_to_json(&agent_id, &health_results);
+// ─── Pipeline dispatch ────────────────────────────────────────────────────────
 
-    let all_healthy = health_results.iter().all(|h| h.reachable);
-    if all_healthy {
-        info!("king health check passed");
-    } else {
-        warn!("king health check failed — king may not be fully reachable via HTTP");
+/// Runner-wide pipeline state: the retry policy, the lifecycle event stream,
+/// and a cancellation token per in-flight `(run_id, stage)`, so
+/// `pipeline:cancel`, `king:command` `cancel_build`, or a duplicate
+/// assignment can abort it.
+struct PipelineControl {
+    retry: RetryPolicy,
+    lifecycle: Arc<EventStream>,
+    runs: Mutex<HashMap<(String, String), InFlight>>,
+    next_generation: AtomicU64,
+    run_cache: Option<Arc<RunCache>>,
+    include_raw_llm: bool,
+    stage_metrics: bool,
+    stage_timeout: Option<Duration>,
+    max_llm_calls: Option<u32>,
+    recent_events: Arc<RecentEvents>,
+    verbose_results: bool,
+    full_outputs: Option<FullOutputStore>,
+    /// Stage results whose emit failed (the socket was going away), by
+    /// `(run_id, stage)`, waiting to be re-sent on a live connection.
+    undelivered: Mutex<Vec<((String, String), Value)>>,
+    /// Masks secrets in stage results before they are emitted.
+    redactor: Option<Arc<Redactor>>,
+    /// Per run, the ticket of the last queued dispatch and a receiver that
+    /// resolves once it is done.
+    queues: Mutex<HashMap<String, (u64, oneshot::Receiver<()>)>>,
+    next_ticket: AtomicU64,
+}
+
+/// A dispatch's place in its run's queue; see [`PipelineControl::queue`].
+struct RunTurn {
+    control: Arc<PipelineControl>,
+    run_id: String,
+    ticket: u64,
+    previous: Option<oneshot::Receiver<()>>,
+    /// Dropped with the turn, letting the next dispatch of the run go.
+    _done: oneshot::Sender<()>,
+}
+
+impl RunTurn {
+    /// Wait until every dispatch queued before this one has finished.
+    async fn wait(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            // Resolves (with an error) when the previous turn is dropped
+            let _ = previous.await;
+        }
     }
+}
 
-    if let Err(e) = socket.emit(events::AGENT_HEALTH, health_payload).await {
-        warn!(err = %e, "failed to emit health check results");
+impl Drop for RunTurn {
+    fn drop(&mut self) {
+        let mut queues = self.control.lock_queues();
+        if queues
+            .get(&self.run_id)
+            .is_some_and(|(ticket, _)| *ticket == self.ticket)
+        {
+            queues.remove(&self.run_id);
+        }
     }
+}
 
-    // ── Heartbeat loop ───────────────────────────────────────────────────────
-    info!("entering heartbeat loop");
+/// One in-flight stage. The generation tells a superseded dispatch's
+/// `finish` apart from its replacement's.
+struct InFlight {
+    generation: u64,
+    token: CancellationToken,
+}
 
-    let mut first = true;
-    loop {
-        tokio::time::sleep(Duration::from_secs(30)).await;
-
-        // Re-register on first heartbeat as a safety net for reconnects
-        if first {
-            first = false;
-            let reg = json!({
-                "agent_id":     agent_id.clone(),
-                "role":         role.clone(),
-                "capabilities": capabilities,
-                "skills":       skill_names,
-            });
-            if let Err(e) = socket.emit(events::AGENT_REGISTER, reg).await {
-                warn!(err = %e, "heartbeat re-registration failed");
+impl PipelineControl {
+    fn new(retry: RetryPolicy, lifecycle: Arc<EventStream>) -> Self {
+        Self {
+            retry,
+            lifecycle,
+            runs: Mutex::new(HashMap::new()),
+            next_generation: AtomicU64::new(0),
+            run_cache: None,
+            include_raw_llm: false,
+            stage_metrics: false,
+            stage_timeout: None,
+            max_llm_calls: None,
+            recent_events: Arc::new(RecentEvents::disabled()),
+            verbose_results: false,
+            full_outputs: None,
+            undelivered: Mutex::new(Vec::new()),
+            redactor: None,
+            queues: Mutex::new(HashMap::new()),
+            next_ticket: AtomicU64::new(0),
+        }
+    }
+
+    /// Send full stage outputs inline by default; otherwise only stages
+    /// whose metadata asks for `verbose: true` get them.
+    fn with_verbose_results(mut self, verbose: bool) -> Self {
+        self.verbose_results = verbose;
+        self
+    }
+
+    /// Where the full output of a compacted stage result is written.
+    fn with_full_outputs(mut self, store: Option<FullOutputStore>) -> Self {
+        self.full_outputs = store;
+        self
+    }
+
+    fn with_recent_events(mut self, recent: Arc<RecentEvents>) -> Self {
+        self.recent_events = recent;
+        self
+    }
+
+    fn with_stage_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.stage_timeout = timeout;
+        self
+    }
+
+    fn with_max_llm_calls(mut self, max: Option<u32>) -> Self {
+        self.max_llm_calls = max;
+        self
+    }
+
+    fn with_stage_metrics(mut self, enabled: bool) -> Self {
+        self.stage_metrics = enabled;
+        self
+    }
+
+    fn with_raw_llm(mut self, include: bool) -> Self {
+        self.include_raw_llm = include;
+        self
+    }
+
+    fn with_run_cache(mut self, cache: Option<RunCache>) -> Self {
+        self.run_cache = cache.map(Arc::new);
+        self
+    }
+
+    fn with_redactor(mut self, redactor: Option<Redactor>) -> Self {
+        self.redactor = redactor.map(Arc::new);
+        self
+    }
+
+    /// Queue a dispatch of `stage` behind the run's earlier ones, so a run's
+    /// stages run and report in the order king sent them. A dispatch of the
+    /// same stage still in flight is cancelled right away (king re-assigned
+    /// it) rather than waited for.
+    fn queue(control: &Arc<Self>, run_id: &str, stage: &str) -> RunTurn {
+        if control
+            .lock_runs()
+            .contains_key(&(run_id.to_string(), stage.to_string()))
+        {
+            control.cancel(run_id, Some(stage));
+        }
+        let ticket = control.next_ticket.fetch_add(1, Ordering::Relaxed);
+        let (done, finished) = oneshot::channel();
+        let previous = control
+            .lock_queues()
+            .insert(run_id.to_string(), (ticket, finished))
+            .map(|(_, previous)| previous);
+        RunTurn {
+            control: Arc::clone(control),
+            run_id: run_id.to_string(),
+            ticket,
+            previous,
+            _done: done,
+        }
+    }
+
+    fn lock_queues(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<String, (u64, oneshot::Receiver<()>)>> {
+        self.queues.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Track a new dispatch of `(run_id, stage)`, cancelling any dispatch of
+    /// the same stage still in flight (king re-assigned it).
+    fn start(&self, run_id: &str, stage: &str) -> (u64, CancellationToken) {
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        let token = CancellationToken::new();
+        let previous = self.lock_runs().insert(
+            (run_id.to_string(), stage.to_string()),
+            InFlight {
+                generation,
+                token: token.clone(),
+            },
+        );
+        if let Some(previous) = previous {
+            warn!(
+                run_id,
+                stage, "duplicate assignment — cancelling in-flight dispatch"
+            );
+            previous.token.cancel();
+        }
+        // King re-assigned the stage; the new dispatch reports for it
+        self.lock_undelivered()
+            .retain(|((run, st), _)| run != run_id || st != stage);
+        (generation, token)
+    }
+
+    /// Whether any stage is still being dispatched.
+    fn is_busy(&self) -> bool {
+        !self.lock_runs().is_empty()
+    }
+
+    /// Wait up to `timeout` for in-flight stages to finish; false when some
+    /// are still running.
+    async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.is_busy() {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
             }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
         }
+        true
+    }
 
-        let payload = json!({
-            "agent_id": agent_id.clone(),
-            "status":   "alive",
-        });
+    /// Keep a stage result that could not be emitted, replacing any earlier
+    /// one for the same stage.
+    fn hold_result(&self, run_id: &str, stage: &str, result: Value) {
+        let key = (run_id.to_string(), stage.to_string());
+        let mut undelivered = self.lock_undelivered();
+        undelivered.retain(|(k, _)| *k != key);
+        undelivered.push((key, result));
+    }
 
-        if let Err(e) = socket.emit(events::AGENT_STATUS, payload).await {
-            warn!(err = %e, "heartbeat emission failed");
+    /// Re-send held stage results on `socket`, marked `redelivered: true`
+    /// so king can drop ones it already has. Results that fail again stay
+    /// held.
+    async fn redeliver(&self, socket: &dyn Emit) {
+        let held = std::mem::take(&mut *self.lock_undelivered());
+        for ((run_id, stage), mut result) in held {
+            result["redelivered"] = json!(true);
+            match socket
+                .emit(events::PIPELINE_STAGE_RESULT, result.clone())
+                .await
+            {
+                Ok(()) => info!(run_id = %run_id, stage = %stage, "re-sent held stage result"),
+                Err(e) => {
+                    warn!(run_id = %run_id, stage = %stage, err = %e, "re-sending stage result failed");
+                    self.hold_result(&run_id, &stage, result);
+                }
+            }
         }
     }
-}
 
-// ─── Pipeline dispatch ────────────────────────────────────────────────────────
+    fn lock_undelivered(&self) -> std::sync::MutexGuard<'_, Vec<((String, String), Value)>> {
+        self.undelivered.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stop tracking a dispatch. Returns false when a newer dispatch of the
+    /// same stage has replaced it, i.e. its result is stale.
+    fn finish(&self, run_id: &str, stage: &str, generation: u64) -> bool {
+        let mut runs = self.lock_runs();
+        let key = (run_id.to_string(), stage.to_string());
+        if runs.get(&key).is_some_and(|f| f.generation == generation) {
+            runs.remove(&key);
+            return true;
+        }
+        false
+    }
+
+    /// Cancel `stage` of `run_id` (every stage when `None`); returns false
+    /// when nothing matching is in flight.
+    fn cancel(&self, run_id: &str, stage: Option<&str>) -> bool {
+        let runs = self.lock_runs();
+        let mut found = false;
+        for ((run, st), in_flight) in runs.iter() {
+            if run == run_id && stage.is_none_or(|s| s == st) {
+                in_flight.token.cancel();
+                found = true;
+            }
+        }
+        found
+    }
+
+    fn lock_runs(&self) -> std::sync::MutexGuard<'_, HashMap<(String, String), InFlight>> {
+        self.runs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
 
 async fn dispatch_pipeline(
     soul: &Soul,
     data: &Value,
-    socket: &rust_socketio::asynchronous::Client,
+    socket: Arc<dyn Emit>,
     gateway: &Arc<GatewayClient>,
-    skills: &[LoadedSkill],
+    skills: &SkillSet,
     handler: &dyn AgentHandler,
+    control: &PipelineControl,
 ) {
+    let retry = &control.retry;
     let run_id = data["run_id"].as_str().unwrap_or("unknown").to_string();
     let stage = data["stage"].as_str().unwrap_or("unknown").to_string();
     let artifact_id = data["artifact_id"].as_str().unwrap_or("").to_string();
@@ -347,61 +1231,349 @@ async fn dispatch_pipeline(
         "processing pipeline event"
     );
 
+    let (generation, cancel) = control.start(&run_id, &stage);
+    let skills_used = SkillUsageLog::default();
     let ctx = PipelineContext {
         soul,
         gateway,
-        skills,
+        skills: StageSkills::new(skills, skills_used.clone()),
         run_id: run_id.clone(),
         stage: stage.clone(),
         artifact_id: artifact_id.clone(),
         metadata,
+        cancel,
+        skills_used,
+        emitter: Arc::clone(&socket),
+        run_cache: control.run_cache.clone(),
+        warnings: Default::default(),
+        pipeline_requests: Default::default(),
     };
+    control.lifecycle.emit(
+        Lifecycle::StageStarted,
+        json!({ "run_id": run_id, "stage": stage }),
+    );
+
+    // Per-run budget override supplied by king
+    if let Some(budget) = ctx.metadata["budget_tokens"].as_u64() {
+        gateway.set_run_budget(&run_id, budget);
+    }
 
-    let result = handler.on_pipeline(ctx).await;
+    // Re-run the whole handler on transient failures, per the retry policy
+    let started = std::time::Instant::now();
+    let raw_llm = gateway_client::RawCapture::default();
+    let usage = gateway_client::UsageCapture::default().with_call_limit(control.max_llm_calls);
+    let mut attempts = 0;
+    let mut forced = None;
+    let precheck =
+        check_capabilities(data, skills, handler).and_then(|()| handler.validate_pipeline(&ctx));
+    let cached = || {
+        let cache = control.run_cache.as_ref()?;
+        cache.load_result(&run_id, &stage)
+    };
+    let result = if let Err(e) = precheck {
+        warn!(run_id = %run_id, stage = %stage, err = %e, "pipeline event rejected");
+        forced = Some(StageStatus::Rejected);
+        Err(e)
+    } else if let Some(cached) = cached() {
+        info!(run_id = %run_id, stage = %stage, "stage already completed, resending cached output");
+        forced = Some(StageStatus::Skipped);
+        Ok(cached)
+    } else {
+        loop {
+            attempts += 1;
+            ctx.warnings.clear();
+            ctx.pipeline_requests.clear();
+            let attempt = gateway_client::capture_raw(
+                raw_llm.clone(),
+                gateway_client::capture_usage(
+                    usage.clone(),
+                    gateway_client::scope_run(
+                        run_id.clone(),
+                        handler.on_pipeline_output(ctx.clone()),
+                    ),
+                ),
+            );
+            // Stop waiting on the handler as soon as the stage is cancelled
+            // or its deadline passes
+            let outcome = tokio::select! {
+                outcome = attempt => outcome,
+                _ = ctx.cancel.cancelled() => Err(Cancelled.into()),
+                _ = sleep_or_pending(control.stage_timeout) => {
+                    Err(StageTimedOut(control.stage_timeout.unwrap_or_default()).into())
+                }
+            };
+            // A handler that finished as the cancel landed must not report output
+            let outcome = match outcome {
+                Ok(_) if ctx.cancel.is_cancelled() => Err(Cancelled.into()),
+                other => other,
+            };
+            match outcome {
+                Err(e) if retry.should_retry(error::classify(&e), attempts) => {
+                    let delay = retry.delay(attempts);
+                    warn!(
+                        run_id = %run_id,
+                        attempt = attempts,
+                        kind = %error::classify(&e),
+                        delay_ms = delay.as_millis() as u64,
+                        err = %e,
+                        "transient pipeline failure, retrying"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                other => break other,
+            }
+        }
+    };
+    if !control.finish(&run_id, &stage, generation) {
+        // The replacement dispatch reports for this stage
+        info!(run_id = %run_id, stage = %stage, "dispatch superseded, dropping its stage result");
+        control
+            .recent_events
+            .outcome(&run_id, &stage, "superseded", None);
+        return;
+    }
+    if ctx.cancel.is_cancelled() {
+        // King already gave up on this stage; a result would be stale
+        info!(run_id = %run_id, stage = %stage, "dispatch cancelled, dropping its stage result");
+        control
+            .recent_events
+            .outcome(&run_id, &stage, "cancelled", None);
+        return;
+    }
 
     // Emit pipeline:stage_result back to king
-    let (status, output, error_msg) = match result {
-        Ok(output) => ("completed", output, None),
+    let status = forced.unwrap_or_else(|| stage_status(&result));
+
+    // Mask secrets once, before any copy is cached, stored or emitted
+    let mut result = result;
+    let mut pipeline_requests = ctx.pipeline_requests.snapshot();
+    let mut masked = 0;
+    if let Some(redactor) = &control.redactor {
+        if let Ok(out) = &mut result {
+            masked += redactor.redact(&mut out.output);
+            if let Some(metrics) = &mut out.metrics {
+                masked += redactor.redact(metrics);
+            }
+        }
+        for (_, metadata) in &mut pipeline_requests {
+            masked += redactor.redact(metadata);
+        }
+    }
+
+    if status == StageStatus::Completed
+        && let (Ok(out), Some(cache)) = (&result, &control.run_cache)
+        && let Err(e) = cache.store(&run_id, &stage, out)
+    {
+        warn!(run_id = %run_id, stage = %stage, err = %e, "failed to cache stage result");
+    }
+    let (
+        HandlerOutput {
+            output,
+            mut artifacts,
+            metrics: handler_metrics,
+        },
+        error_msg,
+    ) = match result {
+        Ok(out) => (out, None),
         Err(e) => {
             error!(
                 role = %soul.role,
                 run_id = %run_id,
+                ?status,
                 err = %e,
                 "pipeline stage failed"
             );
-            ("failed", Value::Null, Some(e.to_string()))
+            (HandlerOutput::default(), Some(e.to_string()))
         }
     };
+    control
+        .recent_events
+        .outcome(&run_id, &stage, status.as_str(), error_msg.as_deref());
 
-    let stage_result = json!({
-        "run_id": run_id,
-        "stage": stage,
-        "agent_id": soul.agent_id,
-        "status": status,
+    // Compact by default; the full output goes to an artifact instead
+    let output = if control.verbose_results || stage_output::wants_verbose(&ctx.metadata) {
+        output
+    } else {
+        let (compacted, trimmed) = stage_output::compact(&output);
+        if trimmed && let Some(store) = &control.full_outputs {
+            match store.store(&run_id, &stage, &output) {
+                Ok(artifact) => artifacts.push(artifact),
+                Err(e) => {
+                    warn!(run_id = %run_id, stage = %stage, err = %e, "failed to store full stage output");
+                }
+            }
+        }
+        compacted
+    };
+
+    let mut stage_result = json!({
+        "run_id": run_id,
+        "stage": stage,
+        "agent_id": soul.agent_id,
+        "status": status,
         "artifact_id": artifact_id,
         "output": output,
         "error": error_msg,
+        "attempts": attempts,
+        "skills_used": ctx.skills_used.snapshot(),
+        "output_schema": handler.output_schema_version(),
     });
+    let warnings = ctx.warnings.snapshot();
+    if !warnings.is_empty() {
+        stage_result["warnings"] = json!(warnings);
+    }
+    if control.include_raw_llm {
+        stage_result["_raw_llm"] = json!(raw_llm.texts());
+    }
+    if let Some(redactor) = &control.redactor {
+        for key in ["error", "warnings", "_raw_llm"] {
+            if let Some(value) = stage_result.get_mut(key) {
+                masked += redactor.redact(value);
+            }
+        }
+    }
+    if masked > 0 {
+        warn!(run_id = %run_id, stage = %stage, masked, "redacted secrets from stage result");
+    }
+    if !artifacts.is_empty() {
+        stage_result["artifacts"] = json!(artifacts);
+    }
+    for artifact in &artifacts {
+        let payload = json!({
+            "run_id": run_id,
+            "stage": stage,
+            "agent_id": soul.agent_id,
+            "artifact": artifact,
+        });
+        if let Err(e) = socket.emit(PIPELINE_ARTIFACT, payload).await {
+            warn!(run_id = %run_id, artifact = %artifact.name, err = %e, "failed to emit pipeline:artifact");
+        }
+    }
+
+    let transition = if status.is_success() {
+        Lifecycle::StageCompleted
+    } else {
+        Lifecycle::StageFailed
+    };
+    control.lifecycle.emit(
+        transition,
+        json!({
+            "run_id": run_id,
+            "stage": stage,
+            "attempts": attempts,
+            "error": stage_result["error"],
+        }),
+    );
 
     if let Err(e) = socket
-        .emit(events::PIPELINE_STAGE_RESULT, stage_result)
+        .emit(events::PIPELINE_STAGE_RESULT, stage_result.clone())
         .await
     {
-        error!(
+        warn!(
             run_id = %run_id,
             stage = %stage,
             err = %e,
-            "failed to emit pipeline:stage_result"
+            "failed to emit pipeline:stage_result, holding it for redelivery"
         );
+        control.hold_result(&run_id, &stage, stage_result);
+    }
+
+    // Follow-up runs only chain off work that actually completed
+    if status == StageStatus::Completed {
+        for (requested_stage, metadata) in pipeline_requests {
+            let payload = json!({
+                "run_id": run_id,
+                "stage": stage,
+                "agent_id": soul.agent_id,
+                "requested_stage": requested_stage,
+                "metadata": metadata,
+            });
+            if let Err(e) = socket.emit(PIPELINE_REQUEST, payload).await {
+                warn!(run_id = %run_id, requested_stage = %requested_stage, err = %e, "failed to emit pipeline:request");
+            }
+        }
+    }
+
+    if control.stage_metrics || handler_metrics.is_some() {
+        let totals = usage.totals();
+        let mut metrics = json!({
+            "run_id": run_id,
+            "stage": stage,
+            "agent_id": soul.agent_id,
+            "status": status,
+            "attempts": attempts,
+            "llm_calls": totals.llm_calls,
+            "llm_calls_started": usage.calls_started(),
+            "llm_call_limit": control.max_llm_calls,
+            "prompt_tokens": totals.prompt_tokens,
+            "completion_tokens": totals.completion_tokens,
+            "gateway_latency_ms": totals.gateway_latency_ms,
+            "skill_calls": ctx.skills_used.snapshot().len(),
+            "wall_ms": started.elapsed().as_millis() as u64,
+        });
+        if let Some(handler_metrics) = handler_metrics {
+            metrics["handler"] = handler_metrics;
+        }
+        if let Err(e) = socket.emit(PIPELINE_STAGE_METRICS, metrics).await {
+            warn!(run_id = %run_id, err = %e, "failed to emit pipeline:stage_metrics");
+        }
+    }
+}
+
+/// Stage deadline exceeded (`STAGE_TIMEOUT_SECS`).
+#[derive(Debug, thiserror::Error)]
+#[error("stage timed out after {}s", .0.as_secs_f64())]
+struct StageTimedOut(Duration);
+
+/// Status of a stage that ran its handler.
+fn stage_status<T>(result: &Result<T>) -> StageStatus {
+    match result {
+        Ok(_) => StageStatus::Completed,
+        Err(e) if e.is::<Cancelled>() => StageStatus::Cancelled,
+        Err(e) if e.is::<StageTimedOut>() || e.is::<self_upgrade::CommandTimedOut>() => {
+            StageStatus::TimedOut
+        }
+        Err(_) => StageStatus::Failed,
     }
 }
 
+/// Deduplicated capabilities announced to king: advertised skills' plus the
+/// handler's built-in ones.
+fn agent_capabilities(handler: &dyn AgentHandler, skills: &[LoadedSkill]) -> Vec<String> {
+    let mut caps = skill_engine::advertised_capabilities(skills);
+    caps.extend(handler.capabilities());
+    caps.sort();
+    caps.dedup();
+    caps
+}
+
+/// Refuse events whose `required_capabilities` aren't all advertised here.
+fn check_capabilities(
+    data: &Value,
+    skills: &[LoadedSkill],
+    handler: &dyn AgentHandler,
+) -> Result<()> {
+    let Some(required) = data["required_capabilities"].as_array() else {
+        return Ok(());
+    };
+    let available = agent_capabilities(handler, skills);
+    let missing: Vec<&str> = required
+        .iter()
+        .filter_map(Value::as_str)
+        .filter(|cap| !available.iter().any(|a| a == cap))
+        .collect();
+    if !missing.is_empty() {
+        bail!("agent lacks required capabilities: {}", missing.join(", "));
+    }
+    Ok(())
+}
+
 // ─── Task evaluate dispatch ──────────────────────────────────────────────────
 
 async fn dispatch_task_evaluate(
     soul: &Soul,
     data: &Value,
-    socket: &rust_socketio::asynchronous::Client,
+    socket: Arc<dyn Emit>,
     gateway: &Arc<GatewayClient>,
     agent_id: &str,
     handler: &dyn AgentHandler,
@@ -424,6 +1596,7 @@ async fn dispatch_task_evaluate(
         exit_code,
         latency_ms,
         metadata,
+        emitter: Arc::clone(&socket),
     };
 
     match handler.on_task_evaluate(ctx).await {
@@ -447,26 +1620,27 @@ async fn dispatch_task_evaluate(
 
 // ─── Debug prompt dispatch ────────────────────────────────────────────────────
 
-async fn dispatch_debug_prompt(
+async fn dispatch_debug_prompt<E: Emit + Clone + 'static>(
     soul: &Soul,
     data: &Value,
-    socket: &rust_socketio::asynchronous::Client,
+    socket: &E,
     gateway: &Arc<GatewayClient>,
     agent_id: &str,
     role: &str,
 ) {
     let request_id = data["request_id"].as_str().unwrap_or("unknown").to_string();
     let task_id = data["task_id"].as_str().map(|s| s.to_string());
-    let model = data["model"].as_str().unwrap_or("gpt-4o-mini").to_string();
+    // An explicit request model wins; otherwise the agent's own default
+    let model = data["model"]
+        .as_str()
+        .unwrap_or_else(|| soul.default_model())
+        .to_string();
     let prompt = data["prompt"].as_str().unwrap_or("").to_string();
     let temperature = data["temperature"].as_f64();
     let max_tokens = data["max_tokens"].as_u64().map(|n| n as u32);
 
-    // Prepend provider prefix if specified
-    let full_model = match data["provider"].as_str() {
-        Some(p) if !p.is_empty() => format!("{p}:{model}"),
-        _ => model.clone(),
-    };
+    // Prepend provider prefix if specified (without double-prefixing)
+    let full_model = ModelRef::with_provider(&model, data["provider"].as_str()).to_string();
 
     info!(
         agent_id = %agent_id,
@@ -548,6 +1722,9 @@ async fn dispatch_debug_prompt(
                 "error": e.to_string(),
                 "latency_ms": latency_ms,
             });
+            if let Some(stream_err) = e.downcast_ref::<StreamError>() {
+                payload["partial_response"] = json!(stream_err.partial);
+            }
             if let Some(ref tid) = task_id {
                 payload["task_id"] = json!(tid);
             }
@@ -573,3 +1750,1457 @@ fn payload_to_json(payload: &Payload) -> Option<Value> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::MAX_PIPELINE_REQUESTS;
+    use crate::test_support::{self, RecordingEmitter};
+    use async_trait::async_trait;
+    use std::sync::atomic::AtomicU32;
+
+    /// Fails with a transient error on the first call, then succeeds.
+    struct FlakyHandler {
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl AgentHandler for FlakyHandler {
+        async fn on_pipeline(&self, _ctx: PipelineContext<'_>) -> Result<Value> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(error::transient(anyhow::anyhow!("flaky")));
+            }
+            Ok(json!({ "ok": true }))
+        }
+    }
+
+    /// Calls the `test-skill` skill once.
+    struct SkillUser;
+
+    #[async_trait]
+    impl AgentHandler for SkillUser {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            ctx.invoke_skill("test-skill", &json!({ "q": "x" })).await
+        }
+    }
+
+    /// Produces output in `learning`; returns the cached `learning` output
+    /// in any later stage.
+    struct StageReader;
+
+    #[async_trait]
+    impl AgentHandler for StageReader {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            if ctx.stage == "learning" {
+                return Ok(json!({ "candidate": "weather-api" }));
+            }
+            Ok(ctx.previous_stage("learning").unwrap_or(Value::Null))
+        }
+    }
+
+    #[tokio::test]
+    async fn later_stage_reads_cached_earlier_output() {
+        let dir = test_support::temp_dir("stage-cache");
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let emitter = Arc::new(RecordingEmitter::default());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()))
+                .with_run_cache(Some(RunCache::new(&dir, Duration::from_secs(60), 10)));
+
+        for stage in ["learning", "building"] {
+            let data = json!({ "run_id": "run-1", "stage": stage });
+            dispatch_pipeline(
+                &soul,
+                &data,
+                emitter.clone(),
+                &gateway,
+                test_support::no_skills(),
+                &StageReader,
+                &control,
+            )
+            .await;
+        }
+
+        let (_, building) = &emitter.events()[1];
+        assert_eq!(building["stage"], "building");
+        assert_eq!(building["output"]["candidate"], "weather-api");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn startup_errors_are_reported_after_registration() {
+        let king = RecordingEmitter::with_ack(json!({ "accepted": true }));
+        let startup = vec![AgentError::new(
+            ErrorCategory::GatewayUnreachable,
+            "gateway unreachable at startup: http://127.0.0.1:9/health",
+        )];
+
+        let ack = register_with_king(
+            &king,
+            json!({ "agent_id": "a" }),
+            &registration::Requirements::default(),
+            "a",
+            &startup,
+        )
+        .await
+        .unwrap();
+
+        assert!(ack.accepted);
+        let names: Vec<String> = king.events().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            [events::AGENT_REGISTER, crate::agent_error::AGENT_ERROR]
+        );
+        assert_eq!(king.events()[1].1["category"], "gateway_unreachable");
+    }
+
+    #[tokio::test]
+    async fn gateways_are_probed_concurrently() {
+        let slow = || {
+            test_support::MockResponse::new(200, "text/plain", "ok")
+                .with_delay(Duration::from_millis(600))
+        };
+        let a = test_support::MockServer::start(vec![slow()]).await;
+        let b = test_support::MockServer::start(vec![slow()]).await;
+        let gateway =
+            GatewayClient::new(&format!("{},{},http://127.0.0.1:9", a.url, b.url)).unwrap();
+
+        let started = std::time::Instant::now();
+        let errors = probe_gateway(&gateway).await;
+
+        assert!(started.elapsed() < Duration::from_millis(1100));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("127.0.0.1:9"), "{errors:?}");
+    }
+
+    #[tokio::test]
+    async fn handshake_carries_configured_auth_header() {
+        let server = test_support::MockServer::start(vec![test_support::MockResponse::new(
+            401,
+            "text/plain",
+            "unauthorized",
+        )])
+        .await;
+        let headers = HandshakeHeaders::default().with("Authorization", "Bearer s3cret");
+
+        let connect = king_client_builder(&server.url, &headers)
+            .reconnect(false)
+            .connect()
+            .await;
+
+        assert!(connect.is_err());
+        let handshake = &server.requests()[0];
+        assert!(
+            handshake.path.starts_with("/socket.io/"),
+            "{}",
+            handshake.path
+        );
+        assert_eq!(handshake.headers["authorization"], "Bearer s3cret");
+        assert!(!format!("{headers:?}").contains("s3cret"));
+    }
+
+    /// Never finishes on its own; signals once it has started.
+    struct Stuck(tokio::sync::Notify);
+
+    #[async_trait]
+    impl AgentHandler for Stuck {
+        async fn on_pipeline(&self, _ctx: PipelineContext<'_>) -> Result<Value> {
+            self.0.notify_one();
+            std::future::pending().await
+        }
+    }
+
+    /// A socket king has gone away from: every emit fails.
+    struct DeadSocket;
+
+    #[async_trait]
+    impl Emit for DeadSocket {
+        async fn emit(&self, _event: &str, _payload: Value) -> Result<()> {
+            bail!("socket closed")
+        }
+    }
+
+    struct Gated(tokio::sync::Notify);
+
+    #[async_trait]
+    impl AgentHandler for Gated {
+        async fn on_pipeline(&self, _ctx: PipelineContext<'_>) -> Result<Value> {
+            self.0.notified().await;
+            Ok(json!({ "built": true }))
+        }
+    }
+
+    #[tokio::test]
+    async fn result_finished_during_disconnect_is_delivered_after_reconnect() {
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let handler = Gated(tokio::sync::Notify::new());
+        let data = json!({ "run_id": "run-1", "stage": "building" });
+
+        // The stage is mid-flight when the socket dies; the drain waits for it
+        let dispatch = dispatch_pipeline(
+            &soul,
+            &data,
+            Arc::new(DeadSocket),
+            &gateway,
+            test_support::no_skills(),
+            &handler,
+            &control,
+        );
+        let reconnect = async {
+            tokio::task::yield_now().await;
+            assert!(control.is_busy());
+            assert!(!control.drain(Duration::from_millis(20)).await);
+            handler.0.notify_one();
+            assert!(control.drain(Duration::from_secs(5)).await);
+        };
+        tokio::join!(dispatch, reconnect);
+
+        let reconnected = RecordingEmitter::default();
+        control.redeliver(&reconnected).await;
+        let events = reconnected.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, events::PIPELINE_STAGE_RESULT);
+        assert_eq!(events[0].1["run_id"], "run-1");
+        assert_eq!(events[0].1["output"], json!({ "built": true }));
+        assert_eq!(events[0].1["redelivered"], true);
+
+        // Delivered once: nothing left to re-send on the next heartbeat
+        control.redeliver(&reconnected).await;
+        assert_eq!(reconnected.events().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelled_in_flight_stage_sends_no_result() {
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let emitter = Arc::new(RecordingEmitter::default());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let handler = Stuck(tokio::sync::Notify::new());
+        let data = json!({ "run_id": "run-1", "stage": "building" });
+
+        let dispatch = dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &handler,
+            &control,
+        );
+        let cancel = async {
+            handler.0.notified().await;
+            assert!(!control.cancel("run-1", Some("learning")));
+            assert!(control.cancel("run-1", Some("building")));
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(dispatch, cancel)
+        })
+        .await
+        .expect("cancelled stage did not finish");
+
+        assert!(emitter.events().is_empty(), "{:?}", emitter.events());
+        assert!(
+            !control.cancel("run-1", None),
+            "finished stage still tracked"
+        );
+    }
+
+    /// Logs when each stage starts and ends; `slow` stages take a while.
+    #[derive(Default)]
+    struct Journal(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl AgentHandler for Journal {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            self.0.lock().unwrap().push(format!("{}+", ctx.stage));
+            if ctx.metadata["slow"] == true {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            self.0.lock().unwrap().push(format!("{}-", ctx.stage));
+            Ok(Value::Null)
+        }
+    }
+
+    #[tokio::test]
+    async fn queued_stages_of_a_run_dispatch_in_order() {
+        let soul = Arc::new(test_support::soul("building"));
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let emitter = Arc::new(RecordingEmitter::default());
+        let handler = Arc::new(Journal::default());
+        let control = Arc::new(PipelineControl::new(
+            RetryPolicy::default(),
+            Arc::new(EventStream::disabled()),
+        ));
+
+        let mut tasks = Vec::new();
+        for data in [
+            json!({ "run_id": "run-1", "stage": "learning", "metadata": { "slow": true } }),
+            json!({ "run_id": "run-1", "stage": "building" }),
+        ] {
+            let mut turn = PipelineControl::queue(
+                &control,
+                data["run_id"].as_str().unwrap(),
+                data["stage"].as_str().unwrap(),
+            );
+            let (soul, gateway, handler, control) = (
+                Arc::clone(&soul),
+                Arc::clone(&gateway),
+                Arc::clone(&handler),
+                Arc::clone(&control),
+            );
+            let emitter: Arc<dyn Emit> = emitter.clone();
+            tasks.push(tokio::spawn(async move {
+                turn.wait().await;
+                dispatch_pipeline(
+                    &soul,
+                    &data,
+                    emitter,
+                    &gateway,
+                    test_support::no_skills(),
+                    &*handler,
+                    &control,
+                )
+                .await;
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(
+            *handler.0.lock().unwrap(),
+            vec!["learning+", "learning-", "building+", "building-"]
+        );
+        let stages: Vec<Value> = emitter
+            .events()
+            .iter()
+            .map(|(_, r)| r["stage"].clone())
+            .collect();
+        assert_eq!(stages, vec![json!("learning"), json!("building")]);
+        assert!(control.lock_queues().is_empty());
+    }
+
+    /// Hangs like [`Stuck`] when metadata says `stuck`, otherwise answers.
+    struct StuckOnce(tokio::sync::Notify);
+
+    #[async_trait]
+    impl AgentHandler for StuckOnce {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            if ctx.metadata["stuck"] == true {
+                self.0.notify_one();
+                std::future::pending::<()>().await;
+            }
+            Ok(json!({ "fresh": true }))
+        }
+    }
+
+    #[tokio::test]
+    async fn superseded_dispatch_emits_no_stale_result() {
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let emitter = Arc::new(RecordingEmitter::default());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let handler = StuckOnce(tokio::sync::Notify::new());
+        let old = json!({ "run_id": "run-1", "stage": "building", "metadata": { "stuck": true } });
+        let new = json!({ "run_id": "run-1", "stage": "building" });
+
+        let first = dispatch_pipeline(
+            &soul,
+            &old,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &handler,
+            &control,
+        );
+        let second = async {
+            handler.0.notified().await;
+            dispatch_pipeline(
+                &soul,
+                &new,
+                emitter.clone(),
+                &gateway,
+                test_support::no_skills(),
+                &handler,
+                &control,
+            )
+            .await;
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(first, second)
+        })
+        .await
+        .expect("superseded stage did not finish");
+
+        let results: Vec<Value> = emitter
+            .events()
+            .into_iter()
+            .filter(|(event, _)| event == events::PIPELINE_STAGE_RESULT)
+            .map(|(_, payload)| payload)
+            .collect();
+        assert_eq!(results.len(), 1, "{results:?}");
+        assert_eq!(results[0]["status"], "completed");
+        assert_eq!(results[0]["output"]["fresh"], true);
+    }
+
+    /// Answers immediately, or fails when metadata asks it to.
+    struct Echo;
+
+    #[async_trait]
+    impl AgentHandler for Echo {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            if ctx.metadata["fail"] == true {
+                bail!("boom");
+            }
+            Ok(json!({ "stage": ctx.stage }))
+        }
+    }
+
+    #[tokio::test]
+    async fn each_outcome_reports_its_own_status() {
+        let dir = test_support::temp_dir("stage-status");
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()))
+                .with_run_cache(Some(RunCache::new(&dir, Duration::from_secs(60), 10)))
+                .with_stage_timeout(Some(Duration::from_millis(50)));
+        let stuck = Stuck(tokio::sync::Notify::new());
+
+        let cases: [(Value, &dyn AgentHandler, &str); 5] = [
+            (json!({ "run_id": "r1", "stage": "a" }), &Echo, "completed"),
+            (json!({ "run_id": "r1", "stage": "a" }), &Echo, "skipped"),
+            (
+                json!({ "run_id": "r2", "stage": "a", "metadata": { "fail": true } }),
+                &Echo,
+                "failed",
+            ),
+            (
+                json!({ "run_id": "r3", "stage": "a", "required_capabilities": ["vision"] }),
+                &Echo,
+                "rejected",
+            ),
+            (json!({ "run_id": "r4", "stage": "a" }), &stuck, "timed_out"),
+        ];
+        for (data, handler, expected) in cases {
+            let emitter = Arc::new(RecordingEmitter::default());
+            dispatch_pipeline(
+                &soul,
+                &data,
+                emitter.clone(),
+                &gateway,
+                test_support::no_skills(),
+                handler,
+                &control,
+            )
+            .await;
+            let (_, result) = &emitter.events()[0];
+            assert_eq!(result["status"], expected, "{data}");
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    struct SchemaV3;
+
+    #[async_trait]
+    impl AgentHandler for SchemaV3 {
+        fn output_schema_version(&self) -> u32 {
+            3
+        }
+
+        async fn on_pipeline(&self, _ctx: PipelineContext<'_>) -> Result<Value> {
+            Ok(json!({ "items": [] }))
+        }
+    }
+
+    #[tokio::test]
+    async fn stage_result_carries_output_schema_version() {
+        let soul = test_support::soul("learning");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let data = json!({ "run_id": "run-1", "stage": "learning" });
+
+        for (handler, expected) in [(&SchemaV3 as &dyn AgentHandler, 3), (&Echo, 1)] {
+            let emitter = Arc::new(RecordingEmitter::default());
+            dispatch_pipeline(
+                &soul,
+                &data,
+                emitter.clone(),
+                &gateway,
+                test_support::no_skills(),
+                handler,
+                &control,
+            )
+            .await;
+            assert_eq!(emitter.events()[0].1["output_schema"], expected);
+        }
+    }
+
+    /// Parses a single completion, like the kernel handlers do.
+    struct Parser;
+
+    #[async_trait]
+    impl AgentHandler for Parser {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            let text = ctx
+                .gateway
+                .chat_completion("m", "s", "u", None, None)
+                .await?;
+            Ok(serde_json::from_str(&text)?)
+        }
+    }
+
+    #[tokio::test]
+    async fn raw_llm_text_is_attached_only_when_enabled() {
+        let server = test_support::MockServer::start(vec![test_support::MockResponse::json(
+            200,
+            &json!({ "choices": [{ "message": { "content": "{\"score\": 0.8}" } }] }),
+        )])
+        .await;
+        let soul = test_support::soul("evaluation");
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let data = json!({ "run_id": "run-1", "stage": "evaluation" });
+
+        for include in [false, true] {
+            let emitter = Arc::new(RecordingEmitter::default());
+            let control =
+                PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()))
+                    .with_raw_llm(include);
+            dispatch_pipeline(
+                &soul,
+                &data,
+                emitter.clone(),
+                &gateway,
+                test_support::no_skills(),
+                &Parser,
+                &control,
+            )
+            .await;
+
+            let (_, result) = &emitter.events()[0];
+            assert_eq!(result["output"]["score"], 0.8);
+            if include {
+                assert_eq!(result["_raw_llm"], json!(["{\"score\": 0.8}"]));
+            } else {
+                assert!(result.get("_raw_llm").is_none());
+            }
+        }
+    }
+
+    /// Keeps asking the LLM, like a repair loop that never converges.
+    struct Chatty;
+
+    #[async_trait]
+    impl AgentHandler for Chatty {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            for _ in 0..3 {
+                ctx.gateway
+                    .chat_completion("m", "s", "u", None, None)
+                    .await?;
+            }
+            Ok(json!({}))
+        }
+    }
+
+    #[tokio::test]
+    async fn exceeding_the_llm_call_limit_fails_the_stage() {
+        let reply = || {
+            test_support::MockResponse::json(
+                200,
+                &json!({ "choices": [{ "message": { "content": "again" } }] }),
+            )
+        };
+        let server = test_support::MockServer::start(vec![reply(), reply()]).await;
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let emitter = Arc::new(RecordingEmitter::default());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()))
+                .with_max_llm_calls(Some(2))
+                .with_stage_metrics(true);
+        let data = json!({ "run_id": "run-1", "stage": "building" });
+
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &Chatty,
+            &control,
+        )
+        .await;
+
+        let events = emitter.events();
+        let result = &events[0].1;
+        assert_eq!(result["status"], "failed");
+        assert!(
+            result["error"].as_str().unwrap().contains("call limit"),
+            "{result}"
+        );
+        assert_eq!(server.requests().len(), 2);
+        let metrics = &events[1].1;
+        assert_eq!(events[1].0, PIPELINE_STAGE_METRICS);
+        assert_eq!(metrics["llm_calls"], 2);
+        assert_eq!(metrics["llm_calls_started"], 3);
+        assert_eq!(metrics["llm_call_limit"], 2);
+    }
+
+    /// Answers with the behavior prompt it was dispatched with.
+    struct BehaviorEcho;
+
+    #[async_trait]
+    impl AgentHandler for BehaviorEcho {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            Ok(json!({ "behavior": ctx.soul.behavior }))
+        }
+    }
+
+    #[tokio::test]
+    async fn reloaded_behavior_reaches_later_dispatches() {
+        let dir = test_support::temp_dir("reload-soul");
+        let write_soul = |behavior: &str| {
+            std::fs::write(
+                dir.join("soul.md"),
+                format!("## Role\nlearning\n\n## Behavior\n{behavior}\n"),
+            )
+            .unwrap();
+        };
+        write_soul("Find skills carefully.");
+        let shared = SharedSoul::new(&dir, soul::load_soul(&dir).unwrap());
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let gate = EventGate::strict(["pipeline:next"]);
+        let dispatch = |run_id: &'static str| {
+            let emitter = Arc::new(RecordingEmitter::default());
+            let soul = shared.load();
+            let (gateway, control) = (&gateway, &control);
+            async move {
+                let data = json!({ "run_id": run_id, "stage": "learning" });
+                dispatch_pipeline(
+                    &soul,
+                    &data,
+                    emitter.clone(),
+                    gateway,
+                    test_support::no_skills(),
+                    &BehaviorEcho,
+                    control,
+                )
+                .await;
+                emitter.events()[0].1["output"]["behavior"].clone()
+            }
+        };
+
+        assert_eq!(dispatch("run-1").await, "Find skills carefully.");
+
+        write_soul("Find skills quickly.\n\n## Events\n- pipeline:next\n- debug:prompt");
+        reload_soul(&shared, &gate);
+        assert_eq!(dispatch("run-2").await, "Find skills quickly.");
+        // The allow-list follows the reloaded `## Events`
+        assert!(gate.admit("debug:prompt"));
+
+        // A broken soul.md, or one with another role, keeps the last good one
+        for broken in ["no role here", "## Role\nbuilding\n\n## Behavior\nBuild.\n"] {
+            std::fs::write(dir.join("soul.md"), broken).unwrap();
+            reload_soul(&shared, &gate);
+            assert_eq!(dispatch("run-3").await, "Find skills quickly.");
+        }
+        assert_eq!(shared.load().role, "learning");
+    }
+
+    struct Cautious;
+
+    #[async_trait]
+    impl AgentHandler for Cautious {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            ctx.warn("manifest declares no capabilities");
+            ctx.warn("optional endpoint https://example.test is down");
+            Ok(json!({ "built": true }))
+        }
+    }
+
+    #[tokio::test]
+    async fn handler_warnings_are_attached_to_the_stage_result() {
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let emitter = Arc::new(RecordingEmitter::default());
+        let data = json!({ "run_id": "run-1", "stage": "building" });
+
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &Cautious,
+            &control,
+        )
+        .await;
+
+        let (_, result) = emitter
+            .events()
+            .into_iter()
+            .find(|(event, _)| event == events::PIPELINE_STAGE_RESULT)
+            .unwrap();
+        assert_eq!(result["status"], "completed");
+        assert_eq!(
+            result["warnings"],
+            json!([
+                "manifest declares no capabilities",
+                "optional endpoint https://example.test is down",
+            ])
+        );
+
+        // A clean stage carries no `warnings` at all
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &Bulky,
+            &control,
+        )
+        .await;
+        let last = emitter.events().pop().unwrap();
+        assert!(last.1.get("warnings").is_none());
+    }
+
+    struct Chaining;
+
+    #[async_trait]
+    impl AgentHandler for Chaining {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            assert!(ctx.request_pipeline("building", json!({ "skill": "weather" })));
+            for n in 1..MAX_PIPELINE_REQUESTS {
+                assert!(ctx.request_pipeline("learning", json!({ "topic": n })));
+            }
+            assert!(!ctx.request_pipeline("learning", json!({ "topic": "one too many" })));
+            Ok(json!({ "candidates": ["weather"] }))
+        }
+    }
+
+    #[tokio::test]
+    async fn handler_pipeline_requests_are_emitted_after_the_stage_result() {
+        let soul = test_support::soul("learning");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let emitter = Arc::new(RecordingEmitter::default());
+        let data = json!({ "run_id": "run-1", "stage": "learning" });
+
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &Chaining,
+            &control,
+        )
+        .await;
+
+        let events = emitter.events();
+        assert_eq!(events[0].0, events::PIPELINE_STAGE_RESULT);
+        assert_eq!(
+            events[0].1["warnings"][0],
+            "pipeline request for 'learning' dropped: limit of 5 per stage reached"
+        );
+        let requests: Vec<&Value> = events
+            .iter()
+            .filter(|(event, _)| event == PIPELINE_REQUEST)
+            .map(|(_, payload)| payload)
+            .collect();
+        assert_eq!(requests.len(), MAX_PIPELINE_REQUESTS);
+        assert_eq!(requests[0]["run_id"], "run-1");
+        assert_eq!(requests[0]["stage"], "learning");
+        assert_eq!(requests[0]["agent_id"], soul.agent_id);
+        assert_eq!(requests[0]["requested_stage"], "building");
+        assert_eq!(requests[0]["metadata"], json!({ "skill": "weather" }));
+        assert_eq!(requests[4]["metadata"]["topic"], 4);
+    }
+
+    struct Leaky;
+
+    #[async_trait]
+    impl AgentHandler for Leaky {
+        async fn on_pipeline(&self, _ctx: PipelineContext<'_>) -> Result<Value> {
+            Ok(json!({
+                "endpoint": "https://api.test/v1/forecast?city=oslo&api_key=abcd1234efgh",
+                "notes": ["authenticated with sk-live0123456789abcdef"],
+                "password": "hunter2",
+                "score": 0.9,
+            }))
+        }
+    }
+
+    /// Leaks a token through every channel a handler has.
+    struct LeakyEverywhere;
+
+    #[async_trait]
+    impl AgentHandler for LeakyEverywhere {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            self.on_pipeline_output(ctx).await.map(|out| out.output)
+        }
+
+        async fn on_pipeline_output(&self, ctx: PipelineContext<'_>) -> Result<HandlerOutput> {
+            ctx.request_pipeline("building", json!({ "token": "sk-live0123456789abcdef" }));
+            Ok(HandlerOutput::from(json!({
+                "raw_response": format!("used sk-live0123456789abcdef {}", "x".repeat(1000)),
+                "score": 0.9,
+            }))
+            .with_metrics(json!({ "auth": "sk-live0123456789abcdef" })))
+        }
+    }
+
+    #[tokio::test]
+    async fn secrets_never_reach_the_cache_full_outputs_or_side_events() {
+        let soul = test_support::soul("learning");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let dir = test_support::temp_dir("redact-everywhere");
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()))
+                .with_redactor(Some(Redactor::new()))
+                .with_run_cache(Some(RunCache::new(
+                    dir.join("cache"),
+                    Duration::from_secs(60),
+                    10,
+                )))
+                .with_full_outputs(Some(FullOutputStore::new(dir.join("full"))));
+        let emitter = Arc::new(RecordingEmitter::default());
+        let data = json!({ "run_id": "run-1", "stage": "learning" });
+
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &LeakyEverywhere,
+            &control,
+        )
+        .await;
+
+        let events = emitter.events();
+        for event in [
+            PIPELINE_REQUEST,
+            PIPELINE_STAGE_METRICS,
+            events::PIPELINE_STAGE_RESULT,
+        ] {
+            let (_, payload) = events.iter().find(|(name, _)| name == event).unwrap();
+            assert!(
+                !payload.to_string().contains("sk-live"),
+                "{event}: {payload}"
+            );
+        }
+        let cached = control
+            .run_cache
+            .as_ref()
+            .unwrap()
+            .load_result("run-1", "learning")
+            .unwrap();
+        assert!(!cached.output.to_string().contains("sk-live"));
+        assert!(!format!("{:?}", cached.metrics).contains("sk-live"));
+        let full =
+            std::fs::read_to_string(dir.join("full").join("run-1").join("learning.json")).unwrap();
+        assert!(full.contains("[REDACTED]"), "{full}");
+        assert!(!full.contains("sk-live"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn secrets_in_handler_output_are_redacted_before_emit() {
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let data = json!({ "run_id": "run-1", "stage": "building" });
+        let result = async |control: &PipelineControl| {
+            let emitter = Arc::new(RecordingEmitter::default());
+            dispatch_pipeline(
+                &soul,
+                &data,
+                emitter.clone(),
+                &gateway,
+                test_support::no_skills(),
+                &Leaky,
+                control,
+            )
+            .await;
+            let (_, result) = emitter
+                .events()
+                .into_iter()
+                .find(|(event, _)| event == events::PIPELINE_STAGE_RESULT)
+                .unwrap();
+            result["output"].clone()
+        };
+
+        let redacting =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()))
+                .with_redactor(Some(
+                    Redactor::new().with_values(["0123456789abcdef".to_string()]),
+                ));
+        let output = result(&redacting).await;
+        assert_eq!(
+            output["endpoint"],
+            "https://api.test/v1/forecast?city=oslo&api_key=[REDACTED]"
+        );
+        assert_eq!(output["notes"][0], "authenticated with [REDACTED]");
+        assert_eq!(output["password"], "[REDACTED]");
+        assert_eq!(output["score"], 0.9);
+        assert!(!output.to_string().contains("0123456789abcdef"));
+
+        // Turned off, the output goes out as the handler returned it
+        let plain = PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let output = result(&plain).await;
+        assert_eq!(output["password"], "hunter2");
+    }
+
+    /// Returns a heavy output: a raw response and a long array.
+    struct Bulky;
+
+    #[async_trait]
+    impl AgentHandler for Bulky {
+        async fn on_pipeline(&self, _ctx: PipelineContext<'_>) -> Result<Value> {
+            Ok(json!({
+                "score": 0.8,
+                "raw_response": "x".repeat(1000),
+                "candidates": (0..30).collect::<Vec<_>>(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn compact_results_omit_heavy_fields_unless_verbose() {
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let soul = test_support::soul("learning");
+        let dir = test_support::temp_dir("stage-results");
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()))
+                .with_full_outputs(Some(FullOutputStore::new(&dir)));
+
+        let emitter = Arc::new(RecordingEmitter::default());
+        let data = json!({ "run_id": "run-1", "stage": "learning" });
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &Bulky,
+            &control,
+        )
+        .await;
+        let events = emitter.events();
+        let (_, result) = events
+            .iter()
+            .find(|(event, _)| event == events::PIPELINE_STAGE_RESULT)
+            .unwrap();
+        assert_eq!(result["output"]["score"], 0.8);
+        assert!(result["output"].get("raw_response").is_none());
+        assert_eq!(
+            result["output"]["candidates"].as_array().unwrap().len(),
+            stage_output::MAX_ARRAY_ITEMS
+        );
+        // The full output is kept as a fetchable artifact
+        assert_eq!(
+            result["artifacts"][0]["name"],
+            stage_output::FULL_OUTPUT_ARTIFACT
+        );
+        let path = result["artifacts"][0]["uri"].as_str().unwrap();
+        let full: Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(full["candidates"].as_array().unwrap().len(), 30);
+        assert_eq!(full["raw_response"].as_str().unwrap().len(), 1000);
+
+        let emitter = Arc::new(RecordingEmitter::default());
+        let data =
+            json!({ "run_id": "run-2", "stage": "learning", "metadata": { "verbose": true } });
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &Bulky,
+            &control,
+        )
+        .await;
+        let events = emitter.events();
+        let (_, result) = events
+            .iter()
+            .find(|(event, _)| event == events::PIPELINE_STAGE_RESULT)
+            .unwrap();
+        assert_eq!(
+            result["output"]["raw_response"].as_str().unwrap().len(),
+            1000
+        );
+        assert!(result.get("artifacts").is_none());
+    }
+
+    /// Rejects every event; records whether `on_pipeline` ran anyway.
+    #[derive(Default)]
+    struct Picky(std::sync::atomic::AtomicBool);
+
+    #[async_trait]
+    impl AgentHandler for Picky {
+        fn validate_pipeline(&self, ctx: &PipelineContext<'_>) -> Result<()> {
+            bail!("missing new_version for {}", ctx.artifact_id)
+        }
+
+        async fn on_pipeline(&self, _ctx: PipelineContext<'_>) -> Result<Value> {
+            self.0.store(true, Ordering::SeqCst);
+            Ok(Value::Null)
+        }
+    }
+
+    #[tokio::test]
+    async fn validation_rejection_skips_main_handler() {
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let emitter = Arc::new(RecordingEmitter::default());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let handler = Picky::default();
+        let data = json!({ "run_id": "run-1", "stage": "building", "artifact_id": "evo-king" });
+
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &handler,
+            &control,
+        )
+        .await;
+
+        assert!(!handler.0.load(Ordering::SeqCst));
+        let (_, result) = &emitter.events()[0];
+        assert_eq!(result["status"], "rejected");
+        assert_eq!(result["attempts"], 0);
+        assert!(
+            result["error"]
+                .as_str()
+                .unwrap()
+                .contains("missing new_version for evo-king")
+        );
+    }
+
+    /// One LLM call followed by one skill call.
+    struct CallsBoth;
+
+    #[async_trait]
+    impl AgentHandler for CallsBoth {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            ctx.gateway
+                .chat_completion("m", "s", "u", None, None)
+                .await?;
+            // Recorded like `ctx.invoke_skill`
+            ctx.skills.execute("test-skill", &json!({})).await
+        }
+    }
+
+    #[tokio::test]
+    async fn stage_metrics_aggregate_llm_and_skill_calls() {
+        let server = test_support::MockServer::start(vec![test_support::MockResponse::json(
+            200,
+            &json!({
+                "choices": [{ "message": { "content": "ok" } }],
+                "usage": { "prompt_tokens": 30, "completion_tokens": 12, "total_tokens": 42 }
+            }),
+        )])
+        .await;
+        let soul = test_support::soul("learning");
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let skills = SkillSet::new(vec![test_support::http_skill(&server.url)]);
+        let emitter = Arc::new(RecordingEmitter::default());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()))
+                .with_stage_metrics(true);
+        let data = json!({ "run_id": "run-1", "stage": "learning" });
+
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            &skills,
+            &CallsBoth,
+            &control,
+        )
+        .await;
+
+        let events = emitter.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].0, events::PIPELINE_STAGE_RESULT);
+        let (event, metrics) = &events[1];
+        assert_eq!(event, PIPELINE_STAGE_METRICS);
+        assert_eq!(metrics["status"], "completed");
+        assert_eq!(metrics["llm_calls"], 1);
+        assert_eq!(metrics["prompt_tokens"], 30);
+        assert_eq!(metrics["completion_tokens"], 12);
+        assert_eq!(metrics["skill_calls"], 1);
+        assert!(metrics["gateway_latency_ms"].is_u64());
+        assert!(metrics["wall_ms"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn stage_result_lists_invoked_skills() {
+        let server = test_support::MockServer::start(vec![test_support::MockResponse::json(
+            200,
+            &json!({ "hits": 1 }),
+        )])
+        .await;
+        let soul = test_support::soul("learning");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let skills = SkillSet::new(vec![test_support::http_skill(&server.url)]);
+        let emitter = Arc::new(RecordingEmitter::default());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let data = json!({ "run_id": "run-1", "stage": "learning" });
+
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            &skills,
+            &SkillUser,
+            &control,
+        )
+        .await;
+
+        let (_, result) = &emitter.events()[0];
+        assert_eq!(result["status"], "completed");
+        assert_eq!(result["output"]["hits"], 1);
+        let used = result["skills_used"].as_array().unwrap();
+        assert_eq!(used.len(), 1);
+        assert_eq!(used[0]["name"], "test-skill");
+        assert_eq!(used[0]["success"], true);
+        assert!(used[0]["latency_ms"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn pipeline_retries_transient_failure() {
+        let soul = test_support::soul("learning");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let handler = FlakyHandler {
+            calls: AtomicU32::new(0),
+        };
+        let emitter = Arc::new(RecordingEmitter::default());
+        let retry = RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let data = json!({ "run_id": "run-1", "stage": "learning" });
+
+        let stdout = test_support::SharedBuf::default();
+        let lifecycle = Arc::new(EventStream::to_writer("test", Box::new(stdout.clone())));
+        let control = PipelineControl::new(retry, lifecycle);
+
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &handler,
+            &control,
+        )
+        .await;
+
+        let events = emitter.events();
+        assert_eq!(events.len(), 1);
+        let (event, result) = &events[0];
+        assert_eq!(event, events::PIPELINE_STAGE_RESULT);
+        assert_eq!(result["status"], "completed");
+        assert_eq!(result["attempts"], 2);
+        assert_eq!(handler.calls.load(Ordering::SeqCst), 2);
+
+        let transitions: Vec<Value> = stdout
+            .json_lines()
+            .iter()
+            .map(|l| l["event"].clone())
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![json!("stage_started"), json!("stage_completed")]
+        );
+    }
+
+    #[tokio::test]
+    async fn debug_prompt_defaults_to_agent_model() {
+        let done = || {
+            test_support::MockResponse::sse(&[
+                r#"{"choices":[{"delta":{"content":"ok"}}]}"#,
+                "[DONE]",
+            ])
+        };
+        let server = test_support::MockServer::start(vec![done(), done()]).await;
+        let gateway = Arc::new(GatewayClient::new(&server.url).unwrap());
+        let mut soul = test_support::soul("learning");
+        soul.model = Some("claude-haiku".to_string());
+        let emitter = RecordingEmitter::default();
+
+        let omitted = json!({ "request_id": "r1", "prompt": "hi" });
+        dispatch_debug_prompt(&soul, &omitted, &emitter, &gateway, "a", "learning").await;
+        let explicit = json!({ "request_id": "r2", "prompt": "hi", "model": "gpt-4o" });
+        dispatch_debug_prompt(&soul, &explicit, &emitter, &gateway, "a", "learning").await;
+
+        let models: Vec<Value> = server
+            .requests()
+            .iter()
+            .map(|r| r.json()["model"].clone())
+            .collect();
+        assert_eq!(models, vec![json!("claude-haiku"), json!("gpt-4o")]);
+        let responses: Vec<Value> = emitter
+            .events()
+            .into_iter()
+            .filter(|(event, _)| event == events::DEBUG_RESPONSE)
+            .map(|(_, payload)| payload["model"].clone())
+            .collect();
+        assert_eq!(responses, vec![json!("claude-haiku"), json!("gpt-4o")]);
+    }
+
+    struct Summarizer;
+
+    #[async_trait]
+    impl AgentHandler for Summarizer {
+        async fn on_pipeline(&self, _ctx: PipelineContext<'_>) -> Result<Value> {
+            Ok(Value::Null)
+        }
+
+        fn capabilities(&self) -> Vec<String> {
+            vec!["summarization".to_string(), "test".to_string()]
+        }
+    }
+
+    #[test]
+    fn handler_capabilities_merge_with_skill_capabilities() {
+        let skills = [test_support::http_skill("http://127.0.0.1:9")];
+
+        assert_eq!(
+            agent_capabilities(&Summarizer, &skills),
+            vec!["summarization".to_string(), "test".to_string()]
+        );
+        let data = json!({ "required_capabilities": ["summarization", "test"] });
+        assert!(check_capabilities(&data, &skills, &Summarizer).is_ok());
+    }
+
+    #[derive(Default)]
+    struct Ticker {
+        ticks: Mutex<Vec<u64>>,
+        release: tokio::sync::Notify,
+        disconnects: Mutex<Vec<DisconnectReason>>,
+    }
+
+    #[async_trait]
+    impl AgentHandler for Ticker {
+        async fn on_pipeline(&self, _ctx: PipelineContext<'_>) -> Result<Value> {
+            Ok(Value::Null)
+        }
+
+        async fn on_heartbeat(&self, ctx: &HeartbeatContext<'_>) {
+            self.ticks.lock().unwrap().push(ctx.tick);
+            if ctx.tick == 4 {
+                self.release.notified().await;
+            }
+        }
+
+        async fn on_disconnect(&self, ctx: &DisconnectContext<'_>) {
+            self.disconnects.lock().unwrap().push(ctx.reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn heartbeat_hook_fires_on_each_tick_without_blocking() {
+        let handler = Arc::new(Ticker::default());
+        let mut hook = HeartbeatHook {
+            handler: Arc::clone(&handler),
+            soul: Arc::new(SharedSoul::new(".", test_support::soul("learning"))),
+            gateway: Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap()),
+            emitter: Arc::new(RecordingEmitter::default()),
+            tick: 0,
+            running: None,
+            disconnect_timeout: DISCONNECT_HOOK_TIMEOUT,
+        };
+
+        for _ in 0..3 {
+            hook.fire();
+            hook.running.take().unwrap().await.unwrap();
+        }
+        assert_eq!(*handler.ticks.lock().unwrap(), vec![1, 2, 3]);
+
+        // A hook stuck on tick 4 neither blocks `fire` nor overlaps with tick 5
+        hook.fire();
+        tokio::task::yield_now().await;
+        hook.fire();
+        assert_eq!(*handler.ticks.lock().unwrap(), vec![1, 2, 3, 4]);
+        handler.release.notify_one();
+        hook.running.take().unwrap().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn disconnect_waits_for_running_heartbeat_and_reports_reason() {
+        let handler = Arc::new(Ticker::default());
+        let mut hook = HeartbeatHook {
+            handler: Arc::clone(&handler),
+            soul: Arc::new(SharedSoul::new(".", test_support::soul("learning"))),
+            gateway: Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap()),
+            emitter: Arc::new(RecordingEmitter::default()),
+            tick: 3,
+            running: None,
+            disconnect_timeout: DISCONNECT_HOOK_TIMEOUT,
+        };
+
+        // Tick 4 blocks until released; on_disconnect must wait for it
+        hook.fire();
+        let release = async {
+            tokio::task::yield_now().await;
+            assert!(handler.disconnects.lock().unwrap().is_empty());
+            handler.release.notify_one();
+        };
+        tokio::join!(hook.disconnect(DisconnectReason::TransportError), release);
+        hook.disconnect(DisconnectReason::Shutdown).await;
+
+        assert_eq!(*handler.ticks.lock().unwrap(), vec![4]);
+        assert_eq!(
+            *handler.disconnects.lock().unwrap(),
+            vec![DisconnectReason::TransportError, DisconnectReason::Shutdown]
+        );
+    }
+
+    #[tokio::test]
+    async fn hung_heartbeat_is_abandoned_on_disconnect() {
+        let handler = Arc::new(Ticker::default());
+        let mut hook = HeartbeatHook {
+            handler: Arc::clone(&handler),
+            soul: Arc::new(SharedSoul::new(".", test_support::soul("learning"))),
+            gateway: Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap()),
+            emitter: Arc::new(RecordingEmitter::default()),
+            tick: 3,
+            running: None,
+            disconnect_timeout: Duration::from_millis(50),
+        };
+
+        // Tick 4 is never released
+        hook.fire();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            hook.disconnect(DisconnectReason::Shutdown),
+        )
+        .await
+        .expect("disconnect waited on a hung heartbeat");
+        assert!(handler.disconnects.lock().unwrap().is_empty());
+
+        // The aborted task drops its handle on the handler
+        for _ in 0..100 {
+            if Arc::strong_count(&handler) == 2 {
+                return;
+            }
+            tokio::task::yield_now().await;
+        }
+        panic!("heartbeat task was not aborted");
+    }
+
+    struct Packager;
+
+    #[async_trait]
+    impl AgentHandler for Packager {
+        async fn on_pipeline(&self, ctx: PipelineContext<'_>) -> Result<Value> {
+            self.on_pipeline_output(ctx).await.map(|out| out.output)
+        }
+
+        async fn on_pipeline_output(&self, _ctx: PipelineContext<'_>) -> Result<HandlerOutput> {
+            Ok(HandlerOutput::from(json!({ "binary": "evo-king" }))
+                .with_artifact(
+                    crate::handler::ArtifactRef::new("archive", "/tmp/evo-king.tar.gz")
+                        .with_size(1024),
+                )
+                .with_metrics(json!({ "compile_ms": 1200 })))
+        }
+    }
+
+    #[tokio::test]
+    async fn handler_output_maps_to_result_artifact_and_metrics_events() {
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let emitter = Arc::new(RecordingEmitter::default());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()));
+        let data = json!({ "run_id": "run-1", "stage": "building" });
+
+        dispatch_pipeline(
+            &soul,
+            &data,
+            emitter.clone(),
+            &gateway,
+            test_support::no_skills(),
+            &Packager,
+            &control,
+        )
+        .await;
+
+        let events = emitter.events();
+        let names: Vec<&str> = events.iter().map(|(e, _)| e.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                PIPELINE_ARTIFACT,
+                events::PIPELINE_STAGE_RESULT,
+                PIPELINE_STAGE_METRICS
+            ]
+        );
+        let artifact = json!({ "name": "archive", "uri": "/tmp/evo-king.tar.gz", "size": 1024 });
+        assert_eq!(events[0].1["artifact"], artifact);
+        assert_eq!(events[1].1["output"], json!({ "binary": "evo-king" }));
+        assert_eq!(events[1].1["artifacts"], json!([artifact]));
+        assert_eq!(events[2].1["handler"]["compile_ms"], 1200);
+    }
+
+    #[tokio::test]
+    async fn cached_redispatch_resends_artifacts_and_metrics() {
+        let dir = test_support::temp_dir("stage-cache-full");
+        let soul = test_support::soul("building");
+        let gateway = Arc::new(GatewayClient::new("http://127.0.0.1:9").unwrap());
+        let control =
+            PipelineControl::new(RetryPolicy::default(), Arc::new(EventStream::disabled()))
+                .with_run_cache(Some(RunCache::new(&dir, Duration::from_secs(60), 10)));
+        let data = json!({ "run_id": "run-1", "stage": "building" });
+
+        let mut runs = Vec::new();
+        for _ in 0..2 {
+            let emitter = Arc::new(RecordingEmitter::default());
+            dispatch_pipeline(
+                &soul,
+                &data,
+                emitter.clone(),
+                &gateway,
+                test_support::no_skills(),
+                &Packager,
+                &control,
+            )
+            .await;
+            runs.push(emitter.events());
+        }
+
+        let (first, resent) = (&runs[0], &runs[1]);
+        assert_eq!(resent[1].1["status"], "skipped");
+        for i in 0..3 {
+            assert_eq!(resent[i].0, first[i].0);
+        }
+        assert_eq!(resent[0].1["artifact"], first[0].1["artifact"]);
+        assert_eq!(resent[1].1["artifacts"], first[1].1["artifacts"]);
+        assert_eq!(resent[2].1["handler"]["compile_ms"], 1200);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
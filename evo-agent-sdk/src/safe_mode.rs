@@ -0,0 +1,43 @@
+//! Safe mode (`EVO_SAFE_MODE=1`).
+//!
+//! Disables everything that executes code or mutates remote state —
+//! self-upgrade builds, release publishing, code skills, and non-GET skill
+//! endpoints — leaving only LLM calls and health checks. Blocked actions
+//! fail with [`SafeModeRefused`] and a warning naming the action.
+
+use std::future::Future;
+use std::sync::OnceLock;
+use tracing::warn;
+
+tokio::task_local! {
+    static OVERRIDE: bool;
+}
+
+/// Error returned when an action is blocked by safe mode.
+#[derive(Debug, thiserror::Error)]
+#[error("{action} refused: agent is running in safe mode (EVO_SAFE_MODE=1)")]
+pub struct SafeModeRefused {
+    pub action: &'static str,
+}
+
+/// Whether safe mode is on for this process (read once from env).
+pub fn enabled() -> bool {
+    static FROM_ENV: OnceLock<bool> = OnceLock::new();
+    OVERRIDE
+        .try_with(|on| *on)
+        .unwrap_or_else(|_| *FROM_ENV.get_or_init(|| crate::config::env_flag("EVO_SAFE_MODE")))
+}
+
+/// Refuse `action` when safe mode is on.
+pub fn guard(action: &'static str) -> Result<(), SafeModeRefused> {
+    if enabled() {
+        warn!(action, "safe mode: action refused");
+        return Err(SafeModeRefused { action });
+    }
+    Ok(())
+}
+
+/// Run `fut` with safe mode forced on or off, regardless of env.
+pub async fn scope<F: Future>(on: bool, fut: F) -> F::Output {
+    OVERRIDE.scope(on, fut).await
+}
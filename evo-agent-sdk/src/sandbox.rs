@@ -0,0 +1,140 @@
+//! Process sandboxing for code skills.
+//!
+//! Every code skill runs with a stripped environment (allow-list only) and
+//! its working directory confined to the skill folder. Optionally, CPU time
+//! and address-space rlimits are applied (Unix) and the process is launched
+//! under a wrapper such as `firejail` or `bwrap`.
+
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::config::env_parse;
+
+/// Variables passed through to skills when `SKILL_ENV_ALLOW` is unset.
+pub const DEFAULT_ENV_ALLOW: &[&str] = &["PATH", "HOME", "LANG", "LC_ALL", "TZ", "TMPDIR"];
+
+/// The process env vars skills may read, from `SKILL_ENV_ALLOW`
+/// (comma-separated); `None` when unset.
+pub fn env_allow_from_env() -> Option<Vec<String>> {
+    let allow = std::env::var("SKILL_ENV_ALLOW").ok()?;
+    Some(
+        allow
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// Restrictions applied to a spawned code skill.
+#[derive(Debug, Clone)]
+pub struct SkillSandbox {
+    /// Environment variables inherited from the runner; everything else is dropped.
+    pub env_allow: Vec<String>,
+    /// `RLIMIT_CPU` in seconds (Unix only).
+    pub cpu_secs: Option<u64>,
+    /// `RLIMIT_AS` in bytes (Unix only).
+    pub memory_bytes: Option<u64>,
+    /// Wrapper command and args prepended to the skill invocation.
+    pub wrapper: Vec<String>,
+    /// Wall-clock limit; the child is killed when exceeded.
+    pub timeout: Duration,
+}
+
+impl Default for SkillSandbox {
+    fn default() -> Self {
+        Self {
+            env_allow: DEFAULT_ENV_ALLOW.iter().map(|s| s.to_string()).collect(),
+            cpu_secs: None,
+            memory_bytes: None,
+            wrapper: Vec::new(),
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+impl SkillSandbox {
+    /// Read `SKILL_ENV_ALLOW` (comma-separated), `SKILL_CPU_SECS`,
+    /// `SKILL_MEMORY_MB`, `SKILL_SANDBOX_WRAPPER` (whitespace-separated
+    /// command, e.g. `firejail --quiet --net=none`) and `SKILL_TIMEOUT_SECS`.
+    pub fn from_env() -> Self {
+        let mut sandbox = Self::default();
+        if let Some(allow) = env_allow_from_env() {
+            sandbox.env_allow = allow;
+        }
+        sandbox.cpu_secs = env_parse("SKILL_CPU_SECS");
+        sandbox.memory_bytes = env_parse::<u64>("SKILL_MEMORY_MB").map(|mb| mb * 1024 * 1024);
+        if let Ok(wrapper) = std::env::var("SKILL_SANDBOX_WRAPPER") {
+            sandbox.wrapper = wrapper.split_whitespace().map(String::from).collect();
+        }
+        if let Some(secs) = env_parse::<u64>("SKILL_TIMEOUT_SECS") {
+            sandbox.timeout = Duration::from_secs(secs);
+        }
+        sandbox
+    }
+
+    /// Build a command running `program` in `cwd` under this sandbox.
+    pub fn command(&self, program: &Path, cwd: &Path) -> Command {
+        let mut cmd = match self.wrapper.split_first() {
+            Some((wrapper, args)) => {
+                let mut cmd = Command::new(wrapper);
+                cmd.args(args).arg(program);
+                cmd
+            }
+            None => Command::new(program),
+        };
+
+        cmd.current_dir(cwd).env_clear().kill_on_drop(true);
+        for name in &self.env_allow {
+            if let Some(value) = std::env::var_os(name) {
+                cmd.env(name, value);
+            }
+        }
+
+        #[cfg(unix)]
+        self.apply_rlimits(&mut cmd);
+
+        cmd
+    }
+
+    #[cfg(unix)]
+    fn apply_rlimits(&self, cmd: &mut Command) {
+        let (cpu, mem) = (self.cpu_secs, self.memory_bytes);
+        if cpu.is_none() && mem.is_none() {
+            return;
+        }
+        // SAFETY: the closure runs between fork and exec and only calls
+        // `setrlimit`, which is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(secs) = cpu {
+                    set_rlimit(libc::RLIMIT_CPU, secs)?;
+                }
+                if let Some(bytes) = mem {
+                    set_rlimit(libc::RLIMIT_AS, bytes)?;
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type RlimitResource = libc::c_int;
+
+#[cfg(unix)]
+fn set_rlimit(resource: RlimitResource, value: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    };
+    // SAFETY: `limit` is a valid rlimit for the duration of the call.
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -5,13 +5,23 @@
 //! and deploys new versions of the evo system components.
 
 use anyhow::{Context, Result, bail};
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
+use crate::build_lock::BuildLocks;
+use crate::config::sleep_or_pending;
+use crate::health_check;
+use crate::safe_mode;
+
 // ─── Types ──────────────────────────────────────────────────────────────────
 
 /// A single repo entry from `repos.json`.
@@ -26,6 +36,59 @@ pub struct RepoEntry {
     pub binary_path: String,
     #[serde(rename = "type", default)]
     pub repo_type: String,
+    #[serde(default)]
+    pub timeouts: CommandTimeouts,
+}
+
+/// Per-operation limits for the commands a self-upgrade build runs, set in
+/// a `repos.json` entry as `"timeouts": { "git_secs": 60, ... }`. Omitted
+/// fields keep their defaults; `0` means no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandTimeouts {
+    /// `git pull` / `git rev-parse`.
+    pub git_secs: u64,
+    /// `cargo build --release`. An hour by default, so a wedged build
+    /// can't hold the build lock forever; `0` restores the unbounded build.
+    pub build_secs: u64,
+    /// Copying files and creating the archive.
+    pub package_secs: u64,
+    /// `gh release create` / `gh release upload`.
+    pub publish_secs: u64,
+}
+
+impl Default for CommandTimeouts {
+    fn default() -> Self {
+        Self {
+            git_secs: 120,
+            build_secs: 3600,
+            package_secs: 300,
+            publish_secs: 600,
+        }
+    }
+}
+
+/// `0` disables a limit.
+fn limit(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+impl CommandTimeouts {
+    pub fn git(&self) -> Option<Duration> {
+        limit(self.git_secs)
+    }
+
+    pub fn build(&self) -> Option<Duration> {
+        limit(self.build_secs)
+    }
+
+    pub fn package(&self) -> Option<Duration> {
+        limit(self.package_secs)
+    }
+
+    pub fn publish(&self) -> Option<Duration> {
+        limit(self.publish_secs)
+    }
 }
 
 /// Top-level `repos.json` structure.
@@ -36,6 +99,64 @@ pub struct ReposJson {
     pub repos: HashMap<String, RepoEntry>,
 }
 
+impl ReposJson {
+    /// Look up `name` and resolve its paths.
+    ///
+    /// Fails when the component is not listed or its `local_path` does not
+    /// exist, so callers can use the result without re-checking.
+    pub fn component(&self, name: &str) -> Result<ResolvedRepo> {
+        let entry = self
+            .repos
+            .get(name)
+            .with_context(|| format!("Component '{name}' not found in repos.json"))?;
+
+        let local_path = resolve_path(&entry.local_path);
+        if entry.local_path.is_empty() || !local_path.is_dir() {
+            bail!(
+                "Repo path for '{name}' does not exist: {}",
+                local_path.display()
+            );
+        }
+
+        let binary_path = Some(resolve_path(&entry.binary_path))
+            .filter(|p| !entry.binary_path.is_empty() && p.exists());
+
+        Ok(ResolvedRepo {
+            name: name.to_string(),
+            github: entry.github.clone(),
+            installed_version: entry.installed_version.clone(),
+            binary_name: binary_name(name, &entry.repo_type),
+            local_path,
+            binary_path,
+            timeouts: entry.timeouts,
+        })
+    }
+}
+
+/// A `repos.json` entry with its paths resolved and checked.
+#[derive(Debug, Clone)]
+pub struct ResolvedRepo {
+    pub name: String,
+    pub github: String,
+    pub installed_version: String,
+    /// Checkout directory; guaranteed to exist.
+    pub local_path: PathBuf,
+    /// Currently installed binary, when configured and present on disk.
+    pub binary_path: Option<PathBuf>,
+    /// Name of the built binary (`evo-kernel-agent-*` → `evo-agent-*`).
+    pub binary_name: String,
+    pub timeouts: CommandTimeouts,
+}
+
+/// Binary produced by building `component`.
+pub fn binary_name(component: &str, repo_type: &str) -> String {
+    if repo_type == "kernel-agent" || component.starts_with("evo-kernel-agent-") {
+        component.replace("evo-kernel-agent-", "evo-agent-")
+    } else {
+        component.to_string()
+    }
+}
+
 /// Result of a build operation.
 #[derive(Debug, Serialize)]
 pub struct BuildResult {
@@ -46,9 +167,80 @@ pub struct BuildResult {
     pub release_url: String,
 }
 
+/// Returned when a command exceeds its [`CommandTimeouts`] limit.
+#[derive(Debug, thiserror::Error)]
+#[error("{operation} timed out after {}s", after.as_secs_f64())]
+pub struct CommandTimedOut {
+    pub operation: String,
+    pub after: Duration,
+}
+
+/// Returned when a build is aborted through its cancellation token.
+#[derive(Debug, thiserror::Error)]
+#[error("build cancelled")]
+pub struct Cancelled;
+
+/// Detached-signature scheme used for release archives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// `minisign -V` with a minisign public key; signature at `<archive>.minisig`.
+    #[default]
+    Minisign,
+    /// `gpgv` with an exported public keyring; signature at `<archive>.sig`.
+    Gpg,
+}
+
+impl SignatureScheme {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Minisign => "minisig",
+            Self::Gpg => "sig",
+        }
+    }
+}
+
+/// Opt-in signature verification of release archives before extraction.
+#[derive(Debug, Clone, Default)]
+pub struct SignaturePolicy {
+    /// Public key (minisign) or keyring (gpg) trusted to sign releases.
+    pub public_key: Option<PathBuf>,
+    /// Fail validation when the signature is missing or invalid.
+    pub require_signature: bool,
+    pub scheme: SignatureScheme,
+}
+
+impl SignaturePolicy {
+    /// Read the policy from `EVO_RELEASE_PUBKEY`, `EVO_REQUIRE_SIGNATURE`
+    /// and `EVO_SIGNATURE_SCHEME` (`minisign` | `gpg`).
+    pub fn from_env() -> Self {
+        Self {
+            public_key: std::env::var("EVO_RELEASE_PUBKEY")
+                .ok()
+                .map(|p| resolve_path(&p)),
+            require_signature: matches!(
+                std::env::var("EVO_REQUIRE_SIGNATURE").as_deref(),
+                Ok("1") | Ok("true")
+            ),
+            scheme: match std::env::var("EVO_SIGNATURE_SCHEME").as_deref() {
+                Ok("gpg") => SignatureScheme::Gpg,
+                _ => SignatureScheme::Minisign,
+            },
+        }
+    }
+
+    /// Path of the detached signature expected next to `archive`.
+    pub fn signature_path(&self, archive: &Path) -> PathBuf {
+        let mut name = archive.as_os_str().to_owned();
+        name.push(".");
+        name.push(self.scheme.extension());
+        PathBuf::from(name)
+    }
+}
+
 /// Result of a pre-load validation.
 #[derive(Debug, Serialize)]
 pub struct ValidationResult {
+    pub signature_verified: bool,
     pub binary_exists: bool,
     pub binary_executable: bool,
     pub soul_md_exists: bool,
@@ -64,6 +256,32 @@ pub fn is_self_upgrade(metadata: &Value) -> bool {
     metadata["build_type"].as_str() == Some("self_upgrade")
 }
 
+/// Reject a self-upgrade request whose `component` or `new_version` is
+/// missing or malformed, before any build work starts. `component` falls
+/// back to `artifact_id`, as in the building handler.
+pub fn validate_upgrade_metadata(metadata: &Value, artifact_id: &str) -> Result<()> {
+    let component = metadata["component"].as_str().unwrap_or(artifact_id);
+    if component.is_empty() {
+        bail!("self-upgrade request is missing `component`");
+    }
+    if !component
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("invalid self-upgrade component name: {component}");
+    }
+    match metadata["new_version"].as_str() {
+        Some(v)
+            if v.trim_start_matches('v')
+                .starts_with(|c: char| c.is_ascii_digit()) =>
+        {
+            Ok(())
+        }
+        Some(v) => bail!("invalid self-upgrade version: {v}"),
+        None => bail!("self-upgrade request is missing `new_version`"),
+    }
+}
+
 /// Resolve `~/.evo-agents` respecting `EVO_HOME` env var.
 pub fn evo_home() -> PathBuf {
     let raw = std::env::var("EVO_HOME").unwrap_or_else(|_| "~/.evo-agents".to_string());
@@ -85,19 +303,68 @@ pub fn load_repos_json() -> Result<ReposJson> {
 
 /// Run a shell command and return stdout, failing on non-zero exit.
 pub async fn run_cmd(program: &str, args: &[&str], cwd: Option<&Path>) -> Result<String> {
+    run_cmd_cancellable(program, args, cwd, &CancellationToken::new()).await
+}
+
+/// Like [`run_cmd`], but kills the child and returns [`Cancelled`] as soon
+/// as `cancel` fires.
+pub async fn run_cmd_cancellable(
+    program: &str,
+    args: &[&str],
+    cwd: Option<&Path>,
+    cancel: &CancellationToken,
+) -> Result<String> {
+    run_cmd_timed(program, args, cwd, cancel, None).await
+}
+
+/// Like [`run_cmd_cancellable`], but also kills the child and returns
+/// [`CommandTimedOut`] once `timeout` elapses.
+pub async fn run_cmd_timed(
+    program: &str,
+    args: &[&str],
+    cwd: Option<&Path>,
+    cancel: &CancellationToken,
+    timeout: Option<Duration>,
+) -> Result<String> {
+    if cancel.is_cancelled() {
+        return Err(Cancelled.into());
+    }
+
     let mut cmd = Command::new(program);
-    cmd.args(args);
+    cmd.args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
     if let Some(dir) = cwd {
         cmd.current_dir(dir);
     }
 
     info!(cmd = %program, args = ?args, "running command");
 
-    let output = cmd
-        .output()
-        .await
+    let child = cmd
+        .spawn()
         .with_context(|| format!("Failed to spawn: {program} {}", args.join(" ")))?;
 
+    // Dropping the child on cancellation kills it (kill_on_drop)
+    let output = tokio::select! {
+        output = child.wait_with_output() => output
+            .with_context(|| format!("Failed to wait for: {program} {}", args.join(" ")))?,
+        _ = cancel.cancelled() => {
+            warn!(cmd = %program, "command cancelled — killing child");
+            return Err(Cancelled.into());
+        }
+        _ = sleep_or_pending(timeout) => {
+            let operation = match args.first() {
+                Some(sub) => format!("{program} {sub}"),
+                None => program.to_string(),
+            };
+            let after = timeout.unwrap_or_default();
+            error!(operation = %operation, timeout_secs = after.as_secs(), "command timed out — killing child");
+            return Err(CommandTimedOut { operation, after }.into());
+        }
+    };
+
     let stdout = String::from_utf8_lossy(&output.stdout).to_string();
     let stderr = String::from_utf8_lossy(&output.stderr).to_string();
 
@@ -164,16 +431,39 @@ pub fn detect_target() -> &'static str {
 /// 4. Package binary + soul.md + skills/ into .tar.gz
 /// 5. `gh release create` to publish
 pub async fn build_and_release(component: &str, new_version: &str) -> Result<BuildResult> {
-    let repos = load_repos_json()?;
-    let entry = repos
-        .repos
-        .get(component)
-        .with_context(|| format!("Component '{component}' not found in repos.json"))?;
+    build_and_release_cancellable(component, new_version, &CancellationToken::new()).await
+}
 
-    let repo_path = resolve_path(&entry.local_path);
-    if !repo_path.exists() {
-        bail!("Repo path does not exist: {}", repo_path.display());
-    }
+/// [`build_and_release`] that aborts when `cancel` fires: the in-flight
+/// command is killed, staging and any partial archive are removed, and
+/// [`Cancelled`] is returned.
+pub async fn build_and_release_cancellable(
+    component: &str,
+    new_version: &str,
+    cancel: &CancellationToken,
+) -> Result<BuildResult> {
+    build_and_release_with(component, new_version, cancel, false).await
+}
+
+/// [`build_and_release_cancellable`] with an explicit `force_rebuild`.
+///
+/// Each successful build records a [`BuildCheckpoint`]; unless
+/// `force_rebuild` is set, a later build of the same component, version and
+/// commit skips `cargo build` and republishes the checkpointed archive.
+pub async fn build_and_release_with(
+    component: &str,
+    new_version: &str,
+    cancel: &CancellationToken,
+    force_rebuild: bool,
+) -> Result<BuildResult> {
+    safe_mode::guard("self-upgrade build")?;
+    let repo = load_repos_json()?.component(component)?;
+    let repo_path = repo.local_path;
+    let binary_name = repo.binary_name;
+    let timeouts = repo.timeouts;
+
+    // One build per repo, and a host-wide cap; released on every exit path
+    let _locks = BuildLocks::from_env().acquire(component, cancel).await?;
 
     info!(
         component,
@@ -181,36 +471,178 @@ pub async fn build_and_release(component: &str, new_version: &str) -> Result<Bui
         "starting self-upgrade build"
     );
 
+    // Remove staging (and later the partial archive) unless the build completes
+    let mut cleanup = BuildCleanup::new(repo_path.join("staging"));
+
     // 1. git pull
-    run_cmd("git", &["pull", "origin", "main"], Some(&repo_path)).await?;
+    let git = timeouts.git();
+    run_cmd_timed(
+        "git",
+        &["pull", "origin", "main"],
+        Some(&repo_path),
+        cancel,
+        git,
+    )
+    .await?;
 
-    // 2. cargo build --release
-    let build_args = vec!["build", "--release"];
-    run_cmd("cargo", &build_args, Some(&repo_path)).await?;
+    let commit = run_cmd_timed("git", &["rev-parse", "HEAD"], Some(&repo_path), cancel, git)
+        .await?
+        .trim()
+        .to_string();
 
-    // 3. Determine binary name
-    let binary_name = if entry.repo_type == "kernel-agent" {
-        component.replace("evo-kernel-agent-", "evo-agent-")
+    // 2-4. Build and package, unless an earlier identical build (e.g. before
+    // a pipeline retry) left a checkpointed archive
+
+    let checkpoints = checkpoint_dir();
+    let reused = if force_rebuild {
+        None
     } else {
-        component.to_string()
+        BuildCheckpoint::find(&checkpoints, component, new_version, &commit)
+    };
+    let archive_path = match reused {
+        Some(checkpoint) => {
+            info!(
+                component,
+                version = new_version,
+                commit = %commit,
+                archive = %checkpoint.archive_path.display(),
+                "reusing checkpointed build archive"
+            );
+            checkpoint.archive_path
+        }
+        None => {
+            let archive_path = package_release(
+                component,
+                new_version,
+                &repo_path,
+                &binary_name,
+                &timeouts,
+                &mut cleanup,
+                cancel,
+            )
+            .await?;
+            checkpoint_archive(
+                &mut cleanup,
+                &checkpoints,
+                component,
+                new_version,
+                &commit,
+                &archive_path,
+            )?;
+            archive_path
+        }
     };
 
-    let release_binary = repo_path.join("target/release").join(&binary_name);
+    // 5. gh release create
+    safe_mode::guard("GitHub release publish")?;
+    let gh_repo = &repo.github;
+    let release_url = format!("https://github.com/{gh_repo}/releases/tag/{new_version}");
+
+    let gh_result = run_cmd_timed(
+        "gh",
+        &[
+            "release",
+            "create",
+            new_version,
+            "--repo",
+            gh_repo,
+            "--title",
+            &format!("Release {new_version}"),
+            "--notes",
+            &format!("Auto-release {new_version} via self-upgrade pipeline"),
+            &archive_path.to_string_lossy(),
+        ],
+        Some(&repo_path),
+        cancel,
+        timeouts.publish(),
+    )
+    .await;
+
+    match gh_result {
+        Ok(output) => info!(output = %output.trim(), "GitHub release created"),
+        Err(e) => {
+            warn!(err = %e, "gh release create failed — release may already exist");
+            // Try uploading to existing release
+            run_cmd_timed(
+                "gh",
+                &[
+                    "release",
+                    "upload",
+                    new_version,
+                    "--repo",
+                    gh_repo,
+                    "--clobber",
+                    &archive_path.to_string_lossy(),
+                ],
+                Some(&repo_path),
+                cancel,
+                timeouts.publish(),
+            )
+            .await
+            .ok();
+        }
+    }
+    if cancel.is_cancelled() {
+        return Err(Cancelled.into());
+    }
+
+    cleanup.disarm();
+    info!(
+        component,
+        version = new_version,
+        archive = %archive_path.display(),
+        "build and release complete"
+    );
+
+    Ok(BuildResult {
+        component: component.to_string(),
+        new_version: new_version.to_string(),
+        archive_path: archive_path.to_string_lossy().to_string(),
+        binary_name,
+        release_url,
+    })
+}
+
+/// `cargo build --release` in `repo_path` and package the binary, soul.md
+/// and skills/ into a `.tar.gz`, returning the archive path.
+async fn package_release(
+    component: &str,
+    new_version: &str,
+    repo_path: &Path,
+    binary_name: &str,
+    timeouts: &CommandTimeouts,
+    cleanup: &mut BuildCleanup,
+    cancel: &CancellationToken,
+) -> Result<PathBuf> {
+    // cargo build --release
+    let build_args = vec!["build", "--release"];
+    run_cmd_timed(
+        "cargo",
+        &build_args,
+        Some(repo_path),
+        cancel,
+        timeouts.build(),
+    )
+    .await?;
+
+    // Locate built binary
+    let release_binary = repo_path.join("target/release").join(binary_name);
 
     if !release_binary.exists() {
         bail!("Built binary not found at: {}", release_binary.display());
     }
 
-    // 4. Package archive
+    // Package archive
     let archive_name = format!("{binary_name}-{new_version}-{}.tar.gz", detect_target());
     let archive_path = repo_path.join(&archive_name);
+    cleanup.push(archive_path.clone());
 
     // Create staging directory
     let staging_dir = repo_path.join("staging").join(component);
     tokio::fs::create_dir_all(&staging_dir).await?;
 
     // Copy binary
-    tokio::fs::copy(&release_binary, staging_dir.join(&binary_name)).await?;
+    tokio::fs::copy(&release_binary, staging_dir.join(binary_name)).await?;
 
     // Copy soul.md if exists
     let soul_src = repo_path.join("soul.md");
@@ -221,7 +653,7 @@ pub async fn build_and_release(component: &str, new_version: &str) -> Result<Bui
     // Copy skills/ if exists
     let skills_src = repo_path.join("skills");
     if skills_src.is_dir() {
-        run_cmd(
+        run_cmd_timed(
             "cp",
             &[
                 "-r",
@@ -229,13 +661,18 @@ pub async fn build_and_release(component: &str, new_version: &str) -> Result<Bui
                 &staging_dir.to_string_lossy(),
             ],
             None,
+            cancel,
+            timeouts.package(),
         )
         .await
         .ok(); // non-fatal
     }
+    if cancel.is_cancelled() {
+        return Err(Cancelled.into());
+    }
 
     // Create tar.gz
-    run_cmd(
+    run_cmd_timed(
         "tar",
         &[
             "czf",
@@ -245,6 +682,8 @@ pub async fn build_and_release(component: &str, new_version: &str) -> Result<Bui
             component,
         ],
         None,
+        cancel,
+        timeouts.package(),
     )
     .await?;
 
@@ -253,65 +692,118 @@ pub async fn build_and_release(component: &str, new_version: &str) -> Result<Bui
         .await
         .ok();
 
-    // 5. gh release create
-    let gh_repo = &entry.github;
-    let release_url = format!("https://github.com/{gh_repo}/releases/tag/{new_version}");
+    Ok(archive_path)
+}
 
-    let gh_result = run_cmd(
-        "gh",
-        &[
-            "release",
-            "create",
-            new_version,
-            "--repo",
-            gh_repo,
-            "--title",
-            &format!("Release {new_version}"),
-            "--notes",
-            &format!("Auto-release {new_version} via self-upgrade pipeline"),
-            &archive_path.to_string_lossy(),
-        ],
-        Some(&repo_path),
-    )
-    .await;
+/// Record `archive` as the checkpoint for this build and take it out of
+/// `cleanup`, so a failed publish leaves it for the retry to reuse.
+fn checkpoint_archive(
+    cleanup: &mut BuildCleanup,
+    checkpoints: &Path,
+    component: &str,
+    version: &str,
+    commit: &str,
+    archive: &Path,
+) -> Result<()> {
+    BuildCheckpoint::new(component, version, commit, archive)?.save(checkpoints)?;
+    cleanup.keep(archive);
+    Ok(())
+}
 
-    match gh_result {
-        Ok(output) => info!(output = %output.trim(), "GitHub release created"),
-        Err(e) => {
-            warn!(err = %e, "gh release create failed — release may already exist");
-            // Try uploading to existing release
-            run_cmd(
-                "gh",
-                &[
-                    "release",
-                    "upload",
-                    new_version,
-                    "--repo",
-                    gh_repo,
-                    "--clobber",
-                    &archive_path.to_string_lossy(),
-                ],
-                Some(&repo_path),
-            )
-            .await
-            .ok();
+/// Removes build leftovers on drop unless [`disarm`](Self::disarm)ed, so
+/// failed or cancelled builds don't leave partial state behind.
+struct BuildCleanup {
+    paths: Vec<PathBuf>,
+    armed: bool,
+}
+
+impl BuildCleanup {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            paths: vec![path],
+            armed: true,
         }
     }
 
-    info!(
-        component,
-        version = new_version,
-        archive = %archive_path.display(),
-        "build and release complete"
-    );
+    fn push(&mut self, path: PathBuf) {
+        self.paths.push(path);
+    }
 
-    Ok(BuildResult {
-        component: component.to_string(),
-        new_version: new_version.to_string(),
-        archive_path: archive_path.to_string_lossy().to_string(),
-        binary_name,
-        release_url,
-    })
+    /// Stop tracking `path`; it survives a failed build.
+    fn keep(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+    }
+
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for BuildCleanup {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        for path in &self.paths {
+            let removed = if path.is_dir() {
+                std::fs::remove_dir_all(path)
+            } else {
+                std::fs::remove_file(path)
+            };
+            if removed.is_ok() {
+                info!(path = %path.display(), "removed partial build output");
+            }
+        }
+    }
+}
+
+/// Record of a packaged build, stored as
+/// `<EVO_HOME>/data/upgrade-checkpoints/<component>.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BuildCheckpoint {
+    pub component: String,
+    pub version: String,
+    pub commit: String,
+    pub archive_path: PathBuf,
+    pub archive_size: u64,
+}
+
+impl BuildCheckpoint {
+    pub fn new(component: &str, version: &str, commit: &str, archive: &Path) -> Result<Self> {
+        let archive_size = std::fs::metadata(archive)
+            .with_context(|| format!("Failed to stat {}", archive.display()))?
+            .len();
+        Ok(Self {
+            component: component.to_string(),
+            version: version.to_string(),
+            commit: commit.to_string(),
+            archive_path: archive.to_path_buf(),
+            archive_size,
+        })
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(format!("{}.json", self.component));
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The checkpoint for exactly this build, if its archive is still on
+    /// disk with the recorded size.
+    pub fn find(dir: &Path, component: &str, version: &str, commit: &str) -> Option<Self> {
+        let path = dir.join(format!("{component}.json"));
+        let checkpoint: Self = serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()?;
+        let intact = std::fs::metadata(&checkpoint.archive_path)
+            .is_ok_and(|m| m.is_file() && m.len() == checkpoint.archive_size);
+        (checkpoint.version == version && checkpoint.commit == commit && intact)
+            .then_some(checkpoint)
+    }
+}
+
+fn checkpoint_dir() -> PathBuf {
+    evo_home().join("data").join("upgrade-checkpoints")
 }
 
 // ─── Pre-load Validation Stage ──────────────────────────────────────────────
@@ -320,19 +812,23 @@ pub async fn build_and_release(component: &str, new_version: &str) -> Result<Bui
 ///
 /// Steps:
 /// 1. Download the release archive (or use local path)
-/// 2. Extract to temp directory
-/// 3. Check: binary exists + executable, soul.md, skills/
-/// 4. Spawn binary with `--version` (or health check)
+/// 2. Verify its detached signature (when [`SignaturePolicy::from_env`] requires it)
+/// 3. Extract to temp directory
+/// 4. Check: binary exists + executable, soul.md, skills/
+/// 5. Spawn binary with `--version` (or health check)
 pub async fn validate_release(
     component: &str,
     version: &str,
     archive_path_or_url: &str,
 ) -> Result<ValidationResult> {
+    let policy = SignaturePolicy::from_env();
     let home = evo_home();
     let temp_dir = home
         .join("data")
         .join(format!("validate-{component}-{version}"));
     tokio::fs::create_dir_all(&temp_dir).await?;
+    // Removed on every exit path, early errors included
+    let _cleanup = BuildCleanup::new(temp_dir.clone());
 
     info!(component, version, "validating release archive");
 
@@ -345,6 +841,20 @@ pub async fn validate_release(
         PathBuf::from(archive_path_or_url)
     };
 
+    // Verify signature before touching the archive contents
+    let signature_path = if policy.require_signature && archive_path_or_url.starts_with("http") {
+        let local_sig = policy.signature_path(&archive_path);
+        let sig_url = format!("{archive_path_or_url}.{}", policy.scheme.extension());
+        if let Err(e) = download_file(&sig_url, &local_sig).await {
+            warn!(url = %sig_url, err = %e, "failed to download release signature");
+        }
+        local_sig
+    } else {
+        policy.signature_path(&archive_path)
+    };
+
+    let signature_verified = enforce_signature(&archive_path, &signature_path, &policy).await?;
+
     // Extract
     run_cmd(
         "tar",
@@ -367,12 +877,11 @@ pub async fn validate_release(
         temp_dir.clone()
     };
 
-    // Determine binary name
-    let binary_name = if component.starts_with("evo-kernel-agent-") {
-        component.replace("evo-kernel-agent-", "evo-agent-")
-    } else {
-        component.to_string()
-    };
+    // Determine binary name (repos.json when available on this host)
+    let binary_name = load_repos_json()
+        .and_then(|r| r.component(component))
+        .map(|r| r.binary_name)
+        .unwrap_or_else(|_| binary_name(component, ""));
 
     let binary_path = extracted_dir.join(&binary_name);
     let binary_exists = binary_path.exists();
@@ -400,6 +909,7 @@ pub async fn validate_release(
 
     // Health check: try running binary with --version or --help
     let health_check_passed = if binary_exists && binary_executable {
+        safe_mode::guard("release binary execution")?;
         let result = Command::new(&binary_path).arg("--help").output().await;
         match result {
             Ok(output) => output.status.success() || output.status.code() == Some(0),
@@ -415,10 +925,8 @@ pub async fn validate_release(
 
     let all_passed = binary_exists && binary_executable && soul_md_exists;
 
-    // Clean up temp dir
-    tokio::fs::remove_dir_all(&temp_dir).await.ok();
-
     let result = ValidationResult {
+        signature_verified,
         binary_exists,
         binary_executable,
         soul_md_exists,
@@ -436,22 +944,102 @@ pub async fn validate_release(
     Ok(result)
 }
 
+/// Verify `archive` against its detached `signature` when the policy requires it.
+///
+/// Returns `Ok(false)` without checking anything when verification is disabled,
+/// `Ok(true)` for a good signature, and an error for a missing or bad one.
+pub async fn enforce_signature(
+    archive: &Path,
+    signature: &Path,
+    policy: &SignaturePolicy,
+) -> Result<bool> {
+    if !policy.require_signature {
+        return Ok(false);
+    }
+
+    let public_key = policy
+        .public_key
+        .as_ref()
+        .context("Signature verification required but no public key configured")?;
+
+    if !signature.exists() {
+        bail!(
+            "Signature verification required but signature not found: {}",
+            signature.display()
+        );
+    }
+
+    let archive_str = archive.to_string_lossy();
+    let signature_str = signature.to_string_lossy();
+    let key_str = public_key.to_string_lossy();
+
+    let result = match policy.scheme {
+        SignatureScheme::Minisign => {
+            run_cmd(
+                "minisign",
+                &[
+                    "-V",
+                    "-p",
+                    &key_str,
+                    "-m",
+                    &archive_str,
+                    "-x",
+                    &signature_str,
+                ],
+                None,
+            )
+            .await
+        }
+        SignatureScheme::Gpg => {
+            run_cmd(
+                "gpgv",
+                &["--keyring", &key_str, &signature_str, &archive_str],
+                None,
+            )
+            .await
+        }
+    };
+
+    match result {
+        Ok(_) => {
+            info!(archive = %archive.display(), scheme = ?policy.scheme, "release signature verified");
+            Ok(true)
+        }
+        Err(e) => bail!("Invalid signature for {}: {e}", archive.display()),
+    }
+}
+
 // ─── Evaluation Stage ───────────────────────────────────────────────────────
 
 /// Evaluate a self-upgrade release by comparing to current.
 pub async fn evaluate_upgrade(component: &str, new_version: &str) -> Result<Value> {
-    let repos = load_repos_json()?;
-    let entry = repos.repos.get(component);
+    evaluate_upgrade_in(&evo_home(), component, new_version).await
+}
+
+/// [`evaluate_upgrade`] against the `repos.json` under `home`. The current
+/// version and binary come straight from the entry, so they're reported
+/// even when the component has no local checkout.
+async fn evaluate_upgrade_in(home: &Path, component: &str, new_version: &str) -> Result<Value> {
+    let entry = read_repo_entry(home, component);
+    if entry.is_none() {
+        warn!(
+            component,
+            "component not in repos.json — reporting unknown current version"
+        );
+    }
 
     let current_version = entry
+        .as_ref()
         .map(|e| e.installed_version.clone())
+        .filter(|v| !v.is_empty())
         .unwrap_or_else(|| "unknown".to_string());
 
     // Check binary size (if current binary exists)
-    let current_size = entry.and_then(|e| {
-        let p = resolve_path(&e.binary_path);
-        std::fs::metadata(p).ok().map(|m| m.len())
-    });
+    let current_size = entry
+        .as_ref()
+        .filter(|e| !e.binary_path.is_empty())
+        .and_then(|e| std::fs::metadata(resolve_path(&e.binary_path)).ok())
+        .map(|m| m.len());
 
     info!(
         component,
@@ -474,6 +1062,109 @@ pub async fn evaluate_upgrade(component: &str, new_version: &str) -> Result<Valu
     }))
 }
 
+// ─── Post-activation Verification ───────────────────────────────────────────
+
+/// Emitted once a deployed upgrade is confirmed running.
+pub const SELF_UPGRADE_VERIFIED: &str = "self_upgrade:verified";
+/// Emitted when a deployed upgrade isn't confirmed within the timeout.
+pub const SELF_UPGRADE_FAILED: &str = "self_upgrade:failed";
+
+/// How to confirm that king's `update.sh` actually brought the new version up.
+#[derive(Debug, Clone)]
+pub struct VerifyPolicy {
+    /// Give up (and report failure) after this long.
+    pub timeout: Duration,
+    /// Delay between checks.
+    pub interval: Duration,
+    /// Optional URL that must answer 2xx once the new binary is up.
+    pub health_url: Option<String>,
+}
+
+impl VerifyPolicy {
+    /// Read `UPGRADE_VERIFY_TIMEOUT_SECS` (unset or `0` disables verification)
+    /// and `UPGRADE_VERIFY_HEALTH_URL`.
+    pub fn from_env() -> Option<Self> {
+        let secs: u64 = std::env::var("UPGRADE_VERIFY_TIMEOUT_SECS")
+            .ok()?
+            .parse()
+            .ok()
+            .filter(|s| *s > 0)?;
+        Some(Self {
+            timeout: Duration::from_secs(secs),
+            interval: Duration::from_secs(2),
+            health_url: std::env::var("UPGRADE_VERIFY_HEALTH_URL").ok(),
+        })
+    }
+}
+
+/// Wait until `repos.json` under `home` reports `new_version` installed for
+/// `component` (and the health URL, if any, responds), or the timeout passes.
+///
+/// Returns the event payload; `verified` tells which event to emit.
+pub async fn verify_activation(
+    home: &Path,
+    component: &str,
+    new_version: &str,
+    policy: &VerifyPolicy,
+) -> Value {
+    let started = std::time::Instant::now();
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap_or_default();
+    let wanted = new_version.trim_start_matches('v');
+    let mut installed = String::new();
+
+    loop {
+        installed = read_installed_version(home, component).unwrap_or(installed);
+        let version_ok = installed.trim_start_matches('v') == wanted;
+        let health_ok = match (&policy.health_url, version_ok) {
+            (Some(url), true) => health_check::probe_url(&client, url).await.reachable,
+            _ => true,
+        };
+
+        if version_ok && health_ok {
+            info!(component, new_version, "self-upgrade verified");
+            return serde_json::json!({
+                "component": component,
+                "new_version": new_version,
+                "verified": true,
+                "elapsed_ms": started.elapsed().as_millis() as u64,
+            });
+        }
+
+        if started.elapsed() >= policy.timeout {
+            let reason = if version_ok {
+                "health probe did not pass"
+            } else {
+                "new version not reported installed"
+            };
+            warn!(component, new_version, installed = %installed, reason, "self-upgrade verification failed");
+            return serde_json::json!({
+                "component": component,
+                "new_version": new_version,
+                "verified": false,
+                "installed_version": installed,
+                "reason": reason,
+                "elapsed_ms": started.elapsed().as_millis() as u64,
+            });
+        }
+
+        tokio::time::sleep(policy.interval).await;
+    }
+}
+
+fn read_installed_version(home: &Path, component: &str) -> Option<String> {
+    read_repo_entry(home, component).map(|e| e.installed_version)
+}
+
+/// `component`'s raw `repos.json` entry under `home`, paths unchecked.
+fn read_repo_entry(home: &Path, component: &str) -> Option<RepoEntry> {
+    let content = std::fs::read_to_string(home.join("repos.json")).ok()?;
+    let mut repos: ReposJson = serde_json::from_str(&content).ok()?;
+    repos.repos.remove(component)
+}
+
 // ─── Internal Helpers ───────────────────────────────────────────────────────
 
 fn resolve_path(raw: &str) -> PathBuf {
@@ -485,6 +1176,12 @@ fn resolve_path(raw: &str) -> PathBuf {
     PathBuf::from(raw)
 }
 
+/// How often a streamed download logs its progress.
+const DOWNLOAD_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Stream `url` to `dest`, logging status, size and progress. An HTML
+/// response is refused: it is almost always an error page (e.g. an expired
+/// signed URL) rather than the requested file.
 async fn download_file(url: &str, dest: &Path) -> Result<()> {
     info!(url, dest = %dest.display(), "downloading file");
 
@@ -493,13 +1190,485 @@ async fn download_file(url: &str, dest: &Path) -> Result<()> {
         .build()?;
 
     let resp = client.get(url).send().await?;
-    if !resp.status().is_success() {
-        bail!("Download failed: HTTP {}", resp.status());
+    let status = resp.status();
+    let expected = resp.content_length();
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    info!(
+        url,
+        status = status.as_u16(),
+        content_length = ?expected,
+        content_type = %content_type,
+        "download response"
+    );
+    if !status.is_success() {
+        bail!("Download failed: HTTP {status} ({content_type})");
+    }
+    if content_type.starts_with("text/html") {
+        bail!(
+            "Download of {url} returned an HTML page (content-type {content_type}) instead of a file; \
+             the link may have expired or require authentication"
+        );
+    }
+
+    let mut file = tokio::fs::File::create(dest)
+        .await
+        .with_context(|| format!("Failed to create {}", dest.display()))?;
+    let mut stream = resp.bytes_stream();
+    let mut received: u64 = 0;
+    let mut last_report = std::time::Instant::now();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.context("Error reading download stream")?;
+        file.write_all(&chunk).await?;
+        received += chunk.len() as u64;
+        if last_report.elapsed() >= DOWNLOAD_PROGRESS_INTERVAL {
+            info!(url, received, expected = ?expected, "download in progress");
+            last_report = std::time::Instant::now();
+        }
     }
+    file.flush().await?;
 
-    let bytes = resp.bytes().await?;
-    tokio::fs::write(dest, &bytes).await?;
+    if let Some(expected) = expected
+        && received != expected
+    {
+        bail!("Download of {url} truncated: got {received} of {expected} bytes");
+    }
 
-    info!(size = bytes.len(), "download complete");
+    info!(size = received, "download complete");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockResponse, MockServer, temp_dir};
+
+    #[tokio::test]
+    async fn html_error_page_is_not_saved_as_archive() {
+        let server = MockServer::start(vec![MockResponse::new(
+            200,
+            "text/html; charset=utf-8",
+            "<html><body>Request has expired</body></html>",
+        )])
+        .await;
+        let dir = temp_dir("download-html");
+        let dest = dir.join("evo-king.tar.gz");
+
+        let err = download_file(&format!("{}/release.tar.gz", server.url), &dest)
+            .await
+            .unwrap_err();
+
+        let msg = err.to_string();
+        assert!(msg.contains("HTML page"), "{msg}");
+        assert!(msg.contains("expired"), "{msg}");
+        assert!(!dest.exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn git_timeout_is_enforced_independently_of_build_timeout() {
+        let entry: RepoEntry = serde_json::from_value(serde_json::json!({
+            "github": "org/evo-king",
+            "timeouts": { "git_secs": 1, "build_secs": 30 },
+        }))
+        .unwrap();
+        let timeouts = entry.timeouts;
+        assert_eq!(
+            timeouts.package_secs,
+            CommandTimeouts::default().package_secs
+        );
+        let cancel = CancellationToken::new();
+
+        let started = std::time::Instant::now();
+        let err = run_cmd_timed("sleep", &["5"], None, &cancel, timeouts.git())
+            .await
+            .unwrap_err();
+        let timed_out = err.downcast_ref::<CommandTimedOut>().unwrap();
+        assert_eq!(timed_out.operation, "sleep 5");
+        assert!(started.elapsed() < Duration::from_secs(3));
+
+        run_cmd_timed("sleep", &["0.1"], None, &cancel, timeouts.build())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn identical_rebuild_reuses_checkpointed_archive() {
+        let dir = temp_dir("checkpoint");
+        let archive = dir.join("evo-king-v1.2.0.tar.gz");
+        std::fs::write(&archive, b"archive bytes").unwrap();
+        let checkpoints = dir.join("checkpoints");
+
+        BuildCheckpoint::new("evo-king", "v1.2.0", "abc123", &archive)
+            .unwrap()
+            .save(&checkpoints)
+            .unwrap();
+
+        let reused = BuildCheckpoint::find(&checkpoints, "evo-king", "v1.2.0", "abc123").unwrap();
+        assert_eq!(reused.archive_path, archive);
+        // New commit, new version or a damaged archive all force a rebuild
+        assert!(BuildCheckpoint::find(&checkpoints, "evo-king", "v1.2.0", "def456").is_none());
+        assert!(BuildCheckpoint::find(&checkpoints, "evo-king", "v1.3.0", "abc123").is_none());
+        std::fs::write(&archive, b"trunc").unwrap();
+        assert!(BuildCheckpoint::find(&checkpoints, "evo-king", "v1.2.0", "abc123").is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn failed_publish_keeps_checkpointed_archive_for_the_retry() {
+        let dir = temp_dir("checkpoint-publish");
+        let staging = dir.join("staging");
+        std::fs::create_dir_all(&staging).unwrap();
+        let archive = dir.join("evo-king-v1.2.0.tar.gz");
+        let checkpoints = dir.join("checkpoints");
+        let cancel = CancellationToken::new();
+
+        // First attempt: packaged and checkpointed, then publishing fails
+        let publish = {
+            let mut cleanup = BuildCleanup::new(staging.clone());
+            cleanup.push(archive.clone());
+            std::fs::write(&archive, b"archive bytes").unwrap();
+            checkpoint_archive(
+                &mut cleanup,
+                &checkpoints,
+                "evo-king",
+                "v1.2.0",
+                "abc123",
+                &archive,
+            )
+            .unwrap();
+            run_cmd_cancellable("false", &[], None, &cancel).await
+        };
+        assert!(publish.is_err());
+        assert!(!staging.exists(), "staging left behind");
+
+        // The retry finds the archive intact instead of rebuilding
+        let reused = BuildCheckpoint::find(&checkpoints, "evo-king", "v1.2.0", "abc123").unwrap();
+        assert_eq!(reused.archive_path, archive);
+        assert_eq!(std::fs::read(&archive).unwrap(), b"archive bytes");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn signature_disabled_skips_verification() {
+        let dir = temp_dir("sig-disabled");
+        let archive = dir.join("release.tar.gz");
+        std::fs::write(&archive, b"archive").unwrap();
+
+        let policy = SignaturePolicy::default();
+        let verified = enforce_signature(&archive, &policy.signature_path(&archive), &policy)
+            .await
+            .unwrap();
+        assert!(!verified);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Install stub `minisign` and `gpgv` in `dir/bin` that accept a
+    /// signature only when it reads `signed <archive contents>`, and put
+    /// them first on `PATH`. Returns the previous `PATH`; hold
+    /// `ENV_LOCK` until it is restored.
+    #[cfg(unix)]
+    fn stub_verifiers(dir: &Path) -> std::ffi::OsString {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bin = dir.join("bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        // minisign -V -p <key> -m <archive> -x <sig>; gpgv --keyring <key> <sig> <archive>
+        for (name, archive, signature) in [("minisign", "$5", "$7"), ("gpgv", "$4", "$3")] {
+            let script = bin.join(name);
+            std::fs::write(
+                &script,
+                format!(
+                    "#!/bin/sh\n[ \"$(cat \"{signature}\")\" = \"signed $(cat \"{archive}\")\" ] || {{ echo bad signature >&2; exit 1; }}\n"
+                ),
+            )
+            .unwrap();
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let previous = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![bin];
+        paths.extend(std::env::split_paths(&previous));
+        // SAFETY: callers hold the env lock
+        unsafe { std::env::set_var("PATH", std::env::join_paths(paths).unwrap()) };
+        previous
+    }
+
+    /// Write the signature the stub verifiers accept for `archive`.
+    #[cfg(unix)]
+    fn stub_sign(archive: &Path, signature: &Path) {
+        let mut contents = b"signed ".to_vec();
+        contents.extend(std::fs::read(archive).unwrap());
+        std::fs::write(signature, contents).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_minisign_signature_is_rejected() {
+        let dir = temp_dir("sig-minisign-missing");
+        let archive = dir.join("release.tar.gz");
+        std::fs::write(&archive, b"release contents").unwrap();
+
+        let policy = SignaturePolicy {
+            public_key: Some(dir.join("release.pub")),
+            require_signature: true,
+            scheme: SignatureScheme::Minisign,
+        };
+        let signature = policy.signature_path(&archive);
+        assert!(
+            signature
+                .to_string_lossy()
+                .ends_with("release.tar.gz.minisig")
+        );
+
+        let err = enforce_signature(&archive, &signature, &policy)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("signature not found"), "{err}");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn valid_and_invalid_minisign_signatures() {
+        let _env = crate::test_support::ENV_LOCK.lock().await;
+        let dir = temp_dir("sig-minisign");
+        let path = stub_verifiers(&dir);
+
+        let archive = dir.join("release.tar.gz");
+        std::fs::write(&archive, b"release contents").unwrap();
+        let policy = SignaturePolicy {
+            public_key: Some(dir.join("release.pub")),
+            require_signature: true,
+            scheme: SignatureScheme::Minisign,
+        };
+        let signature = policy.signature_path(&archive);
+
+        stub_sign(&archive, &signature);
+        let valid = enforce_signature(&archive, &signature, &policy).await;
+
+        std::fs::write(&archive, b"tampered contents").unwrap();
+        let tampered = enforce_signature(&archive, &signature, &policy).await;
+
+        unsafe { std::env::set_var("PATH", path) };
+        assert!(valid.unwrap());
+        let err = tampered.unwrap_err();
+        assert!(err.to_string().contains("Invalid signature"), "{err}");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn valid_and_invalid_gpg_signatures() {
+        let _env = crate::test_support::ENV_LOCK.lock().await;
+        let dir = temp_dir("sig-gpg");
+        let path = stub_verifiers(&dir);
+
+        let archive = dir.join("release.tar.gz");
+        std::fs::write(&archive, b"release contents").unwrap();
+
+        let policy = SignaturePolicy {
+            public_key: Some(dir.join("release.gpg")),
+            require_signature: true,
+            scheme: SignatureScheme::Gpg,
+        };
+        let signature = policy.signature_path(&archive);
+        assert!(signature.to_string_lossy().ends_with("release.tar.gz.sig"));
+
+        // Missing signature is rejected
+        let missing = enforce_signature(&archive, &signature, &policy).await;
+
+        // Valid signature
+        stub_sign(&archive, &signature);
+        let valid = enforce_signature(&archive, &signature, &policy).await;
+
+        // Tampered archive no longer matches the signature
+        std::fs::write(&archive, b"tampered contents").unwrap();
+        let tampered = enforce_signature(&archive, &signature, &policy).await;
+
+        unsafe { std::env::set_var("PATH", path) };
+        let err = missing.unwrap_err();
+        assert!(err.to_string().contains("signature not found"), "{err}");
+        assert!(valid.unwrap());
+        let err = tampered.unwrap_err();
+        assert!(err.to_string().contains("Invalid signature"), "{err}");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn failed_validation_removes_its_temp_dir() {
+        let _env = crate::test_support::ENV_LOCK.lock().await;
+        let home = temp_dir("validate-cleanup");
+        let archive = home.join("release.tar.gz");
+        std::fs::write(&archive, b"not a tarball").unwrap();
+
+        let previous = std::env::var_os("EVO_HOME");
+        // SAFETY: the env lock serializes env mutation across tests
+        unsafe { std::env::set_var("EVO_HOME", &home) };
+        let result = validate_release("evo-king", "v1.2.0", &archive.to_string_lossy()).await;
+        match previous {
+            Some(value) => unsafe { std::env::set_var("EVO_HOME", value) },
+            None => unsafe { std::env::remove_var("EVO_HOME") },
+        }
+
+        assert!(result.is_err());
+        assert!(!home.join("data").join("validate-evo-king-v1.2.0").exists());
+        std::fs::remove_dir_all(&home).ok();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cancel_kills_child_and_cleans_up() {
+        let dir = temp_dir("cancel-build");
+        let staging = dir.join("staging");
+        std::fs::create_dir_all(&staging).unwrap();
+        let pid_file = dir.join("pid");
+
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        let pid_path = pid_file.clone();
+        tokio::spawn(async move {
+            while !pid_path.exists() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            trigger.cancel();
+        });
+
+        let script = format!("echo $$ > {}; exec sleep 30", pid_file.display());
+        let started = std::time::Instant::now();
+        let err = {
+            let _cleanup = BuildCleanup::new(staging.clone());
+            run_cmd_cancellable("sh", &["-c", &script], None, &cancel)
+                .await
+                .unwrap_err()
+        };
+        assert!(err.is::<Cancelled>(), "{err}");
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        assert!(!staging.exists(), "staging left behind");
+
+        // The killed child is reaped shortly after cancellation
+        let pid = std::fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .to_string();
+        let proc_stat = PathBuf::from(format!("/proc/{pid}/stat"));
+        let mut gone = false;
+        for _ in 0..100 {
+            let stat = std::fs::read_to_string(&proc_stat).unwrap_or_default();
+            if stat.is_empty() || stat.contains(") Z ") {
+                gone = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(gone, "child {pid} still running after cancel");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn resolves_components_from_repos_json() {
+        let dir = temp_dir("repos-json");
+        let checkout = dir.join("learning");
+        std::fs::create_dir_all(&checkout).unwrap();
+        let binary = dir.join("evo-agent-learning");
+        std::fs::write(&binary, b"bin").unwrap();
+
+        let repos: ReposJson = serde_json::from_value(serde_json::json!({
+            "repos": {
+                "evo-kernel-agent-learning": {
+                    "github": "ai-evo-agents/evo-kernel-agent-learning",
+                    "local_path": checkout,
+                    "binary_path": binary,
+                    "type": "kernel-agent",
+                },
+                "evo-gone": {
+                    "github": "ai-evo-agents/evo-gone",
+                    "local_path": dir.join("gone"),
+                },
+            }
+        }))
+        .unwrap();
+
+        let repo = repos.component("evo-kernel-agent-learning").unwrap();
+        assert_eq!(repo.local_path, checkout);
+        assert_eq!(repo.binary_path.as_deref(), Some(binary.as_path()));
+        assert_eq!(repo.binary_name, "evo-agent-learning");
+
+        let missing = repos.component("evo-king").unwrap_err().to_string();
+        assert!(
+            missing.contains("'evo-king' not found in repos.json"),
+            "{missing}"
+        );
+
+        let no_dir = repos.component("evo-gone").unwrap_err().to_string();
+        assert!(
+            no_dir.contains("Repo path for 'evo-gone' does not exist"),
+            "{no_dir}"
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    fn write_installed(home: &Path, version: &str) {
+        let repos = serde_json::json!({
+            "repos": { "evo-king": { "github": "ai-evo-agents/evo-king", "installed_version": version } }
+        });
+        std::fs::write(home.join("repos.json"), repos.to_string()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn evaluation_reports_installed_version_without_a_checkout() {
+        let home = temp_dir("evaluate-no-checkout");
+        write_installed(&home, "v0.1.0");
+
+        let eval = evaluate_upgrade_in(&home, "evo-king", "v0.2.0")
+            .await
+            .unwrap();
+        assert_eq!(eval["current_version"], "v0.1.0");
+        assert_eq!(eval["new_version"], "v0.2.0");
+
+        let unknown = evaluate_upgrade_in(&home, "evo-gone", "v0.2.0")
+            .await
+            .unwrap();
+        assert_eq!(unknown["current_version"], "unknown");
+        std::fs::remove_dir_all(&home).ok();
+    }
+
+    fn fast_policy(timeout_ms: u64) -> VerifyPolicy {
+        VerifyPolicy {
+            timeout: Duration::from_millis(timeout_ms),
+            interval: Duration::from_millis(20),
+            health_url: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn activation_verified_once_version_is_installed() {
+        let home = temp_dir("verify-ok");
+        write_installed(&home, "v0.1.0");
+
+        let updater = home.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            write_installed(&updater, "v0.2.0");
+        });
+
+        let outcome = verify_activation(&home, "evo-king", "v0.2.0", &fast_policy(5000)).await;
+        assert_eq!(outcome["verified"], true, "{outcome}");
+        std::fs::remove_dir_all(&home).ok();
+    }
+
+    #[tokio::test]
+    async fn activation_times_out_when_version_never_appears() {
+        let home = temp_dir("verify-timeout");
+        write_installed(&home, "v0.1.0");
+
+        let outcome = verify_activation(&home, "evo-king", "v0.2.0", &fast_policy(150)).await;
+        assert_eq!(outcome["verified"], false);
+        assert_eq!(outcome["installed_version"], "v0.1.0");
+        assert_eq!(outcome["reason"], "new version not reported installed");
+        std::fs::remove_dir_all(&home).ok();
+    }
+}
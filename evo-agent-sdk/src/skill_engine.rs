@@ -1,7 +1,16 @@
 use anyhow::{Context, Result};
-use evo_common::skill::{SkillConfig, SkillManifest};
+use evo_common::skill::{HttpMethod, SkillConfig, SkillEndpoint, SkillManifest};
+use serde::Serialize;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use tracing::{info, warn};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, error, info, warn};
+
+use crate::safe_mode;
+use crate::sandbox::SkillSandbox;
 
 // ─── Skill discovery ──────────────────────────────────────────────────────────
 
@@ -12,25 +21,421 @@ pub struct LoadedSkill {
     pub manifest: SkillManifest,
     pub config: Option<SkillConfig>,
     pub path: PathBuf,
+    /// Whether this skill's capabilities are announced to king at
+    /// registration. Non-advertised skills are still invocable locally.
+    pub advertise: bool,
+    /// Declared dependencies that are missing, unsatisfied themselves, or
+    /// part of a cycle. Non-empty means the skill is not advertised.
+    pub unsatisfied: Vec<String>,
+    /// How a config skill with several endpoints runs them.
+    pub execution: ExecutionPolicy,
+    /// Env vars visible only to this skill's calls.
+    pub env: SkillEnv,
+}
+
+/// How a config skill runs its endpoints, from `[execution]` in config.toml.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// One after another in config order, stopping at the first failure.
+    #[default]
+    Sequential,
+    /// Concurrently, collecting every endpoint's result or error.
+    Parallel,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutionPolicy {
+    pub mode: ExecutionMode,
+    /// Parallel mode only: endpoints in flight at once (`None` = all).
+    pub max_concurrent: Option<usize>,
+    /// Sequential mode only: endpoints (by name) whose input is the previous
+    /// endpoint's response instead of the skill input, set with
+    /// `input = "previous"` in their `[[endpoints]]` entry.
+    pub piped: Vec<String>,
+}
+
+impl ExecutionPolicy {
+    /// Parse the `[execution]` table of a config.toml, plus each endpoint's
+    /// `input`; absent or invalid values keep the sequential default.
+    fn from_config(toml_str: &str) -> Self {
+        let Ok(config) = toml::from_str::<toml::Table>(toml_str) else {
+            return Self::default();
+        };
+        let piped = config
+            .get("endpoints")
+            .and_then(|e| e.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|endpoint| {
+                let name = endpoint.get("name")?.as_str()?;
+                match endpoint.get("input")?.as_str() {
+                    Some("previous") => Some(name.to_string()),
+                    other => {
+                        warn!(endpoint = name, input = ?other, "unknown endpoint input, using the skill input");
+                        None
+                    }
+                }
+            })
+            .collect();
+        let Some(table) = config.get("execution").and_then(|t| t.as_table()) else {
+            return Self {
+                piped,
+                ..Self::default()
+            };
+        };
+        let mode = match table.get("mode").and_then(|m| m.as_str()) {
+            None | Some("sequential") => ExecutionMode::Sequential,
+            Some("parallel") => ExecutionMode::Parallel,
+            Some(other) => {
+                warn!(
+                    mode = other,
+                    "unknown skill execution mode, using sequential"
+                );
+                ExecutionMode::Sequential
+            }
+        };
+        let max_concurrent = table
+            .get("max_concurrent")
+            .and_then(|n| n.as_integer())
+            .filter(|n| *n > 0)
+            .map(|n| n as usize);
+        Self {
+            mode,
+            max_concurrent,
+            piped,
+        }
+    }
+
+    fn pipes(&self, endpoint: &str) -> bool {
+        self.piped.iter().any(|name| name == endpoint)
+    }
+}
+
+/// Env vars scoped to one skill: the `[env]` table of its config.toml,
+/// overridden by its `.env` file (`KEY=VALUE` lines). They resolve the
+/// skill's `auth_ref` ahead of the process env and are passed to its code,
+/// but never enter the process env, so other skills can't read them. Code
+/// skills see only the `SKILL_ENV_ALLOW` vars of the process env. Values are
+/// never logged.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SkillEnv(HashMap<String, String>);
+
+impl SkillEnv {
+    fn load(skill_dir: &Path, config_str: Option<&str>) -> Result<Self> {
+        let mut vars = HashMap::new();
+        if let Some(table) = config_str
+            .and_then(|c| toml::from_str::<toml::Table>(c).ok())
+            .and_then(|t| t.get("env")?.as_table().cloned())
+        {
+            for (name, value) in table {
+                let value = match value {
+                    toml::Value::String(s) => s,
+                    other => other.to_string(),
+                };
+                vars.insert(name, value);
+            }
+        }
+
+        let env_path = skill_dir.join(".env");
+        if env_path.exists() {
+            let content = std::fs::read_to_string(&env_path)
+                .with_context(|| format!("Failed to read {}", env_path.display()))?;
+            for (n, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let line = line.strip_prefix("export ").unwrap_or(line);
+                let Some((name, value)) = line.split_once('=') else {
+                    warn!(path = %env_path.display(), line = n + 1, "skipping .env line without '='");
+                    continue;
+                };
+                let value = value.trim();
+                let value = [('"', '"'), ('\'', '\'')]
+                    .iter()
+                    .find_map(|(open, close)| value.strip_prefix(*open)?.strip_suffix(*close))
+                    .unwrap_or(value);
+                vars.insert(name.trim().to_string(), value.to_string());
+            }
+        }
+        Ok(Self(vars))
+    }
+
+    /// The skill's own value for `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// The skill's own value for `name`, else the process env's.
+    pub fn var(&self, name: &str) -> Option<String> {
+        match self.get(name) {
+            Some(value) => Some(value.to_string()),
+            None => std::env::var(name).ok(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Names only; the values are secrets.
+impl std::fmt::Debug for SkillEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<&String> = self.0.keys().collect();
+        names.sort();
+        f.debug_tuple("SkillEnv").field(&names).finish()
+    }
+}
+
+/// Which skill to keep when two skill directories declare the same `name`.
+///
+/// Directories are visited in file-name order, so "first" and "last" are
+/// deterministic across hosts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    #[default]
+    FirstWins,
+    LastWins,
+}
+
+impl DuplicatePolicy {
+    /// Read `SKILL_DUPLICATE_POLICY` (`first` or `last`, default `first`).
+    pub fn from_env() -> Self {
+        match std::env::var("SKILL_DUPLICATE_POLICY").as_deref() {
+            Ok("last") => Self::LastWins,
+            _ => Self::FirstWins,
+        }
+    }
+}
+
+/// What to do with a skill whose declared `dependencies` can't be satisfied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingDependencyPolicy {
+    /// Load it, but record the gaps in [`LoadedSkill::unsatisfied`] and
+    /// don't advertise it.
+    #[default]
+    Flag,
+    /// Don't load it at all.
+    Skip,
+}
+
+impl MissingDependencyPolicy {
+    /// Read `SKILL_MISSING_DEPS` (`flag` or `skip`, default `flag`).
+    pub fn from_env() -> Self {
+        match std::env::var("SKILL_MISSING_DEPS").as_deref() {
+            Ok("skip") => Self::Skip,
+            _ => Self::Flag,
+        }
+    }
+}
+
+/// Skills whose `dependencies` form a cycle.
+#[derive(Debug, thiserror::Error)]
+#[error("skill dependency cycle among: {}", skills.join(", "))]
+pub struct DependencyCycle {
+    pub skills: Vec<String>,
+}
+
+/// A skill directory whose manifest could not be loaded.
+#[derive(Debug, Clone)]
+pub struct SkillLoadFailure {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Outcome of scanning `skills/`: what loaded and what didn't.
+#[derive(Debug, Clone, Default)]
+pub struct SkillLoad {
+    pub skills: Vec<LoadedSkill>,
+    pub failed: Vec<SkillLoadFailure>,
 }
 
-/// Scan `<agent_dir>/skills/` and load all valid skill manifests.
+/// Scan `<agent_dir>/skills/` and load all valid skill manifests, resolving
+/// duplicate names with [`DuplicatePolicy::from_env`] and ordering them with
+/// [`order_by_dependencies`].
 pub fn load_skills(agent_dir: &Path) -> Vec<LoadedSkill> {
+    load_skills_report(agent_dir).skills
+}
+
+/// [`load_skills`], also returning the skill directories that failed to load.
+pub fn load_skills_report(agent_dir: &Path) -> SkillLoad {
+    let SkillLoad { mut skills, failed } = scan_skills(agent_dir, DuplicatePolicy::from_env());
+    if let Err(e) = order_by_dependencies(&mut skills, MissingDependencyPolicy::from_env()) {
+        error!(err = %e, "skills in a dependency cycle are loaded last and not advertised");
+    }
+    if skills.iter().any(|s| !s.manifest.dependencies.is_empty()) {
+        let order: Vec<&str> = skills.iter().map(|s| s.name.as_str()).collect();
+        info!(?order, "resolved skill load order");
+    }
+    SkillLoad { skills, failed }
+}
+
+/// [`load_skills`] with an explicit duplicate-name policy. Skills are
+/// returned sorted by name, without dependency resolution.
+pub fn load_skills_with(agent_dir: &Path, policy: DuplicatePolicy) -> Vec<LoadedSkill> {
+    scan_skills(agent_dir, policy).skills
+}
+
+fn scan_skills(agent_dir: &Path, policy: DuplicatePolicy) -> SkillLoad {
     let skills_dir = agent_dir.join("skills");
 
     let entries = match std::fs::read_dir(&skills_dir) {
         Ok(e) => e,
         Err(_) => {
             info!("no skills/ directory found — agent has no pre-loaded skills");
-            return vec![];
+            return SkillLoad::default();
         }
     };
 
-    entries
+    let mut dirs: Vec<PathBuf> = entries
         .filter_map(|e| e.ok())
         .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
-        .filter_map(|e| load_skill(&e.path()).ok())
-        .collect()
+        .map(|e| e.path())
+        .collect();
+    dirs.sort();
+
+    let mut skills: Vec<LoadedSkill> = Vec::with_capacity(dirs.len());
+    let mut failed = Vec::new();
+    for dir in &dirs {
+        let skill = match load_skill(dir) {
+            Ok(skill) => skill,
+            Err(e) => {
+                warn!(path = %dir.display(), err = %format!("{e:#}"), "failed to load skill");
+                failed.push(SkillLoadFailure {
+                    path: dir.clone(),
+                    error: format!("{e:#}"),
+                });
+                continue;
+            }
+        };
+        let Some(idx) = skills.iter().position(|s| s.name == skill.name) else {
+            skills.push(skill);
+            continue;
+        };
+        let (kept, dropped) = match policy {
+            DuplicatePolicy::FirstWins => (&skills[idx].path, &skill.path),
+            DuplicatePolicy::LastWins => (&skill.path, &skills[idx].path),
+        };
+        warn!(
+            skill = %skill.name,
+            kept = %kept.display(),
+            dropped = %dropped.display(),
+            ?policy,
+            "DUPLICATE SKILL NAME — only one copy will be loaded"
+        );
+        if policy == DuplicatePolicy::LastWins {
+            skills[idx] = skill;
+        }
+    }
+
+    // Stable order for registration payloads and logs
+    skills.sort_by(|a, b| a.name.cmp(&b.name));
+    SkillLoad { skills, failed }
+}
+
+/// Log every loaded skill with its capabilities, plus a summary, so a
+/// missing skill is diagnosable from the startup log alone. Warns when
+/// nothing loaded for a role that should have skills: any non-kernel role,
+/// or any role whose skill directories all failed.
+pub fn log_skill_load(role: &str, load: &SkillLoad) {
+    for skill in &load.skills {
+        info!(
+            skill = %skill.name,
+            capabilities = ?skill.manifest.capabilities,
+            advertise = skill.advertise,
+            unsatisfied = ?skill.unsatisfied,
+            "skill ready"
+        );
+    }
+    info!(
+        loaded = load.skills.len(),
+        failed = load.failed.len(),
+        "skills loaded"
+    );
+    let expected = !crate::kernel_handlers::is_kernel_role(role) || !load.failed.is_empty();
+    if load.skills.is_empty() && expected {
+        warn!(
+            role = %role,
+            failed = load.failed.len(),
+            "NO SKILLS LOADED — this agent has nothing to advertise or invoke"
+        );
+    }
+}
+
+/// Reorder `skills` so every skill follows its dependencies, breaking ties
+/// by name.
+///
+/// Skills with missing (or transitively unsatisfied) dependencies are
+/// handled per `policy`. Skills in a cycle are moved to the end, marked
+/// unsatisfied, and reported as a [`DependencyCycle`].
+pub fn order_by_dependencies(
+    skills: &mut Vec<LoadedSkill>,
+    policy: MissingDependencyPolicy,
+) -> Result<(), DependencyCycle> {
+    skills.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Propagate unsatisfied dependencies until nothing changes
+    loop {
+        let available: Vec<String> = skills
+            .iter()
+            .filter(|s| s.unsatisfied.is_empty())
+            .map(|s| s.name.clone())
+            .collect();
+        let mut changed = false;
+        for skill in skills.iter_mut().filter(|s| s.unsatisfied.is_empty()) {
+            let missing: Vec<String> = skill
+                .manifest
+                .dependencies
+                .iter()
+                .filter(|d| !available.contains(d))
+                .cloned()
+                .collect();
+            if !missing.is_empty() {
+                warn!(skill = %skill.name, ?missing, ?policy, "skill dependencies not satisfied");
+                skill.unsatisfied = missing;
+                skill.advertise = false;
+                changed = true;
+            }
+        }
+        if policy == MissingDependencyPolicy::Skip {
+            skills.retain(|s| s.unsatisfied.is_empty());
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // Kahn's algorithm, always taking the first ready skill in name order
+    let mut pending = std::mem::take(skills);
+    while let Some(idx) = pending.iter().position(|s| {
+        s.manifest.dependencies.iter().all(|d| {
+            skills.iter().any(|done| &done.name == d) || !pending.iter().any(|p| &p.name == d)
+        })
+    }) {
+        skills.push(pending.remove(idx));
+    }
+
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let cycle: Vec<String> = pending.iter().map(|s| s.name.clone()).collect();
+    for mut skill in pending {
+        skill.unsatisfied = skill
+            .manifest
+            .dependencies
+            .iter()
+            .filter(|d| cycle.contains(d))
+            .cloned()
+            .collect();
+        skill.advertise = false;
+        skills.push(skill);
+    }
+    Err(DependencyCycle { skills: cycle })
 }
 
 fn load_skill(skill_dir: &Path) -> Result<LoadedSkill> {
@@ -40,31 +445,237 @@ fn load_skill(skill_dir: &Path) -> Result<LoadedSkill> {
     let manifest: SkillManifest = toml::from_str(&manifest_str)
         .with_context(|| format!("Failed to parse {}", manifest_path.display()))?;
 
-    let config = read_skill_config(skill_dir);
+    let config = read_skill_config(skill_dir)?;
+    let config_str = std::fs::read_to_string(skill_dir.join("config.toml")).ok();
+
+    // `advertise` and `execution` aren't part of the shared schemas; read
+    // them from the raw files (`advertise` falls back to config.toml).
+    let advertise = read_advertise(&manifest_str)
+        .or_else(|| config_str.as_deref().and_then(read_advertise))
+        .unwrap_or(true);
+    let execution = config_str
+        .as_deref()
+        .map(ExecutionPolicy::from_config)
+        .unwrap_or_default();
+    let env = SkillEnv::load(skill_dir, config_str.as_deref())?;
 
     let name = manifest.name.clone();
-    info!(skill = %name, path = %skill_dir.display(), "loaded skill");
+    debug!(skill = %name, path = %skill_dir.display(), advertise, "parsed skill manifest");
 
     Ok(LoadedSkill {
         name,
         manifest,
         config,
         path: skill_dir.to_path_buf(),
+        advertise,
+        unsatisfied: vec![],
+        execution,
+        env,
     })
 }
 
-fn read_skill_config(skill_dir: &Path) -> Option<SkillConfig> {
+fn read_advertise(toml_str: &str) -> Option<bool> {
+    toml::from_str::<toml::Table>(toml_str)
+        .ok()?
+        .get("advertise")?
+        .as_bool()
+}
+
+/// Deduplicated capabilities of all advertised skills, for registration.
+pub fn advertised_capabilities(skills: &[LoadedSkill]) -> Vec<String> {
+    let mut caps: Vec<String> = skills
+        .iter()
+        .filter(|s| s.advertise)
+        .flat_map(|s| s.manifest.capabilities.iter().cloned())
+        .collect();
+    caps.sort();
+    caps.dedup();
+    caps
+}
+
+/// The skill's config.toml, if it has one. An invalid one (e.g. an endpoint
+/// `method` other than GET/POST/PUT/DELETE/PATCH) fails the skill's load
+/// rather than leaving it without endpoints.
+fn read_skill_config(skill_dir: &Path) -> Result<Option<SkillConfig>> {
     let config_path = skill_dir.join("config.toml");
     if !config_path.exists() {
-        return None;
+        return Ok(None);
     }
-    let content = std::fs::read_to_string(&config_path).ok()?;
-    toml::from_str(&content).ok()
+    let content = std::fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    toml::from_str(&content)
+        .map(Some)
+        .with_context(|| format!("Failed to parse {}", config_path.display()))
 }
 
 // ─── Skill execution ──────────────────────────────────────────────────────────
 
+/// One skill invocation, as reported in `pipeline:stage_result.skills_used`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkillUse {
+    pub name: String,
+    pub success: bool,
+    pub latency_ms: u64,
+}
+
+/// Skill invocations made during one pipeline dispatch.
+#[derive(Debug, Clone, Default)]
+pub struct SkillUsageLog(Arc<Mutex<Vec<SkillUse>>>);
+
+impl SkillUsageLog {
+    pub fn record(&self, usage: SkillUse) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).push(usage);
+    }
+
+    pub fn snapshot(&self) -> Vec<SkillUse> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// The agent's loaded skills, runnable by name. Derefs to the slice, so it
+/// can be iterated and searched like one.
+#[derive(Debug, Clone, Default)]
+pub struct SkillSet {
+    skills: Vec<LoadedSkill>,
+}
+
+impl SkillSet {
+    pub const fn new(skills: Vec<LoadedSkill>) -> Self {
+        Self { skills }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LoadedSkill> {
+        self.skills.iter().find(|s| s.name == name)
+    }
+
+    /// Run the skill called `name` — code skills sandboxed, config skills
+    /// through a shared HTTP client. Not recorded anywhere; within a stage,
+    /// `ctx.skills` is a [`StageSkills`] whose `execute` is.
+    pub async fn execute(
+        &self,
+        name: &str,
+        input: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let skill = self
+            .get(name)
+            .with_context(|| format!("Skill '{name}' is not loaded"))?;
+        run_skill(skill, input).await
+    }
+}
+
+impl std::ops::Deref for SkillSet {
+    type Target = [LoadedSkill];
+
+    fn deref(&self) -> &[LoadedSkill] {
+        &self.skills
+    }
+}
+
+impl From<Vec<LoadedSkill>> for SkillSet {
+    fn from(skills: Vec<LoadedSkill>) -> Self {
+        Self::new(skills)
+    }
+}
+
+/// The skills as one pipeline stage sees them: the agent's [`SkillSet`],
+/// with every [`execute`](Self::execute) recorded in the stage's
+/// [`SkillUsageLog`] (the stage result's `skills_used`). Derefs to the set.
+#[derive(Debug, Clone)]
+pub struct StageSkills<'a> {
+    set: &'a SkillSet,
+    log: SkillUsageLog,
+}
+
+impl<'a> StageSkills<'a> {
+    pub fn new(set: &'a SkillSet, log: SkillUsageLog) -> Self {
+        Self { set, log }
+    }
+
+    /// The underlying set, for calls that shouldn't be recorded.
+    pub fn set(&self) -> &'a SkillSet {
+        self.set
+    }
+
+    /// [`SkillSet::execute`], recording the call.
+    pub async fn execute(
+        &self,
+        name: &str,
+        input: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        invoke_skill(self.set, name, input, &self.log).await
+    }
+}
+
+impl std::ops::Deref for StageSkills<'_> {
+    type Target = SkillSet;
+
+    fn deref(&self) -> &SkillSet {
+        self.set
+    }
+}
+
+/// Invoke the skill called `name` — code skills run sandboxed, config skills
+/// make their HTTP call — and record the outcome in `log`.
+pub async fn invoke_skill(
+    skills: &[LoadedSkill],
+    name: &str,
+    input: &serde_json::Value,
+    log: &SkillUsageLog,
+) -> Result<serde_json::Value> {
+    let skill = skills
+        .iter()
+        .find(|s| s.name == name)
+        .with_context(|| format!("Skill '{name}' is not loaded"))?;
+
+    let start = Instant::now();
+    let result = run_skill(skill, input).await;
+
+    log.record(SkillUse {
+        name: skill.name.clone(),
+        success: result.is_ok(),
+        latency_ms: start.elapsed().as_millis() as u64,
+    });
+    result
+}
+
+async fn run_skill(skill: &LoadedSkill, input: &serde_json::Value) -> Result<serde_json::Value> {
+    if skill.manifest.has_code {
+        run_code_skill(skill, input, skill_sandbox()).await
+    } else {
+        run_config_skill(skill_http_client(), skill, input).await
+    }
+}
+
+/// Shared HTTP client for config skills.
+fn skill_http_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Sandbox for code skills, read once from env.
+fn skill_sandbox() -> &'static SkillSandbox {
+    static SANDBOX: OnceLock<SkillSandbox> = OnceLock::new();
+    SANDBOX.get_or_init(SkillSandbox::from_env)
+}
+
 /// Execute a config-only skill by making HTTP calls defined in its config.
+///
+/// A single endpoint's response is returned as-is. With several endpoints
+/// the skill's [`ExecutionPolicy`] decides how they run, and the output is
+/// `{ "results": { <endpoint>: ... }, "errors": { <endpoint>: "..." } }`.
+/// Sequential runs stop at (and fail with) the first endpoint error. Every
+/// endpoint gets the skill input unless it opts into chaining: with
+/// `input = "previous"` it gets the last response instead, and its URL can
+/// reference `{previous.<path>}` or `{responses.<endpoint>.<path>}`.
+/// `{key}` in an endpoint URL is filled from the input's `key` field.
+///
+/// Each endpoint is called with its own `method`: `GET` and `DELETE` send
+/// the input as query parameters, the others as a JSON body.
 pub async fn run_config_skill(
     client: &reqwest::Client,
     skill: &LoadedSkill,
@@ -79,15 +690,103 @@ pub async fn run_config_skill(
         return Ok(serde_json::json!({ "status": "no_endpoints" }));
     }
 
-    // For now execute the first endpoint (extend in future phases)
-    let endpoint = &config.endpoints[0];
-    info!(skill = %skill.name, url = %endpoint.url, "calling skill endpoint");
+    if let [endpoint] = config.endpoints.as_slice() {
+        return call_endpoint(client, skill, config, endpoint, input, None).await;
+    }
 
-    let mut req = client.post(&endpoint.url).json(input);
+    let mut results = serde_json::Map::new();
+    let mut errors = serde_json::Map::new();
+    match skill.execution.mode {
+        ExecutionMode::Sequential => {
+            let mut previous = None;
+            for endpoint in &config.endpoints {
+                // Earlier responses reach an endpoint only when it asks for
+                // them: as its input, or through URL references
+                let chain = previous
+                    .as_ref()
+                    .map(|last| serde_json::json!({ "previous": last, "responses": results }));
+                let endpoint_input = match &previous {
+                    Some(last) if skill.execution.pipes(&endpoint.name) => last,
+                    _ => input,
+                };
+                let out = call_endpoint(
+                    client,
+                    skill,
+                    config,
+                    endpoint,
+                    endpoint_input,
+                    chain.as_ref(),
+                )
+                .await
+                .with_context(|| format!("Endpoint '{}' failed", endpoint.name))?;
+                results.insert(endpoint.name.clone(), out.clone());
+                previous = Some(out);
+            }
+        }
+        ExecutionMode::Parallel => {
+            use futures_util::StreamExt;
+            let limit = skill
+                .execution
+                .max_concurrent
+                .unwrap_or(config.endpoints.len());
+            // Futures built up front: a closure over borrowed endpoints trips
+            // the `Send` check in `async_trait` handlers calling this
+            let calls: Vec<_> = config
+                .endpoints
+                .iter()
+                .map(|endpoint| async move {
+                    let out = call_endpoint(client, skill, config, endpoint, input, None).await;
+                    (endpoint.name.clone(), out)
+                })
+                .collect();
+            let outcomes: Vec<_> = futures_util::stream::iter(calls)
+                .buffered(limit)
+                .collect()
+                .await;
+            for (name, out) in outcomes {
+                match out {
+                    Ok(value) => {
+                        results.insert(name, value);
+                    }
+                    Err(e) => {
+                        warn!(skill = %skill.name, endpoint = %name, err = %e, "skill endpoint failed");
+                        errors.insert(name, serde_json::json!(format!("{e:#}")));
+                    }
+                }
+            }
+        }
+    }
+    Ok(serde_json::json!({ "results": results, "errors": errors }))
+}
+
+/// Make one endpoint call of a config skill.
+async fn call_endpoint(
+    client: &reqwest::Client,
+    skill: &LoadedSkill,
+    config: &SkillConfig,
+    endpoint: &SkillEndpoint,
+    input: &serde_json::Value,
+    chain: Option<&serde_json::Value>,
+) -> Result<serde_json::Value> {
+    if endpoint.method != HttpMethod::Get {
+        safe_mode::guard("non-GET skill endpoint call")?;
+    }
+    let url = expand_url(&endpoint.url, input, chain)
+        .with_context(|| format!("Invalid URL for endpoint '{}'", endpoint.name))?;
+    // The template: filled-in values may be tokens or user ids
+    info!(skill = %skill.name, url = %endpoint.url, method = ?endpoint.method, "calling skill endpoint");
+
+    let mut req = match endpoint.method {
+        HttpMethod::Get => client.get(&url).query(&query_pairs(input)),
+        HttpMethod::Delete => client.delete(&url).query(&query_pairs(input)),
+        HttpMethod::Post => client.post(&url).json(input),
+        HttpMethod::Put => client.put(&url).json(input),
+        HttpMethod::Patch => client.patch(&url).json(input),
+    };
 
     // Inject API key if auth_ref is set
     if let Some(auth_ref) = &config.auth_ref {
-        if let Ok(key) = std::env::var(auth_ref) {
+        if let Some(key) = skill.env.var(auth_ref) {
             req = req.bearer_auth(key);
         } else {
             warn!(auth_ref = %auth_ref, "auth env var not set for skill");
@@ -96,11 +795,717 @@ pub async fn run_config_skill(
 
     let resp = req.send().await.context("Skill HTTP request failed")?;
     let status = resp.status();
-    let body: serde_json::Value = resp.json().await.unwrap_or_else(|_| serde_json::json!({}));
 
     if !status.is_success() {
-        anyhow::bail!("Skill endpoint returned {status}: {body}");
+        let text = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Skill endpoint returned {status}: {text}");
+    }
+
+    decode_response(resp).await
+}
+
+/// Fill `{key}` placeholders in `url` from the input object's string, number
+/// or boolean fields (percent-encoded). In a sequential chain,
+/// `{previous.<path>}` and `{responses.<endpoint>.<path>}` read earlier
+/// responses from `chain` instead. The input itself is left as is. Fails
+/// when a placeholder has no such field.
+fn expand_url(
+    url: &str,
+    input: &serde_json::Value,
+    chain: Option<&serde_json::Value>,
+) -> Result<String> {
+    let fields = input.as_object();
+    let mut expanded = String::with_capacity(url.len());
+    let mut rest = url;
+    while let Some(open) = rest.find('{') {
+        let Some(len) = rest[open..].find('}') else {
+            anyhow::bail!("unclosed '{{' in {url}");
+        };
+        let key = &rest[open + 1..open + len];
+        let chained = key.starts_with("previous.") || key.starts_with("responses.");
+        let found = if chained {
+            chain.and_then(|c| c.pointer(&format!("/{}", key.replace('.', "/"))))
+        } else {
+            fields.and_then(|f| f.get(key))
+        };
+        let value = match found {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(v @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_))) => v.to_string(),
+            Some(_) => anyhow::bail!(
+                "input field '{key}' for {{{key}}} must be a string, number or boolean"
+            ),
+            None => anyhow::bail!("input has no field '{key}' for {{{key}}}"),
+        };
+        expanded.push_str(&rest[..open]);
+        expanded.push_str(&percent_encode(&value));
+        rest = &rest[open + len + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters, so a
+/// value stays within one path segment.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Query parameters for a body-less request: the input's top-level fields,
+/// strings as-is and anything else as JSON.
+fn query_pairs(input: &serde_json::Value) -> Vec<(String, String)> {
+    let Some(map) = input.as_object() else {
+        return Vec::new();
+    };
+    map.iter()
+        .filter(|(_, v)| !v.is_null())
+        .map(|(k, v)| match v {
+            serde_json::Value::String(s) => (k.clone(), s.clone()),
+            other => (k.clone(), other.to_string()),
+        })
+        .collect()
+}
+
+/// Execute a code skill inside `sandbox`.
+///
+/// Runs the skill's entrypoint (manifest `entrypoint`, default `run`, relative
+/// to the skill dir) with `input` as JSON on stdin. Stdout is parsed as JSON,
+/// falling back to `{ "text": ... }`.
+pub async fn run_code_skill(
+    skill: &LoadedSkill,
+    input: &serde_json::Value,
+    sandbox: &SkillSandbox,
+) -> Result<serde_json::Value> {
+    safe_mode::guard("code skill execution")?;
+    let entrypoint = std::fs::read_to_string(skill.path.join("manifest.toml"))
+        .ok()
+        .and_then(|m| toml::from_str::<toml::Table>(&m).ok())
+        .and_then(|t| t.get("entrypoint")?.as_str().map(String::from))
+        .unwrap_or_else(|| "run".to_string());
+
+    // Entrypoints must stay inside the skill directory
+    let relative = Path::new(&entrypoint);
+    if relative.is_absolute()
+        || relative
+            .components()
+            .any(|c| matches!(c, std::path::Component::ParentDir))
+    {
+        anyhow::bail!(
+            "Skill '{}' entrypoint escapes its directory: {entrypoint}",
+            skill.name
+        );
+    }
+    let program = skill.path.join(relative);
+    if !program.is_file() {
+        anyhow::bail!(
+            "Skill '{}' entrypoint not found: {}",
+            skill.name,
+            program.display()
+        );
+    }
+
+    info!(skill = %skill.name, entrypoint = %program.display(), "running code skill");
+
+    let mut child = sandbox
+        .command(&program, &skill.path)
+        .envs(skill.env.iter())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to spawn skill '{}'", skill.name))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.to_string().as_bytes()).await.ok();
+    }
+
+    let output = tokio::time::timeout(sandbox.timeout, child.wait_with_output())
+        .await
+        .with_context(|| {
+            format!(
+                "Skill '{}' timed out after {}s",
+                skill.name,
+                sandbox.timeout.as_secs()
+            )
+        })?
+        .with_context(|| format!("Failed to wait for skill '{}'", skill.name))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "Skill '{}' exited with {}: {}",
+            skill.name,
+            output.status,
+            stderr.trim()
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    Ok(serde_json::from_str(&stdout).unwrap_or_else(|_| serde_json::json!({ "text": stdout })))
+}
+
+/// Decode a skill response body according to its `Content-Type`.
+///
+/// JSON is parsed as-is, text types are returned as `{ "text": ... }`, and
+/// anything else (binary payloads) is an error rather than a silent `{}`.
+async fn decode_response(resp: reqwest::Response) -> Result<serde_json::Value> {
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or("").trim().to_lowercase())
+        .unwrap_or_default();
+
+    let is_json = content_type == "application/json" || content_type.ends_with("+json");
+    let is_text = content_type.starts_with("text/")
+        || content_type == "application/xml"
+        || content_type.ends_with("+xml");
+
+    if is_json {
+        let text = resp.text().await.context("Failed to read skill response")?;
+        if text.trim().is_empty() {
+            return Ok(serde_json::json!({}));
+        }
+        return serde_json::from_str(&text).context("Skill returned invalid JSON");
+    }
+
+    if is_text || content_type.is_empty() {
+        let text = resp.text().await.context("Failed to read skill response")?;
+        // Untyped bodies are still JSON more often than not
+        if content_type.is_empty()
+            && let Ok(value) = serde_json::from_str(&text)
+        {
+            return Ok(value);
+        }
+        return Ok(serde_json::json!({ "text": text }));
+    }
+
+    anyhow::bail!("Skill endpoint returned unsupported content type: {content_type}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, MockResponse, MockServer, http_skill};
+    use serde_json::json;
+
+    async fn run_against(response: MockResponse) -> Result<serde_json::Value> {
+        let server = MockServer::start(vec![response]).await;
+        let skill = http_skill(&server.url);
+        run_config_skill(&reqwest::Client::new(), &skill, &json!({ "q": 1 })).await
+    }
+
+    #[tokio::test]
+    async fn json_response_is_parsed() {
+        let out = run_against(MockResponse::json(200, &json!({ "hits": [1, 2] })))
+            .await
+            .unwrap();
+        assert_eq!(out, json!({ "hits": [1, 2] }));
+    }
+
+    #[tokio::test]
+    async fn text_response_is_wrapped() {
+        let out = run_against(MockResponse::new(200, "text/plain; charset=utf-8", "hello"))
+            .await
+            .unwrap();
+        assert_eq!(out, json!({ "text": "hello" }));
+    }
+
+    #[tokio::test]
+    async fn binary_response_is_an_error() {
+        let err = run_against(MockResponse::new(
+            200,
+            "application/octet-stream",
+            vec![0u8, 159, 146, 150],
+        ))
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("unsupported content type"),
+            "{err}"
+        );
+    }
+
+    fn multi_endpoint_skill(urls: &[(&str, &str)], execution: ExecutionPolicy) -> LoadedSkill {
+        let mut skill = http_skill("");
+        let config = skill.config.as_mut().unwrap();
+        let template = config.endpoints[0].clone();
+        config.endpoints = urls
+            .iter()
+            .map(|(name, url)| SkillEndpoint {
+                name: name.to_string(),
+                url: url.to_string(),
+                ..template.clone()
+            })
+            .collect();
+        skill.execution = execution;
+        skill
+    }
+
+    #[tokio::test]
+    async fn sequential_endpoints_run_in_order_and_stop_at_failure() {
+        let auth = MockServer::start(vec![MockResponse::json(200, &json!({ "token": "t" }))]).await;
+        let data = MockServer::start(vec![MockResponse::json(200, &json!({ "rows": 3 }))]).await;
+        let skill = multi_endpoint_skill(
+            &[("auth", &auth.url), ("data", &data.url)],
+            ExecutionPolicy::default(),
+        );
+        let out = run_config_skill(&reqwest::Client::new(), &skill, &json!({}))
+            .await
+            .unwrap();
+        let order: Vec<&String> = out["results"].as_object().unwrap().keys().collect();
+        assert_eq!(order, ["auth", "data"]);
+        assert_eq!(out["results"]["data"]["rows"], 3);
+
+        let broken = MockServer::start(vec![MockResponse::new(500, "text/plain", "down")]).await;
+        let skill = multi_endpoint_skill(
+            &[("auth", &broken.url), ("data", &data.url)],
+            ExecutionPolicy::default(),
+        );
+        let err = run_config_skill(&reqwest::Client::new(), &skill, &json!({}))
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("Endpoint 'auth' failed"),
+            "{err:#}"
+        );
+        assert_eq!(
+            data.requests().len(),
+            1,
+            "data must not run after auth fails"
+        );
+    }
+
+    #[tokio::test]
+    async fn endpoints_see_earlier_responses_only_when_they_opt_in() {
+        let auth = MockServer::start(vec![MockResponse::json(
+            200,
+            &json!({ "token": "t-1", "user_id": "u7" }),
+        )])
+        .await;
+        let data = MockServer::start(vec![MockResponse::json(200, &json!({ "rows": 3 }))]).await;
+        let format = MockServer::start(vec![MockResponse::json(200, &json!({ "ok": true }))]).await;
+        let data_url = format!("{}/users/{{responses.auth.user_id}}", data.url);
+        let execution = ExecutionPolicy::from_config(
+            "[[endpoints]]\nname = \"format\"\nurl = \"x\"\nmethod = \"POST\"\ninput = \"previous\"\n",
+        );
+        assert_eq!(execution.piped, ["format"]);
+        let mut skill = multi_endpoint_skill(
+            &[
+                ("auth", &auth.url),
+                ("data", &data_url),
+                ("format", &format.url),
+            ],
+            execution,
+        );
+        skill.config.as_mut().unwrap().endpoints[1].method = HttpMethod::Get;
+
+        let out = run_config_skill(&reqwest::Client::new(), &skill, &json!({ "city": "Oslo" }))
+            .await
+            .unwrap();
+        assert_eq!(out["results"]["data"]["rows"], 3);
+
+        let auth_request = &auth.requests()[0];
+        assert_eq!(auth_request.method, "POST");
+        assert_eq!(auth_request.json(), json!({ "city": "Oslo" }));
+
+        // The GET references one earlier field in its URL; nothing else from
+        // earlier responses (least of all the token) is sent
+        let data_request = &data.requests()[0];
+        assert_eq!(data_request.method, "GET");
+        assert_eq!(data_request.path, "/users/u7?city=Oslo");
+
+        // `input = "previous"` sends the last response as the body, as-is
+        assert_eq!(format.requests()[0].json(), json!({ "rows": 3 }));
+    }
+
+    #[tokio::test]
+    async fn endpoint_method_is_read_from_config_and_unknown_ones_fail_the_load() {
+        let server = MockServer::start(vec![MockResponse::json(200, &json!({ "hits": 2 }))]).await;
+        let agent_dir = test_support::temp_dir("skill-method");
+        write_skill(&agent_dir, "search", "web-search", "");
+        std::fs::write(
+            agent_dir.join("skills/search/config.toml"),
+            format!(
+                "[[endpoints]]\nname = \"search\"\nurl = \"{}\"\nmethod = \"GET\"\n",
+                server.url
+            ),
+        )
+        .unwrap();
+        write_skill(&agent_dir, "probe", "probe", "");
+        std::fs::write(
+            agent_dir.join("skills/probe/config.toml"),
+            "[[endpoints]]\nname = \"probe\"\nurl = \"http://127.0.0.1:9\"\nmethod = \"HEAD\"\n",
+        )
+        .unwrap();
+
+        let load = load_skills_report(&agent_dir);
+        assert_eq!(load.failed.len(), 1);
+        let failure = &load.failed[0].error;
+        assert!(failure.contains("config.toml"), "{failure}");
+        assert!(failure.contains("unknown variant `HEAD`"), "{failure}");
+
+        let [skill] = load.skills.as_slice() else {
+            panic!("expected only the GET skill to load");
+        };
+        let out = run_config_skill(
+            &reqwest::Client::new(),
+            skill,
+            &json!({ "q": "rust", "limit": 5 }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(out["hits"], 2);
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "GET");
+        assert!(
+            request.path.ends_with("?limit=5&q=rust"),
+            "{}",
+            request.path
+        );
+        assert!(request.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn skill_set_executes_a_loaded_skill_by_name() {
+        let server =
+            MockServer::start(vec![MockResponse::json(200, &json!({ "stars": 42 }))]).await;
+        let skills = SkillSet::new(vec![test_support::http_skill(&server.url)]);
+
+        let out = skills
+            .execute("test-skill", &json!({ "repo": "evo" }))
+            .await
+            .unwrap();
+        assert_eq!(out["stars"], 42);
+        assert_eq!(server.requests()[0].json(), json!({ "repo": "evo" }));
+        // Derefs to the slice of loaded skills
+        assert_eq!(skills.len(), 1);
+
+        let err = skills
+            .execute("github-search", &json!({}))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Skill 'github-search' is not loaded");
+    }
+
+    #[tokio::test]
+    async fn url_placeholders_are_filled_from_the_input() {
+        let server = MockServer::start(vec![MockResponse::json(200, &json!({ "repos": 2 }))]).await;
+        let mut skill = http_skill(&format!("{}/users/{{user_id}}/repos", server.url));
+        skill.config.as_mut().unwrap().endpoints[0].method = HttpMethod::Get;
+        let client = reqwest::Client::new();
+
+        let out = run_config_skill(
+            &client,
+            &skill,
+            &json!({ "user_id": "ada lovelace", "per_page": 5 }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(out["repos"], 2);
+        // The input is still sent whole
+        assert_eq!(
+            server.requests()[0].path,
+            "/users/ada%20lovelace/repos?per_page=5&user_id=ada+lovelace"
+        );
+
+        let err = run_config_skill(&client, &skill, &json!({ "per_page": 5 }))
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("input has no field 'user_id' for {user_id}"),
+            "{err:#}"
+        );
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn parallel_endpoints_aggregate_results_and_errors() {
+        let slow = MockServer::start(vec![
+            MockResponse::json(200, &json!({ "n": 1 })).with_delay(Duration::from_millis(200)),
+        ])
+        .await;
+        let fast = MockServer::start(vec![MockResponse::json(200, &json!({ "n": 2 }))]).await;
+        let broken = MockServer::start(vec![MockResponse::new(503, "text/plain", "busy")]).await;
+        let skill = multi_endpoint_skill(
+            &[
+                ("slow", &slow.url),
+                ("fast", &fast.url),
+                ("broken", &broken.url),
+            ],
+            ExecutionPolicy {
+                mode: ExecutionMode::Parallel,
+                max_concurrent: Some(3),
+                ..Default::default()
+            },
+        );
+        let out = run_config_skill(&reqwest::Client::new(), &skill, &json!({}))
+            .await
+            .unwrap();
+        assert_eq!(out["results"]["slow"]["n"], 1);
+        assert_eq!(out["results"]["fast"]["n"], 2);
+        assert!(
+            out["errors"]["broken"].as_str().unwrap().contains("503"),
+            "{out}"
+        );
+
+        assert_eq!(
+            ExecutionPolicy::from_config("[execution]\nmode = \"parallel\"\nmax_concurrent = 2\n"),
+            ExecutionPolicy {
+                mode: ExecutionMode::Parallel,
+                max_concurrent: Some(2),
+                ..Default::default()
+            }
+        );
+        assert_eq!(ExecutionPolicy::from_config(""), ExecutionPolicy::default());
+    }
+
+    fn write_skill(agent_dir: &Path, name: &str, capability: &str, extra: &str) {
+        write_skill_at(agent_dir, name, name, capability, extra);
+    }
+
+    fn write_skill_at(agent_dir: &Path, dir: &str, name: &str, capability: &str, extra: &str) {
+        let dir = agent_dir.join("skills").join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("manifest.toml"),
+            format!(
+                "name = \"{name}\"\nversion = \"0.1.0\"\ndescription = \"t\"\n\
+                 capabilities = [\"{capability}\"]\ninputs = []\noutputs = []\n{extra}"
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn skills_load_after_their_dependencies() {
+        let agent_dir = test_support::temp_dir("deps-chain");
+        write_skill_at(&agent_dir, "a", "alpha", "a", "dependencies = [\"beta\"]\n");
+        write_skill_at(&agent_dir, "b", "beta", "b", "dependencies = [\"gamma\"]\n");
+        write_skill_at(&agent_dir, "c", "gamma", "c", "");
+        write_skill_at(&agent_dir, "d", "delta", "d", "");
+        write_skill_at(
+            &agent_dir,
+            "e",
+            "orphan",
+            "e",
+            "dependencies = [\"missing\"]\n",
+        );
+
+        let mut skills = load_skills_with(&agent_dir, DuplicatePolicy::FirstWins);
+        order_by_dependencies(&mut skills, MissingDependencyPolicy::Flag).unwrap();
+        let order: Vec<&str> = skills.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(order, vec!["delta", "gamma", "beta", "alpha", "orphan"]);
+        let orphan = skills.iter().find(|s| s.name == "orphan").unwrap();
+        assert_eq!(orphan.unsatisfied, vec!["missing"]);
+        assert!(!advertised_capabilities(&skills).contains(&"e".to_string()));
+
+        let mut skills = load_skills_with(&agent_dir, DuplicatePolicy::FirstWins);
+        order_by_dependencies(&mut skills, MissingDependencyPolicy::Skip).unwrap();
+        assert!(skills.iter().all(|s| s.name != "orphan"));
+        std::fs::remove_dir_all(&agent_dir).ok();
+    }
+
+    #[test]
+    fn dependency_cycle_is_reported() {
+        let agent_dir = test_support::temp_dir("deps-cycle");
+        write_skill_at(&agent_dir, "a", "ping", "p", "dependencies = [\"pong\"]\n");
+        write_skill_at(&agent_dir, "b", "pong", "q", "dependencies = [\"ping\"]\n");
+        write_skill_at(&agent_dir, "c", "solo", "s", "");
+
+        let mut skills = load_skills_with(&agent_dir, DuplicatePolicy::FirstWins);
+        let err = order_by_dependencies(&mut skills, MissingDependencyPolicy::Flag).unwrap_err();
+        assert_eq!(err.skills, vec!["ping", "pong"]);
+        assert_eq!(skills[0].name, "solo");
+        assert!(
+            skills[1..]
+                .iter()
+                .all(|s| !s.advertise && !s.unsatisfied.is_empty())
+        );
+        std::fs::remove_dir_all(&agent_dir).ok();
+    }
+
+    #[test]
+    fn non_advertised_skill_is_loaded_but_not_registered() {
+        let agent_dir = test_support::temp_dir("advertise");
+        write_skill(&agent_dir, "public", "search", "");
+        write_skill(&agent_dir, "staging", "scrape", "advertise = false\n");
+
+        let skills = load_skills(&agent_dir);
+        assert_eq!(skills.len(), 2);
+        assert!(skills.iter().any(|s| s.name == "staging" && !s.advertise));
+
+        assert_eq!(advertised_capabilities(&skills), vec!["search".to_string()]);
+    }
+
+    #[test]
+    fn duplicate_skill_names_load_once_per_policy() {
+        let agent_dir = test_support::temp_dir("duplicates");
+        write_skill_at(&agent_dir, "a-search", "search", "web", "");
+        write_skill_at(&agent_dir, "b-search", "search", "news", "");
+
+        let first = load_skills_with(&agent_dir, DuplicatePolicy::FirstWins);
+        assert_eq!(first.len(), 1);
+        assert!(first[0].path.ends_with("a-search"));
+
+        let last = load_skills_with(&agent_dir, DuplicatePolicy::LastWins);
+        assert_eq!(last.len(), 1);
+        assert!(last[0].path.ends_with("b-search"));
+        assert_eq!(last[0].manifest.capabilities, vec!["news".to_string()]);
+        std::fs::remove_dir_all(&agent_dir).ok();
+    }
+
+    #[test]
+    fn skills_and_capabilities_are_sorted_independent_of_directories() {
+        let agent_dir = test_support::temp_dir("ordering");
+        write_skill_at(&agent_dir, "a-dir", "zeta", "search", "");
+        write_skill_at(&agent_dir, "b-dir", "alpha", "weather", "");
+        write_skill_at(&agent_dir, "c-dir", "mid", "analytics", "");
+
+        let skills = load_skills_with(&agent_dir, DuplicatePolicy::FirstWins);
+        let names: Vec<&str> = skills.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["alpha", "mid", "zeta"]);
+
+        let mut reversed = skills.clone();
+        reversed.reverse();
+        assert_eq!(
+            advertised_capabilities(&reversed),
+            vec!["analytics", "search", "weather"]
+        );
+        std::fs::remove_dir_all(&agent_dir).ok();
     }
 
-    Ok(body)
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn code_skill_env_is_restricted_to_allow_list() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let agent_dir = test_support::temp_dir("sandbox");
+        write_skill(&agent_dir, "envdump", "debug", "has_code = true\n");
+        let skill_dir = agent_dir.join("skills/envdump");
+        let script = skill_dir.join("run");
+        std::fs::write(&script, "#!/bin/sh\nenv\necho \"CWD=$(pwd -P)\"\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let sandbox = SkillSandbox::default();
+        // Any variable of the test process that isn't allow-listed must not leak
+        let (leaked, _) = std::env::vars()
+            .find(|(k, _)| !sandbox.env_allow.contains(k))
+            .expect("test process has a non-allow-listed env var");
+
+        let skill = load_skills(&agent_dir).remove(0);
+        let output = run_code_skill(&skill, &json!({}), &sandbox).await.unwrap();
+        let env = output["text"].as_str().unwrap();
+        let names: Vec<&str> = env.lines().filter_map(|l| l.split('=').next()).collect();
+
+        assert!(!names.contains(&leaked.as_str()), "{leaked} leaked: {env}");
+        assert!(names.contains(&"PATH"));
+        let cwd = skill_dir.canonicalize().unwrap();
+        assert!(env.contains(&format!("CWD={}", cwd.display())), "{env}");
+        std::fs::remove_dir_all(&agent_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn skill_uses_its_scoped_env_over_the_global_one() {
+        const TOKEN: &str = "EVO_TEST_SCOPED_API_TOKEN";
+        let _env = test_support::ENV_LOCK.lock().await;
+        // SAFETY: the env lock serializes env mutation across tests
+        unsafe { std::env::set_var(TOKEN, "global-token") };
+        let server = MockServer::start(vec![
+            MockResponse::json(200, &json!({ "ok": true })),
+            MockResponse::json(200, &json!({ "ok": true })),
+        ])
+        .await;
+        let agent_dir = test_support::temp_dir("skill-env");
+        let config = format!(
+            "auth_ref = \"{TOKEN}\"\n\n[[endpoints]]\nname = \"call\"\nurl = \"{}\"\nmethod = \"POST\"\n\n[env]\nREGION = \"eu\"\n",
+            server.url
+        );
+        for name in ["scoped", "plain"] {
+            write_skill(&agent_dir, name, name, "");
+            std::fs::write(
+                agent_dir.join(format!("skills/{name}/config.toml")),
+                &config,
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            agent_dir.join("skills/scoped/.env"),
+            format!("# scoped secrets\nexport {TOKEN}=\"scoped-token\"\n"),
+        )
+        .unwrap();
+
+        let skills = load_skills(&agent_dir);
+        let scoped = skills.iter().find(|s| s.name == "scoped").unwrap();
+        let plain = skills.iter().find(|s| s.name == "plain").unwrap();
+        assert_eq!(scoped.env.get(TOKEN), Some("scoped-token"));
+        assert_eq!(scoped.env.get("REGION"), Some("eu"));
+        assert!(!format!("{scoped:?}").contains("scoped-token"));
+
+        let client = reqwest::Client::new();
+        run_config_skill(&client, scoped, &json!({})).await.unwrap();
+        run_config_skill(&client, plain, &json!({})).await.unwrap();
+        let requests = server.requests();
+        assert_eq!(requests[0].headers["authorization"], "Bearer scoped-token");
+        // Without a scoped value the global one is used
+        assert_eq!(requests[1].headers["authorization"], "Bearer global-token");
+
+        unsafe { std::env::remove_var(TOKEN) };
+        std::fs::remove_dir_all(&agent_dir).ok();
+    }
+
+    #[test]
+    fn skill_load_logs_each_skill_and_failures() {
+        use tracing_subscriber::prelude::*;
+
+        let agent_dir = test_support::temp_dir("skill-load-log");
+        write_skill(&agent_dir, "search", "web-search", "");
+        let broken = agent_dir.join("skills/broken");
+        std::fs::create_dir_all(&broken).unwrap();
+        std::fs::write(broken.join("manifest.toml"), "name = ").unwrap();
+
+        let buf = test_support::SharedBuf::default();
+        let writer = buf.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(move || writer.clone()),
+        );
+        let load = tracing::subscriber::with_default(subscriber, || {
+            let load = load_skills_report(&agent_dir);
+            log_skill_load("custom", &load);
+            log_skill_load("custom", &SkillLoad::default());
+            load
+        });
+
+        assert_eq!(load.skills.len(), 1);
+        assert_eq!(load.failed.len(), 1);
+        let lines = buf.json_lines();
+        let message = |m: &str| lines.iter().find(|l| l["fields"]["message"] == m).cloned();
+        let ready = message("skill ready").expect("per-skill line");
+        assert_eq!(ready["fields"]["skill"], "search");
+        assert!(
+            ready["fields"]["capabilities"]
+                .as_str()
+                .unwrap()
+                .contains("web-search")
+        );
+        let summary = message("skills loaded").expect("summary line");
+        assert_eq!(summary["fields"]["loaded"], 1);
+        assert_eq!(summary["fields"]["failed"], 1);
+        assert!(message("failed to load skill").is_some());
+        assert!(lines.iter().any(|l| {
+            l["fields"]["message"]
+                .as_str()
+                .is_some_and(|m| m.starts_with("NO SKILLS LOADED"))
+        }));
+        std::fs::remove_dir_all(&agent_dir).ok();
+    }
 }
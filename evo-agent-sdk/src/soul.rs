@@ -1,5 +1,13 @@
-use anyhow::{Context, Result};
-use std::path::Path;
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+
+use crate::config::env_parse;
+
+/// Behaviors shorter than this many characters get a startup warning.
+pub const DEFAULT_MIN_BEHAVIOR_CHARS: usize = 20;
 
 // ─── Soul definition ──────────────────────────────────────────────────────────
 
@@ -8,14 +16,66 @@ use std::path::Path;
 pub struct Soul {
     /// The agent's role (e.g. "learning", "building").
     pub role: String,
-    /// The agent's unique identifier (defaults to role + UUID).
+    /// The agent's unique identifier: the soul's `## Agent ID` (or
+    /// front-matter `agent_id`) when set, else derived from folder and role.
     pub agent_id: String,
     /// The `## Behavior` section content — used as the LLM system prompt.
     pub behavior: String,
-    /// Raw markdown body of the soul (stored for future introspection).
+    /// Replica suffix appended to the derived `agent_id`, if any.
+    pub replica_id: Option<String>,
+    /// Model from the `## Model` section (or front-matter `model`), used by
+    /// the kernel handlers and whenever a request names none.
+    pub model: Option<String>,
+    /// Per-agent feature flags from the `[flags]` table of `evo.toml` and
+    /// the soul's TOML front-matter (front-matter wins).
+    pub flags: HashMap<String, bool>,
+    /// Markdown body of the soul, without its front-matter (stored for
+    /// future introspection).
     pub body: String,
 }
 
+/// The running agent's [`Soul`], swappable without a restart (king command
+/// `reload_soul`). Readers take a snapshot with [`load`](Self::load), so a
+/// dispatch keeps one consistent soul even if a reload lands mid-stage.
+pub struct SharedSoul {
+    agent_dir: PathBuf,
+    current: RwLock<Arc<Soul>>,
+}
+
+impl SharedSoul {
+    pub fn new(agent_dir: impl Into<PathBuf>, soul: Soul) -> Self {
+        Self {
+            agent_dir: agent_dir.into(),
+            current: RwLock::new(Arc::new(soul)),
+        }
+    }
+
+    /// The soul in effect right now.
+    pub fn load(&self) -> Arc<Soul> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Re-read `soul.md` and swap it in. The agent keeps its `agent_id` and
+    /// replica until restart, and the role can't change: the handler was
+    /// chosen for it at startup. On error the current soul stays in effect.
+    pub fn reload(&self) -> Result<Arc<Soul>> {
+        let mut soul = load_soul(&self.agent_dir)
+            .with_context(|| format!("Failed to reload soul from {}", self.agent_dir.display()))?;
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        if soul.role != current.role {
+            bail!(
+                "role changed from {} to {}; restart the agent to change roles",
+                current.role,
+                soul.role
+            );
+        }
+        soul.agent_id = current.agent_id.clone();
+        soul.replica_id = current.replica_id.clone();
+        *current = Arc::new(soul);
+        Ok(Arc::clone(&current))
+    }
+}
+
 // ─── Parsing ──────────────────────────────────────────────────────────────────
 
 /// Read and parse `soul.md` from `agent_dir`.
@@ -30,51 +90,454 @@ pub struct Soul {
 /// ## Behavior
 /// ...
 /// ```
+///
+/// The file may start with front-matter: TOML between `+++` lines, or YAML
+/// between `---` lines, e.g. a `[flags]` table or `flags:` mapping.
 pub fn load_soul(agent_dir: &Path) -> Result<Soul> {
     let path = agent_dir.join("soul.md");
-    let content = std::fs::read_to_string(&path)
-        .with_context(|| format!("Failed to read {}", path.display()))?;
+    // Windows-authored files: keep `\r` out of the behavior prompt
+    let raw = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .replace("\r\n", "\n");
+    let (front_matter, content) = split_front_matter(&raw);
+    let front_matter = front_matter
+        .map(FrontMatter::parse)
+        .transpose()
+        .with_context(|| format!("Invalid front-matter in {}", path.display()))?
+        .unwrap_or_default();
+
+    let mut flags = match std::fs::read_to_string(agent_dir.join("evo.toml")) {
+        Ok(toml) => toml::from_str(&toml)
+            .map_err(anyhow::Error::from)
+            .and_then(|doc| parse_flags(&doc))
+            .context("Invalid evo.toml")?,
+        Err(_) => HashMap::new(),
+    };
+    flags.extend(front_matter.flags);
+
+    check_required_sections(content, &required_sections())
+        .with_context(|| format!("Invalid {}", path.display()))?;
 
-    let role = extract_section(&content, "Role")
+    let role = extract_section(content, "Role")
         .unwrap_or_else(|| "unknown".to_string())
         .trim()
         .to_lowercase()
         .replace(' ', "-");
 
-    let behavior = extract_full_section(&content, "Behavior").unwrap_or_default();
+    let behavior = resolve_behavior(
+        agent_dir,
+        &extract_full_section(content, "Behavior").unwrap_or_default(),
+    )?;
 
-    // Derive agent ID from folder name + role
-    let folder_name = agent_dir
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("agent");
+    // Replica suffix: AGENT_REPLICA_ID env wins over the soul's `## Replica`
+    let replica_id = std::env::var("AGENT_REPLICA_ID")
+        .ok()
+        .or_else(|| extract_section(content, "Replica"))
+        .and_then(|spec| resolve_replica_id(&spec));
 
-    let agent_id = format!("{folder_name}-{role}");
+    // An explicit ID is used verbatim; otherwise derive it from folder
+    // name + role (+ replica)
+    // (an empty `## Agent ID` section is an error, not a fallback)
+    let explicit_id = if section_headers(content).contains(&"Agent ID") {
+        Some(extract_full_section(content, "Agent ID").unwrap_or_default())
+    } else {
+        front_matter.agent_id
+    };
+    let agent_id = match explicit_id {
+        Some(id) => {
+            validate_agent_id(&id).with_context(|| format!("Invalid {}", path.display()))?
+        }
+        None => {
+            let folder_name = agent_dir
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("agent");
+            derive_agent_id(folder_name, &role, replica_id.as_deref())
+        }
+    };
+
+    // `## Model` wins over front-matter `model`
+    let model = extract_section(content, "Model")
+        .map(|m| m.trim().to_string())
+        .or(front_matter.model.filter(|m| !m.trim().is_empty()));
 
     Ok(Soul {
         role,
         agent_id,
         behavior,
-        body: content,
+        replica_id,
+        model,
+        flags,
+        body: content.to_string(),
+    })
+}
+
+impl Soul {
+    /// Whether feature flag `name` is on. Unset flags are off.
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.get(name).copied().unwrap_or(false)
+    }
+
+    /// The soul's model, or the SDK default (`gpt-4o-mini`) when it sets none.
+    pub fn default_model(&self) -> &str {
+        self.model
+            .as_deref()
+            .unwrap_or(crate::kernel_handlers::DEFAULT_MODEL)
+    }
+}
+
+/// Minimum behavior length from `SOUL_MIN_BEHAVIOR_CHARS` (`0` = no check).
+pub fn min_behavior_chars() -> usize {
+    env_parse("SOUL_MIN_BEHAVIOR_CHARS").unwrap_or(DEFAULT_MIN_BEHAVIOR_CHARS)
+}
+
+/// Warn when the soul's behavior is under `min_chars` characters: a
+/// near-empty system prompt yields poor output long before anything fails.
+/// Returns whether it warned.
+pub fn warn_if_terse_behavior(soul: &Soul, min_chars: usize) -> bool {
+    let len = soul.behavior.trim().chars().count();
+    if len >= min_chars {
+        return false;
+    }
+    warn!(
+        agent_id = %soul.agent_id,
+        behavior_len = len,
+        min_chars,
+        "## Behavior is very short — the system prompt may be too terse for useful output"
+    );
+    true
+}
+
+/// Sections `soul.md` must define: `SOUL_REQUIRED_SECTIONS` (comma-separated),
+/// defaulting to `Role`.
+pub fn required_sections() -> Vec<String> {
+    std::env::var("SOUL_REQUIRED_SECTIONS")
+        .unwrap_or_else(|_| "Role".to_string())
+        .split(',')
+        .map(|s| s.trim().trim_start_matches('#').trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Fail with one error naming every `required` section missing from `content`.
+pub fn check_required_sections(content: &str, required: &[String]) -> Result<()> {
+    let present = section_headers(content);
+    let missing: Vec<&str> = required
+        .iter()
+        .map(String::as_str)
+        .filter(|r| !present.iter().any(|p| p.eq_ignore_ascii_case(r)))
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "soul.md is missing required section(s): {}",
+            missing
+                .iter()
+                .map(|m| format!("## {m}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// The front-matter fence: TOML or YAML.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrontMatterFormat {
+    Toml,
+    Yaml,
+}
+
+/// Split leading front-matter, fenced by `+++` (TOML) or `---` (YAML)
+/// lines, from the markdown that follows it.
+fn split_front_matter(raw: &str) -> (Option<(FrontMatterFormat, &str)>, &str) {
+    let Some((fence, format)) = [
+        ("+++", FrontMatterFormat::Toml),
+        ("---", FrontMatterFormat::Yaml),
+    ]
+    .into_iter()
+    .find(|(f, _)| raw.lines().next().is_some_and(|l| l.trim_end() == *f)) else {
+        return (None, raw);
+    };
+    let after_open = &raw[raw.find('\n').map_or(raw.len(), |i| i + 1)..];
+    let mut offset = 0;
+    for line in after_open.split_inclusive('\n') {
+        if line.trim_end() == fence {
+            return (
+                Some((format, &after_open[..offset])),
+                &after_open[offset + line.len()..],
+            );
+        }
+        offset += line.len();
+    }
+    (None, raw)
+}
+
+/// The keys `load_soul` reads from a soul's front-matter.
+#[derive(Debug, Default)]
+struct FrontMatter {
+    agent_id: Option<String>,
+    model: Option<String>,
+    flags: HashMap<String, bool>,
+}
+
+impl FrontMatter {
+    fn parse((format, src): (FrontMatterFormat, &str)) -> Result<Self> {
+        let doc = match format {
+            FrontMatterFormat::Toml => toml::from_str(src)?,
+            FrontMatterFormat::Yaml => yaml_table(src)?,
+        };
+        let string = |key: &str| -> Result<Option<String>> {
+            doc.get(key)
+                .map(|value| {
+                    value
+                        .as_str()
+                        .map(String::from)
+                        .with_context(|| format!("`{key}` must be a string"))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            agent_id: string("agent_id")?,
+            model: string("model")?,
+            flags: parse_flags(&doc)?,
+        })
+    }
+}
+
+/// The YAML front-matter subset souls use, as a TOML table: `key: value`
+/// scalars and one level of nested mappings (`flags:` followed by indented
+/// `name: true` lines).
+fn yaml_table(src: &str) -> Result<toml::Table> {
+    let mut doc = toml::Table::new();
+    let mut parent: Option<String> = None;
+    for (n, line) in src.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = trimmed.split_once(':') else {
+            bail!("line {}: expected `key: value`", n + 1);
+        };
+        let key = key
+            .trim()
+            .trim_matches(|c| c == '"' || c == '\'')
+            .to_string();
+        let value = yaml_scalar(value);
+        let nested = line.starts_with([' ', '\t']);
+        match (nested, &parent) {
+            (true, Some(parent)) => {
+                if let Some(toml::Value::Table(table)) = doc.get_mut(parent) {
+                    table.insert(key, value.unwrap_or_else(|| "".into()));
+                }
+            }
+            (true, None) => bail!("line {}: unexpected indentation", n + 1),
+            (false, _) => {
+                parent = value.is_none().then(|| key.clone());
+                doc.insert(key, value.unwrap_or_else(|| toml::Table::new().into()));
+            }
+        }
+    }
+    Ok(doc)
+}
+
+/// A YAML scalar; `None` when empty (a nested mapping follows).
+fn yaml_scalar(raw: &str) -> Option<toml::Value> {
+    let raw = raw.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = raw
+            .strip_prefix(quote)
+            .and_then(|r| r.rfind(quote).map(|end| &r[..end]))
+        {
+            return Some(inner.into());
+        }
+    }
+    // An unquoted value ends at a ` #` comment
+    let raw = raw.split(" #").next().unwrap_or_default().trim();
+    Some(match raw {
+        "" => return None,
+        "true" => true.into(),
+        "false" => false.into(),
+        _ => match raw.parse::<i64>() {
+            Ok(n) => n.into(),
+            Err(_) => raw.into(),
+        },
     })
 }
 
+/// Trim an explicitly configured agent ID and check it is usable: non-empty
+/// and without whitespace.
+pub fn validate_agent_id(raw: &str) -> Result<String> {
+    let id = raw.trim();
+    if id.is_empty() {
+        bail!("agent ID is empty");
+    }
+    if id.chars().any(char::is_whitespace) {
+        bail!("agent ID {id:?} must not contain whitespace");
+    }
+    Ok(id.to_string())
+}
+
+/// The boolean entries of a document's `[flags]` table.
+fn parse_flags(doc: &toml::Table) -> Result<HashMap<String, bool>> {
+    let Some(flags) = doc.get("flags") else {
+        return Ok(HashMap::new());
+    };
+    let table = flags.as_table().context("`flags` must be a table")?;
+    table
+        .iter()
+        .map(|(name, value)| match value.as_bool() {
+            Some(on) => Ok((name.clone(), on)),
+            None => bail!("flag `{name}` must be true or false"),
+        })
+        .collect()
+}
+
+/// Each line of `content` with whether it belongs to a fenced code block
+/// (` ``` ` or `~~~`), the fence lines themselves included.
+fn fenced_lines(content: &str) -> impl Iterator<Item = (&str, bool)> {
+    content.lines().scan(None::<&str>, |fence, line| {
+        let trimmed = line.trim();
+        let fenced = match *fence {
+            Some(open) => {
+                if trimmed.starts_with(open) {
+                    *fence = None;
+                }
+                true
+            }
+            None => match ["```", "~~~"].into_iter().find(|f| trimmed.starts_with(f)) {
+                Some(open) => {
+                    *fence = Some(open);
+                    true
+                }
+                None => false,
+            },
+        };
+        Some((line, fenced))
+    })
+}
+
+/// Names of all `## ` headers outside fenced code blocks.
+fn section_headers(content: &str) -> Vec<&str> {
+    fenced_lines(content)
+        .filter(|(_, fenced)| !fenced)
+        .filter_map(|(line, _)| line.trim().strip_prefix("## "))
+        .map(str::trim)
+        .collect()
+}
+
+/// Resolve the `## Behavior` section: a lone `@file: <path>` directive is
+/// replaced by the contents of that file (relative to `agent_dir`); anything
+/// else is returned as-is.
+pub fn resolve_behavior(agent_dir: &Path, section: &str) -> Result<String> {
+    let Some(reference) = section
+        .trim()
+        .strip_prefix("@file:")
+        .map(str::trim)
+        .filter(|r| !r.is_empty() && !r.contains('\n'))
+    else {
+        return Ok(section.to_string());
+    };
+
+    let path = agent_dir.join(reference);
+    let behavior = std::fs::read_to_string(&path).with_context(|| {
+        format!(
+            "Behavior file referenced in soul.md (@file: {reference}) could not be read at {}",
+            path.display()
+        )
+    })?;
+    Ok(behavior
+        .replace("\r\n", "\n")
+        .trim_end()
+        .trim_start_matches(['\n', '\r'])
+        .to_string())
+}
+
+/// Locate the agent directory containing `soul.md`.
+///
+/// `dir` itself is used when it contains `soul.md`. Otherwise its immediate
+/// subdirectories are searched, and exactly one of them must contain a
+/// `soul.md` (e.g. running from a monorepo root with `./agents/learning/`).
+pub fn resolve_agent_dir(dir: &Path) -> Result<PathBuf> {
+    if dir.join("soul.md").is_file() {
+        return Ok(dir.to_path_buf());
+    }
+
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read agent folder {}", dir.display()))?;
+
+    let mut candidates: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && p.join("soul.md").is_file())
+        .collect();
+    candidates.sort();
+
+    match candidates.len() {
+        0 => bail!(
+            "No soul.md found in {} or its subdirectories",
+            dir.display()
+        ),
+        1 => Ok(candidates.remove(0)),
+        _ => bail!(
+            "Ambiguous agent folder {}: multiple subdirectories contain soul.md: {}",
+            dir.display(),
+            candidates
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Build the agent ID as `<folder>-<role>`, with `-<replica>` appended when
+/// running as one of several replicas of the same agent.
+pub fn derive_agent_id(folder_name: &str, role: &str, replica_id: Option<&str>) -> String {
+    match replica_id {
+        Some(replica) => format!("{folder_name}-{role}-{replica}"),
+        None => format!("{folder_name}-{role}"),
+    }
+}
+
+/// Resolve a replica spec into a concrete suffix.
+///
+/// `hostname` expands to the machine hostname, `auto` to a short random id;
+/// any other non-empty value is used verbatim (whitespace replaced by `-`).
+pub fn resolve_replica_id(spec: &str) -> Option<String> {
+    let spec = spec.trim();
+    match spec {
+        "" => None,
+        "hostname" => hostname(),
+        "auto" => Some(uuid::Uuid::new_v4().simple().to_string()[..8].to_string()),
+        other => Some(other.split_whitespace().collect::<Vec<_>>().join("-")),
+    }
+}
+
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+}
+
 /// Extract the first non-empty line of a `## Section` from markdown.
+/// Headers inside fenced code blocks are treated as content.
 pub fn extract_section(content: &str, section: &str) -> Option<String> {
     let marker = format!("## {section}");
     let mut in_section = false;
 
-    for line in content.lines() {
-        if line.trim() == marker {
+    for (line, fenced) in fenced_lines(content) {
+        let trimmed = line.trim();
+        if !fenced && trimmed == marker {
             in_section = true;
             continue;
         }
         if in_section {
-            let trimmed = line.trim();
             if trimmed.is_empty() {
                 continue;
             }
-            if trimmed.starts_with('#') {
+            if !fenced && trimmed.starts_with('#') {
                 break; // next section
             }
             return Some(trimmed.to_string());
@@ -86,42 +549,40 @@ pub fn extract_section(content: &str, section: &str) -> Option<String> {
 
 /// Extract the full multi-line content of a `## Section` from markdown.
 ///
-/// Returns all lines between `## Section` and the next `##` header (or EOF).
+/// Returns all lines between `## Section` and the next `##` header (or EOF)
+/// verbatim, so lists, indentation and blank-line-separated paragraphs
+/// survive. Headers inside fenced code blocks are treated as content.
+/// Leading and trailing blank lines are dropped.
 pub fn extract_full_section(content: &str, section: &str) -> Option<String> {
     let marker = format!("## {section}");
     let mut in_section = false;
     let mut lines = Vec::new();
 
-    for line in content.lines() {
-        if line.trim() == marker {
+    for (line, fenced) in fenced_lines(content) {
+        let trimmed = line.trim();
+        if !fenced && trimmed == marker {
             in_section = true;
             continue;
         }
+        if !fenced && in_section && trimmed.starts_with("## ") {
+            break; // next section
+        }
         if in_section {
-            if line.trim().starts_with("## ") {
-                break; // next section
-            }
             lines.push(line);
         }
     }
 
-    if lines.is_empty() {
-        return None;
-    }
-
-    // Trim leading/trailing empty lines
-    let text = lines.join("\n");
-    let trimmed = text.trim().to_string();
-    if trimmed.is_empty() {
-        None
-    } else {
-        Some(trimmed)
-    }
+    let first = lines.iter().position(|l| !l.trim().is_empty())?;
+    let last = lines.iter().rposition(|l| !l.trim().is_empty())?;
+    Some(lines[first..=last].join("\n"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::temp_dir;
+
+    const SOUL: &str = "# Agent\n\n## Role\nlearning\n";
 
     #[test]
     fn extract_role_from_soul_content() {
@@ -136,6 +597,12 @@ mod tests {
         assert!(extract_section(content, "Role").is_none());
     }
 
+    #[test]
+    fn extract_section_skips_headers_in_code_fences() {
+        let content = "# Agent\n\n## Behavior\nExample soul:\n```md\n## Model\nfake-model\n```\n\n## Model\nreal-model\n";
+        assert_eq!(extract_section(content, "Model").unwrap(), "real-model");
+    }
+
     #[test]
     fn extract_full_behavior_section() {
         let content = "# Learning Agent\n\n## Role\nlearning\n\n## Behavior\n- Discover skills\n- Evaluate candidates\n- Report findings\n\n## Events\n- pipeline:next";
@@ -153,4 +620,257 @@ mod tests {
         assert!(behavior.contains("Do stuff."));
         assert!(behavior.contains("More stuff."));
     }
+
+    #[test]
+    fn header_inside_code_fence_does_not_end_section() {
+        let content = "## Behavior\nEmit reports like:\n```markdown\n## Summary\n- item\n```\nKeep it short.\n\n## Events\n- pipeline:next";
+        let behavior = extract_full_section(content, "Behavior").unwrap();
+        assert_eq!(
+            behavior,
+            "Emit reports like:\n```markdown\n## Summary\n- item\n```\nKeep it short."
+        );
+    }
+
+    #[test]
+    fn blank_lines_and_indentation_are_preserved() {
+        let content =
+            "## Behavior\n\n    indented first line\n\nSecond paragraph.\n\n\n  - nested item\n\n";
+        let behavior = extract_full_section(content, "Behavior").unwrap();
+        assert_eq!(
+            behavior,
+            "    indented first line\n\nSecond paragraph.\n\n\n  - nested item"
+        );
+    }
+
+    #[test]
+    fn behavior_is_read_inline_or_from_referenced_file() {
+        let root = temp_dir("behavior-file");
+        std::fs::write(
+            root.join("soul.md"),
+            "## Role\nlearning\n\n## Behavior\nInline prompt.\n",
+        )
+        .unwrap();
+        assert_eq!(load_soul(&root).unwrap().behavior, "Inline prompt.");
+
+        std::fs::create_dir_all(root.join("prompts")).unwrap();
+        std::fs::write(
+            root.join("prompts/behavior.md"),
+            "\nYou discover skills.\n\n- Be thorough\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("soul.md"),
+            "## Role\nlearning\n\n## Behavior\n@file: prompts/behavior.md\n",
+        )
+        .unwrap();
+        assert_eq!(
+            load_soul(&root).unwrap().behavior,
+            "You discover skills.\n\n- Be thorough"
+        );
+
+        std::fs::remove_file(root.join("prompts/behavior.md")).unwrap();
+        let err = format!("{:#}", load_soul(&root).unwrap_err());
+        assert!(err.contains("@file: prompts/behavior.md"), "{err}");
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn flags_come_from_evo_toml_and_front_matter() {
+        let root = temp_dir("soul-flags");
+        std::fs::write(
+            root.join("evo.toml"),
+            "[flags]\nexperimental = false\nverbose = true\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("soul.md"),
+            "+++\n[flags]\nexperimental = true\n+++\n# Agent\n\n## Role\nlearning\n",
+        )
+        .unwrap();
+
+        let soul = load_soul(&root).unwrap();
+        assert_eq!(soul.role, "learning");
+        assert!(soul.flag("experimental"));
+        assert!(soul.flag("verbose"));
+        assert!(!soul.flag("missing"));
+
+        std::fs::write(
+            root.join("soul.md"),
+            "---\nflags:\n  fast: 1\n---\n## Role\nx\n",
+        )
+        .unwrap();
+        let err = format!("{:#}", load_soul(&root).unwrap_err());
+        assert!(err.contains("flag `fast`"), "{err}");
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn crlf_soul_yields_clean_behavior() {
+        let root = temp_dir("soul-crlf");
+        std::fs::write(
+            root.join("soul.md"),
+            "# Agent\r\n\r\n## Role\r\nlearning\r\n\r\n## Behavior\r\n- One\r\n- Two\r\n",
+        )
+        .unwrap();
+
+        let soul = load_soul(&root).unwrap();
+        assert_eq!(soul.role, "learning");
+        assert_eq!(soul.behavior, "- One\n- Two");
+        assert!(!soul.body.contains('\r'));
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn terse_behavior_logs_a_warning() {
+        use tracing_subscriber::prelude::*;
+
+        let buf = crate::test_support::SharedBuf::default();
+        let writer = buf.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(move || writer.clone()),
+        );
+        let mut soul = crate::test_support::soul("learning");
+        soul.behavior = "Be helpful.".to_string();
+        let warned = tracing::subscriber::with_default(subscriber, || {
+            warn_if_terse_behavior(&soul, DEFAULT_MIN_BEHAVIOR_CHARS)
+        });
+
+        assert!(warned);
+        let lines = buf.json_lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "WARN");
+        assert_eq!(lines[0]["fields"]["behavior_len"], 11);
+
+        soul.behavior = "You discover and evaluate new skills.".to_string();
+        assert!(!warn_if_terse_behavior(&soul, DEFAULT_MIN_BEHAVIOR_CHARS));
+        assert!(!warn_if_terse_behavior(&soul, 0));
+    }
+
+    #[test]
+    fn all_missing_required_sections_are_reported() {
+        let content = "# Agent\n\n## Role\nlearning\n\n```\n## Model\n```\n";
+        let required = ["Role", "Model", "Events"].map(String::from);
+        let err = check_required_sections(content, &required)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("## Model"), "{err}");
+        assert!(err.contains("## Events"), "{err}");
+        assert!(!err.contains("## Role"), "{err}");
+
+        assert!(check_required_sections(content, &["Role".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn explicit_agent_id_overrides_derived_one() {
+        let root = temp_dir("soul-agent-id");
+        let load = |soul: &str| {
+            std::fs::write(root.join("soul.md"), soul).unwrap();
+            load_soul(&root)
+        };
+
+        let soul = load("## Role\nlearning\n\n## Agent ID\n  learning-eu-1  \n").unwrap();
+        assert_eq!(soul.agent_id, "learning-eu-1");
+        let soul = load("+++\nagent_id = \"learning-us-2\"\n+++\n## Role\nlearning\n").unwrap();
+        assert_eq!(soul.agent_id, "learning-us-2");
+        let soul = load(
+            "---\nagent_id: learning-us-3 # pinned\nmodel: \"o1\"\nflags:\n  fast: true\n---\n\
+             ## Role\nlearning\n",
+        )
+        .unwrap();
+        assert_eq!(soul.agent_id, "learning-us-3");
+        assert_eq!(soul.model.as_deref(), Some("o1"));
+        assert!(soul.flag("fast"));
+        assert!(soul.body.starts_with("## Role"), "{}", soul.body);
+        let soul = load("## Role\nlearning\n").unwrap();
+        assert!(soul.agent_id.ends_with("-learning"), "{}", soul.agent_id);
+
+        let err = format!(
+            "{:#}",
+            load("## Role\nlearning\n\n## Agent ID\n\n").unwrap_err()
+        );
+        assert!(err.contains("agent ID is empty"), "{err}");
+        let err = format!(
+            "{:#}",
+            load("## Role\nx\n\n## Agent ID\nmy agent\n").unwrap_err()
+        );
+        assert!(err.contains("must not contain whitespace"), "{err}");
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn model_comes_from_section_or_front_matter() {
+        let root = temp_dir("soul-model");
+        let load = |soul: &str| {
+            std::fs::write(root.join("soul.md"), soul).unwrap();
+            load_soul(&root).unwrap()
+        };
+
+        let soul = load("## Role\nlearning\n");
+        assert_eq!(soul.default_model(), "gpt-4o-mini");
+
+        // Front-matter sets the model too; a `## Model` section wins
+        let soul = load("+++\nmodel = \"gpt-4o\"\n+++\n## Role\nlearning\n");
+        assert_eq!(soul.model.as_deref(), Some("gpt-4o"));
+        let soul = load("+++\nmodel = \"gpt-4o\"\n+++\n## Role\nx\n\n## Model\no1\n");
+        assert_eq!(soul.model.as_deref(), Some("o1"));
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn replica_ids_yield_distinct_agent_ids() {
+        let a = derive_agent_id("learning", "learning", Some("r1"));
+        let b = derive_agent_id("learning", "learning", Some("r2"));
+        assert_ne!(a, b);
+        assert_eq!(a, "learning-learning-r1");
+        assert_eq!(
+            derive_agent_id("learning", "learning", None),
+            "learning-learning"
+        );
+    }
+
+    #[test]
+    fn replica_spec_resolution() {
+        assert_eq!(resolve_replica_id("  "), None);
+        assert_eq!(resolve_replica_id("node 2").as_deref(), Some("node-2"));
+        let auto = resolve_replica_id("auto").unwrap();
+        assert_eq!(auto.len(), 8);
+        assert_ne!(auto, resolve_replica_id("auto").unwrap());
+    }
+
+    #[test]
+    fn resolve_agent_dir_prefers_direct_soul() {
+        let root = temp_dir("direct");
+        std::fs::write(root.join("soul.md"), SOUL).unwrap();
+        std::fs::create_dir_all(root.join("nested")).unwrap();
+        std::fs::write(root.join("nested/soul.md"), SOUL).unwrap();
+
+        assert_eq!(resolve_agent_dir(&root).unwrap(), root);
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn resolve_agent_dir_finds_single_nested_soul() {
+        let root = temp_dir("nested");
+        std::fs::create_dir_all(root.join("learning")).unwrap();
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("learning/soul.md"), SOUL).unwrap();
+
+        assert_eq!(resolve_agent_dir(&root).unwrap(), root.join("learning"));
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn resolve_agent_dir_rejects_ambiguous_nesting() {
+        let root = temp_dir("ambiguous");
+        for name in ["learning", "building"] {
+            std::fs::create_dir_all(root.join(name)).unwrap();
+            std::fs::write(root.join(name).join("soul.md"), SOUL).unwrap();
+        }
+
+        let err = resolve_agent_dir(&root).unwrap_err().to_string();
+        assert!(err.contains("Ambiguous"), "{err}");
+        std::fs::remove_dir_all(&root).ok();
+    }
 }
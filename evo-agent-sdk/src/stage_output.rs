@@ -0,0 +1,158 @@
+//! Compact and verbose variants of `pipeline:stage_result` outputs.
+//!
+//! By default the runner sends a compact `output`: `raw_response` fields are
+//! dropped and arrays longer than [`MAX_ARRAY_ITEMS`] are cut short. When
+//! that trims anything, the full output is written to
+//! `<EVO_HOME>/data/stage-results/<run_id>/<stage>.json` and listed as a
+//! `full_output` artifact king can fetch; like the run cache, those files
+//! are pruned by age (`STAGE_RESULTS_TTL_SECS`) and run count
+//! (`STAGE_RESULTS_MAX_RUNS`). A stage whose metadata sets
+//! `verbose: true` (or every stage, with `STAGE_RESULT_VERBOSE=1`) gets the
+//! full output inline instead.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::env_parse;
+use crate::handler::ArtifactRef;
+use crate::prompt_dump::sanitize;
+use crate::run_cache::{DEFAULT_MAX_RUNS, prune_runs};
+
+/// Longest array kept in a compact output.
+pub const MAX_ARRAY_ITEMS: usize = 20;
+
+/// Object keys dropped from compact outputs, at any depth.
+const HEAVY_KEYS: &[&str] = &["raw_response"];
+
+/// Name of the artifact holding a trimmed stage's full output.
+pub const FULL_OUTPUT_ARTIFACT: &str = "full_output";
+
+/// How long full outputs are kept by default: a week.
+const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Whether a stage asked for its full output inline (`metadata.verbose`).
+pub fn wants_verbose(metadata: &Value) -> bool {
+    metadata["verbose"].as_bool() == Some(true)
+}
+
+/// The compact form of `output`, and whether anything was trimmed.
+pub fn compact(output: &Value) -> (Value, bool) {
+    let mut trimmed = false;
+    let compacted = compact_value(output, &mut trimmed);
+    (compacted, trimmed)
+}
+
+fn compact_value(value: &Value, trimmed: &mut bool) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut compacted = serde_json::Map::new();
+            for (key, v) in map {
+                if HEAVY_KEYS.contains(&key.as_str()) {
+                    *trimmed = true;
+                } else {
+                    compacted.insert(key.clone(), compact_value(v, trimmed));
+                }
+            }
+            Value::Object(compacted)
+        }
+        Value::Array(items) => {
+            *trimmed |= items.len() > MAX_ARRAY_ITEMS;
+            Value::Array(
+                items
+                    .iter()
+                    .take(MAX_ARRAY_ITEMS)
+                    .map(|v| compact_value(v, trimmed))
+                    .collect(),
+            )
+        }
+        other => other.clone(),
+    }
+}
+
+/// Where full outputs of compacted stages are written.
+#[derive(Debug, Clone)]
+pub struct FullOutputStore {
+    dir: PathBuf,
+    ttl: Duration,
+    max_runs: usize,
+}
+
+impl FullOutputStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: DEFAULT_TTL,
+            max_runs: DEFAULT_MAX_RUNS,
+        }
+    }
+
+    /// Keep runs for at most `ttl`, and only the newest `max_runs`.
+    pub fn with_retention(mut self, ttl: Duration, max_runs: usize) -> Self {
+        self.ttl = ttl;
+        self.max_runs = max_runs.max(1);
+        self
+    }
+
+    /// `<EVO_HOME>/data/stage-results`, with retention from
+    /// `STAGE_RESULTS_TTL_SECS` (default a week) and `STAGE_RESULTS_MAX_RUNS`
+    /// (default 50).
+    pub fn default_location() -> Self {
+        let ttl =
+            env_parse::<u64>("STAGE_RESULTS_TTL_SECS").map_or(DEFAULT_TTL, Duration::from_secs);
+        let max_runs = env_parse::<usize>("STAGE_RESULTS_MAX_RUNS").unwrap_or(DEFAULT_MAX_RUNS);
+        Self::new(
+            crate::self_upgrade::evo_home()
+                .join("data")
+                .join("stage-results"),
+        )
+        .with_retention(ttl, max_runs)
+    }
+
+    /// Write `output` for `(run_id, stage)`, prune old runs, and describe it
+    /// as an artifact.
+    pub fn store(&self, run_id: &str, stage: &str, output: &Value) -> Result<ArtifactRef> {
+        let run_dir = self.dir.join(sanitize(run_id));
+        std::fs::create_dir_all(&run_dir)
+            .with_context(|| format!("Failed to create {}", run_dir.display()))?;
+        let path = run_dir.join(format!("{}.json", sanitize(stage)));
+        let body = serde_json::to_vec(output)?;
+        std::fs::write(&path, &body)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        prune_runs(&self.dir, self.ttl, self.max_runs);
+        Ok(
+            ArtifactRef::new(FULL_OUTPUT_ARTIFACT, path.display().to_string())
+                .with_size(body.len() as u64)
+                .with_content_type("application/json"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use serde_json::json;
+
+    #[test]
+    fn full_outputs_keep_only_the_newest_runs() {
+        let dir = test_support::temp_dir("full-outputs");
+        let store = FullOutputStore::new(&dir).with_retention(Duration::from_secs(60), 2);
+
+        for (run, age) in [("run-a", 30), ("run-b", 20), ("run-c", 10)] {
+            store
+                .store(run, "building", &json!({ "run": run }))
+                .unwrap();
+            let modified = std::time::SystemTime::now() - Duration::from_secs(age);
+            std::fs::File::open(dir.join(run))
+                .and_then(|f| f.set_modified(modified))
+                .unwrap();
+        }
+
+        assert!(!dir.join("run-a").exists());
+        assert!(dir.join("run-b").join("building.json").exists());
+        assert!(dir.join("run-c").join("building.json").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
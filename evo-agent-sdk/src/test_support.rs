@@ -0,0 +1,387 @@
+//! Minimal in-process HTTP fixtures shared by unit tests.
+//!
+//! `MockServer` answers each incoming connection with the next queued
+//! [`MockResponse`] (repeating the last one once the queue is drained) and
+//! records every request it sees.
+
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::emit::Emit;
+use crate::gateway_client::GatewayClient;
+use crate::handler::PipelineContext;
+use crate::skill_engine::{LoadedSkill, SkillSet, SkillUsageLog, StageSkills};
+use crate::soul::Soul;
+use evo_common::skill::{HttpMethod, SkillConfig, SkillEndpoint, SkillManifest};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Held by tests that set process env vars, so they don't race each other.
+pub(crate) static ENV_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Create a fresh, empty directory under the system temp dir.
+pub(crate) fn temp_dir(prefix: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("evo-sdk-{prefix}-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A minimal soul for handler tests.
+pub(crate) fn soul(role: &str) -> Soul {
+    Soul {
+        role: role.to_string(),
+        agent_id: format!("test-{role}"),
+        behavior: "You are a test agent.".to_string(),
+        replica_id: None,
+        model: None,
+        flags: Default::default(),
+        body: String::new(),
+    }
+}
+
+static NO_SKILLS: SkillSet = SkillSet::new(Vec::new());
+
+/// An empty skill set that outlives any test.
+pub(crate) fn no_skills() -> &'static SkillSet {
+    &NO_SKILLS
+}
+
+/// A pipeline context with the given metadata and no skills.
+pub(crate) fn pipeline_ctx<'a>(
+    soul: &'a Soul,
+    gateway: &'a Arc<GatewayClient>,
+    metadata: Value,
+) -> PipelineContext<'a> {
+    let skills_used = SkillUsageLog::default();
+    PipelineContext {
+        soul,
+        gateway,
+        skills: StageSkills::new(no_skills(), skills_used.clone()),
+        run_id: "run-test".to_string(),
+        stage: soul.role.clone(),
+        artifact_id: "artifact-test".to_string(),
+        metadata,
+        cancel: Default::default(),
+        skills_used,
+        emitter: Arc::new(RecordingEmitter::default()),
+        run_cache: None,
+        warnings: Default::default(),
+        pipeline_requests: Default::default(),
+    }
+}
+
+/// An [`Emit`] sink that records every event instead of sending it.
+#[derive(Default, Clone)]
+pub(crate) struct RecordingEmitter {
+    events: Arc<Mutex<Vec<(String, Value)>>>,
+    ack: Option<Value>,
+    ack_delay: Duration,
+}
+
+impl RecordingEmitter {
+    /// A recorder that answers every `emit_with_ack` with `ack`.
+    pub fn with_ack(ack: Value) -> Self {
+        Self {
+            ack: Some(ack),
+            ..Self::default()
+        }
+    }
+
+    /// A recorder whose ack arrives only after `delay`, whatever timeout the
+    /// caller passes; enforcing the deadline is up to the caller.
+    pub fn with_ack_after(ack: Value, delay: Duration) -> Self {
+        Self {
+            ack_delay: delay,
+            ..Self::with_ack(ack)
+        }
+    }
+
+    pub fn events(&self) -> Vec<(String, Value)> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Emit for RecordingEmitter {
+    async fn emit(&self, event: &str, payload: Value) -> Result<()> {
+        self.events
+            .lock()
+            .unwrap()
+            .push((event.to_string(), payload));
+        Ok(())
+    }
+
+    async fn emit_with_ack(
+        &self,
+        event: &str,
+        payload: Value,
+        timeout: Duration,
+    ) -> Result<Option<Value>> {
+        let _ = timeout;
+        self.emit(event, payload).await?;
+        tokio::time::sleep(self.ack_delay).await;
+        Ok(self.ack.clone())
+    }
+}
+
+/// A config-only skill with a single POST endpoint at `url`.
+pub(crate) fn http_skill(url: &str) -> LoadedSkill {
+    LoadedSkill {
+        name: "test-skill".to_string(),
+        manifest: SkillManifest {
+            name: "test-skill".to_string(),
+            version: "0.1.0".to_string(),
+            description: "test".to_string(),
+            capabilities: vec!["test".to_string()],
+            inputs: vec![],
+            outputs: vec![],
+            dependencies: vec![],
+            has_code: false,
+        },
+        config: Some(SkillConfig {
+            endpoints: vec![SkillEndpoint {
+                name: "call".to_string(),
+                url: url.to_string(),
+                method: HttpMethod::Post,
+                headers: HashMap::new(),
+            }],
+            auth_ref: None,
+            extra: HashMap::new(),
+        }),
+        path: PathBuf::from("skills/test-skill"),
+        advertise: true,
+        unsatisfied: vec![],
+        execution: Default::default(),
+        env: Default::default(),
+    }
+}
+
+/// A cloneable in-memory writer, for capturing output meant for stdout.
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuf {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).to_string()
+    }
+
+    /// Each line parsed as JSON (panics on a malformed line).
+    pub fn json_lines(&self) -> Vec<Value> {
+        self.contents()
+            .lines()
+            .map(|l| serde_json::from_str(l).expect("malformed JSON line"))
+            .collect()
+    }
+}
+
+impl std::io::Write for SharedBuf {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A canned HTTP response.
+#[derive(Debug, Clone)]
+pub(crate) struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub delay: Duration,
+    /// Close the connection after this many body bytes.
+    pub cut_after: Option<usize>,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body: body.into(),
+            delay: Duration::ZERO,
+            cut_after: None,
+        }
+    }
+
+    pub fn json(status: u16, body: &Value) -> Self {
+        Self::new(status, "application/json", body.to_string())
+    }
+
+    /// An SSE body made of `data: <event>` lines.
+    pub fn sse(events: &[&str]) -> Self {
+        let body: String = events.iter().map(|e| format!("data: {e}\n\n")).collect();
+        Self::new(200, "text/event-stream", body)
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Advertise the full `Content-Length` but drop the connection after
+    /// `bytes` of the body, so the client sees a broken read.
+    pub fn with_cut_after(mut self, bytes: usize) -> Self {
+        self.cut_after = Some(bytes);
+        self
+    }
+}
+
+/// A non-streaming chat completion whose message is `content` serialized.
+pub(crate) fn llm_reply(content: Value) -> MockResponse {
+    MockResponse::json(
+        200,
+        &serde_json::json!({ "choices": [{ "message": { "content": content.to_string() } }] }),
+    )
+}
+
+/// A request captured by [`MockServer`].
+#[derive(Debug, Clone)]
+pub(crate) struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lower-cased.
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+pub(crate) struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockServer {
+    pub async fn start(responses: Vec<MockResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let queue = Arc::new(Mutex::new(VecDeque::from(responses)));
+
+        let recorded = Arc::clone(&requests);
+        tokio::spawn(async move {
+            let mut last: Option<MockResponse> = None;
+            while let Ok((stream, _)) = listener.accept().await {
+                let response = {
+                    let mut q = queue.lock().unwrap();
+                    match q.pop_front() {
+                        Some(r) => {
+                            last = Some(r.clone());
+                            r
+                        }
+                        None => last
+                            .clone()
+                            .unwrap_or_else(|| MockResponse::new(404, "text/plain", "")),
+                    }
+                };
+                let recorded = Arc::clone(&recorded);
+                tokio::spawn(handle(stream, response, recorded));
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    response: MockResponse,
+    recorded: Arc<Mutex<Vec<RecordedRequest>>>,
+) {
+    if let Some(request) = read_request(&mut stream).await {
+        recorded.lock().unwrap().push(request);
+    }
+
+    tokio::time::sleep(response.delay).await;
+
+    let mut head = format!("HTTP/1.1 {} Mock\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+
+    let _ = stream.write_all(head.as_bytes()).await;
+    let body = match response.cut_after {
+        Some(n) => &response.body[..n.min(response.body.len())],
+        None => &response.body[..],
+    };
+    let _ = stream.write_all(body).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<RecordedRequest> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+
+    let headers: HashMap<String, String> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+
+    let content_length: usize = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    while buf.len() < header_end + content_length {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let body = String::from_utf8_lossy(&buf[header_end..]).to_string();
+
+    Some(RecordedRequest {
+        method,
+        path,
+        headers,
+        body,
+    })
+}